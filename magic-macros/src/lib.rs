@@ -10,8 +10,16 @@ use syn;
 
 #[proc_macro]
 pub fn vr4300_instr_enum(code: TokenStream) -> TokenStream {
+    instr_enum(code, "../magic-macros/mipsiii.json")
+}
+
+#[proc_macro]
+pub fn rsp_instr_enum(code: TokenStream) -> TokenStream {
+    instr_enum(code, "../magic-macros/rsp.json")
+}
+
+fn instr_enum(code: TokenStream, input: &str) -> TokenStream {
     let enum_name = syn::parse_macro_input!(code as syn::Ident);
-    let input = "../magic-macros/mipsiii.json";
     println!(
         "opening {} from {:?}",
         input,
@@ -36,4 +44,3 @@ pub fn vr4300_instr_enum(code: TokenStream) -> TokenStream {
     };
     result.into()
 }
-
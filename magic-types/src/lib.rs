@@ -1,8 +1,8 @@
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
 use std::fmt;
 use ux::{u5, u6};
-use itertools::Itertools;
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum InstructionType {
@@ -224,94 +224,186 @@ impl Default for MetaInstruction {
 }
 
 impl MetaInstruction {
-    pub fn legal_encodings(&self) -> Box<dyn Iterator<Item = u32>> {
-        let opcode: u32 = u32::from(self.opcode.0) << 26;
-        let mut local_results: Box<dyn Iterator<Item = u32>> = Box::new(opcode..=opcode);
-        match self.itype {
-            InstructionType::R => {
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(
-                            if let Some(funct) = self.funct.map(|x| u32::from(x.0)) {
-                                (funct..=funct)
-                            } else {
-                                (0..=u6::max_value().into())
-                            },
-                        )
-                        .map(|(x, y)| x | y),
-                );
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(if let Some(sa) = self.sa.map(|x| u32::from(x.0)) {
-                            (sa..=sa)
-                        } else {
-                            (0..=u5::max_value().into())
-                        })
-                        .map(|(x, y)| x | (y << 6)),
-                );
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(if let Some(rd) = self.rd.map(|x| u32::from(x.0)) {
-                            (rd..=rd)
-                        } else {
-                            (0..=u5::max_value().into())
-                        })
-                        .map(|(x, y)| x | (y << 11)),
-                );
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(if let Some(rt) = self.rt.map(|x| u32::from(x.0)) {
-                            (rt..=rt)
-                        } else {
-                            (0..=u5::max_value().into())
-                        })
-                        .map(|(x, y)| x | (y << 16)),
-                );
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(if let Some(rs) = self.rs.map(|x| u32::from(x.0)) {
-                            (rs..=rs)
-                        } else {
-                            (0..=u5::max_value().into())
-                        })
-                        .map(|(x, y)| x | (y << 21)),
-                );
+    // The encoding as a (mask, match) pair: a word is this instruction if
+    // `word & mask == match`. The mask covers the opcode and any fixed
+    // fields; everything else, including immediates and jump targets, is
+    // free.
+    pub fn mask_match(&self) -> (u32, u32) {
+        let mut mask = 0x3F << 26;
+        let mut value = u32::from(self.opcode.0) << 26;
+        let fields = match self.itype {
+            InstructionType::R => vec![
+                (self.funct.map(|x| u32::from(x.0)), 0x3F, 0),
+                (self.sa.map(|x| u32::from(x.0)), 0x1F, 6),
+                (self.rd.map(|x| u32::from(x.0)), 0x1F, 11),
+                (self.rt.map(|x| u32::from(x.0)), 0x1F, 16),
+                (self.rs.map(|x| u32::from(x.0)), 0x1F, 21),
+            ],
+            InstructionType::I => vec![
+                (self.rt.map(|x| u32::from(x.0)), 0x1F, 16),
+                (self.rs.map(|x| u32::from(x.0)), 0x1F, 21),
+            ],
+            InstructionType::J => vec![],
+        };
+        for (field, field_mask, shift) in fields {
+            if let Some(field) = field {
+                mask |= field_mask << shift;
+                value |= field << shift;
             }
-            InstructionType::I => {
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(0..=u16::max_value().into())
-                        .map(|(x, y)| x | y),
-                );
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(if let Some(rt) = self.rt.map(|x| u32::from(x.0)) {
-                            (rt..=rt)
-                        } else {
-                            (0..=u5::max_value().into())
-                        })
-                        .map(|(x, y)| x | (y << 16)),
-                );
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(if let Some(rs) = self.rs.map(|x| u32::from(x.0)) {
-                            (rs..=rs)
-                        } else {
-                            (0..=u5::max_value().into())
-                        })
-                        .map(|(x, y)| x | (y << 21)),
-                );
-            }
-            InstructionType::J => {
-                local_results = Box::new(
-                    local_results
-                        .cartesian_product(0..=0x03FF_FFFF)
-                        .map(|(x, y)| x | y),
-                );
+        }
+        (mask, value)
+    }
+
+    pub fn legal_encodings(&self) -> LegalEncodings {
+        let (mask, value) = self.mask_match();
+        LegalEncodings {
+            mask,
+            value,
+            free: 0,
+            remaining: 1 << (!mask).count_ones(),
+        }
+    }
+}
+
+// Every word with the fixed bits of an instruction, in increasing order.
+#[derive(Copy, Clone, Debug)]
+pub struct LegalEncodings {
+    mask: u32,
+    value: u32,
+    // The free bits of the next word.
+    free: u32,
+    remaining: u64,
+}
+
+impl LegalEncodings {
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    // The `index`th of `count` contiguous, roughly equal runs of the
+    // encodings still to come, for splitting work between threads.
+    pub fn shard(&self, index: u64, count: u64) -> LegalEncodings {
+        let position = extract(self.free, !self.mask);
+        let start = self.remaining * index / count;
+        let end = self.remaining * (index + 1) / count;
+        LegalEncodings {
+            free: deposit(position + start, !self.mask),
+            remaining: end - start,
+            ..*self
+        }
+    }
+}
+
+impl Iterator for LegalEncodings {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let word = self.value | self.free;
+        // Carrying through the fixed bits steps to the next free pattern.
+        self.free = (self.free | self.mask).wrapping_add(1) & !self.mask;
+        self.remaining -= 1;
+        Some(word)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.remaining) {
+            Ok(remaining) => (remaining, Some(remaining)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+// Packs the bits of `word` selected by `mask` into the low bits.
+fn extract(word: u32, mask: u32) -> u64 {
+    let mut result = 0;
+    let mut bit = 0;
+    for i in 0..32 {
+        if mask & (1 << i) != 0 {
+            result |= u64::from((word >> i) & 1) << bit;
+            bit += 1;
+        }
+    }
+    result
+}
+
+// Spreads the low bits of `bits` out over the bits set in `mask`.
+fn deposit(bits: u64, mask: u32) -> u32 {
+    let mut result = 0;
+    let mut bit = 0;
+    for i in 0..32 {
+        if mask & (1 << i) != 0 {
+            result |= (((bits >> bit) & 1) as u32) << i;
+            bit += 1;
+        }
+    }
+    result
+}
+
+// Two entries that claim some of the same words, and the lowest such word.
+#[derive(Debug, PartialEq)]
+pub struct Overlap {
+    pub first: String,
+    pub second: String,
+    pub word: u32,
+}
+
+// Every pair of entries whose encodings intersect. An empty result proves
+// that each word decodes to at most one instruction.
+pub fn overlaps(instrs: &[MetaInstruction]) -> Vec<Overlap> {
+    let encodings: Vec<(u32, u32)> = instrs.iter().map(MetaInstruction::mask_match).collect();
+    let mut overlaps = Vec::new();
+    for (i, &(mask_a, match_a)) in encodings.iter().enumerate() {
+        for (j, &(mask_b, match_b)) in encodings.iter().enumerate().skip(i + 1) {
+            if (match_a ^ match_b) & mask_a & mask_b == 0 {
+                overlaps.push(Overlap {
+                    first: instrs[i].name.clone(),
+                    second: instrs[j].name.clone(),
+                    word: match_a | match_b,
+                });
             }
         }
-        local_results.into_iter()
     }
+    overlaps
+}
+
+// The words no entry claims, as disjoint (mask, match) pairs.
+pub fn unclaimed(instrs: &[MetaInstruction]) -> Vec<(u32, u32)> {
+    let mut unclaimed = vec![(0, 0)];
+    for instr in instrs {
+        let claimed = instr.mask_match();
+        unclaimed = unclaimed
+            .into_iter()
+            .flat_map(|words| subtract(words, claimed))
+            .collect();
+    }
+    unclaimed
+}
+
+// The number of words a (mask, match) pair covers.
+pub fn word_count((mask, _): (u32, u32)) -> u64 {
+    1 << (!mask).count_ones()
+}
+
+// Splits the words of `words` that aren't in `claimed` into disjoint pairs,
+// one for each bit `claimed` fixes that `words` leaves free.
+fn subtract(words: (u32, u32), claimed: (u32, u32)) -> Vec<(u32, u32)> {
+    let ((mut mask, mut value), (claimed_mask, claimed_value)) = (words, claimed);
+    if (value ^ claimed_value) & mask & claimed_mask != 0 {
+        return vec![(mask, value)];
+    }
+    let mut rest = Vec::new();
+    let mut split = claimed_mask & !mask;
+    while split != 0 {
+        let bit = split & split.wrapping_neg();
+        rest.push((mask | bit, value | (!claimed_value & bit)));
+        mask |= bit;
+        value |= claimed_value & bit;
+        split &= split - 1;
+    }
+    rest
 }
 
 #[cfg(test)]
@@ -376,4 +468,61 @@ mod tests {
         let except: Exception = serde_json::from_str(r#""overflow""#).unwrap();
         assert_eq!(except, Exception::Overflow);
     }
+
+    #[test]
+    fn legal_encodings_fill_free_fields() {
+        let sll: MetaInstruction = serde_json::from_str(
+            r#"{"name": "SLL", "type": "R", "opcode": "000000", "funct": "000000", "rs": "00000"}"#,
+        )
+        .unwrap();
+        let encodings: Vec<u32> = sll.legal_encodings().collect();
+        assert_eq!(encodings.len(), 1 << 15);
+        assert_eq!(encodings[0], 0);
+        assert_eq!(encodings[1], 1 << 6);
+        assert_eq!(*encodings.last().unwrap(), 0x001F_FFC0);
+        assert!(encodings.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn overlaps_and_unclaimed_words() {
+        let instrs: Vec<MetaInstruction> = serde_json::from_str(
+            r#"[
+                {"name": "SLL", "type": "R", "opcode": "000000", "funct": "000000", "rs": "00000"},
+                {"name": "NOP", "type": "R", "opcode": "000000", "funct": "000000",
+                 "rs": "00000", "rt": "00000", "rd": "00000", "sa": "00000"},
+                {"name": "J", "type": "J", "opcode": "000010"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(instrs[0].mask_match(), (0xFFE0_003F, 0));
+        assert_eq!(
+            overlaps(&instrs),
+            vec![Overlap {
+                first: "SLL".to_string(),
+                second: "NOP".to_string(),
+                word: 0,
+            }]
+        );
+
+        let unclaimed = unclaimed(&instrs[..1]);
+        let count: u64 = unclaimed.iter().map(|&words| word_count(words)).sum();
+        assert_eq!(count, (1 << 32) - (1 << 15));
+        for &(mask, value) in &unclaimed {
+            assert_ne!(value & 0xFFE0_003F, 0, "{:#X} {:#X}", mask, value);
+            assert_eq!(value & !mask, 0);
+        }
+    }
+
+    #[test]
+    fn shards_cover_encodings_once() {
+        let bc1f: MetaInstruction = serde_json::from_str(
+            r#"{"name": "BC1F", "type": "I", "opcode": "010001", "rs": "01000", "rt": "00000"}"#,
+        )
+        .unwrap();
+        let all: Vec<u32> = bc1f.legal_encodings().collect();
+        let mut encodings = bc1f.legal_encodings();
+        encodings.nth(99);
+        let sharded: Vec<u32> = (0..7).flat_map(|i| encodings.shard(i, 7)).collect();
+        assert_eq!(sharded, all[100..]);
+    }
 }
@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use magic::cart::Cartridge;
+use magic::decoder::decode_vr4300;
+use std::fs::File;
+use std::hint::black_box;
+
+const RANDOM_WORDS: usize = 4096;
+// Game code starts after the header and the boot code.
+const ROM_CODE_START: usize = 0x1000;
+const ROM_CODE_LEN: usize = 1 << 20;
+
+fn random_words() -> Vec<u32> {
+    let mut x: u32 = 0x2545_F491;
+    (0..RANDOM_WORDS)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x
+        })
+        .collect()
+}
+
+// The first megabyte of code from the ROM named by MAGIC_BENCH_ROM, if set.
+fn rom_words() -> Option<Vec<u32>> {
+    let path = std::env::var("MAGIC_BENCH_ROM").ok()?;
+    let mut file = match File::open(&path) {
+        Err(why) => panic!("Couldn't open rom file {}: {}", path, why),
+        Ok(file) => file,
+    };
+    let cart = Cartridge::new(&mut file);
+    let rom = cart.rom();
+    let end = rom.len().min(ROM_CODE_START + ROM_CODE_LEN);
+    Some(
+        rom.get(ROM_CODE_START..end)?
+            .chunks_exact(4)
+            .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+            .collect(),
+    )
+}
+
+fn decode_all(words: &[u32]) {
+    for &iw in words {
+        black_box(decode_vr4300(black_box(iw)));
+    }
+}
+
+fn bench_decoder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_vr4300");
+
+    let random = random_words();
+    group.throughput(Throughput::Elements(random.len() as u64));
+    group.bench_function("random", |b| b.iter(|| decode_all(&random)));
+
+    match rom_words() {
+        Some(rom) => {
+            group.throughput(Throughput::Elements(rom.len() as u64));
+            group.bench_function("rom", |b| b.iter(|| decode_all(&rom)));
+        }
+        None => println!("Set MAGIC_BENCH_ROM to a ROM image to benchmark decoding its code"),
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_decoder);
+criterion_main!(benches);
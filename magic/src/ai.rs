@@ -0,0 +1,286 @@
+use crate::scheduler::{Event, Scheduler, CPU_CLOCK_HZ};
+use crate::vi::VI_NTSC_CLOCK_HZ;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const AI_REGS_START: u32 = 0x0450_0000;
+pub const AI_REGS_END: u32 = 0x045F_FFFF;
+
+const AI_DRAM_ADDR: u32 = 0x00;
+const AI_LEN: u32 = 0x04;
+const AI_CONTROL: u32 = 0x08;
+const AI_STATUS: u32 = 0x0C;
+const AI_DACRATE: u32 = 0x10;
+const AI_BITRATE: u32 = 0x14;
+
+const AI_CONTROL_DMA_ENABLE: u32 = 0x01;
+const AI_STATUS_FULL: u32 = (1 << 31) | 1;
+const AI_STATUS_BUSY: u32 = 1 << 30;
+const AI_STATUS_ENABLED: u32 = 1 << 25;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AiDma {
+    pub dram_addr: u32,
+    pub len: u32,
+}
+
+// Receives interleaved 16-bit stereo samples as each buffer starts playing.
+pub trait AudioSink {
+    fn samples(&mut self, rate: u32, samples: &[i16]);
+}
+
+// Writes 16-bit stereo PCM to a WAV file. The sample rate is taken from the
+// first buffer; the header sizes are filled in when the sink is dropped.
+pub struct WavSink<W: Write + Seek> {
+    out: W,
+    rate: Option<u32>,
+    data_len: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create(path: &Path) -> Self {
+        match File::create(path) {
+            Err(why) => panic!("Couldn't create wav file {}: {}", path.display(), why),
+            Ok(file) => WavSink::new(BufWriter::new(file)),
+        }
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut out: W) -> Self {
+        if let Err(why) = write_wav_header(&mut out, 0, 0) {
+            panic!("Couldn't write wav header: {}", why);
+        }
+        WavSink {
+            out,
+            rate: None,
+            data_len: 0,
+        }
+    }
+
+    pub fn finish(&mut self) {
+        let rate = self.rate.unwrap_or(0);
+        let result = self
+            .out
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| write_wav_header(&mut self.out, rate, self.data_len))
+            .and_then(|_| self.out.seek(SeekFrom::End(0)))
+            .and_then(|_| self.out.flush());
+        if let Err(why) = result {
+            panic!("Couldn't finish wav file: {}", why);
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn samples(&mut self, rate: u32, samples: &[i16]) {
+        self.rate.get_or_insert(rate);
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        if let Err(why) = self.out.write_all(&bytes) {
+            panic!("Couldn't write wav samples: {}", why);
+        }
+        self.data_len += bytes.len() as u32;
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn write_wav_header(out: &mut impl Write, rate: u32, data_len: u32) -> std::io::Result<()> {
+    const CHANNELS: u16 = 2;
+    const BITS: u16 = 16;
+    let block_align = CHANNELS * BITS / 8;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&CHANNELS.to_le_bytes())?;
+    out.write_all(&rate.to_le_bytes())?;
+    out.write_all(&(rate * u32::from(block_align)).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&BITS.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())
+}
+
+#[derive(Default)]
+pub struct Ai {
+    dram_addr: u32,
+    control: u32,
+    dacrate: u32,
+    bitrate: u32,
+    // The playing buffer and the time it finishes, then the queued buffer.
+    current: Option<(AiDma, u64)>,
+    queued: Option<AiDma>,
+    started: Option<AiDma>,
+    interrupt: bool,
+}
+
+impl Ai {
+    pub fn read_word(&self, offset: u32, scheduler: &Scheduler) -> u32 {
+        match offset {
+            AI_LEN => match self.current {
+                Some((dma, end)) => {
+                    let remaining = end.saturating_sub(scheduler.now());
+                    let total = self.buffer_cycles(dma.len).max(1);
+                    ((u64::from(dma.len) * remaining / total) as u32) & !7
+                }
+                None => 0,
+            },
+            AI_STATUS => {
+                let mut status = AI_STATUS_ENABLED;
+                if self.current.is_some() {
+                    status |= AI_STATUS_BUSY;
+                }
+                if self.queued.is_some() {
+                    status |= AI_STATUS_FULL;
+                }
+                status
+            }
+            // The remaining registers are write-only and read back as the
+            // current length.
+            AI_DRAM_ADDR | AI_CONTROL | AI_DACRATE | AI_BITRATE => {
+                self.read_word(AI_LEN, scheduler)
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32, scheduler: &mut Scheduler) {
+        match offset {
+            AI_DRAM_ADDR => self.dram_addr = value & 0x00FF_FFF8,
+            AI_LEN => {
+                let dma = AiDma {
+                    dram_addr: self.dram_addr,
+                    len: value & 0x0003_FFF8,
+                };
+                if dma.len == 0 || self.queued.is_some() {
+                    return;
+                }
+                if self.current.is_none() {
+                    self.start(dma, scheduler);
+                } else {
+                    self.queued = Some(dma);
+                }
+            }
+            AI_CONTROL => self.control = value & AI_CONTROL_DMA_ENABLE,
+            AI_STATUS => self.interrupt = false,
+            AI_DACRATE => self.dacrate = value & 0x3FFF,
+            AI_BITRATE => self.bitrate = value & 0xF,
+            _ => {}
+        }
+    }
+
+    // The DAC clocks out one stereo sample every DACRATE + 1 VI clocks.
+    pub fn frequency(&self) -> u32 {
+        (VI_NTSC_CLOCK_HZ / (u64::from(self.dacrate) + 1)) as u32
+    }
+
+    fn buffer_cycles(&self, len: u32) -> u64 {
+        u64::from(len / 4) * (u64::from(self.dacrate) + 1) * CPU_CLOCK_HZ / VI_NTSC_CLOCK_HZ
+    }
+
+    // Starting a buffer raises the interrupt so the game can queue the next.
+    fn start(&mut self, dma: AiDma, scheduler: &mut Scheduler) {
+        let cycles = self.buffer_cycles(dma.len);
+        scheduler.schedule(cycles, Event::AiBufferDone);
+        self.current = Some((dma, scheduler.now() + cycles));
+        if self.control & AI_CONTROL_DMA_ENABLE != 0 {
+            self.started = Some(dma);
+        }
+        self.interrupt = true;
+    }
+
+    // Called when Event::AiBufferDone fires.
+    pub fn buffer_done(&mut self, scheduler: &mut Scheduler) {
+        self.current = None;
+        if let Some(dma) = self.queued.take() {
+            self.start(dma, scheduler);
+        }
+    }
+
+    // Returns the buffer that most recently started playing, if its samples
+    // haven't been fetched yet.
+    pub fn take_started(&mut self) -> Option<AiDma> {
+        self.started.take()
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn double_buffered_dma() {
+        let mut ai = Ai::default();
+        let mut scheduler = Scheduler::default();
+        ai.write_word(AI_CONTROL, AI_CONTROL_DMA_ENABLE, &mut scheduler);
+        // 48.681812MHz / 1104 ~= 44.1kHz
+        ai.write_word(AI_DACRATE, 1103, &mut scheduler);
+        assert_eq!(ai.frequency(), 44_095);
+
+        ai.write_word(AI_DRAM_ADDR, 0x1000, &mut scheduler);
+        ai.write_word(AI_LEN, 0x400, &mut scheduler);
+        ai.write_word(AI_DRAM_ADDR, 0x2000, &mut scheduler);
+        ai.write_word(AI_LEN, 0x400, &mut scheduler);
+        ai.write_word(AI_LEN, 0x400, &mut scheduler);
+        assert!(ai.interrupt());
+        assert_eq!(
+            ai.read_word(AI_STATUS, &scheduler) & (AI_STATUS_FULL | AI_STATUS_BUSY),
+            AI_STATUS_FULL | AI_STATUS_BUSY
+        );
+        assert_eq!(
+            ai.take_started(),
+            Some(AiDma {
+                dram_addr: 0x1000,
+                len: 0x400
+            })
+        );
+        ai.write_word(AI_STATUS, 0, &mut scheduler);
+        assert!(!ai.interrupt());
+
+        // 256 samples at 1104 VI clocks each.
+        let cycles = 256 * 1104 * CPU_CLOCK_HZ / VI_NTSC_CLOCK_HZ;
+        assert_eq!(scheduler.time_of(Event::AiBufferDone), Some(cycles));
+        scheduler.advance(cycles / 2);
+        assert_eq!(ai.read_word(AI_LEN, &scheduler), 0x200);
+        scheduler.advance(cycles - cycles / 2);
+        assert_eq!(scheduler.pop_due(), Some(Event::AiBufferDone));
+        ai.buffer_done(&mut scheduler);
+        assert!(ai.interrupt());
+        assert_eq!(ai.take_started().map(|dma| dma.dram_addr), Some(0x2000));
+        assert_eq!(ai.read_word(AI_STATUS, &scheduler) & AI_STATUS_FULL, 0);
+    }
+
+    #[test]
+    fn wav_output() {
+        let mut sink = WavSink::new(Cursor::new(Vec::new()));
+        sink.samples(32_000, &[1, -1, 0x1234, 0]);
+        sink.finish();
+        let wav = sink.get_ref().get_ref();
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[4..8], &(36u32 + 8).to_le_bytes());
+        assert_eq!(&wav[24..28], &32_000u32.to_le_bytes());
+        assert_eq!(&wav[28..32], &128_000u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &8u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[1, 0, 0xFF, 0xFF, 0x34, 0x12, 0, 0]);
+    }
+}
@@ -1,10 +1,22 @@
 use gumdrop::Options;
+use magic::ai::WavSink;
+use magic::cached_interp::CachedInterpCPU;
+use magic::cart::Cartridge;
+use magic::rdp::Rdp;
+use magic::rdp_capture::{self, RdpCapture};
+use magic::save::ControllerPak;
+use magic::vi::PpmDumper;
 use magic::*;
 use std::error::Error;
 use std::fs::File;
-use std::path::Path;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("rdp-replay") {
+        return rdp_replay();
+    }
+
     let opts = EmuOptions::parse_args_default_or_exit();
     let pifrom_path = Path::new(&opts.pifrom_path);
 
@@ -17,7 +29,131 @@ fn main() {
         Ok(file) => file,
     };
 
-    let _cpu = InterpCPU32bit::<Memory, ICache, MMU32Bit>::new(&mut file);
+    let mut cpu = InterpCPU32bit::<Memory, ICache, DCache, MMU32Bit>::new(&mut file);
+    if let Some(size) = opts.rdram {
+        cpu.bus_mut().set_rdram_size(size);
+    }
+
+    cpu.set_pipelined(opts.pipeline);
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    {
+        if opts.jit || opts.jit_check {
+            cpu.enable_jit(opts.jit_check);
+        }
+    }
+    cpu.bus_mut().set_hle_audio(opts.hle_audio);
+
+    if let Some(capture_path) = &opts.rdp_capture {
+        let capture = RdpCapture::create(Path::new(capture_path), Box::new(Rdp::default()));
+        cpu.bus_mut().set_rdp_backend(Box::new(capture));
+    }
+
+    if let Some(rom_path) = &opts.rom_path {
+        let rom_path = Path::new(rom_path);
+        let mut rom_file = match File::open(rom_path) {
+            Err(why) => panic!("Couldn't open rom file {}: {}", rom_path.display(), why),
+            Ok(file) => file,
+        };
+        let mut cart = Cartridge::new(&mut rom_file);
+        let save_type = opts.save_type.unwrap_or_else(|| cart.detected_save_type());
+        let save_path = match &opts.save_path {
+            Some(path) => PathBuf::from(path),
+            None => rom_path.with_extension(save_type.extension()),
+        };
+        cart.load_save(Some(&save_path), save_type);
+        cpu.bus_mut().insert_cartridge(cart);
+    }
+
+    if let Some(mempak_path) = &opts.mempak_path {
+        let pak = ControllerPak::new(Some(Path::new(mempak_path)));
+        if let Some(controller) = &mut cpu.bus_mut().pif_mut().controllers[0] {
+            controller.pak = Some(pak);
+        }
+    }
+
+    if let Some(frame_dir) = &opts.frame_dir {
+        let dumper = PpmDumper::new(frame_dir, opts.frame_interval.unwrap_or(1));
+        cpu.bus_mut().set_frame_sink(Box::new(dumper));
+    }
+
+    if let Some(wav_path) = &opts.wav_path {
+        let sink = WavSink::create(Path::new(wav_path));
+        cpu.bus_mut().set_audio_sink(Box::new(sink));
+    }
 
     println!("{:#?}", opts);
+
+    let cycles = opts.cycles.unwrap_or(u64::MAX);
+    if opts.cached_interp {
+        let mut cpu = CachedInterpCPU::from(cpu);
+        while cpu.bus_mut().now() < cycles {
+            cpu.step();
+        }
+        cpu.bus_mut().flush_saves();
+    } else {
+        while cpu.bus_mut().now() < cycles {
+            cpu.step();
+        }
+        cpu.bus_mut().flush_saves();
+    }
+}
+
+fn rdp_replay() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let opts = match RdpReplayOptions::parse_args_default(&args) {
+        Err(why) => {
+            eprintln!("rdp-replay: {}", why);
+            std::process::exit(2);
+        }
+        Ok(opts) => opts,
+    };
+    if opts.help_requested() {
+        println!(
+            "Usage: magic rdp-replay CAPTURE IMAGE\n\n{}",
+            RdpReplayOptions::usage()
+        );
+        return;
+    }
+
+    let capture_path = Path::new(&opts.capture_path);
+    let mut file = match File::open(capture_path) {
+        Err(why) => panic!(
+            "Couldn't open capture file {}: {}",
+            capture_path.display(),
+            why
+        ),
+        Ok(file) => file,
+    };
+    let (rdp, rdram) = match rdp_capture::replay(&mut std::io::BufReader::new(&mut file)) {
+        Err(why) => panic!(
+            "Couldn't replay capture file {}: {}",
+            capture_path.display(),
+            why
+        ),
+        Ok(result) => result,
+    };
+    let frame = match rdp.color_frame(&rdram) {
+        Some(frame) => frame,
+        None => panic!(
+            "Capture file {} has no colour image to write",
+            capture_path.display()
+        ),
+    };
+
+    let image_path = Path::new(&opts.image_path);
+    let image = match File::create(image_path) {
+        Err(why) => panic!(
+            "Couldn't create image file {}: {}",
+            image_path.display(),
+            why
+        ),
+        Ok(file) => file,
+    };
+    if let Err(why) = frame.write_ppm(&mut BufWriter::new(image)) {
+        panic!(
+            "Couldn't write image file {}: {}",
+            image_path.display(),
+            why
+        );
+    }
 }
@@ -0,0 +1,544 @@
+use crate::cop0::Exception;
+use crate::decoder::{decode_vr4300, CpuInstrVR4300, CpuInstrVR4300::*};
+use crate::interp::{imm, rd, rs, rt, sa, sext32, simm, Access};
+use crate::{
+    DataCache, InstructionCache, InterpCPU32bit, MemoryBus, CODE_PAGE_SHIFT, CPU, MMU, RDRAM_END,
+};
+use std::collections::HashMap;
+
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+type Handler<C> = fn(&mut C, &Op<C>, u32) -> Result<(), Exception>;
+
+// A decoded instruction: the handler that runs it and its operands, pulled
+// out of the instruction word ahead of time.
+struct Op<C> {
+    handler: Handler<C>,
+    instr: CpuInstrVR4300,
+    iw: u32,
+    s: usize,
+    t: usize,
+    d: usize,
+    // The sign-extended immediate, or the zero-extended one for logical
+    // operations, the shift amount, or the jump target.
+    imm: u64,
+}
+
+type Block<MB, IC, DC, MM> = Vec<Op<InterpCPU32bit<MB, IC, DC, MM>>>;
+
+// An interpreter that decodes each basic block once, the first time it
+// runs, and keeps the decoded instructions keyed by physical address.
+// Blocks are dropped when the RDRAM they were decoded from is written. Like
+// the JIT, it leaves delay slots, pending interrupts, uncached code and
+// pipeline timing to the plain interpreter.
+pub struct CachedInterpCPU<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+{
+    cpu: InterpCPU32bit<MB, IC, DC, MM>,
+    blocks: HashMap<u32, Block<MB, IC, DC, MM>>,
+    // The blocks decoded from each code page.
+    pages: HashMap<u32, Vec<u32>>,
+}
+
+impl<MB, IC, DC, MM> From<InterpCPU32bit<MB, IC, DC, MM>> for CachedInterpCPU<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+{
+    #[allow(unused_mut)]
+    fn from(mut cpu: InterpCPU32bit<MB, IC, DC, MM>) -> Self {
+        // Both watch RDRAM for code writes, so only one of them can.
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        {
+            cpu.jit = None;
+        }
+        CachedInterpCPU {
+            cpu,
+            blocks: HashMap::new(),
+            pages: HashMap::new(),
+        }
+    }
+}
+
+impl<MB, IC, DC, MM> CPU for CachedInterpCPU<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
+    MM::AddressSize: From<u32>,
+{
+    fn new(pifrom_src: &mut impl std::io::Read) -> Self {
+        InterpCPU32bit::new(pifrom_src).into()
+    }
+
+    fn step(&mut self) {
+        let cpu = &mut self.cpu;
+        if cpu.pipeline.is_some() || cpu.delay_slot {
+            return cpu.step();
+        }
+        let pc = cpu.pc;
+        let paddr = match MM::translate(pc.into()) {
+            Some(paddr) if pc & 3 == 0 && MM::is_cached(pc.into()) && paddr <= RDRAM_END => paddr,
+            _ => return cpu.step(),
+        };
+        cpu.sample_interrupts();
+        if cpu.cop0.interrupt_pending() {
+            return cpu.step();
+        }
+
+        for page in cpu.bus.take_code_writes() {
+            if let Some(blocks) = self.pages.remove(&page) {
+                for paddr in blocks {
+                    self.blocks.remove(&paddr);
+                }
+            }
+        }
+        if !self.blocks.contains_key(&paddr) {
+            let block = decode_block(&mut cpu.bus, paddr);
+            let first_page = paddr >> CODE_PAGE_SHIFT;
+            let last_page = (paddr + block.len() as u32 * 4 - 1) >> CODE_PAGE_SHIFT;
+            for page in first_page..=last_page {
+                let blocks = self.pages.entry(page).or_default();
+                if blocks.is_empty() {
+                    cpu.bus.watch_code_page(page);
+                }
+                blocks.push(paddr);
+            }
+            self.blocks.insert(paddr, block);
+        }
+
+        // Runs until control leaves the block, after a taken branch's delay
+        // slot or an exception.
+        let mut cycles = 0;
+        let mut expected = pc;
+        for op in &self.blocks[&paddr] {
+            if cpu.pc != expected {
+                break;
+            }
+            let pc = cpu.pc;
+            let delay_slot = cpu.delay_slot;
+            cpu.pc = cpu.next_pc;
+            cpu.next_pc = cpu.pc.wrapping_add(4);
+            cpu.delay_slot = false;
+            let result = (op.handler)(cpu, op, pc);
+            cpu.gpr[0] = 0;
+            cycles += 1 + std::mem::take(&mut cpu.stall);
+            if let Err(exception) = result {
+                cpu.take_exception(exception, pc, delay_slot);
+                break;
+            }
+            expected = pc.wrapping_add(4);
+        }
+        cpu.bus.tick(cycles);
+    }
+
+    fn run(mut self) {
+        loop {
+            self.step();
+        }
+    }
+}
+
+impl<MB, IC, DC, MM> CachedInterpCPU<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+{
+    pub fn bus_mut(&mut self) -> &mut MB {
+        self.cpu.bus_mut()
+    }
+}
+
+// Decodes from `paddr` up to and including the delay slot of the first
+// branch, or up to the block limit. Blocks stop at the end of the 4KB page,
+// since the next one may be mapped elsewhere, and at the end of RDRAM.
+// Instructions are read straight from RDRAM rather than through the icache,
+// so like the JIT this mode doesn't model icache refills or stale lines.
+fn decode_block<MB, IC, DC, MM>(bus: &mut MB, paddr: u32) -> Block<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
+    MM::AddressSize: From<u32>,
+{
+    let last = (paddr | 0xFFF).min(RDRAM_END);
+    let mut block = Vec::new();
+    let mut end = MAX_BLOCK_INSTRUCTIONS.min((last - paddr) as usize / 4 + 1);
+    while block.len() < end {
+        let iw = bus.read_word(paddr + block.len() as u32 * 4);
+        let op = decode(iw);
+        if ends_block(op.instr) {
+            end = end.min(block.len() + 2);
+        }
+        block.push(op);
+    }
+    block
+}
+
+fn ends_block(instr: CpuInstrVR4300) -> bool {
+    matches!(
+        instr,
+        J | JAL
+            | JR
+            | JALR
+            | BEQ
+            | BNE
+            | BLEZ
+            | BGTZ
+            | BLTZ
+            | BGEZ
+            | BLTZAL
+            | BGEZAL
+            | BEQL
+            | BNEL
+            | BLEZL
+            | BGTZL
+            | BLTZL
+            | BGEZL
+            | BLTZALL
+            | BGEZALL
+            | BC1F
+            | BC1T
+            | BC1FL
+            | BC1TL
+            | ERET
+            | SYSCALL
+            | BREAK
+    )
+}
+
+fn decode<MB, IC, DC, MM>(iw: u32) -> Op<InterpCPU32bit<MB, IC, DC, MM>>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
+    MM::AddressSize: From<u32>,
+{
+    let instr = decode_vr4300(iw);
+    let handler: Handler<InterpCPU32bit<MB, IC, DC, MM>> = match instr {
+        J => InterpCPU32bit::op_j,
+        JAL => InterpCPU32bit::op_jal,
+        JR => InterpCPU32bit::op_jr,
+        BEQ => InterpCPU32bit::op_beq,
+        BNE => InterpCPU32bit::op_bne,
+        ADDIU => InterpCPU32bit::op_addiu,
+        DADDIU => InterpCPU32bit::op_daddiu,
+        SLTI => InterpCPU32bit::op_slti,
+        SLTIU => InterpCPU32bit::op_sltiu,
+        ANDI => InterpCPU32bit::op_andi,
+        ORI => InterpCPU32bit::op_ori,
+        XORI => InterpCPU32bit::op_xori,
+        LUI => InterpCPU32bit::op_lui,
+        ADDU => InterpCPU32bit::op_addu,
+        SUBU => InterpCPU32bit::op_subu,
+        DADDU => InterpCPU32bit::op_daddu,
+        AND => InterpCPU32bit::op_and,
+        OR => InterpCPU32bit::op_or,
+        XOR => InterpCPU32bit::op_xor,
+        SLT => InterpCPU32bit::op_slt,
+        SLTU => InterpCPU32bit::op_sltu,
+        SLL => InterpCPU32bit::op_sll,
+        SRL => InterpCPU32bit::op_srl,
+        SRA => InterpCPU32bit::op_sra,
+        LW => InterpCPU32bit::op_lw,
+        LBU => InterpCPU32bit::op_lbu,
+        SW => InterpCPU32bit::op_sw,
+        SB => InterpCPU32bit::op_sb,
+        // Whether the FPU is usable can change after the block is decoded.
+        _ if iw >> 26 == 0x11 => |cpu, op, pc| {
+            cpu.cop1_usable()?;
+            cpu.execute_decoded(op.instr, op.iw, pc)
+        },
+        _ => |cpu, op, pc| cpu.execute_decoded(op.instr, op.iw, pc),
+    };
+    let imm = match instr {
+        ANDI | ORI | XORI => u64::from(imm(iw)),
+        LUI => sext32(u32::from(imm(iw)) << 16),
+        SLL | SRL | SRA => u64::from(sa(iw)),
+        J | JAL => u64::from((iw & 0x03FF_FFFF) << 2),
+        _ => simm(iw),
+    };
+    Op {
+        handler,
+        instr,
+        iw,
+        s: rs(iw),
+        t: rt(iw),
+        d: rd(iw),
+        imm,
+    }
+}
+
+// Handlers for the most common instructions. Everything else goes through
+// the interpreter's `execute_decoded`.
+impl<MB, IC, DC, MM> InterpCPU32bit<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
+    MM::AddressSize: From<u32>,
+{
+    fn op_j(&mut self, op: &Op<Self>, pc: u32) -> Result<(), Exception> {
+        self.branch(true, (pc.wrapping_add(4) & 0xF000_0000) | op.imm as u32);
+        Ok(())
+    }
+
+    fn op_jal(&mut self, op: &Op<Self>, pc: u32) -> Result<(), Exception> {
+        self.gpr[31] = sext32(pc.wrapping_add(8));
+        self.op_j(op, pc)
+    }
+
+    fn op_jr(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.branch(true, self.gpr[op.s] as u32);
+        Ok(())
+    }
+
+    fn op_beq(&mut self, op: &Op<Self>, pc: u32) -> Result<(), Exception> {
+        let target = pc.wrapping_add(4).wrapping_add((op.imm << 2) as u32);
+        self.branch(self.gpr[op.s] == self.gpr[op.t], target);
+        Ok(())
+    }
+
+    fn op_bne(&mut self, op: &Op<Self>, pc: u32) -> Result<(), Exception> {
+        let target = pc.wrapping_add(4).wrapping_add((op.imm << 2) as u32);
+        self.branch(self.gpr[op.s] != self.gpr[op.t], target);
+        Ok(())
+    }
+
+    fn op_addiu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = sext32((self.gpr[op.s] as u32).wrapping_add(op.imm as u32));
+        Ok(())
+    }
+
+    fn op_daddiu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = self.gpr[op.s].wrapping_add(op.imm);
+        Ok(())
+    }
+
+    fn op_slti(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = ((self.gpr[op.s] as i64) < op.imm as i64) as u64;
+        Ok(())
+    }
+
+    fn op_sltiu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = (self.gpr[op.s] < op.imm) as u64;
+        Ok(())
+    }
+
+    fn op_andi(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = self.gpr[op.s] & op.imm;
+        Ok(())
+    }
+
+    fn op_ori(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = self.gpr[op.s] | op.imm;
+        Ok(())
+    }
+
+    fn op_xori(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = self.gpr[op.s] ^ op.imm;
+        Ok(())
+    }
+
+    fn op_lui(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.t] = op.imm;
+        Ok(())
+    }
+
+    fn op_addu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = sext32((self.gpr[op.s] as u32).wrapping_add(self.gpr[op.t] as u32));
+        Ok(())
+    }
+
+    fn op_subu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = sext32((self.gpr[op.s] as u32).wrapping_sub(self.gpr[op.t] as u32));
+        Ok(())
+    }
+
+    fn op_daddu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = self.gpr[op.s].wrapping_add(self.gpr[op.t]);
+        Ok(())
+    }
+
+    fn op_and(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = self.gpr[op.s] & self.gpr[op.t];
+        Ok(())
+    }
+
+    fn op_or(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = self.gpr[op.s] | self.gpr[op.t];
+        Ok(())
+    }
+
+    fn op_xor(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = self.gpr[op.s] ^ self.gpr[op.t];
+        Ok(())
+    }
+
+    fn op_slt(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = ((self.gpr[op.s] as i64) < self.gpr[op.t] as i64) as u64;
+        Ok(())
+    }
+
+    fn op_sltu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = (self.gpr[op.s] < self.gpr[op.t]) as u64;
+        Ok(())
+    }
+
+    fn op_sll(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = sext32((self.gpr[op.t] as u32) << op.imm);
+        Ok(())
+    }
+
+    fn op_srl(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = sext32((self.gpr[op.t] as u32) >> op.imm);
+        Ok(())
+    }
+
+    fn op_sra(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        self.gpr[op.d] = sext32(((self.gpr[op.t] as i64) >> op.imm) as u32);
+        Ok(())
+    }
+
+    fn op_lw(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        let addr = (self.gpr[op.s] as u32).wrapping_add(op.imm as u32);
+        Self::check_alignment(addr, 4, Access::Load)?;
+        self.gpr[op.t] = sext32(self.load_word(addr)?);
+        Ok(())
+    }
+
+    fn op_lbu(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        let addr = (self.gpr[op.s] as u32).wrapping_add(op.imm as u32);
+        let word = self.load_word(addr)?;
+        self.gpr[op.t] = u64::from((word >> ((3 - (addr & 3)) * 8)) as u8);
+        Ok(())
+    }
+
+    fn op_sw(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        let addr = (self.gpr[op.s] as u32).wrapping_add(op.imm as u32);
+        Self::check_alignment(addr, 4, Access::Store)?;
+        self.store_word(addr, self.gpr[op.t] as u32, 0xFFFF_FFFF)
+    }
+
+    fn op_sb(&mut self, op: &Op<Self>, _pc: u32) -> Result<(), Exception> {
+        let addr = (self.gpr[op.s] as u32).wrapping_add(op.imm as u32);
+        let shift = (3 - (addr & 3)) * 8;
+        self.store_word(addr, (self.gpr[op.t] as u32) << shift, 0xFF << shift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cop0::{COP0_CAUSE, COP0_EPC, COP0_STATUS};
+    use crate::{DCache, ICache, MMU32Bit, Memory};
+
+    fn i_type(op: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+        op << 26 | rs << 21 | rt << 16 | u32::from(imm)
+    }
+
+    fn r_type(rs: u32, rt: u32, rd: u32, sa: u32, funct: u32) -> u32 {
+        rs << 21 | rt << 16 | rd << 11 | sa << 6 | funct
+    }
+
+    type Interp = InterpCPU32bit<Memory, ICache, DCache, MMU32Bit>;
+
+    fn load(cpu: &mut Interp, program: &[u32]) {
+        for (i, &iw) in program.iter().enumerate() {
+            cpu.bus.write_word(0x1000 + i as u32 * 4, iw);
+        }
+        cpu.pc = 0x8000_1000;
+        cpu.next_pc = 0x8000_1004;
+    }
+
+    #[test]
+    fn matches_interpreter() {
+        let program = [
+            i_type(0x09, 0, 8, 10),       // addiu t0, zero, 10
+            i_type(0x0F, 0, 11, 0x8000),  // lui t3, 0x8000
+            i_type(0x0D, 11, 11, 0x2000), // ori t3, t3, 0x2000
+            i_type(0x09, 8, 8, 0xFFFF),   // addiu t0, t0, -1
+            r_type(9, 8, 9, 0, 0x21),     // addu t1, t1, t0
+            i_type(0x2B, 11, 9, 0),       // sw t1, 0(t3)
+            i_type(0x23, 11, 10, 0),      // lw t2, 0(t3)
+            i_type(0x05, 8, 0, 0xFFFB),   // bnez t0, -5
+            r_type(0, 10, 12, 3, 0x03),   // sra t4, t2, 3 (delay slot)
+            i_type(0x24, 11, 13, 3),      // lbu t5, 3(t3)
+            0x0000_000D,                  // break
+        ];
+        let mut interp = Interp::new(&mut [0u8; 2048].as_ref());
+        load(&mut interp, &program);
+        let mut cached = CachedInterpCPU::from(Interp::new(&mut [0u8; 2048].as_ref()));
+        load(&mut cached.cpu, &program);
+        // Both run up to and through the break; the cached interpreter
+        // only stops at block boundaries.
+        let in_program = |pc: u32| (0x8000_1000..=0x8000_1028).contains(&pc);
+        while in_program(interp.pc) {
+            interp.step();
+        }
+        while in_program(cached.cpu.pc) {
+            cached.step();
+        }
+        assert_eq!(cached.cpu.gpr, interp.gpr);
+        assert_eq!(cached.cpu.gpr[9], 45);
+        assert_eq!(cached.cpu.gpr[13], 45);
+        assert_eq!(cached.cpu.pc, interp.pc);
+        for reg in &[COP0_STATUS, COP0_EPC] {
+            assert_eq!(cached.cpu.cop0.regs[*reg], interp.cop0.regs[*reg]);
+        }
+        assert_eq!(
+            cached.cpu.cop0.regs[COP0_CAUSE] & 0x7C,
+            interp.cop0.regs[COP0_CAUSE] & 0x7C
+        );
+    }
+
+    #[test]
+    fn rdram_writes_invalidate_blocks() {
+        let mut cpu = CachedInterpCPU::from(Interp::new(&mut [0u8; 2048].as_ref()));
+        let program = [
+            i_type(0x09, 0, 8, 1),      // addiu t0, zero, 1
+            i_type(0x04, 0, 0, 0xFFFF), // b -1
+            0,                          // nop
+        ];
+        load(&mut cpu.cpu, &program);
+        cpu.step();
+        assert_eq!(cpu.cpu.gpr[8], 1);
+
+        cpu.cpu.bus.write_word(0x1000, i_type(0x09, 0, 8, 2)); // addiu t0, zero, 2
+        cpu.cpu.pc = 0x8000_1000;
+        cpu.cpu.next_pc = 0x8000_1004;
+        cpu.step();
+        assert_eq!(cpu.cpu.gpr[8], 2);
+    }
+
+    #[test]
+    fn blocks_end_at_pages_and_exception_returns() {
+        let mut cpu = Interp::new(&mut [0u8; 2048].as_ref());
+        let block = decode_block::<Memory, ICache, DCache, MMU32Bit>(&mut cpu.bus, 0x1FF8);
+        assert_eq!(block.len(), 2);
+
+        cpu.bus.write_word(0x1004, 0x4200_0018); // eret
+        let block = decode_block::<Memory, ICache, DCache, MMU32Bit>(&mut cpu.bus, 0x1000);
+        assert_eq!(block.len(), 3);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::save::{Save, SaveType};
+use std::path::Path;
+
+pub const CART_DOM2_ADDR2: u32 = 0x0800_0000;
+pub const CART_DOM2_ADDR2_END: u32 = 0x0FFF_FFFF;
+pub const CART_DOM1_ADDR2: u32 = 0x1000_0000;
+pub const CART_DOM1_ADDR2_END: u32 = 0x1FBF_FFFF;
+
+const GAME_CODE_OFFSET: usize = 0x3B;
+
+pub struct Cartridge {
+    rom: Vec<u8>,
+    pub save: Save,
+}
+
+impl Cartridge {
+    // Loads a ROM image in any of the common dump byte orders. Backup memory
+    // is attached separately with load_save.
+    pub fn new(rom_src: &mut impl std::io::Read) -> Self {
+        let mut rom = Vec::new();
+        if let Err(why) = rom_src.read_to_end(&mut rom) {
+            panic!("Couldn't read rom: {}", why);
+        }
+        normalize_byte_order(&mut rom);
+        Cartridge {
+            rom,
+            save: Save::None,
+        }
+    }
+
+    pub fn load_save(&mut self, path: Option<&Path>, save_type: SaveType) {
+        self.save.flush();
+        self.save = Save::new(path, save_type);
+    }
+
+    pub fn detected_save_type(&self) -> SaveType {
+        SaveType::detect(&self.game_code())
+    }
+
+    pub fn game_code(&self) -> [u8; 4] {
+        let mut code = [0; 4];
+        if self.rom.len() >= GAME_CODE_OFFSET + 4 {
+            code.copy_from_slice(&self.rom[GAME_CODE_OFFSET..GAME_CODE_OFFSET + 4]);
+        }
+        code
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn read_word(&self, addr: u32) -> u32 {
+        match addr {
+            CART_DOM2_ADDR2..=CART_DOM2_ADDR2_END => match &self.save {
+                Save::Sram(sram) => {
+                    let mut buf = [0; 4];
+                    sram.dma_read(addr - CART_DOM2_ADDR2, &mut buf);
+                    u32::from_be_bytes(buf)
+                }
+                Save::FlashRam(flash) => flash.read_word(addr - CART_DOM2_ADDR2),
+                _ => 0,
+            },
+            CART_DOM1_ADDR2..=CART_DOM1_ADDR2_END => {
+                let offset = (addr - CART_DOM1_ADDR2) as usize & !3;
+                if offset + 4 <= self.rom.len() {
+                    u32::from_be_bytes([
+                        self.rom[offset],
+                        self.rom[offset + 1],
+                        self.rom[offset + 2],
+                        self.rom[offset + 3],
+                    ])
+                } else {
+                    // Open bus returns the low half of the address twice.
+                    (addr & 0xFFFF) | (addr << 16)
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, addr: u32, value: u32) {
+        if let CART_DOM2_ADDR2..=CART_DOM2_ADDR2_END = addr {
+            match &mut self.save {
+                Save::Sram(sram) => sram.dma_write(addr - CART_DOM2_ADDR2, &value.to_be_bytes()),
+                Save::FlashRam(flash) => flash.write_word(addr - CART_DOM2_ADDR2, value),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn dma_read(&self, addr: u32, buf: &mut [u8]) {
+        match addr {
+            CART_DOM2_ADDR2..=CART_DOM2_ADDR2_END => match &self.save {
+                Save::Sram(sram) => sram.dma_read(addr - CART_DOM2_ADDR2, buf),
+                Save::FlashRam(flash) => flash.dma_read(addr - CART_DOM2_ADDR2, buf),
+                _ => {
+                    for byte in buf.iter_mut() {
+                        *byte = 0;
+                    }
+                }
+            },
+            CART_DOM1_ADDR2..=CART_DOM1_ADDR2_END => {
+                let offset = (addr - CART_DOM1_ADDR2) as usize;
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    *byte = self.rom.get(offset + i).copied().unwrap_or(0);
+                }
+            }
+            _ => {
+                for byte in buf.iter_mut() {
+                    *byte = 0;
+                }
+            }
+        }
+    }
+
+    pub fn dma_write(&mut self, addr: u32, buf: &[u8]) {
+        if let CART_DOM2_ADDR2..=CART_DOM2_ADDR2_END = addr {
+            match &mut self.save {
+                Save::Sram(sram) => sram.dma_write(addr - CART_DOM2_ADDR2, buf),
+                Save::FlashRam(flash) => flash.dma_write(addr - CART_DOM2_ADDR2, buf),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Converts .v64 (byte-swapped) and .n64 (little-endian) dumps to the native
+// big-endian .z64 layout, keyed on the PI domain 1 config word.
+fn normalize_byte_order(rom: &mut [u8]) {
+    if rom.len() < 4 {
+        return;
+    }
+    match rom[..4] {
+        [0x37, 0x80, 0x40, 0x12] => {
+            for chunk in rom.chunks_exact_mut(2) {
+                chunk.swap(0, 1);
+            }
+        }
+        [0x40, 0x12, 0x37, 0x80] => {
+            for chunk in rom.chunks_exact_mut(4) {
+                chunk.reverse();
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_rom(code: &[u8; 4]) -> Vec<u8> {
+        let mut rom = vec![0; 0x1000];
+        rom[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        rom[GAME_CODE_OFFSET..GAME_CODE_OFFSET + 4].copy_from_slice(code);
+        rom
+    }
+
+    #[test]
+    fn byte_orders() {
+        let z64 = test_rom(b"NSME");
+        let mut v64 = z64.clone();
+        for chunk in v64.chunks_exact_mut(2) {
+            chunk.swap(0, 1);
+        }
+        let mut n64 = z64.clone();
+        for chunk in n64.chunks_exact_mut(4) {
+            chunk.reverse();
+        }
+        for image in &[z64, v64, n64] {
+            let cart = Cartridge::new(&mut image.as_slice());
+            assert_eq!(cart.read_word(CART_DOM1_ADDR2), 0x8037_1240);
+            assert_eq!(&cart.game_code(), b"NSME");
+        }
+    }
+
+    #[test]
+    fn sram_on_domain_two() {
+        let rom = test_rom(b"NZLE");
+        let mut cart = Cartridge::new(&mut rom.as_slice());
+        assert_eq!(cart.detected_save_type(), SaveType::Sram);
+        cart.load_save(None, SaveType::Sram);
+        cart.write_word(CART_DOM2_ADDR2 + 0x10, 0xDEAD_BEEF);
+        assert_eq!(cart.read_word(CART_DOM2_ADDR2 + 0x10), 0xDEAD_BEEF);
+    }
+}
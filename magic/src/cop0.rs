@@ -0,0 +1,301 @@
+pub const COP0_INDEX: usize = 0;
+pub const COP0_RANDOM: usize = 1;
+pub const COP0_ENTRY_LO0: usize = 2;
+pub const COP0_ENTRY_LO1: usize = 3;
+pub const COP0_CONTEXT: usize = 4;
+pub const COP0_PAGE_MASK: usize = 5;
+pub const COP0_WIRED: usize = 6;
+pub const COP0_BAD_VADDR: usize = 8;
+pub const COP0_COUNT: usize = 9;
+pub const COP0_ENTRY_HI: usize = 10;
+pub const COP0_COMPARE: usize = 11;
+pub const COP0_STATUS: usize = 12;
+pub const COP0_CAUSE: usize = 13;
+pub const COP0_EPC: usize = 14;
+pub const COP0_PRID: usize = 15;
+pub const COP0_CONFIG: usize = 16;
+pub const COP0_LLADDR: usize = 17;
+pub const COP0_WATCH_LO: usize = 18;
+pub const COP0_WATCH_HI: usize = 19;
+pub const COP0_XCONTEXT: usize = 20;
+pub const COP0_PARITY_ERROR: usize = 26;
+pub const COP0_CACHE_ERROR: usize = 27;
+pub const COP0_TAG_LO: usize = 28;
+pub const COP0_TAG_HI: usize = 29;
+pub const COP0_ERROR_EPC: usize = 30;
+
+pub const STATUS_IE: u64 = 1 << 0;
+pub const STATUS_EXL: u64 = 1 << 1;
+pub const STATUS_ERL: u64 = 1 << 2;
+pub const STATUS_IM: u64 = 0xFF << 8;
+pub const STATUS_BEV: u64 = 1 << 22;
+pub const STATUS_FR: u64 = 1 << 26;
+pub const STATUS_CU1: u64 = 1 << 29;
+
+pub const CAUSE_EXC_CODE: u64 = 0x1F << 2;
+pub const CAUSE_IP: u64 = 0xFF << 8;
+pub const CAUSE_IP_SOFTWARE: u64 = 0x03 << 8;
+pub const CAUSE_IP2: u64 = 1 << 10;
+pub const CAUSE_IP7: u64 = 1 << 15;
+pub const CAUSE_CE: u64 = 0x3 << 28;
+pub const CAUSE_BD: u64 = 1 << 31;
+
+const RESET_STATUS: u64 = STATUS_BEV | STATUS_ERL;
+const RESET_CONFIG: u64 = 0x7006_E463;
+const VR4300_PRID: u64 = 0x0000_0B22;
+const TLB_ENTRIES: u64 = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExceptionCode {
+    Interrupt = 0,
+    TlbModification = 1,
+    TlbLoad = 2,
+    TlbStore = 3,
+    AddressErrorLoad = 4,
+    AddressErrorStore = 5,
+    InstructionBusError = 6,
+    DataBusError = 7,
+    Syscall = 8,
+    Breakpoint = 9,
+    ReservedInstruction = 10,
+    CoprocessorUnusable = 11,
+    Overflow = 12,
+    Trap = 13,
+    FloatingPoint = 15,
+    Watch = 23,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Exception {
+    pub code: ExceptionCode,
+    pub bad_vaddr: Option<u32>,
+    pub coprocessor: u8,
+}
+
+impl Exception {
+    pub fn new(code: ExceptionCode) -> Self {
+        Exception {
+            code,
+            bad_vaddr: None,
+            coprocessor: 0,
+        }
+    }
+
+    pub fn address(code: ExceptionCode, vaddr: u32) -> Self {
+        Exception {
+            code,
+            bad_vaddr: Some(vaddr),
+            coprocessor: 0,
+        }
+    }
+
+    pub fn coprocessor_unusable(coprocessor: u8) -> Self {
+        Exception {
+            code: ExceptionCode::CoprocessorUnusable,
+            bad_vaddr: None,
+            coprocessor,
+        }
+    }
+}
+
+pub struct Cop0 {
+    pub regs: [u64; 32],
+    // Count ticks at half the pipeline clock. Rather than being stepped, it
+    // is worked out from the cycle at which it was last zero.
+    count_base: u64,
+}
+
+impl Default for Cop0 {
+    fn default() -> Self {
+        let mut regs = [0; 32];
+        regs[COP0_RANDOM] = TLB_ENTRIES - 1;
+        regs[COP0_STATUS] = RESET_STATUS;
+        regs[COP0_CONFIG] = RESET_CONFIG;
+        regs[COP0_PRID] = VR4300_PRID;
+        Cop0 {
+            regs,
+            count_base: 0,
+        }
+    }
+}
+
+impl Cop0 {
+    pub fn read(&self, reg: usize) -> u64 {
+        self.regs[reg & 0x1F]
+    }
+
+    pub fn write(&mut self, reg: usize, value: u64) {
+        match reg & 0x1F {
+            // Count is set through set_count, which needs the time.
+            COP0_RANDOM | COP0_PRID | COP0_BAD_VADDR | COP0_COUNT => {}
+            COP0_CAUSE => {
+                let cause = &mut self.regs[COP0_CAUSE];
+                *cause = (*cause & !CAUSE_IP_SOFTWARE) | (value & CAUSE_IP_SOFTWARE);
+            }
+            COP0_COMPARE => {
+                self.regs[COP0_COMPARE] = value & 0xFFFF_FFFF;
+                self.set_interrupt(CAUSE_IP7, false);
+            }
+            COP0_WIRED => {
+                self.regs[COP0_WIRED] = value & 0x3F;
+                self.regs[COP0_RANDOM] = TLB_ENTRIES - 1;
+            }
+            reg => self.regs[reg] = value,
+        }
+    }
+
+    // Count as of cycle `now` of the pipeline clock.
+    pub fn count(&self, now: u64) -> u32 {
+        (now.wrapping_sub(self.count_base) / 2) as u32
+    }
+
+    pub fn set_count(&mut self, now: u64, value: u32) {
+        self.count_base = now.wrapping_sub(u64::from(value) * 2);
+    }
+
+    // The cycle after `now` at which Count next reaches Compare, raising the
+    // timer interrupt. The scheduler fires it then, and it has to be asked
+    // again whenever either register is written.
+    pub fn compare_time(&self, now: u64) -> u64 {
+        let elapsed = now.wrapping_sub(self.count_base);
+        let ticks = elapsed / 2;
+        let compare = self.regs[COP0_COMPARE] as u32;
+        let delta = match compare.wrapping_sub(ticks as u32) {
+            0 => 1 << 32,
+            delta => u64::from(delta),
+        };
+        now + (ticks + delta) * 2 - elapsed
+    }
+
+    pub fn status(&self) -> u64 {
+        self.regs[COP0_STATUS]
+    }
+
+    pub fn set_interrupt(&mut self, ip: u64, level: bool) {
+        if level {
+            self.regs[COP0_CAUSE] |= ip;
+        } else {
+            self.regs[COP0_CAUSE] &= !ip;
+        }
+    }
+
+    // An interrupt is taken when any pending, unmasked IP bit is set while
+    // interrupts are enabled and no exception or error is being handled.
+    pub fn interrupt_pending(&self) -> bool {
+        self.interrupt_pending_with(self.regs[COP0_STATUS])
+    }
+
+    // As above, but with `status` standing in for Status.
+    pub fn interrupt_pending_with(&self, status: u64) -> bool {
+        let cause = self.regs[COP0_CAUSE];
+        status & (STATUS_IE | STATUS_EXL | STATUS_ERL) == STATUS_IE
+            && cause & status & CAUSE_IP & STATUS_IM != 0
+    }
+
+    // Updates the exception registers for a new exception and returns the
+    // vector to continue execution at.
+    pub fn enter_exception(&mut self, exception: Exception, pc: u32, delay_slot: bool) -> u32 {
+        let status = self.regs[COP0_STATUS];
+        let mut cause = self.regs[COP0_CAUSE] & !(CAUSE_EXC_CODE | CAUSE_CE);
+        cause |= (exception.code as u64) << 2;
+        cause |= u64::from(exception.coprocessor & 0x3) << 28;
+        if let Some(vaddr) = exception.bad_vaddr {
+            self.regs[COP0_BAD_VADDR] = vaddr as i32 as u64;
+            let context = self.regs[COP0_CONTEXT] & !0x007F_FFF0;
+            self.regs[COP0_CONTEXT] = context | u64::from((vaddr >> 9) & 0x007F_FFF0);
+            let entry_hi = self.regs[COP0_ENTRY_HI] & 0xFF;
+            self.regs[COP0_ENTRY_HI] = entry_hi | (vaddr & 0xFFFF_E000) as i32 as u64;
+        }
+
+        let refill = match exception.code {
+            ExceptionCode::TlbLoad | ExceptionCode::TlbStore => status & STATUS_EXL == 0,
+            _ => false,
+        };
+        if status & STATUS_EXL == 0 {
+            let epc = if delay_slot {
+                cause |= CAUSE_BD;
+                pc.wrapping_sub(4)
+            } else {
+                cause &= !CAUSE_BD;
+                pc
+            };
+            self.regs[COP0_EPC] = epc as i32 as u64;
+        }
+        self.regs[COP0_CAUSE] = cause;
+        self.regs[COP0_STATUS] = status | STATUS_EXL;
+
+        let base: u32 = if status & STATUS_BEV != 0 {
+            0xBFC0_0200
+        } else {
+            0x8000_0000
+        };
+        if refill {
+            base
+        } else {
+            base + 0x180
+        }
+    }
+
+    // ERET returns from an error first, then from an exception.
+    pub fn exception_return(&mut self) -> u32 {
+        let status = self.regs[COP0_STATUS];
+        if status & STATUS_ERL != 0 {
+            self.regs[COP0_STATUS] = status & !STATUS_ERL;
+            self.regs[COP0_ERROR_EPC] as u32
+        } else {
+            self.regs[COP0_STATUS] = status & !STATUS_EXL;
+            self.regs[COP0_EPC] as u32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_masking() {
+        let mut cop0 = Cop0::default();
+        cop0.set_interrupt(CAUSE_IP2, true);
+        assert!(!cop0.interrupt_pending());
+        cop0.write(COP0_STATUS, STATUS_IE | CAUSE_IP2);
+        assert!(cop0.interrupt_pending());
+        cop0.write(COP0_STATUS, STATUS_IE | STATUS_EXL | CAUSE_IP2);
+        assert!(!cop0.interrupt_pending());
+    }
+
+    #[test]
+    fn count_reaches_compare() {
+        let mut cop0 = Cop0::default();
+        cop0.set_count(1001, 0xFFFF_FFF0);
+        cop0.write(COP0_COMPARE, 0x10);
+        assert_eq!(cop0.count(1004), 0xFFFF_FFF1);
+        assert_eq!(cop0.count(1001 + 0x3E), 0x0F);
+        // Count wraps and reaches 0x10 after 0x20 ticks.
+        assert_eq!(cop0.compare_time(1001), 1001 + 0x40);
+        assert_eq!(cop0.count(1001 + 0x40), 0x10);
+        assert_eq!(cop0.compare_time(1002), 1001 + 0x40);
+        // Once there, the next match is a full wrap away.
+        assert_eq!(cop0.compare_time(1001 + 0x40), 1001 + 0x40 + (1 << 33));
+        cop0.set_count(5000, 0x10);
+        assert_eq!(cop0.compare_time(5000), 5000 + (1 << 33));
+        cop0.set_interrupt(CAUSE_IP7, true);
+        cop0.write(COP0_COMPARE, 0x11);
+        assert_eq!(cop0.read(COP0_CAUSE) & CAUSE_IP7, 0);
+        assert_eq!(cop0.compare_time(5001), 5002);
+    }
+
+    #[test]
+    fn exception_in_delay_slot() {
+        let mut cop0 = Cop0::default();
+        cop0.write(COP0_STATUS, 0);
+        let vector =
+            cop0.enter_exception(Exception::new(ExceptionCode::Interrupt), 0x8000_1004, true);
+        assert_eq!(vector, 0x8000_0180);
+        assert_eq!(cop0.read(COP0_EPC) as u32, 0x8000_1000);
+        assert_ne!(cop0.read(COP0_CAUSE) & CAUSE_BD, 0);
+        assert_eq!(cop0.read(COP0_CAUSE) & CAUSE_EXC_CODE, 0);
+        assert_eq!(cop0.exception_return(), 0x8000_1000);
+        assert_eq!(cop0.status() & STATUS_EXL, 0);
+    }
+}
@@ -3,7 +3,7 @@ use magic_macros::vr4300_instr_enum;
 vr4300_instr_enum!(CpuInstrVR4300);
 use CpuInstrVR4300::*;
 
-const DECODER_LOOKUP_TABLE :[CpuInstrVR4300; 312] = [
+const DECODER_LOOKUP_TABLE :[CpuInstrVR4300; DECODER_TABLE_SIZE] = [
 // ============================================================================
 //  Escaped opcode table: Special.
 //
@@ -159,23 +159,81 @@ const DECODER_LOOKUP_TABLE :[CpuInstrVR4300; 312] = [
 //  110 |  C.F  | C.UN  | C.EQ  | C.UEQ | C.OLT | C.ULT | C.OLE | C.ULE |
 //  111 | C.SF  |C.NGLE | C.SEQ | C.NGL | C.LT  | C.NGE | C.LE  | C.NGT |
 //      |-------|-------|-------|-------|-------|-------|-------|-------|
+//
+//  One copy per format, picked by a second escape on the low bits of fmt
+//  (see FMT_ESCAPE_TABLE). Formats other than S, D, W and L are invalid.
 // ========================================================================= */
-    {ADD_S},     {SUB_S},     {MUL_S},     {DIV_S}, // All of these are set to _S to get it to build, fixed later(?)
+    // S (fmt = 16)
+    {ADD_S},     {SUB_S},     {MUL_S},     {DIV_S},
     {SQRT_S},    {ABS_S},     {MOV_S},     {NEG_S},
     {ROUND_L_S}, {TRUNC_L_S}, {CEIL_L_S},  {FLOOR_L_S},
     {ROUND_W_S}, {TRUNC_W_S}, {CEIL_W_S},  {FLOOR_W_S},
-    {Invalid},     {Invalid},     {Invalid},     {Invalid},
-    {Invalid},     {Invalid},     {Invalid},     {Invalid},
-    {Invalid},     {Invalid},     {Invalid},     {Invalid},
-    {Invalid},     {Invalid},     {Invalid},     {Invalid},
-    {CVT_S_D},   {CVT_D_S},   {Invalid},     {Invalid},
-    {CVT_W_S},   {CVT_L_S},   {Invalid},     {Invalid},
-    {Invalid},     {Invalid},     {Invalid},     {Invalid},
-    {Invalid},     {Invalid},     {Invalid},     {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {CVT_D_S},   {Invalid},   {Invalid},
+    {CVT_W_S},   {CVT_L_S},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
     {C_F_S},     {C_UN_S},    {C_EQ_S},    {C_UEQ_S},
     {C_OLT_S},   {C_ULT_S},   {C_OLE_S},   {C_ULE_S},
     {C_SF_S},    {C_NGLE_S},  {C_SEQ_S},   {C_NGL_S},
     {C_LT_S},    {C_NGE_S},   {C_LE_S},    {C_NGT_S},
+
+    // D (fmt = 17)
+    {ADD_D},     {SUB_D},     {MUL_D},     {DIV_D},
+    {SQRT_D},    {ABS_D},     {MOV_D},     {NEG_D},
+    {ROUND_L_D}, {TRUNC_L_D}, {CEIL_L_D},  {FLOOR_L_D},
+    {ROUND_W_D}, {TRUNC_W_D}, {CEIL_W_D},  {FLOOR_W_D},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {CVT_S_D},   {Invalid},   {Invalid},   {Invalid},
+    {CVT_W_D},   {CVT_L_D},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {C_F_D},     {C_UN_D},    {C_EQ_D},    {C_UEQ_D},
+    {C_OLT_D},   {C_ULT_D},   {C_OLE_D},   {C_ULE_D},
+    {C_SF_D},    {C_NGLE_D},  {C_SEQ_D},   {C_NGL_D},
+    {C_LT_D},    {C_NGE_D},   {C_LE_D},    {C_NGT_D},
+
+    // W (fmt = 20)
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {CVT_S_W},   {CVT_D_W},   {Invalid},   {Invalid},
+    {Invalid},   {CVT_L_W},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+
+    // L (fmt = 21)
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {CVT_S_L},   {CVT_D_L},   {Invalid},   {Invalid},
+    {CVT_W_L},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
+    {Invalid},   {Invalid},   {Invalid},   {Invalid},
     
 // ============================================================================
 //  Escaped opcode table: COP1/3.
@@ -225,23 +283,40 @@ const DECODER_LOOKUP_TABLE :[CpuInstrVR4300; 312] = [
   {LL},      {LWC1},    {Invalid},    {Invalid},
   {LLD},     {LDC1},    {Invalid},    {LD},
   {SC},      {SWC1},    {Invalid},    {Invalid},
-  {SCD},     {SDC1},    {Invalid},    {SD}
+  {SCD},     {SDC1},    {Invalid},    {SD},
+
+// ============================================================================
+//  Invalid opcodes, padding the table out to a power of two.
+// ============================================================================
+  {Invalid}, {Invalid}, {Invalid}, {Invalid},
+  {Invalid}, {Invalid}, {Invalid}, {Invalid}
 ];
 
 #[derive(Debug, Copy, Clone)]
 struct OpcodeEscape {
     shift: u8,
+    mask: u8,
+    offset: u16,
+    // Selects an entry in FMT_ESCAPE_TABLE from the low bits of fmt. Only
+    // the FPU operations have one; it's zero everywhere else, which picks
+    // an entry that leaves the lookup unchanged.
+    fmt_mask: u8
+}
+
+#[derive(Debug, Copy, Clone)]
+struct FmtEscape {
     mask: u8,
     offset: u16
 }
 
+const DECODER_TABLE_SIZE: usize = 512;
 const SPEC_OFFSET: u16 = 0;
 const FUNC_MASK: u8 = 0x3F;
 const FUNC_SHIFT: u8 = 0;
 const REGIMM_OFFSET: u16 = 64;
 const RT_MASK: u8 = 0x1F;
 const RT_SHIFT: u8 = 16;
-const DEFAULT_OFFSET: u16 = 248;
+const DEFAULT_OFFSET: u16 = 440;
 const OPCODE_MASK: u8 = 0x3F;
 const OPCODE_SHIFT: u8 = 26;
 const COP0_0_OFFSET: u16 = 96;
@@ -252,329 +327,346 @@ const COP0_3_OFFSET: u16 = 168;
 const RT_MASK_SHORT: u8 = 0x3;
 const COP1_0_OFFSET: u16 = 172;
 const COP1_2_OFFSET: u16 = 180;
-const COP1_3_OFFSET: u16 = 244;
-const INVALID_OFFSET: u16 = 65535;
+const COP1_3_OFFSET: u16 = 436;
+const INVALID_OFFSET: u16 = 504;
+const INVALID_MASK: u8 = 0;
+const NO_FMT: u8 = 0;
+const FMT_MASK: u8 = 0x7;
+
+// Offsets of each format's FPU table from COP1_2_OFFSET, indexed by the low
+// three bits of fmt. S is first so that operations without a format, which
+// always index entry 0, are unaffected.
+const FMT_ESCAPE_TABLE: [FmtEscape; 8] = [
+    FmtEscape {mask: FUNC_MASK, offset: 0},                                // S
+    FmtEscape {mask: FUNC_MASK, offset: 64},                               // D
+    FmtEscape {mask: INVALID_MASK, offset: INVALID_OFFSET - COP1_2_OFFSET},
+    FmtEscape {mask: INVALID_MASK, offset: INVALID_OFFSET - COP1_2_OFFSET},
+    FmtEscape {mask: FUNC_MASK, offset: 128},                              // W
+    FmtEscape {mask: FUNC_MASK, offset: 192},                              // L
+    FmtEscape {mask: INVALID_MASK, offset: INVALID_OFFSET - COP1_2_OFFSET},
+    FmtEscape {mask: INVALID_MASK, offset: INVALID_OFFSET - COP1_2_OFFSET},
+];
 
 const OPCODE_ESCAPE_TABLE: [OpcodeEscape; 256] = [
-    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET}, // Special
-    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET},
-    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET},
-    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET},
-    
-    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET}, // RegImm
-    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET},
-    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET},
-    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // J
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // JAL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BEQ
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BNE
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BLEZ
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BGTZ
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // ADDI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // ADDIU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SLTI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SLTIU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // ANDI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // ORI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // XORI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LUI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: RS_MASK_SHORT, shift: RS_SHIFT, offset: COP0_0_OFFSET}, // COP0_0
-    OpcodeEscape {mask: RT_MASK_SHORT, shift: RT_SHIFT, offset: COP0_3_OFFSET}, // COP0_3
-    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: COP0_2_OFFSET}, // COP0_2
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    
-    OpcodeEscape {mask: RS_MASK_SHORT, shift: RS_SHIFT, offset: COP1_0_OFFSET}, // COP1_0
-    OpcodeEscape {mask: RT_MASK_SHORT, shift: RT_SHIFT, offset: COP1_3_OFFSET}, // COP1_3
-    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: COP1_2_OFFSET}, // COP1_2
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BEQL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BNEL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BLEZL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // BGTZL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // DADDI
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // DADDIU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LDL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LDR
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LB
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LH
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LWL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LW
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LBU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LHU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LWR
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LWU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SB
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SH
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SWL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SW
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SBU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SHU
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SWR
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // CACHE
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LL
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LWC1
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LLD
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LDC1
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // LD
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SC
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SWC1
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SCD
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SDC1
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET}, // INVALID
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET},
-    
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET}, // SD
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
-    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET},
+    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET, fmt_mask: NO_FMT}, // Special
+    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: SPEC_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET, fmt_mask: NO_FMT}, // RegImm
+    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: RT_MASK, shift: RT_SHIFT, offset: REGIMM_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // J
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // JAL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BEQ
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BNE
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BLEZ
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BGTZ
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // ADDI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // ADDIU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SLTI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SLTIU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // ANDI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // ORI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // XORI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LUI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: RS_MASK_SHORT, shift: RS_SHIFT, offset: COP0_0_OFFSET, fmt_mask: NO_FMT}, // COP0_0
+    OpcodeEscape {mask: RT_MASK_SHORT, shift: RT_SHIFT, offset: COP0_3_OFFSET, fmt_mask: NO_FMT}, // COP0_3
+    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: COP0_2_OFFSET, fmt_mask: NO_FMT}, // COP0_2
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    
+    OpcodeEscape {mask: RS_MASK_SHORT, shift: RS_SHIFT, offset: COP1_0_OFFSET, fmt_mask: NO_FMT}, // COP1_0
+    OpcodeEscape {mask: RT_MASK_SHORT, shift: RT_SHIFT, offset: COP1_3_OFFSET, fmt_mask: NO_FMT}, // COP1_3
+    OpcodeEscape {mask: FUNC_MASK, shift: FUNC_SHIFT, offset: COP1_2_OFFSET, fmt_mask: FMT_MASK}, // COP1_2
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BEQL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BNEL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BLEZL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // BGTZL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // DADDI
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // DADDIU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LDL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LDR
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LB
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LH
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LWL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LW
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LBU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LHU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LWR
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LWU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SB
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SH
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SWL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SW
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SBU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SHU
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SWR
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // CACHE
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LL
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LWC1
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LLD
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LDC1
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // LD
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SC
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SWC1
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SCD
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SDC1
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT}, // INVALID
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: INVALID_MASK, shift: OPCODE_SHIFT, offset: INVALID_OFFSET, fmt_mask: NO_FMT},
+    
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT}, // SD
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
+    OpcodeEscape {mask: OPCODE_MASK, shift: OPCODE_SHIFT, offset: DEFAULT_OFFSET, fmt_mask: NO_FMT},
     
 ];
 
@@ -586,457 +678,15 @@ const OPCODE_ESCAPE_TABLE: [OpcodeEscape; 256] = [
 //  return group + index;
 //}
 
-// Scalar decoder
+// Scalar decoder. Always two escape loads and one table load, with no
+// branches: the indices are masked into range, so there are no bounds checks
+// either, and invalid encodings land on an Invalid entry rather than
+// panicking.
 pub fn decode_vr4300(iw: u32) -> CpuInstrVR4300 {
     let escape = OPCODE_ESCAPE_TABLE[(iw as usize) >> 24];
-    let index = (iw >> escape.shift) & (escape.mask as u32);
-    
-    let result = DECODER_LOOKUP_TABLE[(escape.offset as usize) + (index as usize)];
-    match result {
-        ADD_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    ADD_S
-                },
-                17 => {
-                    ADD_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        SUB_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    SUB_S
-                },
-                17 => {
-                    SUB_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        MUL_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    MUL_S
-                },
-                17 => {
-                    MUL_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        DIV_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    DIV_S
-                },
-                17 => {
-                    DIV_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        SQRT_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    SQRT_S
-                },
-                17 => {
-                    SQRT_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        ABS_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    ABS_S
-                },
-                17 => {
-                    ABS_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        MOV_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    MOV_S
-                },
-                17 => {
-                    MOV_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        NEG_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    NEG_S
-                },
-                17 => {
-                    NEG_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        ROUND_L_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    ROUND_L_S
-                },
-                17 => {
-                    ROUND_L_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        TRUNC_L_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    TRUNC_L_S
-                },
-                17 => {
-                    TRUNC_L_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        CEIL_L_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    CEIL_L_S
-                },
-                17 => {
-                    CEIL_L_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        FLOOR_L_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    FLOOR_L_S
-                },
-                17 => {
-                    FLOOR_L_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        ROUND_W_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    ROUND_W_S
-                },
-                17 => {
-                    ROUND_W_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        TRUNC_W_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    TRUNC_W_S
-                },
-                17 => {
-                    TRUNC_W_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        CEIL_W_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    CEIL_W_S
-                },
-                17 => {
-                    CEIL_W_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        FLOOR_W_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    FLOOR_W_S
-                },
-                17 => {
-                    FLOOR_W_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        CVT_S_D => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                17 => {
-                    CVT_S_D
-                },
-                20 => {
-                    CVT_S_W
-                },
-                21 => {
-                    CVT_S_L
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        CVT_D_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    CVT_D_S
-                },
-                20 => {
-                    CVT_D_W
-                },
-                21 => {
-                    CVT_D_L
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        CVT_W_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    CVT_W_S
-                },
-                17 => {
-                    CVT_W_D
-                },
-                21 => {
-                    CVT_W_L
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        CVT_L_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    CVT_L_S
-                },
-                17 => {
-                    CVT_L_D
-                },
-                20 => {
-                    CVT_L_W
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_F_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_F_S
-                },
-                17 => {
-                    C_F_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_UN_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_UN_S
-                },
-                17 => {
-                    C_UN_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_EQ_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_EQ_S
-                },
-                17 => {
-                    C_EQ_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_UEQ_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_UEQ_S
-                },
-                17 => {
-                    C_UEQ_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_OLT_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_OLT_S
-                },
-                17 => {
-                    C_OLT_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_ULT_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_ULT_S
-                },
-                17 => {
-                    C_ULT_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_OLE_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_OLE_S
-                },
-                17 => {
-                    C_OLE_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_ULE_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_ULE_S
-                },
-                17 => {
-                    C_ULE_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_SF_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_SF_S
-                },
-                17 => {
-                    C_SF_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_NGLE_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_NGLE_S
-                },
-                17 => {
-                    C_NGLE_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_SEQ_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_SEQ_S
-                },
-                17 => {
-                    C_SEQ_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_NGL_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_NGL_S
-                },
-                17 => {
-                    C_NGL_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_LT_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_LT_S
-                },
-                17 => {
-                    C_LT_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_NGE_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_NGE_S
-                },
-                17 => {
-                    C_NGE_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_LE_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_LE_S
-                },
-                17 => {
-                    C_LE_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        C_NGT_S => {
-            let rs = (iw >> 21) & 0x1F;
-            match rs {
-                16 => {
-                    C_NGT_S
-                },
-                17 => {
-                    C_NGT_D
-                },
-                _ => panic!("invalid floating point format")
-            }
-        },
-        _ => result
-    }
+    let fmt = FMT_ESCAPE_TABLE[((iw >> RS_SHIFT) & u32::from(escape.fmt_mask)) as usize];
+    let index = (iw >> escape.shift) & u32::from(escape.mask & fmt.mask);
+
+    let offset = escape.offset as usize + fmt.offset as usize + index as usize;
+    DECODER_LOOKUP_TABLE[offset & (DECODER_TABLE_SIZE - 1)]
 }
@@ -0,0 +1,231 @@
+use crate::rsp::SP_MEM_SIZE;
+
+pub const DP_REGS_START: u32 = 0x0410_0000;
+pub const DP_REGS_END: u32 = 0x041F_FFFF;
+
+pub const DPC_START: u32 = 0x00;
+pub const DPC_END: u32 = 0x04;
+pub const DPC_CURRENT: u32 = 0x08;
+pub const DPC_STATUS: u32 = 0x0C;
+pub const DPC_CLOCK: u32 = 0x10;
+pub const DPC_BUFBUSY: u32 = 0x14;
+pub const DPC_PIPEBUSY: u32 = 0x18;
+pub const DPC_TMEM: u32 = 0x1C;
+
+pub const DPC_STATUS_XBUS: u32 = 1 << 0;
+pub const DPC_STATUS_FREEZE: u32 = 1 << 1;
+pub const DPC_STATUS_FLUSH: u32 = 1 << 2;
+pub const DPC_STATUS_PIPE_BUSY: u32 = 1 << 5;
+pub const DPC_STATUS_CMD_BUSY: u32 = 1 << 6;
+pub const DPC_STATUS_CBUF_READY: u32 = 1 << 7;
+pub const DPC_STATUS_START_VALID: u32 = 1 << 10;
+
+const DPC_CLEAR_XBUS: u32 = 1 << 0;
+const DPC_SET_XBUS: u32 = 1 << 1;
+const DPC_CLEAR_FREEZE: u32 = 1 << 2;
+const DPC_SET_FREEZE: u32 = 1 << 3;
+const DPC_CLEAR_FLUSH: u32 = 1 << 4;
+const DPC_SET_FLUSH: u32 = 1 << 5;
+const DPC_CLEAR_CLOCK: u32 = 1 << 9;
+
+const DPC_ADDR_MASK: u32 = 0x00FF_FFF8;
+
+fn be_word(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b))
+}
+
+// Anything that can consume the RDP command stream. Words are offered as
+// they are fetched; the backend returns how many it used, and a trailing
+// partial command is offered again along with the words that follow it.
+pub trait RdpBackend {
+    fn process(&mut self, commands: &[u64], rdram: &mut [u8]) -> usize;
+    // Whether a SYNC_FULL has completed since the last call.
+    fn take_full_sync(&mut self) -> bool;
+}
+
+// The DP command registers. Commands are fetched from RDRAM, or from DMEM
+// in XBUS mode, between CURRENT and END. A START written while a buffer is
+// being fetched is held until the next END write, which switches to it.
+//
+// The backend runs commands as soon as they are fetched, so the RDP never
+// looks busy for long and the busy counters never count.
+#[derive(Default)]
+pub struct Dp {
+    start: u32,
+    end: u32,
+    current: u32,
+    start_valid: bool,
+    status: u32,
+    clock: u32,
+    pending: Vec<u64>,
+}
+
+impl Dp {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            DPC_START => self.start,
+            DPC_END => self.end,
+            DPC_CURRENT => self.current,
+            DPC_STATUS => self.status(),
+            DPC_CLOCK => self.clock,
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        match offset {
+            DPC_START if !self.start_valid => {
+                self.start = value & DPC_ADDR_MASK;
+                self.start_valid = true;
+            }
+            DPC_END => {
+                self.end = value & DPC_ADDR_MASK;
+                if self.start_valid {
+                    self.current = self.start;
+                    self.start_valid = false;
+                }
+            }
+            DPC_STATUS => self.write_status(value),
+            _ => {}
+        }
+    }
+
+    fn write_status(&mut self, value: u32) {
+        let pairs = [
+            (DPC_CLEAR_XBUS, DPC_SET_XBUS, DPC_STATUS_XBUS),
+            (DPC_CLEAR_FREEZE, DPC_SET_FREEZE, DPC_STATUS_FREEZE),
+            (DPC_CLEAR_FLUSH, DPC_SET_FLUSH, DPC_STATUS_FLUSH),
+        ];
+        for &(clear, set, bit) in pairs.iter() {
+            if value & clear != 0 {
+                self.status &= !bit;
+            }
+            if value & set != 0 {
+                self.status |= bit;
+            }
+        }
+        if value & DPC_CLEAR_CLOCK != 0 {
+            self.clock = 0;
+        }
+    }
+
+    pub fn status(&self) -> u32 {
+        let mut status = self.status;
+        if self.busy() {
+            status |= DPC_STATUS_PIPE_BUSY | DPC_STATUS_CMD_BUSY;
+        }
+        if self.start_valid {
+            status |= DPC_STATUS_START_VALID;
+        } else {
+            status |= DPC_STATUS_CBUF_READY;
+        }
+        status
+    }
+
+    fn busy(&self) -> bool {
+        self.current < self.end || !self.pending.is_empty()
+    }
+
+    // Fetches everything up to END and hands it to the backend. Returns
+    // whether a SYNC_FULL was run, which raises the DP interrupt.
+    pub fn run(&mut self, backend: &mut dyn RdpBackend, rdram: &mut [u8], dmem: &[u8]) -> bool {
+        if self.status & DPC_STATUS_FREEZE != 0 || self.current >= self.end {
+            return false;
+        }
+        while self.current < self.end {
+            let word = if self.status & DPC_STATUS_XBUS != 0 {
+                let addr = self.current as usize & (SP_MEM_SIZE - 1);
+                be_word(&dmem[addr..addr + 8])
+            } else {
+                let addr = self.current as usize;
+                match rdram.get(addr..addr + 8) {
+                    Some(bytes) => be_word(bytes),
+                    None => 0,
+                }
+            };
+            self.pending.push(word);
+            self.current += 8;
+        }
+        let used = backend.process(&self.pending, rdram);
+        self.pending.drain(..used);
+        self.clock = (self.clock + used as u32) & 0x00FF_FFFF;
+        backend.take_full_sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Takes whole pairs of words; a word of all ones is a full sync.
+    #[derive(Default)]
+    struct Recorder {
+        words: Vec<u64>,
+        full_sync: bool,
+    }
+
+    impl RdpBackend for Recorder {
+        fn process(&mut self, commands: &[u64], _rdram: &mut [u8]) -> usize {
+            let used = commands.len() & !1;
+            self.words.extend_from_slice(&commands[..used]);
+            self.full_sync |= commands[..used].contains(&!0);
+            used
+        }
+
+        fn take_full_sync(&mut self) -> bool {
+            std::mem::take(&mut self.full_sync)
+        }
+    }
+
+    #[test]
+    fn start_is_double_buffered() {
+        let mut dp = Dp::default();
+        let mut backend = Recorder::default();
+        let mut rdram: Vec<u8> = (0..=0xFF).collect();
+        let dmem = [0; SP_MEM_SIZE];
+        dp.write_word(DPC_START, 0x10);
+        assert_eq!(
+            dp.read_word(DPC_STATUS) & DPC_STATUS_START_VALID,
+            DPC_STATUS_START_VALID
+        );
+        dp.write_word(DPC_END, 0x28);
+        assert_eq!(dp.read_word(DPC_CURRENT), 0x10);
+        // A second START waits for the next END.
+        dp.write_word(DPC_START, 0x80);
+        assert!(!dp.run(&mut backend, &mut rdram, &dmem));
+        assert_eq!(
+            backend.words,
+            vec![0x1011_1213_1415_1617, 0x1819_1A1B_1C1D_1E1F]
+        );
+        assert_eq!(dp.read_word(DPC_CURRENT), 0x28);
+        // The odd word out is still pending.
+        assert_ne!(dp.read_word(DPC_STATUS) & DPC_STATUS_CMD_BUSY, 0);
+
+        dp.write_word(DPC_END, 0x88);
+        assert_eq!(dp.read_word(DPC_START), 0x80);
+        dp.run(&mut backend, &mut rdram, &dmem);
+        assert_eq!(backend.words[2], 0x2021_2223_2425_2627);
+        assert_eq!(backend.words[3], 0x8081_8283_8485_8687);
+        assert_eq!(dp.read_word(DPC_STATUS) & DPC_STATUS_CMD_BUSY, 0);
+        assert_eq!(dp.read_word(DPC_CLOCK), 4);
+    }
+
+    #[test]
+    fn xbus_reads_dmem_and_freeze_holds_commands() {
+        let mut dp = Dp::default();
+        let mut backend = Recorder::default();
+        let mut rdram = vec![0; 0x100];
+        let mut dmem = [0; SP_MEM_SIZE];
+        dmem[0x400..0x410].copy_from_slice(&[0xFF; 16]);
+        dp.write_word(DPC_STATUS, DPC_SET_XBUS | DPC_SET_FREEZE);
+        dp.write_word(DPC_START, 0x400);
+        dp.write_word(DPC_END, 0x410);
+        assert!(!dp.run(&mut backend, &mut rdram, &dmem));
+        assert!(backend.words.is_empty());
+
+        dp.write_word(DPC_STATUS, DPC_CLEAR_FREEZE);
+        assert_eq!(dp.read_word(DPC_STATUS) & 0x7, DPC_STATUS_XBUS);
+        assert!(dp.run(&mut backend, &mut rdram, &dmem));
+        assert_eq!(backend.words, vec![!0, !0]);
+    }
+}
@@ -0,0 +1,558 @@
+use crate::cop0::{Exception, ExceptionCode};
+use crate::interp::{rd, rt, sa, FCR31_CONDITION};
+use crate::{DataCache, InstructionCache, InterpCPU32bit, MemoryBus, MMU};
+
+const FMT_S: u32 = 16;
+const FMT_D: u32 = 17;
+const FMT_W: u32 = 20;
+const FMT_L: u32 = 21;
+
+// FCR31 holds cause, enable and flag fields with one bit for each of these,
+// except that unimplemented operation is only a cause and can't be masked.
+const INEXACT: u32 = 0x01;
+const UNDERFLOW: u32 = 0x02;
+const OVERFLOW: u32 = 0x04;
+const DIVIDE_BY_ZERO: u32 = 0x08;
+const INVALID: u32 = 0x10;
+const UNIMPLEMENTED: u32 = 0x20;
+const FCR31_FLAG_SHIFT: u32 = 2;
+const FCR31_ENABLE_SHIFT: u32 = 7;
+const FCR31_CAUSE_SHIFT: u32 = 12;
+const FCR31_CAUSE: u32 = 0x3F << FCR31_CAUSE_SHIFT;
+const FCR31_FLUSH: u32 = 1 << 24;
+
+// The VR4300 uses the original MIPS NaN encoding, in which the top bit of
+// the fraction marks a signaling NaN. Invalid operations produce these
+// quiet NaNs.
+const DEFAULT_NAN_S: u32 = 0x7FBF_FFFF;
+const DEFAULT_NAN_D: u64 = 0x7FF7_FFFF_FFFF_FFFF;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Class {
+    Number,
+    Subnormal,
+    Infinite,
+    QuietNan,
+    SignalingNan,
+}
+
+fn classify_s(bits: u32) -> Class {
+    let exponent = (bits >> 23) & 0xFF;
+    let fraction = bits & 0x007F_FFFF;
+    match (exponent, fraction) {
+        (0, 0) => Class::Number,
+        (0, _) => Class::Subnormal,
+        (0xFF, 0) => Class::Infinite,
+        (0xFF, _) if fraction & 0x0040_0000 != 0 => Class::SignalingNan,
+        (0xFF, _) => Class::QuietNan,
+        _ => Class::Number,
+    }
+}
+
+fn classify_d(bits: u64) -> Class {
+    let exponent = (bits >> 52) & 0x7FF;
+    let fraction = bits & 0x000F_FFFF_FFFF_FFFF;
+    match (exponent, fraction) {
+        (0, 0) => Class::Number,
+        (0, _) => Class::Subnormal,
+        (0x7FF, 0) => Class::Infinite,
+        (0x7FF, _) if fraction & 0x0008_0000_0000_0000 != 0 => Class::SignalingNan,
+        (0x7FF, _) => Class::QuietNan,
+        _ => Class::Number,
+    }
+}
+
+// Rounds a double to the precision of `fmt`. Single results are worked out
+// in double precision first, which for the operations here gives the same
+// answer as rounding the exact result once.
+fn round_to(fmt: u32, value: f64) -> f64 {
+    if fmt == FMT_S {
+        value as f32 as f64
+    } else {
+        value
+    }
+}
+
+fn min_normal(fmt: u32) -> f64 {
+    if fmt == FMT_S {
+        f64::from(f32::MIN_POSITIVE)
+    } else {
+        f64::MIN_POSITIVE
+    }
+}
+
+// Rounds to an integer in one of the four FCR31 rounding modes.
+fn round_integer(mode: u32, value: f64) -> f64 {
+    match mode {
+        0 => value.round_ties_even(),
+        1 => value.trunc(),
+        2 => value.ceil(),
+        _ => value.floor(),
+    }
+}
+
+// Arithmetic always rounds to nearest; the FCR31 rounding mode is applied
+// to conversions to integers.
+impl<MB, IC, DC, MM> InterpCPU32bit<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
+    MM::AddressSize: From<u32>,
+{
+    // Runs a COP1 arithmetic, conversion or compare instruction. The decoder
+    // has already rejected the format and function combinations that don't
+    // exist, so both are taken straight from the instruction word.
+    pub(crate) fn execute_fpu(&mut self, iw: u32) -> Result<(), Exception> {
+        let fmt = (iw >> 21) & 0x1F;
+        let funct = iw & 0x3F;
+        let (fs, ft, fd) = (rd(iw), rt(iw), sa(iw) as usize);
+        if funct == 0x06 {
+            // MOV.fmt copies the bits, whatever they are.
+            if fmt == FMT_S {
+                self.fpr_write_word(fd, self.fpr_read_word(fs));
+            } else {
+                self.fpr[self.fpr_index_dword(fd)] = self.fpr[self.fpr_index_dword(fs)];
+            }
+            return Ok(());
+        }
+        self.fcr31 &= !FCR31_CAUSE;
+        match funct {
+            0x00..=0x07 => self.fpu_arithmetic(fmt, funct, fs, ft, fd),
+            0x08..=0x0F => {
+                let long = funct < 0x0C;
+                self.fpu_to_integer(fmt, funct & 3, long, fs, fd)
+            }
+            0x20 | 0x21 => {
+                let to = if funct == 0x20 { FMT_S } else { FMT_D };
+                self.fpu_convert(fmt, to, fs, fd)
+            }
+            0x24 | 0x25 => {
+                let mode = self.fcr31 & 3;
+                self.fpu_to_integer(fmt, mode, funct == 0x25, fs, fd)
+            }
+            _ => self.fpu_compare(fmt, funct & 0xF, fs, ft),
+        }
+    }
+
+    // Whether FCR31 has a cause bit set that the program asked to trap on,
+    // which a CTC1 can set up directly.
+    pub(crate) fn fpu_exception_pending(&self) -> bool {
+        let cause = (self.fcr31 & FCR31_CAUSE) >> FCR31_CAUSE_SHIFT;
+        let enables = ((self.fcr31 >> FCR31_ENABLE_SHIFT) & 0x1F) | UNIMPLEMENTED;
+        cause & enables != 0
+    }
+
+    fn read_float(&self, fmt: u32, reg: usize) -> (f64, Class) {
+        if fmt == FMT_S {
+            let bits = self.fpr_read_word(reg);
+            (f64::from(f32::from_bits(bits)), classify_s(bits))
+        } else {
+            let bits = self.fpr[self.fpr_index_dword(reg)];
+            (f64::from_bits(bits), classify_d(bits))
+        }
+    }
+
+    fn write_float(&mut self, fmt: u32, reg: usize, value: f64) {
+        if fmt == FMT_S {
+            let bits = if value.is_nan() {
+                DEFAULT_NAN_S
+            } else {
+                (value as f32).to_bits()
+            };
+            self.fpr_write_word(reg, bits);
+        } else {
+            let bits = if value.is_nan() {
+                DEFAULT_NAN_D
+            } else {
+                value.to_bits()
+            };
+            self.fpr[self.fpr_index_dword(reg)] = bits;
+        }
+    }
+
+    // Records the exceptions an instruction raised, trapping if any of them
+    // is enabled. Otherwise they are added to the flags and the caller goes
+    // on to write its result.
+    fn signal(&mut self, cause: u32) -> Result<(), Exception> {
+        self.fcr31 |= cause << FCR31_CAUSE_SHIFT;
+        if self.fpu_exception_pending() {
+            return Err(Exception::new(ExceptionCode::FloatingPoint));
+        }
+        self.fcr31 |= (cause & 0x1F) << FCR31_FLAG_SHIFT;
+        Ok(())
+    }
+
+    // Operands that are NaNs or denormals decide the result on their own.
+    fn special_operands(
+        &mut self,
+        fmt: u32,
+        fd: usize,
+        classes: &[Class],
+    ) -> Option<Result<(), Exception>> {
+        let cause = if classes.contains(&Class::Subnormal) {
+            UNIMPLEMENTED
+        } else if classes.contains(&Class::SignalingNan) {
+            INVALID
+        } else if classes.contains(&Class::QuietNan) {
+            0
+        } else {
+            return None;
+        };
+        Some(
+            self.signal(cause)
+                .map(|()| self.write_float(fmt, fd, f64::NAN)),
+        )
+    }
+
+    // Rounds `value`, which is exact unless `inexact` says otherwise, into
+    // `fd`. Tiny results either flush to zero, if FCR31 asks for that, or
+    // are left to software as an unimplemented operation.
+    fn write_rounded(
+        &mut self,
+        fmt: u32,
+        fd: usize,
+        value: f64,
+        inexact: bool,
+        infinite: bool,
+    ) -> Result<(), Exception> {
+        let mut result = round_to(fmt, value);
+        let tiny = (value != 0.0 && value.abs() < min_normal(fmt)) || (value == 0.0 && inexact);
+        let cause = if result.is_infinite() && !infinite {
+            OVERFLOW | INEXACT
+        } else if tiny {
+            let traps = (self.fcr31 >> FCR31_ENABLE_SHIFT) & (UNDERFLOW | INEXACT);
+            if self.fcr31 & FCR31_FLUSH == 0 || traps != 0 {
+                UNIMPLEMENTED
+            } else {
+                result = 0.0f64.copysign(value);
+                UNDERFLOW | INEXACT
+            }
+        } else if inexact || result != value {
+            INEXACT
+        } else {
+            0
+        };
+        self.signal(cause)?;
+        self.write_float(fmt, fd, result);
+        Ok(())
+    }
+
+    // ADD, SUB, MUL, DIV, SQRT, ABS and NEG. Each works out the nearest
+    // double along with whether it was exact, using a fused multiply-add to
+    // find the rounding error.
+    fn fpu_arithmetic(
+        &mut self,
+        fmt: u32,
+        funct: u32,
+        fs: usize,
+        ft: usize,
+        fd: usize,
+    ) -> Result<(), Exception> {
+        let (a, a_class) = self.read_float(fmt, fs);
+        let (b, b_class) = self.read_float(fmt, ft);
+        let classes: &[Class] = if funct >= 0x04 {
+            &[a_class]
+        } else {
+            &[a_class, b_class]
+        };
+        if let Some(result) = self.special_operands(fmt, fd, classes) {
+            return result;
+        }
+        let infinite = classes.contains(&Class::Infinite);
+        let (value, inexact) = match funct {
+            0x00 | 0x01 => {
+                let b = if funct == 0x00 { b } else { -b };
+                let sum = a + b;
+                let a_part = sum - b;
+                let error = (a - a_part) + (b - (sum - a_part));
+                (sum, error != 0.0)
+            }
+            0x02 => {
+                let product = a * b;
+                (product, a.mul_add(b, -product) != 0.0)
+            }
+            0x03 => {
+                let quotient = a / b;
+                (quotient, (-quotient).mul_add(b, a) != 0.0)
+            }
+            0x04 => {
+                let root = a.sqrt();
+                (root, (-root).mul_add(root, a) != 0.0)
+            }
+            0x05 => (a.abs(), false),
+            _ => (-a, false),
+        };
+        if value.is_nan() {
+            self.signal(INVALID)?;
+            self.write_float(fmt, fd, f64::NAN);
+            return Ok(());
+        }
+        if funct == 0x03 && b == 0.0 && !infinite {
+            self.signal(DIVIDE_BY_ZERO)?;
+            self.write_float(fmt, fd, value);
+            return Ok(());
+        }
+        // With an infinite operand the error terms are NaNs, but the result
+        // is exact.
+        self.write_rounded(fmt, fd, value, inexact && !infinite, infinite)
+    }
+
+    // CVT.S and CVT.D, from either float format or either integer format.
+    fn fpu_convert(&mut self, from: u32, to: u32, fs: usize, fd: usize) -> Result<(), Exception> {
+        match from {
+            FMT_W => {
+                let value = f64::from(self.fpr_read_word(fs) as i32);
+                self.write_rounded(to, fd, value, false, false)
+            }
+            FMT_L => {
+                let value = self.fpr[self.fpr_index_dword(fs)] as i64;
+                // Only 55-bit integers are converted in hardware.
+                if !(-(1 << 55)..(1 << 55)).contains(&value) {
+                    return self.signal(UNIMPLEMENTED);
+                }
+                let result = if to == FMT_S {
+                    f64::from(value as f32)
+                } else {
+                    value as f64
+                };
+                self.signal(if result as i64 != value { INEXACT } else { 0 })?;
+                self.write_float(to, fd, result);
+                Ok(())
+            }
+            _ => {
+                let (value, class) = self.read_float(from, fs);
+                if let Some(result) = self.special_operands(to, fd, &[class]) {
+                    return result;
+                }
+                self.write_rounded(to, fd, value, false, class == Class::Infinite)
+            }
+        }
+    }
+
+    // CVT.W, CVT.L, ROUND, TRUNC, CEIL and FLOOR. Anything that doesn't fit
+    // the destination, including NaNs and infinities, is left to software.
+    fn fpu_to_integer(
+        &mut self,
+        fmt: u32,
+        mode: u32,
+        long: bool,
+        fs: usize,
+        fd: usize,
+    ) -> Result<(), Exception> {
+        let (value, class) = self.read_float(fmt, fs);
+        let result = round_integer(mode, value);
+        let in_range = if long {
+            result.abs() < (1u64 << 53) as f64
+        } else {
+            result >= f64::from(i32::MIN) && result <= f64::from(i32::MAX)
+        };
+        if class != Class::Number || !in_range {
+            return self.signal(UNIMPLEMENTED);
+        }
+        self.signal(if result != value { INEXACT } else { 0 })?;
+        if long {
+            self.fpr[self.fpr_index_dword(fd)] = result as i64 as u64;
+        } else {
+            self.fpr_write_word(fd, result as i32 as u32);
+        }
+        Ok(())
+    }
+
+    // C.cond: the low three bits of the condition pick which of unordered,
+    // equal and less than make it true; the fourth makes any NaN invalid
+    // rather than only signaling ones.
+    fn fpu_compare(&mut self, fmt: u32, cond: u32, fs: usize, ft: usize) -> Result<(), Exception> {
+        let (a, a_class) = self.read_float(fmt, fs);
+        let (b, b_class) = self.read_float(fmt, ft);
+        let unordered = a.is_nan() || b.is_nan();
+        let signaling = a_class == Class::SignalingNan || b_class == Class::SignalingNan;
+        let invalid = unordered && (cond & 8 != 0 || signaling);
+        self.signal(if invalid { INVALID } else { 0 })?;
+        let condition =
+            (cond & 1 != 0 && unordered) || (cond & 2 != 0 && a == b) || (cond & 4 != 0 && a < b);
+        if condition {
+            self.fcr31 |= FCR31_CONDITION;
+        } else {
+            self.fcr31 &= !FCR31_CONDITION;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cop0::{COP0_STATUS, STATUS_CU1, STATUS_FR};
+    use crate::{DCache, ICache, MMU32Bit, Memory, CPU};
+
+    type Cpu = InterpCPU32bit<Memory, ICache, DCache, MMU32Bit>;
+
+    fn cpu() -> Cpu {
+        let mut cpu = Cpu::new(&mut [0u8; 2048].as_slice());
+        cpu.cop0.write(COP0_STATUS, STATUS_CU1 | STATUS_FR);
+        cpu
+    }
+
+    fn fp_op(fmt: u32, ft: u32, fs: u32, fd: u32, funct: u32) -> u32 {
+        0x11 << 26 | fmt << 21 | ft << 16 | fs << 11 | fd << 6 | funct
+    }
+
+    fn flags(cpu: &Cpu) -> u32 {
+        (cpu.fcr31 >> FCR31_FLAG_SHIFT) & 0x1F
+    }
+
+    fn cause(cpu: &Cpu) -> u32 {
+        (cpu.fcr31 & FCR31_CAUSE) >> FCR31_CAUSE_SHIFT
+    }
+
+    #[test]
+    fn arithmetic_sets_flags() {
+        let mut cpu = cpu();
+        cpu.fpr[1] = u64::from(1.5f32.to_bits());
+        cpu.fpr[2] = u64::from(2.25f32.to_bits());
+        cpu.execute_fpu(fp_op(FMT_S, 2, 1, 3, 0x00)).unwrap(); // add.s
+        assert_eq!(cpu.fpr[3] as u32, 3.75f32.to_bits());
+        assert_eq!(flags(&cpu), 0);
+
+        cpu.fpr[1] = 1.0f64.to_bits();
+        cpu.fpr[2] = 3.0f64.to_bits();
+        cpu.execute_fpu(fp_op(FMT_D, 2, 1, 3, 0x03)).unwrap(); // div.d
+        assert_eq!(f64::from_bits(cpu.fpr[3]), 1.0 / 3.0);
+        assert_eq!(cause(&cpu), INEXACT);
+
+        cpu.fpr[1] = u64::from(f32::MAX.to_bits());
+        cpu.execute_fpu(fp_op(FMT_S, 1, 1, 3, 0x02)).unwrap(); // mul.s
+        assert_eq!(cpu.fpr[3] as u32, f32::INFINITY.to_bits());
+        assert_eq!(cause(&cpu), OVERFLOW | INEXACT);
+
+        cpu.fpr[2] = 0;
+        cpu.execute_fpu(fp_op(FMT_S, 2, 1, 3, 0x03)).unwrap(); // div.s
+        assert_eq!(cpu.fpr[3] as u32, f32::INFINITY.to_bits());
+        assert_eq!(cause(&cpu), DIVIDE_BY_ZERO);
+        assert_eq!(flags(&cpu), INEXACT | OVERFLOW | DIVIDE_BY_ZERO);
+
+        // Multiplying by infinity is exact.
+        cpu.fpr[1] = f64::INFINITY.to_bits();
+        cpu.fpr[2] = 3.0f64.to_bits();
+        cpu.execute_fpu(fp_op(FMT_D, 2, 1, 3, 0x02)).unwrap(); // mul.d
+        assert_eq!(cause(&cpu), 0);
+    }
+
+    #[test]
+    fn invalid_operations_trap_when_enabled() {
+        let mut cpu = cpu();
+        cpu.fpr[1] = (-1.0f64).to_bits();
+        cpu.execute_fpu(fp_op(FMT_D, 0, 1, 3, 0x04)).unwrap(); // sqrt.d
+        assert_eq!(cpu.fpr[3], DEFAULT_NAN_D);
+        assert_eq!(flags(&cpu), INVALID);
+
+        cpu.fcr31 = INVALID << FCR31_ENABLE_SHIFT;
+        cpu.fpr[3] = 0;
+        let exception = cpu.execute_fpu(fp_op(FMT_D, 0, 1, 3, 0x04)).unwrap_err();
+        assert_eq!(exception.code, ExceptionCode::FloatingPoint);
+        assert_eq!(cause(&cpu), INVALID);
+        assert_eq!(flags(&cpu), 0);
+        assert_eq!(cpu.fpr[3], 0);
+
+        // Quiet NaNs pass through without complaint; signaling ones don't.
+        cpu.fpr[1] = DEFAULT_NAN_D;
+        cpu.execute_fpu(fp_op(FMT_D, 1, 1, 3, 0x00)).unwrap(); // add.d
+        assert_eq!(cpu.fpr[3], DEFAULT_NAN_D);
+        cpu.fpr[1] = 0x7FF8_0000_0000_0000;
+        assert!(cpu.execute_fpu(fp_op(FMT_D, 1, 1, 3, 0x00)).is_err());
+    }
+
+    #[test]
+    fn denormals_are_unimplemented_unless_flushed() {
+        let mut cpu = cpu();
+        cpu.fpr[1] = 1;
+        cpu.fpr[2] = 1.0f64.to_bits();
+        let exception = cpu.execute_fpu(fp_op(FMT_D, 2, 1, 3, 0x02)).unwrap_err();
+        assert_eq!(exception.code, ExceptionCode::FloatingPoint);
+        assert_eq!(cause(&cpu), UNIMPLEMENTED);
+
+        cpu.fpr[1] = u64::from(f32::MIN_POSITIVE.to_bits());
+        cpu.fpr[2] = u64::from(0.5f32.to_bits());
+        assert!(cpu.execute_fpu(fp_op(FMT_S, 2, 1, 3, 0x02)).is_err());
+        cpu.fcr31 = FCR31_FLUSH;
+        cpu.execute_fpu(fp_op(FMT_S, 2, 1, 3, 0x02)).unwrap(); // mul.s
+        assert_eq!(cpu.fpr[3] as u32, 0);
+        assert_eq!(flags(&cpu), UNDERFLOW | INEXACT);
+    }
+
+    #[test]
+    fn conversions_round_and_range_check() {
+        let mut cpu = cpu();
+        cpu.fpr[1] = u64::from(2.5f32.to_bits());
+        // cvt.w.s in each rounding mode, then round, trunc, ceil and floor.
+        for (mode, expected) in [(0, 2), (1, 2), (2, 3), (3, 2)] {
+            cpu.fcr31 = mode;
+            cpu.execute_fpu(fp_op(FMT_S, 0, 1, 3, 0x24)).unwrap();
+            assert_eq!(cpu.fpr[3] as u32, expected, "mode {}", mode);
+            assert_eq!(cause(&cpu), INEXACT);
+        }
+        cpu.fpr[1] = (-2.5f64).to_bits();
+        for (funct, expected) in [(0x08, -2), (0x09, -2), (0x0A, -2), (0x0B, -3)] {
+            cpu.execute_fpu(fp_op(FMT_D, 0, 1, 3, funct)).unwrap();
+            assert_eq!(cpu.fpr[3] as i64, expected, "funct {:#X}", funct);
+        }
+        cpu.fpr[1] = 3e9f64.to_bits();
+        assert!(cpu.execute_fpu(fp_op(FMT_D, 0, 1, 3, 0x0D)).is_err()); // trunc.w.d
+        cpu.execute_fpu(fp_op(FMT_D, 0, 1, 3, 0x09)).unwrap(); // trunc.l.d
+        assert_eq!(cpu.fpr[3], 3_000_000_000);
+
+        cpu.fpr[1] = 16_777_217;
+        cpu.execute_fpu(fp_op(FMT_W, 0, 1, 3, 0x20)).unwrap(); // cvt.s.w
+        assert_eq!(cpu.fpr[3] as u32, 16_777_216f32.to_bits());
+        assert_eq!(cause(&cpu), INEXACT);
+        cpu.execute_fpu(fp_op(FMT_L, 0, 1, 3, 0x21)).unwrap(); // cvt.d.l
+        assert_eq!(f64::from_bits(cpu.fpr[3]), 16_777_217.0);
+        cpu.fpr[1] = 1 << 55;
+        assert!(cpu.execute_fpu(fp_op(FMT_L, 0, 1, 3, 0x21)).is_err());
+
+        cpu.fpr[1] = 0.1f64.to_bits();
+        cpu.execute_fpu(fp_op(FMT_D, 0, 1, 3, 0x20)).unwrap(); // cvt.s.d
+        assert_eq!(cpu.fpr[3] as u32, 0.1f32.to_bits());
+        assert_eq!(cause(&cpu), INEXACT);
+        cpu.execute_fpu(fp_op(FMT_S, 0, 3, 4, 0x21)).unwrap(); // cvt.d.s
+        assert_eq!(f64::from_bits(cpu.fpr[4]), f64::from(0.1f32));
+        assert_eq!(cause(&cpu), 0);
+    }
+
+    #[test]
+    fn compares_set_the_condition() {
+        let mut cpu = cpu();
+        cpu.fpr[1] = u64::from(1.0f32.to_bits());
+        cpu.fpr[2] = u64::from(2.0f32.to_bits());
+        cpu.fpr[3] = u64::from(DEFAULT_NAN_S);
+        let compare = |cpu: &mut Cpu, ft, fs, cond: u32| {
+            cpu.execute_fpu(fp_op(FMT_S, ft, fs, 0, 0x30 | cond))
+                .map(|()| cpu.fcr31 & FCR31_CONDITION != 0)
+        };
+        assert_eq!(compare(&mut cpu, 2, 1, 0xC), Ok(true)); // c.lt.s
+        assert_eq!(compare(&mut cpu, 1, 2, 0xC), Ok(false));
+        assert_eq!(compare(&mut cpu, 1, 1, 0xE), Ok(true)); // c.le.s
+        assert_eq!(compare(&mut cpu, 3, 1, 0x2), Ok(false)); // c.eq.s
+        assert_eq!(compare(&mut cpu, 3, 1, 0x1), Ok(true)); // c.un.s
+        assert_eq!(flags(&cpu), 0);
+        // c.ngle.s signals on any NaN.
+        assert_eq!(compare(&mut cpu, 3, 1, 0x9), Ok(true));
+        assert_eq!(flags(&cpu), INVALID);
+    }
+
+    #[test]
+    fn ctc1_can_raise_a_pending_exception() {
+        let mut cpu = cpu();
+        // ctc1 t0, $31 with the unimplemented operation cause set.
+        cpu.gpr[8] = u64::from(UNIMPLEMENTED << FCR31_CAUSE_SHIFT);
+        let iw: u32 = 0x11 << 26 | 0x06 << 21 | 8 << 16 | 31 << 11;
+        let exception = cpu.execute(iw, 0).unwrap_err();
+        assert_eq!(exception.code, ExceptionCode::FloatingPoint);
+        cpu.gpr[8] = 0;
+        assert_eq!(cpu.execute(iw, 0), Ok(()));
+    }
+}
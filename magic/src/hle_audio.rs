@@ -0,0 +1,957 @@
+use crate::rsp::SP_MEM_SIZE;
+
+// libultra places the OSTask structure at the end of DMEM before starting
+// the RSP on a task.
+const TASK_OFFSET: usize = 0xFC0;
+const TASK_TYPE: usize = 0x00;
+const TASK_UCODE_DATA: usize = 0x18;
+const TASK_DATA_PTR: usize = 0x30;
+const TASK_DATA_SIZE: usize = 0x34;
+
+const M_AUDTASK: u32 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AudioAbi {
+    // The original libultra audio microcode (ABI 1).
+    Audio,
+    // Nintendo's later "naudio" ucode, with fixed DMEM buffers.
+    Naudio,
+    // The nead ucodes used by Zelda, Mario Kart and friends (ABI 2).
+    Nead,
+    // Factor 5's MusyX.
+    MusyX,
+}
+
+impl AudioAbi {
+    // Only ABI 1 and naudio have native command lists. Nead and MusyX are
+    // still identified, so callers can tell why, but their tasks are left
+    // to the RSP to run the microcode.
+    pub fn supported(self) -> bool {
+        matches!(self, AudioAbi::Audio | AudioAbi::Naudio)
+    }
+}
+
+// The ucode text differs between every game build, so a hash of it would
+// need an entry per game. The first words of the data segment (the command
+// dispatch table) are shared by every build of an ABI and pin it down.
+pub fn identify(rdram: &[u8], ucode_data: u32) -> Option<AudioAbi> {
+    let word = |offset: u32| read_u32(rdram, ucode_data.wrapping_add(offset));
+    if word(0x00) == 0x0000_0001 {
+        if word(0x30) == 0xF000_0F00 {
+            match word(0x28) {
+                0x1E24_138C | 0x1DC8_138C | 0x1E3C_1390 => Some(AudioAbi::Audio),
+                _ => None,
+            }
+        } else {
+            match word(0x10) {
+                0x0001_0010 => Some(AudioAbi::MusyX),
+                0x1118_1350 | 0x1118_12E0 | 0x1104_12AC | 0x1104_12CC | 0x1CD0_1250
+                | 0x1F08_122C | 0x1F38_122C | 0x1F68_1230 | 0x1F80_1250 | 0x1094_11F8
+                | 0x1EAC_11B8 => Some(AudioAbi::Nead),
+                _ => None,
+            }
+        }
+    } else {
+        match word(0x10) {
+            0x0000_0001 => Some(AudioAbi::MusyX),
+            0x0000_127C | 0x0000_1280 => Some(AudioAbi::Naudio),
+            _ => None,
+        }
+    }
+}
+
+fn read_u32(rdram: &[u8], addr: u32) -> u32 {
+    let i = addr as usize & !3;
+    match rdram.get(i..i + 4) {
+        Some(word) => u32::from_be_bytes([word[0], word[1], word[2], word[3]]),
+        None => 0,
+    }
+}
+
+fn read_i16(rdram: &[u8], addr: u32) -> i16 {
+    let i = addr as usize & !1;
+    match rdram.get(i..i + 2) {
+        Some(half) => i16::from_be_bytes([half[0], half[1]]),
+        None => 0,
+    }
+}
+
+fn write_i16(rdram: &mut [u8], addr: u32, value: i16) {
+    let i = addr as usize & !1;
+    if let Some(half) = rdram.get_mut(i..i + 2) {
+        half.copy_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn clamp_s16(value: i32) -> i16 {
+    value.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+}
+
+// Runs an audio task natively if DMEM holds one for a supported ABI.
+// Returns false, leaving everything untouched, when the RSP should run the
+// microcode itself instead.
+pub fn run_task(dmem: &mut [u8; SP_MEM_SIZE], rdram: &mut [u8]) -> bool {
+    let task = |offset: usize| {
+        let i = TASK_OFFSET + offset;
+        u32::from_be_bytes([dmem[i], dmem[i + 1], dmem[i + 2], dmem[i + 3]])
+    };
+    if task(TASK_TYPE) != M_AUDTASK {
+        return false;
+    }
+    let abi = match identify(rdram, task(TASK_UCODE_DATA)) {
+        Some(abi) if abi.supported() => abi,
+        _ => return false,
+    };
+    let data_ptr = task(TASK_DATA_PTR);
+    let data_size = task(TASK_DATA_SIZE);
+    let mut alist = AudioList::new(abi, dmem);
+    for offset in (0..data_size & !7).step_by(8) {
+        let w1 = read_u32(rdram, data_ptr.wrapping_add(offset));
+        let w2 = read_u32(rdram, data_ptr.wrapping_add(offset + 4));
+        alist.command(rdram, w1, w2);
+    }
+    true
+}
+
+// Buffer addresses in ABI 1 commands are relative to this DMEM offset.
+const AUDIO_DMEM_BASE: u16 = 0x5C0;
+
+const NAUDIO_COUNT: u16 = 0x170;
+const NAUDIO_MAIN: u16 = 0x4F0;
+const NAUDIO_MAIN2: u16 = 0x660;
+const NAUDIO_DRY_LEFT: u16 = 0x9D0;
+const NAUDIO_DRY_RIGHT: u16 = 0xB40;
+const NAUDIO_WET_LEFT: u16 = 0xCB0;
+const NAUDIO_WET_RIGHT: u16 = 0xE20;
+
+const A_INIT: u8 = 0x01;
+const A_LOOP: u8 = 0x02;
+const A_LEFT: u8 = 0x02;
+const A_VOL: u8 = 0x04;
+const A_AUX: u8 = 0x08;
+
+// Size of the state an envelope mixer saves between tasks.
+const ENVMIX_STATE_SIZE: u32 = 80;
+
+fn align(value: u16, to: u16) -> u16 {
+    value.wrapping_add(to - 1) & !(to - 1)
+}
+
+// A volume ramp in 16.16 fixed point, stepping towards its target.
+#[derive(Copy, Clone, Default)]
+struct Ramp {
+    value: i32,
+    target: i32,
+    step: i32,
+}
+
+impl Ramp {
+    fn step(&mut self) -> i16 {
+        self.value = self.value.wrapping_add(self.step);
+        let reached = if self.step <= 0 {
+            self.value <= self.target
+        } else {
+            self.value >= self.target
+        };
+        if reached {
+            self.value = self.target;
+            self.step = 0;
+        }
+        (self.value >> 16) as i16
+    }
+}
+
+struct AudioList<'a> {
+    abi: AudioAbi,
+    dmem: &'a mut [u8; SP_MEM_SIZE],
+    segments: [u32; 16],
+    input: u16,
+    output: u16,
+    count: u16,
+    dry_right: u16,
+    wet_left: u16,
+    wet_right: u16,
+    vol: [i16; 2],
+    target: [i16; 2],
+    rate: [i32; 2],
+    dry: i16,
+    wet: i16,
+    loop_addr: u32,
+    table: [i16; 128],
+}
+
+impl<'a> AudioList<'a> {
+    fn new(abi: AudioAbi, dmem: &'a mut [u8; SP_MEM_SIZE]) -> Self {
+        AudioList {
+            abi,
+            dmem,
+            segments: [0; 16],
+            input: 0,
+            output: 0,
+            count: 0,
+            dry_right: 0,
+            wet_left: 0,
+            wet_right: 0,
+            vol: [0; 2],
+            target: [0; 2],
+            rate: [0; 2],
+            dry: 0,
+            wet: 0,
+            loop_addr: 0,
+            table: [0; 128],
+        }
+    }
+
+    fn sample(&self, addr: u16) -> i16 {
+        let i = addr as usize & (SP_MEM_SIZE - 2);
+        i16::from_be_bytes([self.dmem[i], self.dmem[i + 1]])
+    }
+
+    fn set_sample(&mut self, addr: u16, value: i16) {
+        let i = addr as usize & (SP_MEM_SIZE - 2);
+        self.dmem[i..i + 2].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn address(&self, segmented: u32) -> u32 {
+        self.segments[((segmented >> 24) & 0xF) as usize].wrapping_add(segmented & 0x00FF_FFFF)
+    }
+
+    fn command(&mut self, rdram: &mut [u8], w1: u32, w2: u32) {
+        match self.abi {
+            AudioAbi::Naudio => self.naudio_command(rdram, w1, w2),
+            _ => self.audio_command(rdram, w1, w2),
+        }
+    }
+
+    fn audio_command(&mut self, rdram: &mut [u8], w1: u32, w2: u32) {
+        let flags = (w1 >> 16) as u8;
+        let buffer = |addr: u32| (addr as u16).wrapping_add(AUDIO_DMEM_BASE);
+        match w1 >> 24 {
+            // ADPCM
+            0x01 => {
+                let address = self.address(w2);
+                let (out, input, count) = (self.output, self.input, align(self.count, 32));
+                self.adpcm(rdram, flags, out, input, count, address);
+            }
+            // CLEARBUFF
+            0x02 => self.clear(buffer(w1), align(w2 as u16, 16)),
+            // ENVMIXER
+            0x03 => {
+                let address = self.address(w2);
+                self.envmix_exp(rdram, flags, address);
+            }
+            // LOADBUFF
+            0x04 => {
+                let address = self.address(w2);
+                self.load(rdram, self.input, address, align(self.count, 8));
+            }
+            // RESAMPLE
+            0x05 => {
+                let address = self.address(w2);
+                let pitch = u32::from(w1 as u16) << 1;
+                let (out, input, count) = (self.output, self.input, align(self.count, 16));
+                self.resample(
+                    rdram,
+                    flags & A_INIT != 0,
+                    out,
+                    input,
+                    count,
+                    pitch,
+                    address,
+                );
+            }
+            // SAVEBUFF
+            0x06 => {
+                let address = self.address(w2);
+                self.save(rdram, self.output, address, align(self.count, 8));
+            }
+            // SEGMENT
+            0x07 => self.segments[((w2 >> 24) & 0xF) as usize] = w2 & 0x00FF_FFFF,
+            // SETBUFF
+            0x08 => {
+                if flags & A_AUX != 0 {
+                    self.dry_right = buffer(w1);
+                    self.wet_left = buffer(w2 >> 16);
+                    self.wet_right = buffer(w2);
+                } else {
+                    self.input = buffer(w1);
+                    self.output = buffer(w2 >> 16);
+                    self.count = w2 as u16;
+                }
+            }
+            // SETVOL
+            0x09 => {
+                if flags & A_AUX != 0 {
+                    self.dry = w1 as i16;
+                    self.wet = w2 as i16;
+                } else {
+                    let lr = if flags & A_LEFT != 0 { 0 } else { 1 };
+                    if flags & A_VOL != 0 {
+                        self.vol[lr] = w1 as i16;
+                    } else {
+                        self.target[lr] = w1 as i16;
+                        self.rate[lr] = w2 as i32;
+                    }
+                }
+            }
+            // DMEMMOVE
+            0x0A => {
+                let count = align(w2 as u16, 16);
+                self.copy(buffer(w2 >> 16), buffer(w1), count);
+            }
+            // LOADADPCM
+            0x0B => {
+                let address = self.address(w2);
+                self.load_table(rdram, address, w1 as u16);
+            }
+            // MIXER
+            0x0C => {
+                let count = align(self.count, 32);
+                self.mix(buffer(w2), buffer(w2 >> 16), count, w1 as i16);
+            }
+            // INTERLEAVE
+            0x0D => {
+                let (left, right) = (buffer(w2 >> 16), buffer(w2));
+                self.interleave(self.output, left, right, align(self.count, 16));
+            }
+            // SETLOOP
+            0x0F => self.loop_addr = self.address(w2),
+            // SPNOOP and the unused POLEF slot.
+            _ => {}
+        }
+    }
+
+    fn naudio_command(&mut self, rdram: &mut [u8], w1: u32, w2: u32) {
+        let flags = (w1 >> 16) as u8;
+        match w1 >> 24 {
+            // ADPCM, with the addresses packed into the second word.
+            0x01 => {
+                let address = w1 & 0x00FF_FFFF;
+                let flags = (w2 >> 28) as u8;
+                let count = align(((w2 >> 16) & 0xFFF) as u16, 32);
+                let input = ((w2 >> 12) & 0xF) as u16 + NAUDIO_MAIN;
+                let out = (w2 & 0xFFF) as u16 + NAUDIO_MAIN;
+                self.adpcm(rdram, flags, out, input, count, address);
+            }
+            0x02 => self.clear(w1 as u16, w2 as u16),
+            0x03 => {
+                let address = self.address(w2);
+                self.vol[1] = w1 as i16;
+                self.envmix_lin(rdram, flags & A_INIT != 0, address);
+            }
+            0x04 | 0x06 => {
+                let count = ((w1 >> 12) & 0xFFF) as u16;
+                let dmem = (w1 & 0xFFF) as u16;
+                let address = self.address(w2);
+                if w1 >> 24 == 0x04 {
+                    self.load(rdram, dmem, address, count);
+                } else {
+                    self.save(rdram, dmem, address, count);
+                }
+            }
+            0x05 => {
+                let address = w1 & 0x00FF_FFFF;
+                let init = (w2 >> 30) & 1 != 0;
+                let pitch = u32::from((w2 >> 14) as u16) << 1;
+                let input = ((w2 >> 2) & 0xFFF) as u16 + NAUDIO_MAIN;
+                let out = if w2 & 3 != 0 {
+                    NAUDIO_MAIN2
+                } else {
+                    NAUDIO_MAIN
+                };
+                self.resample(rdram, init, out, input, NAUDIO_COUNT, pitch, address);
+            }
+            0x09 => {
+                if flags & 0x4 != 0 {
+                    if flags & 0x2 != 0 {
+                        self.vol[0] = w1 as i16;
+                        self.dry = (w2 >> 16) as i16;
+                        self.wet = w2 as i16;
+                    } else {
+                        self.target[1] = w1 as i16;
+                        self.rate[1] = w2 as i32;
+                    }
+                } else {
+                    self.target[0] = w1 as i16;
+                    self.rate[0] = w2 as i32;
+                }
+            }
+            0x0A => {
+                let count = (w2 as u16 + 3) & !3;
+                self.copy((w2 >> 16) as u16, w1 as u16, count);
+            }
+            0x0B => {
+                let address = self.address(w2);
+                self.load_table(rdram, address, w1 as u16);
+            }
+            0x0C => self.mix(w2 as u16, (w2 >> 16) as u16, NAUDIO_COUNT, w1 as i16),
+            0x0D => self.interleave(NAUDIO_MAIN, NAUDIO_DRY_LEFT, NAUDIO_DRY_RIGHT, NAUDIO_COUNT),
+            // The low half of the right rate, set by code inside SETVOL.
+            0x0E => self.rate[1] = (self.rate[1] & !0xFFFF) | (w2 & 0xFFFF) as i32,
+            0x0F => self.loop_addr = self.address(w2),
+            _ => {}
+        }
+    }
+
+    fn clear(&mut self, dmem: u16, count: u16) {
+        for i in 0..count {
+            self.dmem[(dmem.wrapping_add(i)) as usize & (SP_MEM_SIZE - 1)] = 0;
+        }
+    }
+
+    fn load(&mut self, rdram: &[u8], dmem: u16, address: u32, count: u16) {
+        let (dmem, address) = (dmem & !3, address & !7);
+        for i in 0..count {
+            let byte = rdram
+                .get(address.wrapping_add(u32::from(i)) as usize)
+                .copied()
+                .unwrap_or(0);
+            self.dmem[(dmem.wrapping_add(i)) as usize & (SP_MEM_SIZE - 1)] = byte;
+        }
+    }
+
+    fn save(&self, rdram: &mut [u8], dmem: u16, address: u32, count: u16) {
+        let (dmem, address) = (dmem & !3, address & !7);
+        for i in 0..count {
+            if let Some(byte) = rdram.get_mut(address.wrapping_add(u32::from(i)) as usize) {
+                *byte = self.dmem[(dmem.wrapping_add(i)) as usize & (SP_MEM_SIZE - 1)];
+            }
+        }
+    }
+
+    fn copy(&mut self, dmemo: u16, dmemi: u16, count: u16) {
+        for i in 0..count {
+            let byte = self.dmem[(dmemi.wrapping_add(i)) as usize & (SP_MEM_SIZE - 1)];
+            self.dmem[(dmemo.wrapping_add(i)) as usize & (SP_MEM_SIZE - 1)] = byte;
+        }
+    }
+
+    fn load_table(&mut self, rdram: &[u8], address: u32, count: u16) {
+        let entries = (usize::from(align(count, 8)) / 2).min(self.table.len());
+        for i in 0..entries {
+            self.table[i] = read_i16(rdram, address + i as u32 * 2);
+        }
+    }
+
+    fn mix(&mut self, dmemo: u16, dmemi: u16, count: u16, gain: i16) {
+        for i in 0..count / 2 {
+            let dst = self.sample(dmemo.wrapping_add(i * 2));
+            let src = self.sample(dmemi.wrapping_add(i * 2));
+            let mixed = i32::from(dst) + ((i32::from(src) * i32::from(gain)) >> 15);
+            self.set_sample(dmemo.wrapping_add(i * 2), clamp_s16(mixed));
+        }
+    }
+
+    fn interleave(&mut self, dmemo: u16, left: u16, right: u16, count: u16) {
+        for i in 0..count / 2 {
+            let l = self.sample(left.wrapping_add(i * 2));
+            let r = self.sample(right.wrapping_add(i * 2));
+            self.set_sample(dmemo.wrapping_add(i * 4), l);
+            self.set_sample(dmemo.wrapping_add(i * 4 + 2), r);
+        }
+    }
+
+    // Decodes 9-byte frames of 4-bit ADPCM into 16 samples each. The output
+    // starts with the 16 samples of history, which are saved back to RDRAM
+    // for the next task.
+    fn adpcm(
+        &mut self,
+        rdram: &mut [u8],
+        flags: u8,
+        dmemo: u16,
+        dmemi: u16,
+        count: u16,
+        address: u32,
+    ) {
+        let mut last = [0i16; 16];
+        if flags & A_INIT == 0 {
+            let from = if flags & A_LOOP != 0 {
+                self.loop_addr
+            } else {
+                address
+            };
+            for (i, sample) in last.iter_mut().enumerate() {
+                *sample = read_i16(rdram, from + i as u32 * 2);
+            }
+        }
+        let mut out = dmemo;
+        for sample in last.iter() {
+            self.set_sample(out, *sample);
+            out = out.wrapping_add(2);
+        }
+
+        let mut input = dmemi;
+        for _ in 0..count / 32 {
+            let code = self.dmem[input as usize & (SP_MEM_SIZE - 1)];
+            input = input.wrapping_add(1);
+            let scale = u32::from(code >> 4);
+            let rshift = 12u32.saturating_sub(scale);
+            let book = usize::from(code & 0xF) << 4;
+
+            let mut frame = [0i16; 16];
+            for i in 0..8 {
+                let byte = self.dmem[input as usize & (SP_MEM_SIZE - 1)];
+                input = input.wrapping_add(1);
+                frame[i * 2] = ((u16::from(byte & 0xF0) << 8) as i16) >> rshift;
+                frame[i * 2 + 1] = ((u16::from(byte & 0x0F) << 12) as i16) >> rshift;
+            }
+
+            let cb = &self.table[book..book + 16];
+            let history = [last[14], last[15]];
+            residuals(&mut last[..8], &frame[..8], cb, history);
+            let history = [last[6], last[7]];
+            residuals(&mut last[8..], &frame[8..], cb, history);
+
+            for sample in last.iter() {
+                let i = out as usize & (SP_MEM_SIZE - 2);
+                self.dmem[i..i + 2].copy_from_slice(&sample.to_be_bytes());
+                out = out.wrapping_add(2);
+            }
+        }
+
+        for (i, sample) in last.iter().enumerate() {
+            write_i16(rdram, address + i as u32 * 2, *sample);
+        }
+    }
+
+    // Four-tap interpolating resampler with a 16.16 pitch. The four samples
+    // before the input buffer carry history between tasks.
+    #[allow(clippy::too_many_arguments)]
+    fn resample(
+        &mut self,
+        rdram: &mut [u8],
+        init: bool,
+        dmemo: u16,
+        dmemi: u16,
+        count: u16,
+        pitch: u32,
+        address: u32,
+    ) {
+        let mut ipos = dmemi.wrapping_sub(8);
+        let mut accu: u32 = 0;
+        for k in 0..4u16 {
+            let value = if init {
+                0
+            } else {
+                read_i16(rdram, address + u32::from(k) * 2)
+            };
+            self.set_sample(ipos.wrapping_add(k * 2), value);
+        }
+        if !init {
+            accu = read_i16(rdram, address + 8) as u16 as u32;
+        }
+
+        let mut opos = dmemo;
+        for _ in 0..count / 2 {
+            let taps = resample_taps((accu >> 10) & 0x3F);
+            let sum: i64 = (0..4u16)
+                .map(|k| {
+                    i64::from(self.sample(ipos.wrapping_add(k * 2))) * i64::from(taps[k as usize])
+                })
+                .sum();
+            self.set_sample(opos, (sum >> 15).clamp(-32768, 32767) as i16);
+            opos = opos.wrapping_add(2);
+            accu += pitch;
+            ipos = ipos.wrapping_add(((accu >> 16) as u16) * 2);
+            accu &= 0xFFFF;
+        }
+
+        for k in 0..4u16 {
+            write_i16(
+                rdram,
+                address + u32::from(k) * 2,
+                self.sample(ipos.wrapping_add(k * 2)),
+            );
+        }
+        write_i16(rdram, address + 8, accu as u16 as i16);
+    }
+
+    fn load_envmix_state(rdram: &[u8], address: u32) -> [u8; ENVMIX_STATE_SIZE as usize] {
+        let mut state = [0; ENVMIX_STATE_SIZE as usize];
+        for (i, byte) in state.iter_mut().enumerate() {
+            *byte = rdram.get(address as usize + i).copied().unwrap_or(0);
+        }
+        state
+    }
+
+    fn store_envmix_state(rdram: &mut [u8], address: u32, state: &[u8]) {
+        for (i, byte) in state.iter().enumerate() {
+            if let Some(dst) = rdram.get_mut(address as usize + i) {
+                *dst = *byte;
+            }
+        }
+    }
+
+    // Mixes one input sample into the dry and wet outputs with per-channel
+    // gains derived from the two volume ramps.
+    fn envmix_sample(
+        &mut self,
+        buffers: [u16; 4],
+        lanes: usize,
+        l_vol: i16,
+        r_vol: i16,
+        input: i16,
+    ) {
+        let gain =
+            |vol: i16, level: i16| clamp_s16((i32::from(vol) * i32::from(level) + 0x4000) >> 15);
+        let gains = [
+            gain(l_vol, self.dry),
+            gain(r_vol, self.dry),
+            gain(l_vol, self.wet),
+            gain(r_vol, self.wet),
+        ];
+        for i in 0..lanes {
+            let dst = self.sample(buffers[i]);
+            let mixed = i32::from(dst) + ((i32::from(input) * i32::from(gains[i])) >> 15);
+            self.set_sample(buffers[i], clamp_s16(mixed));
+        }
+    }
+
+    // ABI 1 envelope mixer: the ramps approach their targets exponentially,
+    // recomputing the step every 8 samples.
+    fn envmix_exp(&mut self, rdram: &mut [u8], flags: u8, address: u32) {
+        let lanes = if flags & A_AUX != 0 { 4 } else { 2 };
+        let mut ramps = [Ramp::default(); 2];
+        let mut rates = [0i32; 2];
+        let mut seq = [0i32; 2];
+        if flags & A_INIT != 0 {
+            for i in 0..2 {
+                ramps[i].value = i32::from(self.vol[i]) << 16;
+                ramps[i].target = i32::from(self.target[i]) << 16;
+                rates[i] = self.rate[i];
+                seq[i] = i32::from(self.vol[i]).wrapping_mul(self.rate[i]);
+            }
+        } else {
+            let state = Self::load_envmix_state(rdram, address);
+            let word =
+                |i: usize| i32::from_be_bytes([state[i], state[i + 1], state[i + 2], state[i + 3]]);
+            self.wet = i16::from_be_bytes([state[0], state[1]]);
+            self.dry = i16::from_be_bytes([state[4], state[5]]);
+            for i in 0..2 {
+                ramps[i].target = word(8 + i * 4);
+                rates[i] = word(16 + i * 4);
+                seq[i] = word(24 + i * 4);
+                ramps[i].value = word(32 + i * 4);
+            }
+        }
+        for ramp in ramps.iter_mut() {
+            ramp.step = ramp.target.wrapping_sub(ramp.value);
+        }
+
+        let mut ptr = 0;
+        for _ in 0..self.count.div_ceil(16) {
+            for i in 0..2 {
+                if ramps[i].step != 0 {
+                    seq[i] = ((i64::from(seq[i]) * i64::from(rates[i])) >> 16) as i32;
+                    ramps[i].step = seq[i].wrapping_sub(ramps[i].value) >> 3;
+                }
+            }
+            for _ in 0..8 {
+                let l_vol = ramps[0].step();
+                let r_vol = ramps[1].step();
+                let buffers = [
+                    self.output.wrapping_add(ptr),
+                    self.dry_right.wrapping_add(ptr),
+                    self.wet_left.wrapping_add(ptr),
+                    self.wet_right.wrapping_add(ptr),
+                ];
+                let input = self.sample(self.input.wrapping_add(ptr));
+                self.envmix_sample(buffers, lanes, l_vol, r_vol, input);
+                ptr += 2;
+            }
+        }
+
+        let mut state = [0u8; ENVMIX_STATE_SIZE as usize];
+        state[0..2].copy_from_slice(&self.wet.to_be_bytes());
+        state[4..6].copy_from_slice(&self.dry.to_be_bytes());
+        for i in 0..2 {
+            state[8 + i * 4..12 + i * 4].copy_from_slice(&ramps[i].target.to_be_bytes());
+            state[16 + i * 4..20 + i * 4].copy_from_slice(&rates[i].to_be_bytes());
+            state[24 + i * 4..28 + i * 4].copy_from_slice(&seq[i].to_be_bytes());
+            state[32 + i * 4..36 + i * 4].copy_from_slice(&ramps[i].value.to_be_bytes());
+        }
+        Self::store_envmix_state(rdram, address, &state);
+    }
+
+    // naudio envelope mixer: linear ramps into the four fixed output
+    // buffers.
+    fn envmix_lin(&mut self, rdram: &mut [u8], init: bool, address: u32) {
+        let mut ramps = [Ramp::default(); 2];
+        if init {
+            for (i, ramp) in ramps.iter_mut().enumerate() {
+                ramp.step = self.rate[i] / 8;
+                ramp.value = i32::from(self.vol[i]) << 16;
+                ramp.target = i32::from(self.target[i]) << 16;
+            }
+        } else {
+            let state = Self::load_envmix_state(rdram, address);
+            let word =
+                |i: usize| i32::from_be_bytes([state[i], state[i + 1], state[i + 2], state[i + 3]]);
+            self.wet = i16::from_be_bytes([state[0], state[1]]);
+            self.dry = i16::from_be_bytes([state[4], state[5]]);
+            for i in 0..2 {
+                ramps[i].target =
+                    i32::from(i16::from_be_bytes([state[8 + i * 4], state[9 + i * 4]])) << 16;
+                ramps[i].step = word(16 + i * 4);
+                ramps[i].value = word(32 + i * 4);
+            }
+        }
+
+        for k in 0..NAUDIO_COUNT / 2 {
+            let l_vol = ramps[0].step();
+            let r_vol = ramps[1].step();
+            let buffers = [
+                NAUDIO_DRY_LEFT + k * 2,
+                NAUDIO_DRY_RIGHT + k * 2,
+                NAUDIO_WET_LEFT + k * 2,
+                NAUDIO_WET_RIGHT + k * 2,
+            ];
+            let input = self.sample(NAUDIO_MAIN + k * 2);
+            self.envmix_sample(buffers, 4, l_vol, r_vol, input);
+        }
+
+        let mut state = [0u8; ENVMIX_STATE_SIZE as usize];
+        state[0..2].copy_from_slice(&self.wet.to_be_bytes());
+        state[4..6].copy_from_slice(&self.dry.to_be_bytes());
+        for i in 0..2 {
+            let target = (ramps[i].target >> 16) as i16;
+            state[8 + i * 4..10 + i * 4].copy_from_slice(&target.to_be_bytes());
+            state[16 + i * 4..20 + i * 4].copy_from_slice(&ramps[i].step.to_be_bytes());
+            state[32 + i * 4..36 + i * 4].copy_from_slice(&ramps[i].value.to_be_bytes());
+        }
+        Self::store_envmix_state(rdram, address, &state);
+    }
+}
+
+// Applies the second-order predictor from the codebook entry to 8 residual
+// samples.
+fn residuals(dst: &mut [i16], src: &[i16], cb: &[i16], history: [i16; 2]) {
+    let (book1, book2) = cb.split_at(8);
+    for i in 0..8 {
+        let mut accu = i32::from(src[i]) << 11;
+        accu += i32::from(book1[i]) * i32::from(history[0]);
+        accu += i32::from(book2[i]) * i32::from(history[1]);
+        for k in 0..i {
+            accu += i32::from(book2[k]) * i32::from(src[i - 1 - k]);
+        }
+        dst[i] = clamp_s16(accu >> 11);
+    }
+}
+
+// Catmull-Rom coefficients in 1.15 for one of 64 fractional positions
+// between the second and third taps.
+fn resample_taps(phase: u32) -> [i32; 4] {
+    let t = f64::from(phase) / 64.0;
+    let (t2, t3) = (t * t, t * t * t);
+    let taps = [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ];
+    taps.map(|c| (c * 32768.0).round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UCODE_DATA: u32 = 0x1000;
+    const ALIST: u32 = 0x2000;
+
+    fn write_u32(rdram: &mut [u8], addr: u32, value: u32) {
+        let i = addr as usize;
+        rdram[i..i + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn audio_task(rdram: &mut [u8], commands: &[(u32, u32)]) -> [u8; SP_MEM_SIZE] {
+        write_u32(rdram, UCODE_DATA, 1);
+        write_u32(rdram, UCODE_DATA + 0x28, 0x1E24_138C);
+        write_u32(rdram, UCODE_DATA + 0x30, 0xF000_0F00);
+        for (i, (w1, w2)) in commands.iter().enumerate() {
+            write_u32(rdram, ALIST + i as u32 * 8, *w1);
+            write_u32(rdram, ALIST + i as u32 * 8 + 4, *w2);
+        }
+        let mut dmem = [0u8; SP_MEM_SIZE];
+        let fields = [
+            (TASK_TYPE, M_AUDTASK),
+            (TASK_UCODE_DATA, UCODE_DATA),
+            (TASK_DATA_PTR, ALIST),
+            (TASK_DATA_SIZE, commands.len() as u32 * 8),
+        ];
+        for (offset, value) in fields.iter() {
+            let i = TASK_OFFSET + offset;
+            dmem[i..i + 4].copy_from_slice(&value.to_be_bytes());
+        }
+        dmem
+    }
+
+    #[test]
+    fn identifies_abi_from_ucode_data() {
+        let mut rdram = vec![0u8; 0x4000];
+        audio_task(&mut rdram, &[]);
+        assert_eq!(identify(&rdram, UCODE_DATA), Some(AudioAbi::Audio));
+        write_u32(&mut rdram, UCODE_DATA, 0);
+        write_u32(&mut rdram, UCODE_DATA + 0x10, 1);
+        assert_eq!(identify(&rdram, UCODE_DATA), Some(AudioAbi::MusyX));
+        assert!(!AudioAbi::MusyX.supported());
+        write_u32(&mut rdram, UCODE_DATA + 0x10, 0x127C);
+        assert_eq!(identify(&rdram, UCODE_DATA), Some(AudioAbi::Naudio));
+    }
+
+    #[test]
+    fn load_mix_interleave_save() {
+        let mut rdram = vec![0u8; 0x4000];
+        for i in 0..16u32 {
+            write_i16(&mut rdram, 0x3000 + i * 2, 0x1000);
+            write_i16(&mut rdram, 0x3020 + i * 2, -0x0800);
+        }
+        let mut dmem = audio_task(
+            &mut rdram,
+            &[
+                (0x0700_0000, 0x0100_3000), // SEGMENT 1 = 0x3000
+                (0x0800_0000, 0x0040_0020), // SETBUFF in 0, out 0x40, 32 bytes
+                (0x0400_0000, 0x0100_0000), // LOADBUFF from 1:0
+                (0x0800_0020, 0x0080_0020), // SETBUFF in 0x20, out 0x80
+                (0x0400_0000, 0x0100_0020), // LOADBUFF from 1:0x20
+                (0x0C00_4000, 0x0000_0020), // MIXER 0.5 * in 0 into 0x20
+                (0x0800_0000, 0x0100_0020), // SETBUFF out 0x100
+                (0x0D00_0000, 0x0000_0020), // INTERLEAVE 0 and 0x20
+                (0x0800_0000, 0x0100_0040), // SETBUFF out 0x100, 64 bytes
+                (0x0600_0000, 0x0100_0100), // SAVEBUFF to 1:0x100
+            ],
+        );
+        assert!(run_task(&mut dmem, &mut rdram));
+        assert_eq!(read_i16(&rdram, 0x3100), 0x1000);
+        assert_eq!(read_i16(&rdram, 0x3102), 0x0000);
+        assert_eq!(read_i16(&rdram, 0x313C), 0x1000);
+        assert_eq!(read_i16(&rdram, 0x313E), 0x0000);
+    }
+
+    #[test]
+    fn adpcm_with_flat_codebook() {
+        let mut rdram = vec![0u8; 0x4000];
+        // Scale 0 shifts each nibble down to its signed value; a zero
+        // codebook adds no prediction.
+        rdram[0x3000] = 0x00;
+        rdram[0x3001..0x3009].copy_from_slice(&[0x12, 0x34, 0x56, 0x7F, 0x89, 0xAB, 0xCD, 0xEF]);
+        let mut dmem = audio_task(
+            &mut rdram,
+            &[
+                (0x0800_0000, 0x0040_0020), // SETBUFF in 0, out 0x40, 32 bytes
+                (0x0400_0000, 0x0000_3000), // LOADBUFF
+                (0x0101_0000, 0x0000_3800), // ADPCM init, state at 0x3800
+            ],
+        );
+        assert!(run_task(&mut dmem, &mut rdram));
+        let out = |i: usize| {
+            let at = usize::from(AUDIO_DMEM_BASE) + 0x40 + 32 + i * 2;
+            i16::from_be_bytes([dmem[at], dmem[at + 1]])
+        };
+        assert_eq!([out(0), out(1), out(2), out(7)], [1, 2, 3, -1]);
+        assert_eq!([out(8), out(15)], [-8, -1]);
+        // The last frame is saved as the next task's history.
+        assert_eq!(read_i16(&rdram, 0x3800 + 15 * 2), -1);
+    }
+
+    #[test]
+    fn resample_at_unit_pitch_delays_by_the_history() {
+        let mut rdram = vec![0u8; 0x4000];
+        for i in 0..16u32 {
+            write_i16(&mut rdram, 0x3000 + i * 2, i as i16 + 1);
+        }
+        let commands = |flags: u32| {
+            [
+                (0x0800_0000, 0x0040_0020),         // SETBUFF in 0, out 0x40, 32 bytes
+                (0x0400_0000, 0x0000_3000),         // LOADBUFF
+                (0x0500_8000 | flags, 0x0000_3800), // RESAMPLE at pitch 1.0
+            ]
+        };
+        let out = |dmem: &[u8; SP_MEM_SIZE], i: usize| {
+            let at = usize::from(AUDIO_DMEM_BASE) + 0x40 + i * 2;
+            i16::from_be_bytes([dmem[at], dmem[at + 1]])
+        };
+
+        // At a whole position only the second tap counts, so the output
+        // lags the input by the three samples of history.
+        let mut dmem = audio_task(&mut rdram, &commands(0x0001_0000));
+        assert!(run_task(&mut dmem, &mut rdram));
+        assert_eq!([out(&dmem, 0), out(&dmem, 2), out(&dmem, 3)], [0, 0, 1]);
+        assert_eq!(out(&dmem, 15), 13);
+        let state: Vec<i16> = (0..5).map(|i| read_i16(&rdram, 0x3800 + i * 2)).collect();
+        assert_eq!(state, [13, 14, 15, 16, 0]);
+
+        // Without A_INIT the next task picks up from the saved history.
+        let mut dmem = audio_task(&mut rdram, &commands(0));
+        assert!(run_task(&mut dmem, &mut rdram));
+        let first: Vec<i16> = (0..4).map(|i| out(&dmem, i)).collect();
+        assert_eq!(first, [14, 15, 16, 1]);
+    }
+
+    #[test]
+    fn envmixer_applies_volume_and_dry_level() {
+        let mut rdram = vec![0u8; 0x4000];
+        for i in 0..16u32 {
+            write_i16(&mut rdram, 0x3000 + i * 2, 0x1000);
+        }
+        let commands = |flags: u32| {
+            [
+                (0x0906_4000, 0x0000_0000),         // SETVOL left volume 0.5
+                (0x0902_4000, 0x0001_0000),         // SETVOL left target 0.5
+                (0x0904_7FFF, 0x0000_0000),         // SETVOL right volume 1.0
+                (0x0900_7FFF, 0x0001_0000),         // SETVOL right target 1.0
+                (0x0908_4000, 0x0000_0000),         // SETVOL dry 0.5, wet 0
+                (0x0808_0080, 0x00C0_0100),         // SETBUFF dry right 0x80
+                (0x0800_0000, 0x0040_0020),         // SETBUFF in 0, out 0x40, 32 bytes
+                (0x0400_0000, 0x0000_3000),         // LOADBUFF
+                (0x0300_0000 | flags, 0x0000_3800), // ENVMIXER
+            ]
+        };
+        let out = |dmem: &[u8; SP_MEM_SIZE], buffer: usize, i: usize| {
+            let at = usize::from(AUDIO_DMEM_BASE) + buffer + i * 2;
+            i16::from_be_bytes([dmem[at], dmem[at + 1]])
+        };
+
+        let mut dmem = audio_task(&mut rdram, &commands(0x0001_0000));
+        assert!(run_task(&mut dmem, &mut rdram));
+        for i in 0..16 {
+            assert_eq!(out(&dmem, 0x40, i), 0x0400);
+            assert_eq!(out(&dmem, 0x80, i), 0x0800);
+        }
+        // Without an aux flag the wet buffers are untouched.
+        assert_eq!(out(&dmem, 0xC0, 0), 0);
+        assert_eq!(read_i16(&rdram, 0x3804), 0x4000);
+        assert_eq!(read_u32(&rdram, 0x3800 + 32), 0x4000_0000);
+
+        // A continuing task restores the levels and ramps from RDRAM, not
+        // from SETVOL.
+        let mut dmem = audio_task(&mut rdram, &commands(0));
+        // Zero the dry level SETVOL.
+        let at = ALIST as usize + 4 * 8;
+        rdram[at..at + 8].copy_from_slice(&[0x09, 0x08, 0, 0, 0, 0, 0, 0]);
+        assert!(run_task(&mut dmem, &mut rdram));
+        assert_eq!(out(&dmem, 0x40, 15), 0x0400);
+        assert_eq!(out(&dmem, 0x80, 15), 0x0800);
+    }
+
+    #[test]
+    fn nead_and_musyx_tasks_fall_back_to_the_rsp() {
+        let mut rdram = vec![0u8; 0x4000];
+        let mut dmem = audio_task(&mut rdram, &[(0x0200_0000, 0x0000_0010)]);
+        write_u32(&mut rdram, UCODE_DATA + 0x30, 0);
+        write_u32(&mut rdram, UCODE_DATA + 0x10, 0x1F68_1230);
+        assert_eq!(identify(&rdram, UCODE_DATA), Some(AudioAbi::Nead));
+        assert!(!run_task(&mut dmem, &mut rdram));
+        write_u32(&mut rdram, UCODE_DATA + 0x10, 0x0001_0010);
+        assert_eq!(identify(&rdram, UCODE_DATA), Some(AudioAbi::MusyX));
+        assert!(!run_task(&mut dmem, &mut rdram));
+    }
+
+    #[test]
+    fn other_tasks_are_left_to_the_rsp() {
+        let mut rdram = vec![0u8; 0x4000];
+        let mut dmem = audio_task(&mut rdram, &[]);
+        dmem[TASK_OFFSET + 3] = 1;
+        assert!(!run_task(&mut dmem, &mut rdram));
+    }
+}
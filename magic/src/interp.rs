@@ -0,0 +1,1374 @@
+use crate::cop0::*;
+use crate::decoder::{decode_vr4300, CpuInstrVR4300, CpuInstrVR4300::*};
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+use crate::{jit::Block, RDRAM_END};
+use crate::{
+    DataCache, DirtyLine, InstructionCache, InterpCPU32bit, MemoryBus, DCACHE_REFILL_DELAY,
+    DCACHE_WRITEBACK_DELAY, ICACHE_REFILL_DELAY, MEM_WORD_DELAY, MMU,
+};
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+use std::ffi::c_void;
+
+const FCR0_REVISION: u32 = 0x0000_0A00;
+pub(crate) const FCR31_CONDITION: u32 = 1 << 23;
+const FCR31_WRITE_MASK: u32 = 0x0183_FFFF;
+
+pub(crate) fn rs(iw: u32) -> usize {
+    ((iw >> 21) & 0x1F) as usize
+}
+
+pub(crate) fn rt(iw: u32) -> usize {
+    ((iw >> 16) & 0x1F) as usize
+}
+
+pub(crate) fn rd(iw: u32) -> usize {
+    ((iw >> 11) & 0x1F) as usize
+}
+
+pub(crate) fn sa(iw: u32) -> u32 {
+    (iw >> 6) & 0x1F
+}
+
+pub(crate) fn imm(iw: u32) -> u16 {
+    iw as u16
+}
+
+pub(crate) fn simm(iw: u32) -> u64 {
+    iw as u16 as i16 as i64 as u64
+}
+
+pub(crate) fn sext32(value: u32) -> u64 {
+    value as i32 as i64 as u64
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Access {
+    Load,
+    Store,
+}
+
+impl<MB, IC, DC, MM> InterpCPU32bit<MB, IC, DC, MM>
+where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
+    MM::AddressSize: From<u32>,
+{
+    // Executes a single instruction. Interrupts are sampled at the
+    // instruction boundary, before the instruction is fetched.
+    pub(crate) fn step_instruction(&mut self) {
+        self.sample_interrupts();
+        let stale_status = self.pipeline.as_mut().and_then(|p| p.stale_status());
+        let interrupt = match stale_status {
+            Some(status) => self.cop0.interrupt_pending_with(status),
+            None => self.cop0.interrupt_pending(),
+        };
+        if interrupt {
+            let (pc, delay_slot) = (self.pc, self.delay_slot);
+            self.take_exception(Exception::new(ExceptionCode::Interrupt), pc, delay_slot);
+            return;
+        }
+
+        let pc = self.pc;
+        let delay_slot = self.delay_slot;
+        self.pc = self.next_pc;
+        self.next_pc = self.pc.wrapping_add(4);
+        self.delay_slot = false;
+
+        let result = self.fetch(pc).and_then(|iw| {
+            if let Some(pipeline) = &mut self.pipeline {
+                self.stall += pipeline.issue(iw);
+            }
+            self.execute(iw, pc)
+        });
+        self.gpr[0] = 0;
+        if let Err(exception) = result {
+            self.take_exception(exception, pc, delay_slot);
+        }
+    }
+
+    // Brings the interrupt lines from the RCP and the COP0 timer into Cause.
+    // The timer stays raised until Compare is written.
+    pub(crate) fn sample_interrupts(&mut self) {
+        self.cop0
+            .set_interrupt(CAUSE_IP2, self.bus.interrupt_pending());
+        if self.bus.take_timer_interrupt() {
+            self.cop0.set_interrupt(CAUSE_IP7, true);
+            let now = self.bus.now();
+            self.bus.schedule_timer(self.cop0.compare_time(now));
+        }
+    }
+
+    // Runs the translated block at the PC, if there is one, and returns the
+    // cycles it took. Delay slots, pending interrupts and code outside
+    // cached RDRAM are left to the interpreter, as is everything while
+    // pipeline timing is on.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    pub(crate) fn step_jit(&mut self) -> Option<u64> {
+        if self.jit.is_none() || self.pipeline.is_some() || self.delay_slot {
+            return None;
+        }
+        let pc = self.pc;
+        let paddr = MM::translate(pc.into())?;
+        if pc & 3 != 0 || !MM::is_cached(pc.into()) || paddr > RDRAM_END {
+            return None;
+        }
+        self.sample_interrupts();
+        if self.cop0.interrupt_pending() {
+            return None;
+        }
+
+        let jit = self.jit.as_mut()?;
+        for page in self.bus.take_code_writes() {
+            jit.invalidate_page(page);
+        }
+        let bus = &mut self.bus;
+        let mut watched = Vec::new();
+        let block = jit.block(
+            pc,
+            paddr,
+            |addr| bus.read_word(addr),
+            |page| watched.push(page),
+        );
+        let differential = jit.differential;
+        for page in watched {
+            self.bus.watch_code_page(page);
+        }
+        let block = block?;
+        let len = if differential {
+            self.run_block_checked(block);
+            block.len
+        } else {
+            let cpu = self as *mut Self;
+            let result = unsafe {
+                block.run(
+                    (*cpu).gpr.as_mut_ptr(),
+                    cpu as *mut c_void,
+                    Self::jit_memory,
+                )
+            };
+            match result {
+                Ok(next) => {
+                    self.pc = next;
+                    self.next_pc = next.wrapping_add(4);
+                    block.len
+                }
+                // jit_memory has already moved the PC on.
+                Err(pc) => (pc.wrapping_sub(block.start) / 4 + 1) as usize,
+            }
+        };
+        Some(len as u64 + std::mem::take(&mut self.stall))
+    }
+
+    // Runs a load or store from translated code in the interpreter,
+    // stopping the block if it raised an exception or wrote to a page that
+    // was translated.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    extern "sysv64" fn jit_memory(ctx: *mut c_void, iw: u32, pc: u32) -> u32 {
+        let cpu = unsafe { &mut *(ctx as *mut Self) };
+        cpu.pc = pc.wrapping_add(4);
+        cpu.next_pc = pc.wrapping_add(8);
+        cpu.delay_slot = false;
+        if let Err(exception) = cpu.execute(iw, pc) {
+            cpu.take_exception(exception, pc, false);
+            return 1;
+        }
+        cpu.gpr[0] = 0;
+        let writes = cpu.bus.take_code_writes();
+        if writes.is_empty() {
+            return 0;
+        }
+        if let Some(jit) = &mut cpu.jit {
+            for page in writes {
+                jit.invalidate_page(page);
+            }
+        }
+        1
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn run_block_checked(&mut self, block: Block) {
+        // Differential blocks have no loads or stores, so the callback is
+        // never made.
+        let mut native = self.gpr;
+        let cpu = self as *mut Self as *mut c_void;
+        let native_pc = unsafe { block.run(native.as_mut_ptr(), cpu, Self::jit_memory) }
+            .expect("Differential JIT block stopped early");
+        for i in 0..block.len {
+            let pc = self.pc;
+            self.pc = self.next_pc;
+            self.next_pc = self.pc.wrapping_add(4);
+            self.delay_slot = false;
+            let iw = self.bus.read_word(block.paddr + i as u32 * 4);
+            if let Err(exception) = self.execute(iw, pc) {
+                panic!(
+                    "JIT block at {:#010X} raised {:?} in the interpreter",
+                    block.start, exception.code
+                );
+            }
+            self.gpr[0] = 0;
+        }
+        if self.gpr != native || self.pc != native_pc {
+            let mut diffs: Vec<String> = (0..32)
+                .filter(|&r| self.gpr[r] != native[r])
+                .map(|r| format!("r{}: {:#X} vs {:#X}", r, self.gpr[r], native[r]))
+                .collect();
+            if self.pc != native_pc {
+                diffs.push(format!("pc: {:#010X} vs {:#010X}", self.pc, native_pc));
+            }
+            panic!(
+                "JIT block at {:#010X} disagrees with the interpreter: {}",
+                block.start,
+                diffs.join(", ")
+            );
+        }
+    }
+
+    pub(crate) fn take_exception(&mut self, exception: Exception, pc: u32, delay_slot: bool) {
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.flush();
+        }
+        let vector = self.cop0.enter_exception(exception, pc, delay_slot);
+        self.pc = vector;
+        self.next_pc = vector.wrapping_add(4);
+        self.delay_slot = false;
+    }
+
+    fn translate(&self, vaddr: u32, access: Access) -> Result<u32, Exception> {
+        MM::translate(vaddr.into()).ok_or_else(|| {
+            let code = match access {
+                Access::Load => ExceptionCode::TlbLoad,
+                Access::Store => ExceptionCode::TlbStore,
+            };
+            Exception::address(code, vaddr)
+        })
+    }
+
+    pub(crate) fn check_alignment(vaddr: u32, size: u32, access: Access) -> Result<(), Exception> {
+        if vaddr & (size - 1) == 0 {
+            return Ok(());
+        }
+        let code = match access {
+            Access::Load => ExceptionCode::AddressErrorLoad,
+            Access::Store => ExceptionCode::AddressErrorStore,
+        };
+        Err(Exception::address(code, vaddr))
+    }
+
+    fn fetch(&mut self, pc: u32) -> Result<u32, Exception> {
+        Self::check_alignment(pc, 4, Access::Load)?;
+        let paddr = self.translate(pc, Access::Load)?;
+        if !MM::is_cached(pc.into()) {
+            self.stall += MEM_WORD_DELAY as u64;
+            return Ok(self.bus.read_word(paddr));
+        }
+        if let Some(iw) = self.icache.fetch(pc.into(), paddr.into()) {
+            return Ok(iw);
+        }
+        let line = self.refill_icache(pc, paddr);
+        Ok(line[(paddr >> 2) as usize & 7])
+    }
+
+    fn refill_icache(&mut self, vaddr: u32, paddr: u32) -> [u32; 8] {
+        let base = paddr & !31;
+        let mut line = [0; 8];
+        for (i, word) in line.iter_mut().enumerate() {
+            *word = self.bus.read_word(base + i as u32 * 4);
+        }
+        self.icache.fill(vaddr.into(), paddr.into(), line);
+        self.stall += ICACHE_REFILL_DELAY;
+        line
+    }
+
+    // The CACHE instruction. Index operations use the virtual address as
+    // it stands; hit operations translate it first.
+    fn cache(&mut self, op: u32, vaddr: u32) -> Result<(), Exception> {
+        const ICACHE: u32 = 0;
+        const DCACHE: u32 = 1;
+        match op & 0x3 {
+            ICACHE => self.icache_op(op >> 2, vaddr),
+            DCACHE => self.dcache_op(op >> 2, vaddr),
+            _ => Ok(()),
+        }
+    }
+
+    fn icache_op(&mut self, op: u32, vaddr: u32) -> Result<(), Exception> {
+        match op {
+            0 => self.icache.invalidate_index(vaddr.into()),
+            1 => self.cop0.regs[COP0_TAG_LO] = u64::from(self.icache.load_tag(vaddr.into())),
+            2 => {
+                let tag_lo = self.cop0.regs[COP0_TAG_LO] as u32;
+                self.icache.store_tag(vaddr.into(), tag_lo);
+            }
+            4 => {
+                let paddr = self.translate(vaddr, Access::Load)?;
+                self.icache.invalidate_hit(vaddr.into(), paddr.into());
+            }
+            5 => {
+                let paddr = self.translate(vaddr, Access::Load)?;
+                self.refill_icache(vaddr, paddr);
+            }
+            6 => {
+                let paddr = self.translate(vaddr, Access::Load)?;
+                if let Some(line) = self.icache.hit_line(vaddr.into(), paddr.into()) {
+                    for (i, &word) in line.iter().enumerate() {
+                        self.bus.write_word((paddr & !31) + i as u32 * 4, word);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn dcache_op(&mut self, op: u32, vaddr: u32) -> Result<(), Exception> {
+        let evicted = match op {
+            0 => self.dcache.writeback_invalidate_index(vaddr.into()),
+            1 => {
+                self.cop0.regs[COP0_TAG_LO] = u64::from(self.dcache.load_tag(vaddr.into()));
+                None
+            }
+            2 => {
+                let tag_lo = self.cop0.regs[COP0_TAG_LO] as u32;
+                self.dcache.store_tag(vaddr.into(), tag_lo);
+                None
+            }
+            3 => {
+                let paddr = self.translate(vaddr, Access::Store)?;
+                self.dcache.create_dirty_exclusive(paddr.into())
+            }
+            4 => {
+                let paddr = self.translate(vaddr, Access::Load)?;
+                self.dcache.invalidate_hit(paddr.into());
+                None
+            }
+            5 => {
+                let paddr = self.translate(vaddr, Access::Load)?;
+                self.dcache.writeback_invalidate_hit(paddr.into())
+            }
+            6 => {
+                let paddr = self.translate(vaddr, Access::Load)?;
+                self.dcache.writeback_hit(paddr.into())
+            }
+            _ => None,
+        };
+        self.write_back(evicted);
+        Ok(())
+    }
+
+    fn write_back(&mut self, line: Option<DirtyLine>) {
+        if let Some((base, data)) = line {
+            for (i, &word) in data.iter().enumerate() {
+                self.bus.write_word(base + i as u32 * 4, word);
+            }
+            self.stall += DCACHE_WRITEBACK_DELAY;
+        }
+    }
+
+    // Makes sure the line for `paddr` is in the data cache, writing back
+    // whatever it replaces.
+    fn refill_dcache(&mut self, paddr: u32) {
+        if self.dcache.read(paddr.into()).is_some() {
+            return;
+        }
+        let base = paddr & !15;
+        let mut line = [0; 4];
+        for (i, word) in line.iter_mut().enumerate() {
+            *word = self.bus.read_word(base + i as u32 * 4);
+        }
+        let evicted = self.dcache.fill(paddr.into(), line);
+        self.write_back(evicted);
+        self.stall += DCACHE_REFILL_DELAY;
+    }
+
+    // Cached accesses go through the data cache, which allocates on both
+    // loads and stores; kseg1 goes straight to the bus.
+    fn read_data(&mut self, vaddr: u32, paddr: u32) -> u32 {
+        if !MM::is_cached(vaddr.into()) {
+            return self.bus.read_word(paddr);
+        }
+        self.refill_dcache(paddr);
+        self.dcache.read(paddr.into()).unwrap_or(0)
+    }
+
+    fn write_data(&mut self, vaddr: u32, paddr: u32, value: u32, mask: u32) {
+        if MM::is_cached(vaddr.into()) {
+            self.refill_dcache(paddr);
+            self.dcache.write(paddr.into(), value, mask);
+            return;
+        }
+        let value = if mask == 0xFFFF_FFFF {
+            value
+        } else {
+            (self.bus.read_word(paddr) & !mask) | (value & mask)
+        };
+        self.bus.write_word(paddr, value);
+    }
+
+    pub(crate) fn load_word(&mut self, vaddr: u32) -> Result<u32, Exception> {
+        let paddr = self.translate(vaddr, Access::Load)? & !3;
+        Ok(self.read_data(vaddr, paddr))
+    }
+
+    fn load_dword(&mut self, vaddr: u32) -> Result<u64, Exception> {
+        let paddr = self.translate(vaddr, Access::Load)? & !7;
+        let hi = self.read_data(vaddr, paddr);
+        let lo = self.read_data(vaddr, paddr + 4);
+        Ok(u64::from(hi) << 32 | u64::from(lo))
+    }
+
+    pub(crate) fn store_word(
+        &mut self,
+        vaddr: u32,
+        value: u32,
+        mask: u32,
+    ) -> Result<(), Exception> {
+        let paddr = self.translate(vaddr, Access::Store)? & !3;
+        self.write_data(vaddr, paddr, value, mask);
+        Ok(())
+    }
+
+    fn store_dword(&mut self, vaddr: u32, value: u64, mask: u64) -> Result<(), Exception> {
+        self.store_word(vaddr & !7, (value >> 32) as u32, (mask >> 32) as u32)?;
+        self.store_word((vaddr & !7) + 4, value as u32, mask as u32)
+    }
+
+    pub(crate) fn branch(&mut self, condition: bool, target: u32) {
+        if condition {
+            self.next_pc = target;
+        }
+        self.delay_slot = true;
+    }
+
+    // Branch likely instructions nullify the delay slot when not taken.
+    fn branch_likely(&mut self, condition: bool, target: u32) {
+        if condition {
+            self.next_pc = target;
+            self.delay_slot = true;
+        } else {
+            self.pc = self.next_pc;
+            self.next_pc = self.pc.wrapping_add(4);
+        }
+    }
+
+    fn branch_target(pc: u32, iw: u32) -> u32 {
+        pc.wrapping_add(4).wrapping_add((simm(iw) << 2) as u32)
+    }
+
+    fn link(&mut self, reg: usize, pc: u32) {
+        self.gpr[reg] = sext32(pc.wrapping_add(8));
+    }
+
+    fn trap(condition: bool) -> Result<(), Exception> {
+        if condition {
+            Err(Exception::new(ExceptionCode::Trap))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn cop1_usable(&self) -> Result<(), Exception> {
+        if self.cop0.status() & STATUS_CU1 != 0 {
+            Ok(())
+        } else {
+            Err(Exception::coprocessor_unusable(1))
+        }
+    }
+
+    // With Status.FR clear the FPU has 16 64-bit registers, with odd
+    // register numbers addressing the upper half of the even register.
+    pub(crate) fn fpr_read_word(&self, reg: usize) -> u32 {
+        if self.cop0.status() & STATUS_FR != 0 || reg & 1 == 0 {
+            self.fpr[reg] as u32
+        } else {
+            (self.fpr[reg & !1] >> 32) as u32
+        }
+    }
+
+    pub(crate) fn fpr_write_word(&mut self, reg: usize, value: u32) {
+        if self.cop0.status() & STATUS_FR != 0 || reg & 1 == 0 {
+            self.fpr[reg] = (self.fpr[reg] & !0xFFFF_FFFF) | u64::from(value);
+        } else {
+            let even = &mut self.fpr[reg & !1];
+            *even = (*even & 0xFFFF_FFFF) | (u64::from(value) << 32);
+        }
+    }
+
+    pub(crate) fn fpr_index_dword(&self, reg: usize) -> usize {
+        if self.cop0.status() & STATUS_FR != 0 {
+            reg
+        } else {
+            reg & !1
+        }
+    }
+
+    pub(crate) fn execute(&mut self, iw: u32, pc: u32) -> Result<(), Exception> {
+        // With the FPU disabled, COP1 raises Coprocessor Unusable even for
+        // encodings the decoder considers invalid.
+        if iw >> 26 == 0x11 {
+            self.cop1_usable()?;
+        }
+        self.execute_decoded(decode_vr4300(iw), iw, pc)
+    }
+
+    // Executes an instruction that has already been decoded, for callers
+    // that keep the decoded form around.
+    pub(crate) fn execute_decoded(
+        &mut self,
+        instr: CpuInstrVR4300,
+        iw: u32,
+        pc: u32,
+    ) -> Result<(), Exception> {
+        let (s, t, d) = (rs(iw), rt(iw), rd(iw));
+        let addr = (self.gpr[s] as u32).wrapping_add(simm(iw) as u32);
+        match instr {
+            Invalid => return Err(Exception::new(ExceptionCode::ReservedInstruction)),
+
+            // Jumps and branches
+            J => self.branch(
+                true,
+                (pc.wrapping_add(4) & 0xF000_0000) | ((iw & 0x03FF_FFFF) << 2),
+            ),
+            JAL => {
+                self.link(31, pc);
+                self.branch(
+                    true,
+                    (pc.wrapping_add(4) & 0xF000_0000) | ((iw & 0x03FF_FFFF) << 2),
+                );
+            }
+            JR => {
+                let target = self.gpr[s] as u32;
+                self.branch(true, target);
+            }
+            JALR => {
+                let target = self.gpr[s] as u32;
+                self.link(d, pc);
+                self.branch(true, target);
+            }
+            BEQ => self.branch(self.gpr[s] == self.gpr[t], Self::branch_target(pc, iw)),
+            BNE => self.branch(self.gpr[s] != self.gpr[t], Self::branch_target(pc, iw)),
+            BLEZ => self.branch(self.gpr[s] as i64 <= 0, Self::branch_target(pc, iw)),
+            BGTZ => self.branch(self.gpr[s] as i64 > 0, Self::branch_target(pc, iw)),
+            BLTZ => self.branch((self.gpr[s] as i64) < 0, Self::branch_target(pc, iw)),
+            BGEZ => self.branch(self.gpr[s] as i64 >= 0, Self::branch_target(pc, iw)),
+            BLTZAL => {
+                let condition = (self.gpr[s] as i64) < 0;
+                self.link(31, pc);
+                self.branch(condition, Self::branch_target(pc, iw));
+            }
+            BGEZAL => {
+                let condition = self.gpr[s] as i64 >= 0;
+                self.link(31, pc);
+                self.branch(condition, Self::branch_target(pc, iw));
+            }
+            BEQL => self.branch_likely(self.gpr[s] == self.gpr[t], Self::branch_target(pc, iw)),
+            BNEL => self.branch_likely(self.gpr[s] != self.gpr[t], Self::branch_target(pc, iw)),
+            BLEZL => self.branch_likely(self.gpr[s] as i64 <= 0, Self::branch_target(pc, iw)),
+            BGTZL => self.branch_likely(self.gpr[s] as i64 > 0, Self::branch_target(pc, iw)),
+            BLTZL => self.branch_likely((self.gpr[s] as i64) < 0, Self::branch_target(pc, iw)),
+            BGEZL => self.branch_likely(self.gpr[s] as i64 >= 0, Self::branch_target(pc, iw)),
+            BLTZALL => {
+                let condition = (self.gpr[s] as i64) < 0;
+                self.link(31, pc);
+                self.branch_likely(condition, Self::branch_target(pc, iw));
+            }
+            BGEZALL => {
+                let condition = self.gpr[s] as i64 >= 0;
+                self.link(31, pc);
+                self.branch_likely(condition, Self::branch_target(pc, iw));
+            }
+
+            // Immediate arithmetic
+            ADDI => match (self.gpr[s] as i32).checked_add(simm(iw) as i32) {
+                Some(result) => self.gpr[t] = result as i64 as u64,
+                None => return Err(Exception::new(ExceptionCode::Overflow)),
+            },
+            ADDIU => self.gpr[t] = sext32((self.gpr[s] as u32).wrapping_add(simm(iw) as u32)),
+            DADDI => match (self.gpr[s] as i64).checked_add(simm(iw) as i64) {
+                Some(result) => self.gpr[t] = result as u64,
+                None => return Err(Exception::new(ExceptionCode::Overflow)),
+            },
+            DADDIU => self.gpr[t] = self.gpr[s].wrapping_add(simm(iw)),
+            SLTI => self.gpr[t] = ((self.gpr[s] as i64) < simm(iw) as i64) as u64,
+            SLTIU => self.gpr[t] = (self.gpr[s] < simm(iw)) as u64,
+            ANDI => self.gpr[t] = self.gpr[s] & u64::from(imm(iw)),
+            ORI => self.gpr[t] = self.gpr[s] | u64::from(imm(iw)),
+            XORI => self.gpr[t] = self.gpr[s] ^ u64::from(imm(iw)),
+            LUI => self.gpr[t] = sext32(u32::from(imm(iw)) << 16),
+
+            // Register arithmetic
+            ADD => match (self.gpr[s] as i32).checked_add(self.gpr[t] as i32) {
+                Some(result) => self.gpr[d] = result as i64 as u64,
+                None => return Err(Exception::new(ExceptionCode::Overflow)),
+            },
+            ADDU => self.gpr[d] = sext32((self.gpr[s] as u32).wrapping_add(self.gpr[t] as u32)),
+            SUB => match (self.gpr[s] as i32).checked_sub(self.gpr[t] as i32) {
+                Some(result) => self.gpr[d] = result as i64 as u64,
+                None => return Err(Exception::new(ExceptionCode::Overflow)),
+            },
+            SUBU => self.gpr[d] = sext32((self.gpr[s] as u32).wrapping_sub(self.gpr[t] as u32)),
+            DADD => match (self.gpr[s] as i64).checked_add(self.gpr[t] as i64) {
+                Some(result) => self.gpr[d] = result as u64,
+                None => return Err(Exception::new(ExceptionCode::Overflow)),
+            },
+            DADDU => self.gpr[d] = self.gpr[s].wrapping_add(self.gpr[t]),
+            DSUB => match (self.gpr[s] as i64).checked_sub(self.gpr[t] as i64) {
+                Some(result) => self.gpr[d] = result as u64,
+                None => return Err(Exception::new(ExceptionCode::Overflow)),
+            },
+            DSUBU => self.gpr[d] = self.gpr[s].wrapping_sub(self.gpr[t]),
+            AND => self.gpr[d] = self.gpr[s] & self.gpr[t],
+            OR => self.gpr[d] = self.gpr[s] | self.gpr[t],
+            XOR => self.gpr[d] = self.gpr[s] ^ self.gpr[t],
+            NOR => self.gpr[d] = !(self.gpr[s] | self.gpr[t]),
+            SLT => self.gpr[d] = ((self.gpr[s] as i64) < self.gpr[t] as i64) as u64,
+            SLTU => self.gpr[d] = (self.gpr[s] < self.gpr[t]) as u64,
+
+            // Shifts
+            SLL => self.gpr[d] = sext32((self.gpr[t] as u32) << sa(iw)),
+            SRL => self.gpr[d] = sext32((self.gpr[t] as u32) >> sa(iw)),
+            SRA => self.gpr[d] = sext32(((self.gpr[t] as i64) >> sa(iw)) as u32),
+            SLLV => self.gpr[d] = sext32((self.gpr[t] as u32) << (self.gpr[s] & 0x1F)),
+            SRLV => self.gpr[d] = sext32((self.gpr[t] as u32) >> (self.gpr[s] & 0x1F)),
+            SRAV => self.gpr[d] = sext32(((self.gpr[t] as i64) >> (self.gpr[s] & 0x1F)) as u32),
+            DSLL => self.gpr[d] = self.gpr[t] << sa(iw),
+            DSRL => self.gpr[d] = self.gpr[t] >> sa(iw),
+            DSRA => self.gpr[d] = ((self.gpr[t] as i64) >> sa(iw)) as u64,
+            DSLL32 => self.gpr[d] = self.gpr[t] << (sa(iw) + 32),
+            DSRL32 => self.gpr[d] = self.gpr[t] >> (sa(iw) + 32),
+            DSRA32 => self.gpr[d] = ((self.gpr[t] as i64) >> (sa(iw) + 32)) as u64,
+            DSLLV => self.gpr[d] = self.gpr[t] << (self.gpr[s] & 0x3F),
+            DSRLV => self.gpr[d] = self.gpr[t] >> (self.gpr[s] & 0x3F),
+            DSRAV => self.gpr[d] = ((self.gpr[t] as i64) >> (self.gpr[s] & 0x3F)) as u64,
+
+            // Multiply and divide
+            MFHI => self.gpr[d] = self.hi,
+            MTHI => self.hi = self.gpr[s],
+            MFLO => self.gpr[d] = self.lo,
+            MTLO => self.lo = self.gpr[s],
+            MULT => {
+                let result = (self.gpr[s] as i32 as i64) * (self.gpr[t] as i32 as i64);
+                self.lo = sext32(result as u32);
+                self.hi = sext32((result >> 32) as u32);
+            }
+            MULTU => {
+                let result = u64::from(self.gpr[s] as u32) * u64::from(self.gpr[t] as u32);
+                self.lo = sext32(result as u32);
+                self.hi = sext32((result >> 32) as u32);
+            }
+            DIV => {
+                let (n, m) = (self.gpr[s] as i32, self.gpr[t] as i32);
+                if m == 0 {
+                    self.lo = if n < 0 { 1 } else { u64::MAX };
+                    self.hi = n as i64 as u64;
+                } else {
+                    self.lo = n.wrapping_div(m) as i64 as u64;
+                    self.hi = n.wrapping_rem(m) as i64 as u64;
+                }
+            }
+            DIVU => {
+                let (n, m) = (self.gpr[s] as u32, self.gpr[t] as u32);
+                match n.checked_div(m) {
+                    Some(quotient) => {
+                        self.lo = sext32(quotient);
+                        self.hi = sext32(n % m);
+                    }
+                    None => {
+                        self.lo = u64::MAX;
+                        self.hi = sext32(n);
+                    }
+                }
+            }
+            DMULT => {
+                let result = i128::from(self.gpr[s] as i64) * i128::from(self.gpr[t] as i64);
+                self.lo = result as u64;
+                self.hi = (result >> 64) as u64;
+            }
+            DMULTU => {
+                let result = u128::from(self.gpr[s]) * u128::from(self.gpr[t]);
+                self.lo = result as u64;
+                self.hi = (result >> 64) as u64;
+            }
+            DDIV => {
+                let (n, m) = (self.gpr[s] as i64, self.gpr[t] as i64);
+                if m == 0 {
+                    self.lo = if n < 0 { 1 } else { u64::MAX };
+                    self.hi = n as u64;
+                } else {
+                    self.lo = n.wrapping_div(m) as u64;
+                    self.hi = n.wrapping_rem(m) as u64;
+                }
+            }
+            DDIVU => {
+                let (n, m) = (self.gpr[s], self.gpr[t]);
+                match n.checked_div(m) {
+                    Some(quotient) => {
+                        self.lo = quotient;
+                        self.hi = n % m;
+                    }
+                    None => {
+                        self.lo = u64::MAX;
+                        self.hi = n;
+                    }
+                }
+            }
+
+            // Loads
+            LB => {
+                let word = self.load_word(addr)?;
+                self.gpr[t] = (word >> ((3 - (addr & 3)) * 8)) as u8 as i8 as i64 as u64;
+            }
+            LBU => {
+                let word = self.load_word(addr)?;
+                self.gpr[t] = u64::from((word >> ((3 - (addr & 3)) * 8)) as u8);
+            }
+            LH => {
+                Self::check_alignment(addr, 2, Access::Load)?;
+                let word = self.load_word(addr)?;
+                self.gpr[t] = (word >> ((2 - (addr & 2)) * 8)) as u16 as i16 as i64 as u64;
+            }
+            LHU => {
+                Self::check_alignment(addr, 2, Access::Load)?;
+                let word = self.load_word(addr)?;
+                self.gpr[t] = u64::from((word >> ((2 - (addr & 2)) * 8)) as u16);
+            }
+            LW => {
+                Self::check_alignment(addr, 4, Access::Load)?;
+                self.gpr[t] = sext32(self.load_word(addr)?);
+            }
+            LWU => {
+                Self::check_alignment(addr, 4, Access::Load)?;
+                self.gpr[t] = u64::from(self.load_word(addr)?);
+            }
+            LD => {
+                Self::check_alignment(addr, 8, Access::Load)?;
+                self.gpr[t] = self.load_dword(addr)?;
+            }
+            LWL => {
+                let shift = (addr & 3) * 8;
+                let word = self.load_word(addr)?;
+                let old = self.gpr[t] as u32;
+                self.gpr[t] = sext32((old & !(0xFFFF_FFFF << shift)) | (word << shift));
+            }
+            LWR => {
+                let shift = (3 - (addr & 3)) * 8;
+                let word = self.load_word(addr)?;
+                let old = self.gpr[t] as u32;
+                let merged = (old & !(0xFFFF_FFFF >> shift)) | (word >> shift);
+                self.gpr[t] = if shift == 0 {
+                    sext32(merged)
+                } else {
+                    (self.gpr[t] & !0xFFFF_FFFF) | u64::from(merged)
+                };
+            }
+            LDL => {
+                let shift = (addr & 7) * 8;
+                let dword = self.load_dword(addr)?;
+                let old = self.gpr[t];
+                self.gpr[t] = (old & !(u64::MAX << shift)) | (dword << shift);
+            }
+            LDR => {
+                let shift = (7 - (addr & 7)) * 8;
+                let dword = self.load_dword(addr)?;
+                let old = self.gpr[t];
+                self.gpr[t] = (old & !(u64::MAX >> shift)) | (dword >> shift);
+            }
+            LL => {
+                Self::check_alignment(addr, 4, Access::Load)?;
+                let paddr = self.translate(addr, Access::Load)?;
+                self.gpr[t] = sext32(self.load_word(addr)?);
+                self.cop0.regs[COP0_LLADDR] = u64::from(paddr >> 4);
+                self.llbit = true;
+            }
+            LLD => {
+                Self::check_alignment(addr, 8, Access::Load)?;
+                let paddr = self.translate(addr, Access::Load)?;
+                self.gpr[t] = self.load_dword(addr)?;
+                self.cop0.regs[COP0_LLADDR] = u64::from(paddr >> 4);
+                self.llbit = true;
+            }
+
+            // Stores
+            SB => {
+                let shift = (3 - (addr & 3)) * 8;
+                self.store_word(addr, (self.gpr[t] as u32) << shift, 0xFF << shift)?;
+            }
+            SH => {
+                Self::check_alignment(addr, 2, Access::Store)?;
+                let shift = (2 - (addr & 2)) * 8;
+                self.store_word(addr, (self.gpr[t] as u32) << shift, 0xFFFF << shift)?;
+            }
+            SW => {
+                Self::check_alignment(addr, 4, Access::Store)?;
+                self.store_word(addr, self.gpr[t] as u32, 0xFFFF_FFFF)?;
+            }
+            SD => {
+                Self::check_alignment(addr, 8, Access::Store)?;
+                self.store_dword(addr, self.gpr[t], u64::MAX)?;
+            }
+            SWL => {
+                let shift = (addr & 3) * 8;
+                self.store_word(addr, (self.gpr[t] as u32) >> shift, 0xFFFF_FFFF >> shift)?;
+            }
+            SWR => {
+                let shift = (3 - (addr & 3)) * 8;
+                self.store_word(addr, (self.gpr[t] as u32) << shift, 0xFFFF_FFFF << shift)?;
+            }
+            SDL => {
+                let shift = (addr & 7) * 8;
+                self.store_dword(addr, self.gpr[t] >> shift, u64::MAX >> shift)?;
+            }
+            SDR => {
+                let shift = (7 - (addr & 7)) * 8;
+                self.store_dword(addr, self.gpr[t] << shift, u64::MAX << shift)?;
+            }
+            SC => {
+                Self::check_alignment(addr, 4, Access::Store)?;
+                if self.llbit {
+                    self.store_word(addr, self.gpr[t] as u32, 0xFFFF_FFFF)?;
+                }
+                self.gpr[t] = self.llbit as u64;
+            }
+            SCD => {
+                Self::check_alignment(addr, 8, Access::Store)?;
+                if self.llbit {
+                    self.store_dword(addr, self.gpr[t], u64::MAX)?;
+                }
+                self.gpr[t] = self.llbit as u64;
+            }
+
+            // Traps and system calls
+            SYSCALL => return Err(Exception::new(ExceptionCode::Syscall)),
+            BREAK => return Err(Exception::new(ExceptionCode::Breakpoint)),
+            TGE => Self::trap(self.gpr[s] as i64 >= self.gpr[t] as i64)?,
+            TGEU => Self::trap(self.gpr[s] >= self.gpr[t])?,
+            TLT => Self::trap((self.gpr[s] as i64) < self.gpr[t] as i64)?,
+            TLTU => Self::trap(self.gpr[s] < self.gpr[t])?,
+            TEQ => Self::trap(self.gpr[s] == self.gpr[t])?,
+            TNE => Self::trap(self.gpr[s] != self.gpr[t])?,
+            TGEI => Self::trap(self.gpr[s] as i64 >= simm(iw) as i64)?,
+            TGEIU => Self::trap(self.gpr[s] >= simm(iw))?,
+            TLTI => Self::trap((self.gpr[s] as i64) < simm(iw) as i64)?,
+            TLTIU => Self::trap(self.gpr[s] < simm(iw))?,
+            TEQI => Self::trap(self.gpr[s] == simm(iw))?,
+            TNEI => Self::trap(self.gpr[s] != simm(iw))?,
+            SYNC => {}
+            CACHE => self.cache(t as u32, addr)?,
+
+            // System control coprocessor
+            MFC0 | DMFC0 => {
+                let value = match d {
+                    COP0_COUNT => u64::from(self.cop0.count(self.bus.now())),
+                    _ => self.cop0.read(d),
+                };
+                self.gpr[t] = match instr {
+                    MFC0 => sext32(value as u32),
+                    _ => value,
+                };
+            }
+            MTC0 | DMTC0 => {
+                if let (COP0_STATUS, Some(pipeline)) = (d, &mut self.pipeline) {
+                    pipeline.status_write(self.cop0.status());
+                }
+                let value = match instr {
+                    MTC0 => sext32(self.gpr[t] as u32),
+                    _ => self.gpr[t],
+                };
+                let now = self.bus.now();
+                if d == COP0_COUNT {
+                    self.cop0.set_count(now, value as u32);
+                }
+                self.cop0.write(d, value);
+                if d == COP0_COUNT || d == COP0_COMPARE {
+                    self.bus.schedule_timer(self.cop0.compare_time(now));
+                }
+            }
+            ERET => {
+                let target = self.cop0.exception_return();
+                self.pc = target;
+                self.next_pc = target.wrapping_add(4);
+                self.llbit = false;
+            }
+            // TLB emulation is out of scope for now: every mapped access
+            // misses, a probe never finds a match, and reading or writing an
+            // entry is deliberately a no-op until there are entries to hold.
+            TLBP => self.cop0.regs[COP0_INDEX] |= 1 << 31,
+            TLBR | TLBWI | TLBWR => {}
+            // The COP0 condition input is tied low on the N64.
+            BC0F => self.branch(true, Self::branch_target(pc, iw)),
+            BC0T => self.branch(false, Self::branch_target(pc, iw)),
+            BC0FL => self.branch_likely(true, Self::branch_target(pc, iw)),
+            BC0TL => self.branch_likely(false, Self::branch_target(pc, iw)),
+
+            // Floating point moves, loads and stores
+            MFC1 => self.gpr[t] = sext32(self.fpr_read_word(d)),
+            MTC1 => self.fpr_write_word(d, self.gpr[t] as u32),
+            DMFC1 => self.gpr[t] = self.fpr[self.fpr_index_dword(d)],
+            DMTC1 => {
+                let index = self.fpr_index_dword(d);
+                self.fpr[index] = self.gpr[t];
+            }
+            CFC1 => {
+                self.gpr[t] = match d {
+                    0 => sext32(FCR0_REVISION),
+                    31 => sext32(self.fcr31),
+                    _ => 0,
+                }
+            }
+            CTC1 => {
+                if d == 31 {
+                    self.fcr31 = self.gpr[t] as u32 & FCR31_WRITE_MASK;
+                    if self.fpu_exception_pending() {
+                        return Err(Exception::new(ExceptionCode::FloatingPoint));
+                    }
+                }
+            }
+            LWC1 => {
+                self.cop1_usable()?;
+                Self::check_alignment(addr, 4, Access::Load)?;
+                let word = self.load_word(addr)?;
+                self.fpr_write_word(t, word);
+            }
+            LDC1 => {
+                self.cop1_usable()?;
+                Self::check_alignment(addr, 8, Access::Load)?;
+                let dword = self.load_dword(addr)?;
+                let index = self.fpr_index_dword(t);
+                self.fpr[index] = dword;
+            }
+            SWC1 => {
+                self.cop1_usable()?;
+                Self::check_alignment(addr, 4, Access::Store)?;
+                self.store_word(addr, self.fpr_read_word(t), 0xFFFF_FFFF)?;
+            }
+            SDC1 => {
+                self.cop1_usable()?;
+                Self::check_alignment(addr, 8, Access::Store)?;
+                let dword = self.fpr[self.fpr_index_dword(t)];
+                self.store_dword(addr, dword, u64::MAX)?;
+            }
+            BC1F => self.branch(
+                self.fcr31 & FCR31_CONDITION == 0,
+                Self::branch_target(pc, iw),
+            ),
+            BC1T => self.branch(
+                self.fcr31 & FCR31_CONDITION != 0,
+                Self::branch_target(pc, iw),
+            ),
+            BC1FL => self.branch_likely(
+                self.fcr31 & FCR31_CONDITION == 0,
+                Self::branch_target(pc, iw),
+            ),
+            BC1TL => self.branch_likely(
+                self.fcr31 & FCR31_CONDITION != 0,
+                Self::branch_target(pc, iw),
+            ),
+
+            // Floating point arithmetic, conversions and compares
+            _ if iw >> 26 == 0x11 => self.execute_fpu(iw)?,
+            _ => return Err(Exception::new(ExceptionCode::ReservedInstruction)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mi::MiInterrupt;
+    use crate::scheduler::Event;
+    use crate::{DCache, ICache, MMU32Bit, Memory, CPU};
+
+    fn i_type(op: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+        op << 26 | rs << 21 | rt << 16 | u32::from(imm)
+    }
+
+    fn r_type(rs: u32, rt: u32, rd: u32, sa: u32, funct: u32) -> u32 {
+        rs << 21 | rt << 16 | rd << 11 | sa << 6 | funct
+    }
+
+    fn cpu_with_program(program: &[u32]) -> InterpCPU32bit<Memory, ICache, DCache, MMU32Bit> {
+        let mut pifrom: Vec<u8> = program.iter().flat_map(|iw| iw.to_be_bytes()).collect();
+        pifrom.resize(2048, 0);
+        InterpCPU32bit::new(&mut pifrom.as_slice())
+    }
+
+    #[test]
+    fn branch_delay_slot() {
+        let mut cpu = cpu_with_program(&[
+            i_type(0x09, 0, 8, 5),     // addiu t0, zero, 5
+            i_type(0x04, 0, 0, 2),     // beq zero, zero, +2
+            i_type(0x09, 8, 8, 1),     // addiu t0, t0, 1 (delay slot)
+            i_type(0x09, 0, 8, 0x100), // addiu t0, zero, 0x100 (skipped)
+            r_type(8, 8, 9, 0, 0x21),  // addu t1, t0, t0
+            i_type(0x15, 0, 0, 2),     // bnel zero, zero, +2
+            i_type(0x09, 0, 9, 0x100), // addiu t1, zero, 0x100 (nullified)
+            r_type(0, 9, 10, 2, 0x00), // sll t2, t1, 2
+        ]);
+        for _ in 0..6 {
+            cpu.step();
+        }
+        assert_eq!(cpu.gpr[8], 6);
+        assert_eq!(cpu.gpr[9], 12);
+        assert_eq!(cpu.gpr[10], 48);
+        assert_eq!(cpu.pc, 0xBFC0_0020);
+    }
+
+    #[test]
+    fn overflow_exception() {
+        let mut cpu = cpu_with_program(&[
+            i_type(0x0F, 0, 8, 0x7FFF), // lui t0, 0x7FFF
+            i_type(0x0D, 8, 8, 0xFFFF), // ori t0, t0, 0xFFFF
+            i_type(0x08, 8, 9, 1),      // addi t1, t0, 1
+        ]);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(cpu.gpr[9], 0);
+        assert_eq!(cpu.pc, 0xBFC0_0380);
+        assert_eq!(cpu.cop0.read(COP0_EPC) as u32, 0xBFC0_0008);
+        assert_eq!(
+            (cpu.cop0.read(COP0_CAUSE) & CAUSE_EXC_CODE) >> 2,
+            ExceptionCode::Overflow as u64
+        );
+    }
+
+    #[test]
+    fn interrupt_taken_at_instruction_boundary() {
+        let mut cpu = cpu_with_program(&[
+            i_type(0x0F, 0, 8, 0x0040),  // lui t0, 0x0040 (BEV)
+            i_type(0x0D, 8, 8, 0x0401),  // ori t0, t0, IM2 | IE
+            0x4088_6000,                 // mtc0 t0, Status
+            i_type(0x0F, 0, 9, 0xA430),  // lui t1, 0xA430
+            i_type(0x09, 0, 10, 0x0200), // addiu t2, zero, set PI mask
+            i_type(0x2B, 9, 10, 0x000C), // sw t2, MI_INTR_MASK(t1)
+            0x0000_0000,                 // nop
+            0x0000_0000,                 // nop
+        ]);
+        for _ in 0..6 {
+            cpu.step();
+        }
+        assert_eq!(cpu.pc, 0xBFC0_0018);
+        cpu.bus.mi.raise(MiInterrupt::Pi);
+        cpu.step();
+        assert_eq!(cpu.pc, 0xBFC0_0380);
+        assert_eq!(cpu.cop0.read(COP0_EPC) as u32, 0xBFC0_0018);
+        let cause = cpu.cop0.read(COP0_CAUSE);
+        assert_eq!(cause & CAUSE_EXC_CODE, 0);
+        assert_ne!(cause & CAUSE_IP2, 0);
+        assert_ne!(cpu.cop0.status() & STATUS_EXL, 0);
+    }
+
+    #[test]
+    fn timer_interrupt_from_compare() {
+        let mut cpu = cpu_with_program(&[
+            i_type(0x0D, 0, 8, 100), // ori t0, zero, 100
+            0x4088_5800,             // mtc0 t0, Compare
+        ]);
+        cpu.cop0
+            .write(COP0_STATUS, STATUS_BEV | CAUSE_IP7 | STATUS_IE);
+        let mut steps = 0;
+        while cpu.pc != 0xBFC0_0380 {
+            cpu.step();
+            steps += 1;
+            assert!(steps < 10);
+        }
+        assert_ne!(cpu.cop0.read(COP0_CAUSE) & CAUSE_IP7, 0);
+        let now = cpu.bus.scheduler().now();
+        assert!(cpu.cop0.count(now) >= 100);
+        assert_eq!(u64::from(cpu.cop0.count(now)), now / 2);
+    }
+
+    #[test]
+    fn count_and_compare_writes_reschedule_the_timer() {
+        let mut cpu = cpu_with_program(&[
+            i_type(0x0D, 0, 8, 1000), // ori t0, zero, 1000
+            0x4088_5800,              // mtc0 t0, Compare
+            i_type(0x0D, 0, 8, 600),  // ori t0, zero, 600
+            0x4088_4800,              // mtc0 t0, Count
+        ]);
+        // The event lands on the first cycle at which Count equals Compare.
+        let fires_on_compare = |cpu: &InterpCPU32bit<Memory, ICache, DCache, MMU32Bit>| {
+            let time = cpu.bus.scheduler().time_of(Event::CompareMatch).unwrap();
+            cpu.cop0.count(time) == 1000 && cpu.cop0.count(time - 1) == 999
+        };
+        cpu.step();
+        cpu.step();
+        assert!(fires_on_compare(&cpu));
+        let before = cpu.bus.scheduler().time_of(Event::CompareMatch);
+        cpu.step();
+        cpu.step();
+        assert!(cpu.cop0.count(cpu.bus.scheduler().now()) >= 600);
+        assert!(fires_on_compare(&cpu));
+        assert!(cpu.bus.scheduler().time_of(Event::CompareMatch) < before);
+        assert_eq!(cpu.cop0.read(COP0_CAUSE) & CAUSE_IP7, 0);
+    }
+
+    #[test]
+    fn pipeline_interlocks_add_cycles() {
+        let program = [
+            i_type(0x0F, 0, 4, 0xA000), // lui a0, 0xA000
+            i_type(0x23, 4, 8, 0),      // lw t0, 0(a0)
+            i_type(0x09, 8, 9, 1),      // addiu t1, t0, 1
+            r_type(8, 9, 0, 0, 0x18),   // mult t0, t1
+            r_type(0, 0, 10, 0, 0x12),  // mflo t2
+        ];
+        // Run from the icache, as uncached fetches hide the interlocks.
+        let mut fast = cpu_with_program(&[]);
+        let mut pipelined = cpu_with_program(&[]);
+        pipelined.set_pipelined(true);
+        for cpu in [&mut fast, &mut pipelined].iter_mut() {
+            for (i, &iw) in program.iter().enumerate() {
+                cpu.bus.write_word(0x1000 + i as u32 * 4, iw);
+            }
+            cpu.bus.write_word(0, 6);
+            jump(cpu, 0x8000_1000);
+            for _ in 0..program.len() {
+                cpu.step();
+            }
+        }
+        assert_eq!(pipelined.gpr[10], 42);
+        let extra = pipelined.bus.scheduler().now() - fast.bus.scheduler().now();
+        assert_eq!(extra, 1 + 4);
+    }
+
+    #[test]
+    fn pipeline_delays_status_writes_to_interrupts() {
+        let program = [
+            i_type(0x0F, 0, 8, 0x0040), // lui t0, 0x0040 (BEV)
+            i_type(0x0D, 8, 8, 0x0101), // ori t0, t0, IM0 | IE
+            0x4088_6000,                // mtc0 t0, Status
+            0x0000_0000,                // nop
+            0x0000_0000,                // nop
+            0x0000_0000,                // nop
+        ];
+        for &(pipelined, taken_at) in &[(false, 0xBFC0_000C), (true, 0xBFC0_0014)] {
+            let mut cpu = cpu_with_program(&program);
+            cpu.set_pipelined(pipelined);
+            cpu.cop0.write(COP0_CAUSE, 1 << 8);
+            let mut steps = 0;
+            while cpu.pc != 0xBFC0_0380 {
+                cpu.step();
+                steps += 1;
+                assert!(steps <= program.len());
+            }
+            assert_eq!(cpu.cop0.read(COP0_EPC) as u32, taken_at);
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn jit_matches_interpreter_and_sees_new_code() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.enable_jit(true);
+        let program = [
+            i_type(0x09, 0, 8, 10),     // addiu t0, zero, 10
+            i_type(0x09, 8, 8, 0xFFFF), // addiu t0, t0, -1
+            r_type(9, 8, 9, 0, 0x21),   // addu t1, t1, t0
+            i_type(0x05, 8, 0, 0xFFFD), // bnez t0, -3
+            r_type(0, 9, 10, 1, 0x00),  // sll t2, t1, 1 (delay slot)
+            i_type(0x2B, 0, 10, 0x10),  // sw t2, 0x10(zero)
+        ];
+        for (i, &iw) in program.iter().enumerate() {
+            cpu.bus.write_word(0x1000 + i as u32 * 4, iw);
+        }
+        jump(&mut cpu, 0x8000_1000);
+        while cpu.pc != 0x8000_1014 {
+            cpu.step();
+        }
+        assert_eq!(cpu.gpr[9], 45);
+        assert_eq!(cpu.gpr[10], 90);
+
+        // Rewriting the code drops the blocks translated from it.
+        cpu.bus.write_word(0x1000, i_type(0x09, 0, 8, 1)); // addiu t0, zero, 1
+        cpu.gpr[9] = 0;
+        jump(&mut cpu, 0x8000_1000);
+        while cpu.pc != 0x8000_1014 {
+            cpu.step();
+        }
+        assert_eq!(cpu.gpr[9], 0);
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn jit_loads_and_stores_stop_where_they_must() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.enable_jit(false);
+        let program = [
+            i_type(0x0F, 0, 11, 0xA000),  // lui t3, 0xA000
+            i_type(0x23, 11, 8, 0x1100),  // lw t0, 0x1100(t3)
+            i_type(0x2B, 11, 8, 0x1010),  // sw t0, 0x1010(t3)
+            i_type(0x09, 0, 9, 7),        // addiu t1, zero, 7
+            i_type(0x09, 0, 10, 1),       // addiu t2, zero, 1
+            i_type(0x2B, 11, 9, 0x1104),  // sw t1, 0x1104(t3)
+            i_type(0x23, 11, 12, 0x1104), // lw t4, 0x1104(t3)
+            i_type(0x23, 11, 13, 0x1102), // lw t5, 0x1102(t3)
+        ];
+        for (i, &iw) in program.iter().enumerate() {
+            cpu.bus.write_word(0x1000 + i as u32 * 4, iw);
+        }
+        cpu.bus.write_word(0x1100, i_type(0x09, 0, 10, 2)); // addiu t2, zero, 2
+        jump(&mut cpu, 0x8000_1000);
+
+        // The store over translated code ends the block, so the rewritten
+        // instruction is the one that runs.
+        cpu.step();
+        assert_eq!(cpu.pc, 0x8000_100C);
+        while cpu.pc & 0xFFFF_F000 == 0x8000_1000 {
+            cpu.step();
+        }
+        assert_eq!(cpu.gpr[10], 2);
+        assert_eq!(cpu.gpr[12], 7);
+        assert_eq!(cpu.gpr[13], 0);
+        assert_eq!(cpu.pc, 0xBFC0_0380);
+        assert_eq!(cpu.cop0.read(COP0_EPC) as u32, 0x8000_101C);
+        assert_eq!(
+            (cpu.cop0.read(COP0_CAUSE) & CAUSE_EXC_CODE) >> 2,
+            ExceptionCode::AddressErrorLoad as u64
+        );
+    }
+
+    fn jump(cpu: &mut InterpCPU32bit<Memory, ICache, DCache, MMU32Bit>, addr: u32) {
+        cpu.pc = addr;
+        cpu.next_pc = addr + 4;
+    }
+
+    #[test]
+    fn icache_serves_stale_code_until_invalidated() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.bus.write_word(0x1000, i_type(0x09, 0, 8, 1)); // addiu t0, zero, 1
+        jump(&mut cpu, 0x8000_1000);
+        cpu.step();
+        assert_eq!(cpu.gpr[8], 1);
+        let refill = cpu.bus.scheduler().now();
+
+        // Code changed behind the cache's back is not seen through kseg0,
+        // but is through kseg1.
+        cpu.bus.write_word(0x1000, i_type(0x09, 0, 8, 2)); // addiu t0, zero, 2
+        jump(&mut cpu, 0x8000_1000);
+        cpu.step();
+        assert_eq!(cpu.gpr[8], 1);
+        assert_eq!(cpu.bus.scheduler().now() - refill, 1);
+        jump(&mut cpu, 0xA000_1000);
+        cpu.step();
+        assert_eq!(cpu.gpr[8], 2);
+
+        // cache Hit_Invalidate (I), 0(t1)
+        cpu.gpr[9] = 0xFFFF_FFFF_8000_1000;
+        cpu.bus.write_word(0x2000, i_type(0x2F, 9, 0x10, 0));
+        jump(&mut cpu, 0xA000_2000);
+        cpu.step();
+        jump(&mut cpu, 0x8000_1000);
+        cpu.step();
+        assert_eq!(cpu.gpr[8], 2);
+    }
+
+    #[test]
+    fn icache_tags_through_tag_lo() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.gpr[9] = 0xFFFF_FFFF_8000_1040;
+        cpu.bus.write_word(0x1044, 0x1234_5678);
+        let program = [
+            i_type(0x2F, 9, 0x14, 0), // cache Fill (I), 0(t1)
+            i_type(0x2F, 9, 0x04, 0), // cache Index_Load_Tag (I), 0(t1)
+            i_type(0x2F, 9, 0x00, 0), // cache Index_Invalidate (I), 0(t1)
+        ];
+        for (i, &iw) in program.iter().enumerate() {
+            cpu.bus.write_word(0x2000 + i as u32 * 4, iw);
+        }
+        jump(&mut cpu, 0xA000_2000);
+        cpu.step();
+        let line = cpu.icache().line(0x8000_1040);
+        assert!(line.valid());
+        assert_eq!(line.tag(), 0x00001);
+        assert_eq!(line.data()[1], 0x1234_5678);
+        cpu.step();
+        assert_eq!(cpu.cop0.read(COP0_TAG_LO), (0x00001 << 8) | 0x80);
+        cpu.step();
+        assert!(!cpu.icache().line(0x8000_1040).valid());
+
+        // Index_Store_Tag makes the line valid for whatever tag is given.
+        cpu.cop0.write(COP0_TAG_LO, (0x00005 << 8) | 0x80);
+        cpu.bus.write_word(0x2000, i_type(0x2F, 9, 0x08, 0));
+        jump(&mut cpu, 0xA000_2000);
+        cpu.step();
+        assert_eq!(cpu.icache().line(0x8000_1040).tag(), 0x00005);
+        assert_eq!(cpu.icache.fetch(0x8000_1044, 0x1044), None);
+        assert_eq!(cpu.icache.fetch(0x8000_1044, 0x5044), Some(0x1234_5678));
+    }
+
+    fn run_uncached(cpu: &mut InterpCPU32bit<Memory, ICache, DCache, MMU32Bit>, program: &[u32]) {
+        for (i, &iw) in program.iter().enumerate() {
+            cpu.bus.write_word(0x2000 + i as u32 * 4, iw);
+        }
+        jump(cpu, 0xA000_2000);
+        for _ in program {
+            cpu.step();
+        }
+    }
+
+    #[test]
+    fn dcache_is_not_coherent_with_memory() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.gpr[9] = 0xFFFF_FFFF_8000_3000;
+        cpu.gpr[10] = 0x1234;
+        // Stores through kseg0 stay in the cache until written back.
+        run_uncached(&mut cpu, &[i_type(0x2B, 9, 10, 0)]); // sw t2, 0(t1)
+        assert_eq!(cpu.bus.read_word(0x3000), 0);
+        assert_eq!(cpu.dcache.read(0x3000), Some(0x1234));
+        run_uncached(&mut cpu, &[i_type(0x2F, 9, 0x19, 0)]); // cache Hit_Writeback (D)
+        assert_eq!(cpu.bus.read_word(0x3000), 0x1234);
+
+        // Memory written behind the cache's back, as by DMA, isn't seen
+        // until the line is invalidated.
+        cpu.bus.write_word(0x3000, 0x5678);
+        run_uncached(&mut cpu, &[i_type(0x23, 9, 11, 0)]); // lw t3, 0(t1)
+        assert_eq!(cpu.gpr[11], 0x1234);
+        run_uncached(
+            &mut cpu,
+            &[
+                i_type(0x2F, 9, 0x11, 0), // cache Hit_Invalidate (D), 0(t1)
+                i_type(0x23, 9, 11, 0),   // lw t3, 0(t1)
+            ],
+        );
+        assert_eq!(cpu.gpr[11], 0x5678);
+    }
+
+    #[test]
+    fn dcache_writes_back_dirty_lines_on_eviction() {
+        let mut cpu = cpu_with_program(&[]);
+        cpu.gpr[9] = 0xFFFF_FFFF_8000_3000;
+        cpu.gpr[10] = 0x1234;
+        cpu.bus.write_word(0x5008, 0x9ABC);
+        run_uncached(
+            &mut cpu,
+            &[
+                i_type(0x29, 9, 10, 2),   // sh t2, 2(t1)
+                i_type(0x2F, 9, 0x05, 0), // cache Index_Load_Tag (D), 0(t1)
+            ],
+        );
+        assert_eq!(cpu.cop0.read(COP0_TAG_LO), (0x00003 << 8) | 0xC0);
+
+        // 0x5000 shares the line's index, so loading it evicts 0x3000.
+        run_uncached(&mut cpu, &[i_type(0x23, 9, 11, 0x2008)]); // lw t3, 0x2008(t1)
+        assert_eq!(cpu.gpr[11], 0x9ABC);
+        assert_eq!(cpu.bus.read_word(0x3000), 0x1234);
+        assert_eq!(cpu.dcache.read(0x3000), None);
+
+        // Create_Dirty_Exclusive claims a line without reading memory, and
+        // Index_Writeback_Invalidate flushes it.
+        run_uncached(
+            &mut cpu,
+            &[
+                i_type(0x2F, 9, 0x0D, 0), // cache Create_Dirty_Exclusive (D), 0(t1)
+                i_type(0x2B, 9, 10, 4),   // sw t2, 4(t1)
+                i_type(0x2F, 9, 0x01, 0), // cache Index_Writeback_Invalidate (D), 0(t1)
+            ],
+        );
+        assert_eq!(cpu.bus.read_word(0x3004), 0x1234);
+        assert_eq!(cpu.bus.read_word(0x3000), 0);
+    }
+}
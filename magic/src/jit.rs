@@ -0,0 +1,702 @@
+use crate::decoder::{decode_vr4300, CpuInstrVR4300, CpuInstrVR4300::*};
+use crate::CODE_PAGE_SHIFT;
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+// Runs a load or store for translated code: given the context the block was
+// run with, the instruction and its PC, it returns nonzero to stop the block.
+pub type MemoryFn = extern "sysv64" fn(ctx: *mut c_void, iw: u32, pc: u32) -> u32;
+
+// A translated block takes the GPRs and the memory callback and returns the
+// PC to continue at, with bit 32 set if a load or store stopped it there.
+type BlockFn = extern "sysv64" fn(gpr: *mut u64, ctx: *mut c_void, memory: MemoryFn) -> u64;
+
+const CODE_BUFFER_SIZE: usize = 8 << 20;
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+// x86-64 registers the translated code uses. RBX holds the GPR array
+// throughout, RAX and RCX hold operands, and RDX holds the branch target
+// across a delay slot. RBP and R12 keep the memory context and callback,
+// since like RBX they survive calls.
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RBX: u8 = 3;
+
+// Memory that blocks are copied into. Pages are only writable while a
+// block is being copied in, and only executable otherwise.
+struct CodeBuffer {
+    base: *mut u8,
+    used: usize,
+    page_size: usize,
+}
+
+impl CodeBuffer {
+    fn new() -> Self {
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                CODE_BUFFER_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            panic!(
+                "Couldn't map JIT code buffer: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        CodeBuffer {
+            base: base as *mut u8,
+            used: 0,
+            page_size: unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize,
+        }
+    }
+
+    fn push(&mut self, code: &[u8]) -> Option<BlockFn> {
+        if self.used + code.len() > CODE_BUFFER_SIZE {
+            return None;
+        }
+        let start = self.used & !(self.page_size - 1);
+        let len = self.used + code.len() - start;
+        self.protect(start, len, libc::PROT_READ | libc::PROT_WRITE);
+        let dest = unsafe {
+            let dest = self.base.add(self.used);
+            std::ptr::copy_nonoverlapping(code.as_ptr(), dest, code.len());
+            dest
+        };
+        self.protect(start, len, libc::PROT_READ | libc::PROT_EXEC);
+        self.used += code.len();
+        Some(unsafe { std::mem::transmute::<*mut u8, BlockFn>(dest) })
+    }
+
+    fn protect(&self, start: usize, len: usize, prot: libc::c_int) {
+        let result =
+            unsafe { libc::mprotect(self.base.add(start) as *mut libc::c_void, len, prot) };
+        if result != 0 {
+            panic!(
+                "Couldn't protect JIT code buffer: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, CODE_BUFFER_SIZE);
+        }
+    }
+}
+
+// Just enough of an x86-64 assembler for the translations below.
+#[derive(Default)]
+struct Emitter {
+    code: Vec<u8>,
+}
+
+impl Emitter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn imm32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // mov reg, [rbx + gpr * 8]
+    fn load(&mut self, reg: u8, gpr: usize) {
+        self.bytes(&[0x48, 0x8B, 0x80 | reg << 3 | RBX]);
+        self.imm32(gpr as u32 * 8);
+    }
+
+    // mov [rbx + gpr * 8], rax
+    fn store(&mut self, gpr: usize) {
+        if gpr != 0 {
+            self.bytes(&[0x48, 0x89, 0x80 | RAX << 3 | RBX]);
+            self.imm32(gpr as u32 * 8);
+        }
+    }
+
+    // mov reg, imm32 sign-extended to 64 bits
+    fn mov_imm(&mut self, reg: u8, value: u32) {
+        self.bytes(&[0x48, 0xC7, 0xC0 | reg]);
+        self.imm32(value);
+    }
+
+    // movsxd rax, eax
+    fn sext32(&mut self) {
+        self.bytes(&[0x48, 0x63, 0xC0]);
+    }
+
+    // setcc al; movzx eax, al
+    fn set(&mut self, cc: u8) {
+        self.bytes(&[0x0F, 0x90 | cc, 0xC0, 0x0F, 0xB6, 0xC0]);
+    }
+
+    // push rbx; push rbp; push r12; mov rbx, rdi; mov rbp, rsi; mov r12, rdx
+    fn prologue(&mut self) {
+        self.bytes(&[0x53, 0x55, 0x41, 0x54]);
+        self.bytes(&[0x48, 0x89, 0xFB, 0x48, 0x89, 0xF5, 0x49, 0x89, 0xD4]);
+    }
+
+    // pop r12; pop rbp; pop rbx; ret
+    fn epilogue(&mut self) {
+        self.bytes(&[0x41, 0x5C, 0x5D, 0x5B, 0xC3]);
+    }
+
+    fn ret_pc(&mut self, pc: u32) {
+        self.bytes(&[0xB8]);
+        self.imm32(pc);
+        self.epilogue();
+    }
+}
+
+// Condition codes, as the low nibble of SETcc and CMOVcc.
+const CC_B: u8 = 0x2;
+const CC_E: u8 = 0x4;
+const CC_NE: u8 = 0x5;
+const CC_L: u8 = 0xC;
+const CC_GE: u8 = 0xD;
+const CC_LE: u8 = 0xE;
+const CC_G: u8 = 0xF;
+
+fn rs(iw: u32) -> usize {
+    ((iw >> 21) & 0x1F) as usize
+}
+
+fn rt(iw: u32) -> usize {
+    ((iw >> 16) & 0x1F) as usize
+}
+
+fn rd(iw: u32) -> usize {
+    ((iw >> 11) & 0x1F) as usize
+}
+
+fn sa(iw: u32) -> u8 {
+    ((iw >> 6) & 0x1F) as u8
+}
+
+fn simm(iw: u32) -> u32 {
+    iw as u16 as i16 as i32 as u32
+}
+
+// Translates an instruction that only touches the GPRs, returning false
+// for anything the interpreter has to run.
+fn emit_simple(e: &mut Emitter, instr: CpuInstrVR4300, iw: u32) -> bool {
+    let (s, t, d) = (rs(iw), rt(iw), rd(iw));
+    // Immediate forms: rax = rs op imm, into rt.
+    let imm_op: Option<&[u8]> = match instr {
+        ADDIU => Some(&[0x05]),
+        DADDIU => Some(&[0x48, 0x05]),
+        ANDI => Some(&[0x48, 0x25]),
+        ORI => Some(&[0x48, 0x0D]),
+        XORI => Some(&[0x48, 0x35]),
+        SLTI | SLTIU => Some(&[0x48, 0x3D]),
+        _ => None,
+    };
+    if let Some(op) = imm_op {
+        let imm = match instr {
+            ANDI | ORI | XORI => iw & 0xFFFF,
+            _ => simm(iw),
+        };
+        e.load(RAX, s);
+        e.bytes(op);
+        e.imm32(imm);
+        match instr {
+            ADDIU => e.sext32(),
+            SLTI => e.set(CC_L),
+            SLTIU => e.set(CC_B),
+            _ => {}
+        }
+        e.store(t);
+        return true;
+    }
+    // Register forms: rax = rs op rcx (rt), into rd.
+    let reg_op: Option<&[u8]> = match instr {
+        ADDU => Some(&[0x01, 0xC8]),
+        SUBU => Some(&[0x29, 0xC8]),
+        DADDU => Some(&[0x48, 0x01, 0xC8]),
+        DSUBU => Some(&[0x48, 0x29, 0xC8]),
+        AND => Some(&[0x48, 0x21, 0xC8]),
+        OR | NOR => Some(&[0x48, 0x09, 0xC8]),
+        XOR => Some(&[0x48, 0x31, 0xC8]),
+        SLT | SLTU => Some(&[0x48, 0x39, 0xC8]),
+        _ => None,
+    };
+    if let Some(op) = reg_op {
+        e.load(RAX, s);
+        e.load(RCX, t);
+        e.bytes(op);
+        match instr {
+            ADDU | SUBU => e.sext32(),
+            NOR => e.bytes(&[0x48, 0xF7, 0xD0]),
+            SLT => e.set(CC_L),
+            SLTU => e.set(CC_B),
+            _ => {}
+        }
+        e.store(d);
+        return true;
+    }
+    // Shifts by a constant: rax = rt shifted, into rd. The 32-bit right
+    // shifts work on all 64 bits first, as the interpreter does for SRA.
+    let shift: Option<(&[u8], u8, bool)> = match instr {
+        SLL => Some((&[0xC1, 0xE0], sa(iw), true)),
+        SRL => Some((&[0xC1, 0xE8], sa(iw), true)),
+        SRA => Some((&[0x48, 0xC1, 0xF8], sa(iw), true)),
+        DSLL => Some((&[0x48, 0xC1, 0xE0], sa(iw), false)),
+        DSRL => Some((&[0x48, 0xC1, 0xE8], sa(iw), false)),
+        DSRA => Some((&[0x48, 0xC1, 0xF8], sa(iw), false)),
+        DSLL32 => Some((&[0x48, 0xC1, 0xE0], sa(iw) + 32, false)),
+        DSRL32 => Some((&[0x48, 0xC1, 0xE8], sa(iw) + 32, false)),
+        DSRA32 => Some((&[0x48, 0xC1, 0xF8], sa(iw) + 32, false)),
+        _ => None,
+    };
+    if let Some((op, amount, word)) = shift {
+        e.load(RAX, t);
+        e.bytes(op);
+        e.bytes(&[amount]);
+        if word {
+            e.sext32();
+        }
+        e.store(d);
+        return true;
+    }
+    // Shifts by rs: rax = rt shifted by cl, into rd.
+    let shift_var: Option<(&[u8], bool)> = match instr {
+        SLLV => Some((&[0xD3, 0xE0], true)),
+        SRLV => Some((&[0xD3, 0xE8], true)),
+        SRAV => Some((&[0x48, 0xD3, 0xF8], true)),
+        DSLLV => Some((&[0x48, 0xD3, 0xE0], false)),
+        DSRLV => Some((&[0x48, 0xD3, 0xE8], false)),
+        DSRAV => Some((&[0x48, 0xD3, 0xF8], false)),
+        _ => None,
+    };
+    if let Some((op, word)) = shift_var {
+        e.load(RAX, t);
+        e.load(RCX, s);
+        if word {
+            // and ecx, 31
+            e.bytes(&[0x83, 0xE1, 0x1F]);
+        }
+        e.bytes(op);
+        if word {
+            e.sext32();
+        }
+        e.store(d);
+        return true;
+    }
+    match instr {
+        LUI => {
+            e.mov_imm(RAX, (iw & 0xFFFF) << 16);
+            e.store(t);
+            true
+        }
+        SYNC => true,
+        _ => false,
+    }
+}
+
+// Loads and stores are handed to the memory callback, which runs them in
+// the interpreter. The block stops at the instruction if it raised an
+// exception or wrote to translated code.
+fn emit_memory(e: &mut Emitter, instr: CpuInstrVR4300, iw: u32, pc: u32) -> bool {
+    match instr {
+        LB | LBU | LH | LHU | LW | LWU | LD | SB | SH | SW | SD => {}
+        _ => return false,
+    }
+    // mov rdi, rbp; mov esi, iw; mov edx, pc; call r12
+    e.bytes(&[0x48, 0x89, 0xEF, 0xBE]);
+    e.imm32(iw);
+    e.bytes(&[0xBA]);
+    e.imm32(pc);
+    e.bytes(&[0x41, 0xFF, 0xD4]);
+    // test eax, eax; jz over the exit
+    let mut exit = Emitter::default();
+    // mov rax, 1 << 32 | pc
+    exit.bytes(&[0x48, 0xB8]);
+    exit.bytes(&(1 << 32 | u64::from(pc)).to_le_bytes());
+    exit.epilogue();
+    e.bytes(&[0x85, 0xC0, 0x74, exit.code.len() as u8]);
+    e.bytes(&exit.code);
+    true
+}
+
+// Translates a branch or jump up to the point of its delay slot, leaving
+// the PC to continue at in EDX.
+fn emit_branch(e: &mut Emitter, instr: CpuInstrVR4300, iw: u32, pc: u32) -> bool {
+    let (s, t) = (rs(iw), rt(iw));
+    let target = pc.wrapping_add(4).wrapping_add(simm(iw) << 2);
+    let jump = (pc.wrapping_add(4) & 0xF000_0000) | ((iw & 0x03FF_FFFF) << 2);
+    let link = pc.wrapping_add(8);
+    let condition = match instr {
+        BEQ => Some(CC_E),
+        BNE => Some(CC_NE),
+        BLEZ => Some(CC_LE),
+        BGTZ => Some(CC_G),
+        BLTZ => Some(CC_L),
+        BGEZ => Some(CC_GE),
+        _ => None,
+    };
+    if let Some(cc) = condition {
+        e.load(RAX, s);
+        match instr {
+            BEQ | BNE => {
+                e.load(RCX, t);
+                // cmp rax, rcx
+                e.bytes(&[0x48, 0x39, 0xC8]);
+            }
+            // test rax, rax
+            _ => e.bytes(&[0x48, 0x85, 0xC0]),
+        }
+        // mov edx, link; mov ecx, target; cmovcc edx, ecx
+        e.bytes(&[0xBA]);
+        e.imm32(link);
+        e.bytes(&[0xB9]);
+        e.imm32(target);
+        e.bytes(&[0x0F, 0x40 | cc, 0xD1]);
+        return true;
+    }
+    match instr {
+        J | JAL => {
+            e.bytes(&[0xBA]);
+            e.imm32(jump);
+            if instr == JAL {
+                e.mov_imm(RAX, link);
+                e.store(31);
+            }
+            true
+        }
+        JR | JALR => {
+            // mov edx, [rbx + rs * 8]
+            e.bytes(&[0x8B, 0x80 | RDX << 3 | RBX]);
+            e.imm32(s as u32 * 8);
+            if instr == JALR {
+                e.mov_imm(RAX, link);
+                e.store(rd(iw));
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Block {
+    entry: BlockFn,
+    // The instructions translated, which the differential mode replays.
+    pub start: u32,
+    pub len: usize,
+    pub paddr: u32,
+}
+
+impl Block {
+    // Runs the block on the 32 GPRs at `gpr`, returning the PC to continue
+    // at. Loads and stores call `memory` with `ctx`; if one stops the block
+    // its PC is returned as the error, and the callback is responsible for
+    // where execution continues. `gpr` must point to 32 GPRs and `ctx` must
+    // be what `memory` expects.
+    pub(crate) unsafe fn run(
+        &self,
+        gpr: *mut u64,
+        ctx: *mut c_void,
+        memory: MemoryFn,
+    ) -> Result<u32, u32> {
+        let result = (self.entry)(gpr, ctx, memory);
+        if result >> 32 == 0 {
+            Ok(result as u32)
+        } else {
+            Err(result as u32)
+        }
+    }
+}
+
+// Translates runs of register-only instructions, loads and stores, ending
+// at a branch and its delay slot, into x86-64. Multiplies and anything else
+// that can raise an exception end a block early and run in the interpreter.
+// In differential mode loads and stores end blocks too, since replaying
+// them would repeat their side effects. Blocks are found by virtual address
+// and dropped when the pages of RDRAM they came from are written.
+pub struct Jit {
+    buffer: CodeBuffer,
+    // None marks an address whose first instruction can't be translated.
+    blocks: HashMap<u32, Option<Block>>,
+    pages: HashMap<u32, Vec<u32>>,
+    pub differential: bool,
+}
+
+impl Jit {
+    pub fn new(differential: bool) -> Self {
+        Jit {
+            buffer: CodeBuffer::new(),
+            blocks: HashMap::new(),
+            pages: HashMap::new(),
+            differential,
+        }
+    }
+
+    // The block starting at `vaddr`, translating it from `fetch`, which
+    // reads the word at a physical address, if it hasn't been already.
+    // Newly translated pages are passed to `watch`.
+    pub fn block(
+        &mut self,
+        vaddr: u32,
+        paddr: u32,
+        fetch: impl FnMut(u32) -> u32,
+        watch: impl FnMut(u32),
+    ) -> Option<Block> {
+        if let Some(&block) = self.blocks.get(&vaddr) {
+            return block;
+        }
+        let block = self.translate(vaddr, paddr, fetch, watch);
+        self.blocks.insert(vaddr, block);
+        block
+    }
+
+    fn translate(
+        &mut self,
+        vaddr: u32,
+        paddr: u32,
+        mut fetch: impl FnMut(u32) -> u32,
+        mut watch: impl FnMut(u32),
+    ) -> Option<Block> {
+        let mut e = Emitter::default();
+        e.prologue();
+        let mut len = 0;
+        let mut returned = false;
+        while len < MAX_BLOCK_INSTRUCTIONS {
+            let pc = vaddr.wrapping_add(len as u32 * 4);
+            let iw = fetch(paddr + len as u32 * 4);
+            let instr = decode_vr4300(iw);
+            if emit_simple(&mut e, instr, iw)
+                || (!self.differential && emit_memory(&mut e, instr, iw, pc))
+            {
+                len += 1;
+                continue;
+            }
+            // A branch is only taken on with a delay slot we can translate.
+            let mut branch = Emitter::default();
+            let slot = fetch(paddr + len as u32 * 4 + 4);
+            if emit_branch(&mut branch, instr, iw, pc)
+                && emit_simple(&mut branch, decode_vr4300(slot), slot)
+            {
+                e.bytes(&branch.code);
+                // mov eax, edx
+                e.bytes(&[0x89, 0xD0]);
+                e.epilogue();
+                len += 2;
+                returned = true;
+            }
+            break;
+        }
+        let entry = if len == 0 {
+            None
+        } else {
+            if !returned {
+                e.ret_pc(vaddr.wrapping_add(len as u32 * 4));
+            }
+            self.buffer.push(&e.code).or_else(|| {
+                // Out of space: start again with an empty buffer.
+                self.flush();
+                self.buffer.push(&e.code)
+            })
+        };
+        // Only now, after any flush, can the pages be recorded.
+        let first_page = paddr >> CODE_PAGE_SHIFT;
+        let last_page = (paddr + len.max(1) as u32 * 4 - 1) >> CODE_PAGE_SHIFT;
+        for page in first_page..=last_page {
+            let blocks = self.pages.entry(page).or_default();
+            if blocks.is_empty() {
+                watch(page);
+            }
+            blocks.push(vaddr);
+        }
+        Some(Block {
+            entry: entry?,
+            start: vaddr,
+            len,
+            paddr,
+        })
+    }
+
+    // Drops every block translated from a page of RDRAM that was written.
+    pub fn invalidate_page(&mut self, page: u32) {
+        if let Some(blocks) = self.pages.remove(&page) {
+            for vaddr in blocks {
+                self.blocks.remove(&vaddr);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.blocks.clear();
+        self.pages.clear();
+        self.buffer.used = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i_type(op: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+        op << 26 | rs << 21 | rt << 16 | u32::from(imm)
+    }
+
+    fn r_type(rs: u32, rt: u32, rd: u32, sa: u32, funct: u32) -> u32 {
+        rs << 21 | rt << 16 | rd << 11 | sa << 6 | funct
+    }
+
+    // Loads and stores against four words of memory, stopping the block at
+    // any other address.
+    struct TestMemory {
+        gpr: *mut u64,
+        words: [u32; 4],
+    }
+
+    extern "sysv64" fn test_memory(ctx: *mut c_void, iw: u32, _pc: u32) -> u32 {
+        let memory = unsafe { &mut *(ctx as *mut TestMemory) };
+        let gpr = unsafe { std::slice::from_raw_parts_mut(memory.gpr, 32) };
+        let addr = (gpr[rs(iw)] as u32).wrapping_add(simm(iw)) as usize;
+        if addr >= 16 {
+            return 1;
+        }
+        match iw >> 26 {
+            0x23 => gpr[rt(iw)] = memory.words[addr / 4] as i32 as u64,
+            0x2B => memory.words[addr / 4] = gpr[rt(iw)] as u32,
+            _ => unreachable!(),
+        }
+        0
+    }
+
+    fn run(block: &Block, gpr: &mut [u64; 32]) -> Result<u32, u32> {
+        let mut memory = TestMemory {
+            gpr: gpr.as_mut_ptr(),
+            words: [0; 4],
+        };
+        unsafe {
+            block.run(
+                memory.gpr,
+                &mut memory as *mut TestMemory as *mut c_void,
+                test_memory,
+            )
+        }
+    }
+
+    fn translate(jit: &mut Jit, program: &[u32]) -> Option<Block> {
+        jit.block(
+            0x8000_0000,
+            0,
+            |addr| program.get(addr as usize / 4).copied().unwrap_or(0),
+            |_| {},
+        )
+    }
+
+    #[test]
+    fn arithmetic_and_shifts() {
+        let mut jit = Jit::new(false);
+        let program = [
+            i_type(0x0F, 0, 8, 0x8000),  // lui t0, 0x8000
+            i_type(0x09, 8, 9, 0xFFFF),  // addiu t1, t0, -1
+            i_type(0x0B, 9, 10, 5),      // sltiu t2, t1, 5
+            i_type(0x0A, 8, 11, 0),      // slti t3, t0, 0
+            r_type(0, 9, 12, 4, 0x03),   // sra t4, t1, 4
+            r_type(0, 8, 13, 4, 0x3E),   // dsrl32 t5, t0, 4
+            r_type(11, 8, 14, 0, 0x04),  // sllv t6, t0, t3
+            r_type(8, 9, 15, 0, 0x27),   // nor t7, t0, t1
+            i_type(0x0D, 0, 16, 0xBEEF), // ori s0, zero, 0xBEEF
+            r_type(16, 16, 17, 0, 0x2D), // daddu s1, s0, s0
+            r_type(8, 9, 0, 0, 0x18),    // mult t0, t1
+        ];
+        let block = translate(&mut jit, &program).unwrap();
+        assert_eq!(block.len, 10);
+        let mut gpr = [0; 32];
+        assert_eq!(run(&block, &mut gpr), Ok(0x8000_0028));
+        assert_eq!(gpr[8], 0xFFFF_FFFF_8000_0000);
+        assert_eq!(gpr[9], 0x7FFF_FFFF);
+        assert_eq!(gpr[10], 0);
+        assert_eq!(gpr[11], 1);
+        assert_eq!(gpr[12], 0x07FF_FFFF);
+        assert_eq!(gpr[13], 0x0FFF_FFFF);
+        assert_eq!(gpr[14], 0);
+        assert_eq!(gpr[15], 0);
+        assert_eq!(gpr[17], 0x1_7DDE);
+    }
+
+    #[test]
+    fn branches_run_their_delay_slot() {
+        let mut jit = Jit::new(false);
+        let program = [
+            i_type(0x09, 8, 8, 0xFFFF), // addiu t0, t0, -1
+            i_type(0x05, 8, 0, 0xFFFE), // bnez t0, -2
+            i_type(0x09, 9, 9, 2),      // addiu t1, t1, 2
+        ];
+        let block = translate(&mut jit, &program).unwrap();
+        assert_eq!(block.len, 3);
+        let mut gpr = [0; 32];
+        gpr[8] = 2;
+        assert_eq!(run(&block, &mut gpr), Ok(0x8000_0000));
+        assert_eq!(run(&block, &mut gpr), Ok(0x8000_000C));
+        assert_eq!(gpr[9], 4);
+    }
+
+    #[test]
+    fn loads_and_stores_continue_the_block() {
+        let mut jit = Jit::new(false);
+        let program = [
+            i_type(0x2B, 0, 8, 4),     // sw t0, 4(zero)
+            i_type(0x23, 0, 9, 4),     // lw t1, 4(zero)
+            i_type(0x09, 9, 9, 1),     // addiu t1, t1, 1
+            i_type(0x23, 0, 10, 0x40), // lw t2, 0x40(zero)
+            i_type(0x09, 0, 10, 1),    // addiu t2, zero, 1
+            r_type(8, 9, 0, 0, 0x18),  // mult t0, t1
+        ];
+        let block = translate(&mut jit, &program).unwrap();
+        assert_eq!(block.len, 5);
+        let mut gpr = [0; 32];
+        gpr[8] = 0xFFFF_FFFF_8000_0000;
+        // The load from 0x40 stops the block, leaving t2 alone.
+        assert_eq!(run(&block, &mut gpr), Err(0x8000_000C));
+        assert_eq!(gpr[9], 0xFFFF_FFFF_8000_0001);
+        assert_eq!(gpr[10], 0);
+
+        // Differential mode leaves them to the interpreter.
+        let mut jit = Jit::new(true);
+        assert!(translate(&mut jit, &program).is_none());
+    }
+
+    #[test]
+    fn pages_are_watched_after_a_flush() {
+        let mut jit = Jit::new(false);
+        let mut watched = Vec::new();
+        jit.block(0x8000_0000, 0, |_| 0, |page| watched.push(page));
+        // Fill the buffer so the next block flushes it.
+        jit.buffer.used = CODE_BUFFER_SIZE - 1;
+        let block = jit.block(0x8000_1000, 0x1000, |_| 0, |page| watched.push(page));
+        assert!(block.is_some());
+        assert_eq!(watched, vec![0, 1]);
+        assert!(!jit.blocks.contains_key(&0x8000_0000));
+
+        jit.invalidate_page(1);
+        assert!(!jit.blocks.contains_key(&0x8000_1000));
+    }
+
+    #[test]
+    fn untranslatable_code_is_remembered_and_pages_invalidate() {
+        let mut jit = Jit::new(false);
+        let mut watched = Vec::new();
+        let mult = [r_type(8, 9, 0, 0, 0x18)]; // mult t0, t1
+        let block = jit.block(0x8000_1000, 0x1000, |_| mult[0], |page| watched.push(page));
+        assert!(block.is_none());
+        assert_eq!(watched, vec![1]);
+        assert!(jit.block(0x8000_1000, 0x1000, |_| 0, |_| {}).is_none());
+
+        jit.invalidate_page(1);
+        let nop = jit.block(0x8000_1000, 0x1000, |_| 0, |_| {});
+        assert_eq!(nop.map(|block| block.len), Some(MAX_BLOCK_INSTRUCTIONS));
+    }
+}
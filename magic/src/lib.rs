@@ -2,8 +2,54 @@ use arraydeque::{ArrayDeque, Saturating};
 use gumdrop::Options;
 use std::collections::VecDeque;
 use std::error::Error;
-use ux::{u14, u20};
+use ux::u20;
+pub mod ai;
+pub mod cached_interp;
+pub mod cart;
+pub mod cop0;
 pub mod decoder;
+pub mod dp;
+mod fpu;
+pub mod hle_audio;
+mod interp;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+pub mod mi;
+pub mod pi;
+pub mod pif;
+pub mod pipeline;
+pub mod rdp;
+pub mod rdp_capture;
+pub mod ri;
+pub mod rsp;
+pub mod rsp_decoder;
+pub mod save;
+pub mod scheduler;
+pub mod si;
+pub mod sp;
+pub mod vi;
+pub mod vu;
+
+use ai::{Ai, AudioSink, AI_REGS_END, AI_REGS_START};
+use cart::{Cartridge, CART_DOM1_ADDR2_END, CART_DOM2_ADDR2};
+use cop0::Cop0;
+use dp::{Dp, RdpBackend, DP_REGS_END, DP_REGS_START};
+use mi::{Mi, MiInterrupt, MI_REGS_END, MI_REGS_START};
+use pi::{Pi, PiDma, PiDmaDirection, PI_REGS_END, PI_REGS_START};
+use pif::Pif;
+use pif::PIF_RAM_SIZE;
+use pipeline::Pipeline;
+use rdp::Rdp;
+use ri::{Rdram, RdramSize, Ri, RDRAM_REGS_END, RDRAM_REGS_START, RI_REGS_END, RI_REGS_START};
+use rsp::{Rsp, SP_MEM_END, SP_MEM_SIZE, SP_MEM_START};
+use save::{Save, SaveType};
+use scheduler::{Event, Scheduler};
+use si::{Si, SiDma, SiDmaDirection, SI_REGS_END, SI_REGS_START};
+use sp::{
+    Sp, SpCop0, SpDma, SpDmaDirection, SP_PC_REGS_END, SP_PC_REGS_START, SP_REGS_END,
+    SP_REGS_START, SP_STATUS,
+};
+use vi::{FrameSink, Vi, VI_REGS_END, VI_REGS_START};
 
 #[derive(Debug, Options)]
 pub struct EmuOptions {
@@ -11,25 +57,566 @@ pub struct EmuOptions {
     help: bool,
     #[options(help = "path to PIF boot ROM", long = "pifrom", required)]
     pub pifrom_path: String,
+    #[options(help = "path to cartridge ROM", long = "rom", no_short)]
+    pub rom_path: Option<String>,
+    #[options(
+        help = "path to save file (default: next to the ROM)",
+        long = "save",
+        no_short
+    )]
+    pub save_path: Option<String>,
+    #[options(
+        help = "save type: none, eeprom4k, eeprom16k, sram or flashram (default: detect)",
+        no_short
+    )]
+    pub save_type: Option<SaveType>,
+    #[options(
+        help = "path to controller pak file for port 1",
+        long = "mempak",
+        no_short
+    )]
+    pub mempak_path: Option<String>,
+    #[options(
+        help = "directory to write video frames to as PPM images",
+        long = "frame-dir",
+        no_short
+    )]
+    pub frame_dir: Option<String>,
+    #[options(
+        help = "write only every Nth frame (default: 1)",
+        long = "frame-interval",
+        no_short
+    )]
+    pub frame_interval: Option<u64>,
+    #[options(help = "path to write audio output to as WAV", long = "wav", no_short)]
+    pub wav_path: Option<String>,
+    #[options(
+        help = "RDRAM size: 4M, or 8M with the Expansion Pak (default: 4M)",
+        no_short
+    )]
+    pub rdram: Option<RdramSize>,
+    #[options(
+        help = "run audio tasks natively instead of on the RSP",
+        long = "hle-audio",
+        no_short
+    )]
+    pub hle_audio: bool,
+    #[options(
+        help = "path to record RDP command lists to, for rdp-replay",
+        long = "rdp-capture",
+        no_short
+    )]
+    pub rdp_capture: Option<String>,
+    #[options(
+        help = "time the CPU with a model of its pipeline instead of one cycle per instruction",
+        long = "pipeline",
+        no_short
+    )]
+    pub pipeline: bool,
+    #[options(help = "translate CPU code to x86-64 where possible", no_short)]
+    pub jit: bool,
+    #[options(
+        help = "run translated code alongside the interpreter and compare the results",
+        long = "jit-check",
+        no_short
+    )]
+    pub jit_check: bool,
+    #[options(
+        help = "interpret CPU code from blocks decoded once and cached",
+        long = "cached-interp",
+        no_short
+    )]
+    pub cached_interp: bool,
+    #[options(
+        help = "stop after this many CPU cycles and write back saves (default: run until killed)",
+        no_short
+    )]
+    pub cycles: Option<u64>,
+}
+
+// Options for `magic rdp-replay`, which renders a capture made with
+// --rdp-capture without running the rest of the system.
+#[derive(Debug, Options)]
+pub struct RdpReplayOptions {
+    #[options(help = "print help message")]
+    pub help: bool,
+    #[options(free, required, help = "capture file to replay")]
+    pub capture_path: String,
+    #[options(free, required, help = "PPM image to write the framebuffer to")]
+    pub image_path: String,
 }
 
+const RDRAM_END: u32 = 0x03EF_FFFF;
+pub(crate) const CODE_PAGE_SHIFT: u32 = 12;
+const PIFROM_SIZE: usize = 2048;
+const PIFROM_START: u32 = 0x1FC0_0000;
+const PIFROM_END: u32 = 0x1FC0_07BF;
+const PIF_RAM_START: u32 = 0x1FC0_07C0;
+const PIF_RAM_END: u32 = 0x1FC0_07FF;
+
 pub struct Memory {
-    pifrom: [u8; 2048],
+    rdram: Vec<u8>,
+    rdram_regs: Rdram,
+    ri: Ri,
+    pifrom: [u8; PIFROM_SIZE],
+    pif: Pif,
+    rsp: Rsp,
+    sp: Sp,
+    // CPU cycles not yet spent on the RSP, which runs at two thirds of the
+    // CPU clock.
+    rsp_cycles: u64,
+    hle_audio: bool,
+    dp: Dp,
+    rdp: Box<dyn RdpBackend>,
+    mi: Mi,
+    pi: Pi,
+    vi: Vi,
+    ai: Ai,
+    si: Si,
+    cart: Option<Cartridge>,
+    scheduler: Scheduler,
+    frame_sink: Option<Box<dyn FrameSink>>,
+    audio_sink: Option<Box<dyn AudioSink>>,
+    // Set when Count reaches Compare, until the CPU notices.
+    timer_interrupt: bool,
+    // Pages of RDRAM holding translated code, and those written since.
+    watched_pages: Vec<bool>,
+    written_pages: Vec<u32>,
 }
 
 pub trait MemoryBus {
     fn new(pifrom_src: &mut impl std::io::Read) -> Self;
-    //fn fetch(&mut self, addr: u32) -> [u8; 8];
+    fn read_word(&mut self, addr: u32) -> u32;
+    fn write_word(&mut self, addr: u32, value: u32);
+    // Advances the global clock by a number of CPU cycles and handles any
+    // device events that fall due.
+    fn tick(&mut self, cycles: u64);
+    fn interrupt_pending(&self) -> bool;
+    // The global clock, in CPU cycles.
+    fn now(&self) -> u64;
+    // Schedules the COP0 timer interrupt for cycle `time`, replacing any
+    // earlier schedule. take_timer_interrupt reports whether it has fired.
+    fn schedule_timer(&mut self, time: u64);
+    fn take_timer_interrupt(&mut self) -> bool;
+    // Asks to be told the next time the 4KB page of RDRAM numbered `page`
+    // is written, for code translated from it.
+    fn watch_code_page(&mut self, page: u32);
+    // The watched pages written since the last call.
+    fn take_code_writes(&mut self) -> Vec<u32>;
+}
+
+impl Memory {
+    pub fn insert_cartridge(&mut self, cart: Cartridge) {
+        self.cart = Some(cart);
+    }
+
+    pub fn set_rdram_size(&mut self, size: RdramSize) {
+        self.rdram.resize(size.bytes(), 0);
+        self.rdram_regs = Rdram::new(size);
+    }
+
+    pub fn rdram(&self) -> &[u8] {
+        &self.rdram
+    }
+
+    pub fn rdram_mut(&mut self) -> &mut [u8] {
+        &mut self.rdram
+    }
+
+    // Writes by the RDP and HLE audio aren't tracked, as code doesn't run
+    // from framebuffers or audio buffers.
+    fn rdram_written(&mut self, start: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let pages = (start >> CODE_PAGE_SHIFT)..=((start + len - 1) >> CODE_PAGE_SHIFT);
+        for page in pages {
+            if let Some(watched) = self.watched_pages.get_mut(page) {
+                if *watched {
+                    *watched = false;
+                    self.written_pages.push(page as u32);
+                }
+            }
+        }
+    }
+
+    fn run_pi_dma(&mut self, dma: PiDma) {
+        let start = dma.dram_addr as usize;
+        let end = (start + dma.len as usize).min(self.rdram.len());
+        if start >= end {
+            return;
+        }
+        if dma.direction == PiDmaDirection::ToDram {
+            self.rdram_written(start, end - start);
+        }
+        let dram = &mut self.rdram[start..end];
+        match (dma.direction, &mut self.cart) {
+            (PiDmaDirection::ToDram, Some(cart)) => cart.dma_read(dma.cart_addr, dram),
+            (PiDmaDirection::ToDram, None) => {
+                for byte in dram.iter_mut() {
+                    *byte = 0;
+                }
+            }
+            (PiDmaDirection::ToCart, Some(cart)) => cart.dma_write(dma.cart_addr, dram),
+            (PiDmaDirection::ToCart, None) => {}
+        }
+    }
+
+    pub fn set_hle_audio(&mut self, enabled: bool) {
+        self.hle_audio = enabled;
+    }
+
+    // Called when the CPU writes SP_STATUS. Starting the RSP on an audio
+    // task can complete the whole task at once when HLE audio is enabled.
+    fn write_sp_status(&mut self, value: u32) {
+        let was_halted = self.rsp.halted();
+        self.rsp.write_status(value);
+        if was_halted
+            && !self.rsp.halted()
+            && self.hle_audio
+            && hle_audio::run_task(&mut self.rsp.dmem, &mut self.rdram)
+        {
+            self.rsp.finish_task();
+        }
+        self.mi.set_line(MiInterrupt::Sp, self.rsp.interrupt());
+    }
+
+    pub fn set_rdp_backend(&mut self, backend: Box<dyn RdpBackend>) {
+        self.rdp = backend;
+    }
+
+    // Hands any newly submitted commands to the RDP backend.
+    fn run_dp(&mut self) {
+        if self.dp.run(&mut *self.rdp, &mut self.rdram, &self.rsp.dmem) {
+            self.mi.raise(MiInterrupt::Dp);
+        }
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn set_frame_sink(&mut self, sink: Box<dyn FrameSink>) {
+        self.frame_sink = Some(sink);
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+
+    // Samples are big-endian 16-bit stereo pairs in RDRAM.
+    fn play_audio(&mut self) {
+        let dma = match self.ai.take_started() {
+            Some(dma) => dma,
+            None => return,
+        };
+        if let Some(sink) = &mut self.audio_sink {
+            let start = dma.dram_addr as usize;
+            let end = (start + dma.len as usize).min(self.rdram.len());
+            let samples: Vec<i16> = self.rdram[start.min(end)..end]
+                .chunks_exact(2)
+                .map(|s| i16::from_be_bytes([s[0], s[1]]))
+                .collect();
+            sink.samples(self.ai.frequency(), &samples);
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::CompareMatch => self.timer_interrupt = true,
+            Event::AiBufferDone => {
+                self.ai.buffer_done(&mut self.scheduler);
+                self.play_audio();
+                self.mi.set_line(MiInterrupt::Ai, self.ai.interrupt());
+            }
+            Event::SiDmaComplete => {
+                if let Some(dma) = self.si.complete_dma() {
+                    self.run_si_dma(dma);
+                    self.mi.raise(MiInterrupt::Si);
+                }
+            }
+            Event::ViLine => {
+                if self.vi.next_line() {
+                    if let Some(sink) = &mut self.frame_sink {
+                        if let Some(frame) = self.vi.scan_out(&self.rdram) {
+                            sink.frame(self.vi.frames(), &frame);
+                        }
+                    }
+                }
+                self.mi.set_line(MiInterrupt::Vi, self.vi.interrupt());
+                self.scheduler
+                    .schedule(self.vi.line_cycles(), Event::ViLine);
+            }
+            Event::SpDmaComplete => {
+                if let Some(dma) = self.sp.complete_dma(&mut self.scheduler) {
+                    self.run_sp_dma(dma);
+                }
+            }
+            Event::PiDmaComplete => {
+                if let Some(dma) = self.pi.complete_dma() {
+                    self.run_pi_dma(dma);
+                    self.mi.raise(MiInterrupt::Pi);
+                }
+            }
+        }
+    }
+
+    // Rows are contiguous in DMEM/IMEM, wrapping within the 4KB memory, and
+    // separated by `skip` bytes in RDRAM.
+    fn run_sp_dma(&mut self, dma: SpDma) {
+        if dma.direction == SpDmaDirection::ToDram {
+            let span = dma.count * (dma.len + dma.skip);
+            self.rdram_written(dma.dram_addr as usize, span as usize);
+        }
+        let mem = if dma.mem_addr & 0x1000 == 0 {
+            &mut self.rsp.dmem
+        } else {
+            &mut self.rsp.imem
+        };
+        for row in 0..dma.count {
+            for i in 0..dma.len {
+                let mem_index = (dma.mem_addr + row * dma.len + i) as usize & (SP_MEM_SIZE - 1);
+                let dram_index = (dma.dram_addr + row * (dma.len + dma.skip) + i) as usize;
+                match dma.direction {
+                    SpDmaDirection::ToSp => {
+                        mem[mem_index] = self.rdram.get(dram_index).copied().unwrap_or(0)
+                    }
+                    SpDmaDirection::ToDram => {
+                        if let Some(byte) = self.rdram.get_mut(dram_index) {
+                            *byte = mem[mem_index];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs the RSP for its share of the elapsed CPU cycles. It only ever
+    // catches up; a halted RSP forfeits the time.
+    fn run_rsp(&mut self, cycles: u64) {
+        if self.rsp.halted() {
+            self.rsp_cycles = 0;
+            return;
+        }
+        self.rsp_cycles += cycles * 2;
+        let budget = self.rsp_cycles / 3;
+        self.rsp_cycles %= 3;
+        let mut cop0 = SpCop0 {
+            sp: &mut self.sp,
+            dp: &mut self.dp,
+            scheduler: &mut self.scheduler,
+        };
+        self.rsp.run(&mut cop0, budget);
+        self.mi.set_line(MiInterrupt::Sp, self.rsp.interrupt());
+        // The RSP can't lend out DMEM while it runs, so commands it submits
+        // are fetched once its slice is over.
+        self.run_dp();
+    }
+
+    // Commands are run by the PIF as soon as they arrive in PIF RAM, so a
+    // following read picks up the responses.
+    fn run_si_dma(&mut self, dma: SiDma) {
+        let start = dma.dram_addr as usize;
+        let dram = match self.rdram.get_mut(start..start + PIF_RAM_SIZE) {
+            Some(dram) => dram,
+            None => return,
+        };
+        match dma.direction {
+            SiDmaDirection::ToDram => {
+                dram.copy_from_slice(&self.pif.ram);
+                self.rdram_written(start, PIF_RAM_SIZE);
+            }
+            SiDmaDirection::ToPif => {
+                self.pif.ram.copy_from_slice(dram);
+                let mut no_save = Save::None;
+                let save = match &mut self.cart {
+                    Some(cart) => &mut cart.save,
+                    None => &mut no_save,
+                };
+                self.pif.process_joybus(save);
+            }
+        }
+    }
+
+    pub fn pif_mut(&mut self) -> &mut Pif {
+        &mut self.pif
+    }
+
+    pub fn flush_saves(&mut self) {
+        if let Some(cart) = &mut self.cart {
+            cart.save.flush();
+        }
+        for controller in self.pif.controllers.iter_mut().flatten() {
+            if let Some(pak) = &mut controller.pak {
+                pak.flush();
+            }
+        }
+    }
 }
 
 impl MemoryBus for Memory {
     fn new(pifrom_src: &mut impl std::io::Read) -> Self {
-        let mut result = Memory { pifrom: [0; 2048] };
+        let mut result = Memory {
+            rdram: vec![0; RdramSize::default().bytes()],
+            rdram_regs: Rdram::new(RdramSize::default()),
+            ri: Ri::default(),
+            pifrom: [0; PIFROM_SIZE],
+            pif: Pif::default(),
+            rsp: Rsp::default(),
+            sp: Sp::default(),
+            rsp_cycles: 0,
+            hle_audio: false,
+            dp: Dp::default(),
+            rdp: Box::new(Rdp::default()),
+            mi: Mi::default(),
+            pi: Pi::default(),
+            vi: Vi::default(),
+            ai: Ai::default(),
+            si: Si::default(),
+            cart: None,
+            scheduler: Scheduler::default(),
+            timer_interrupt: false,
+            frame_sink: None,
+            audio_sink: None,
+            watched_pages: vec![false; (RDRAM_END as usize + 1) >> CODE_PAGE_SHIFT],
+            written_pages: Vec::new(),
+        };
+        let line_cycles = result.vi.line_cycles();
+        result.scheduler.schedule(line_cycles, Event::ViLine);
         if let Err(why) = pifrom_src.read(&mut result.pifrom) {
             panic!("Couldn't read pifrom: {}", why.description());
         }
         result
     }
+
+    fn read_word(&mut self, addr: u32) -> u32 {
+        match addr {
+            0..=RDRAM_END => {
+                let offset = addr as usize & !3;
+                match self.rdram.get(offset..offset + 4) {
+                    Some(word) => u32::from_be_bytes([word[0], word[1], word[2], word[3]]),
+                    None => 0,
+                }
+            }
+            RDRAM_REGS_START..=RDRAM_REGS_END => self.rdram_regs.read_word(addr - RDRAM_REGS_START),
+            SP_MEM_START..=SP_MEM_END => self.rsp.read_mem_word(addr),
+            SP_REGS_START..=SP_REGS_END => match addr & 0x1F {
+                SP_STATUS => self.rsp.status() | self.sp.dma_status(),
+                offset => self.sp.read_word(offset),
+            },
+            SP_PC_REGS_START..=SP_PC_REGS_END => match addr & 0x7 {
+                0 => self.rsp.pc(),
+                _ => 0,
+            },
+            DP_REGS_START..=DP_REGS_END => self.dp.read_word(addr & 0x1F),
+            MI_REGS_START..=MI_REGS_END => self.mi.read_word(addr & 0xFF),
+            PI_REGS_START..=PI_REGS_END => self.pi.read_word(addr & 0xFF),
+            VI_REGS_START..=VI_REGS_END => self.vi.read_word(addr & 0xFF),
+            AI_REGS_START..=AI_REGS_END => self.ai.read_word(addr & 0xFF, &self.scheduler),
+            RI_REGS_START..=RI_REGS_END => self.ri.read_word(addr & 0xFF),
+            SI_REGS_START..=SI_REGS_END => self.si.read_word(addr & 0xFF),
+            CART_DOM2_ADDR2..=CART_DOM1_ADDR2_END => match &self.cart {
+                Some(cart) => cart.read_word(addr),
+                None => 0,
+            },
+            PIFROM_START..=PIFROM_END => {
+                let offset = (addr - PIFROM_START) as usize & !3;
+                u32::from_be_bytes([
+                    self.pifrom[offset],
+                    self.pifrom[offset + 1],
+                    self.pifrom[offset + 2],
+                    self.pifrom[offset + 3],
+                ])
+            }
+            PIF_RAM_START..=PIF_RAM_END => self.pif.read_word(addr - PIF_RAM_START),
+            _ => 0,
+        }
+    }
+
+    fn write_word(&mut self, addr: u32, value: u32) {
+        match addr {
+            0..=RDRAM_END => {
+                let offset = addr as usize & !3;
+                if let Some(word) = self.rdram.get_mut(offset..offset + 4) {
+                    word.copy_from_slice(&value.to_be_bytes());
+                }
+                self.rdram_written(offset, 4);
+            }
+            RDRAM_REGS_START..=RDRAM_REGS_END => {
+                self.rdram_regs.write_word(addr - RDRAM_REGS_START, value)
+            }
+            SP_MEM_START..=SP_MEM_END => self.rsp.write_mem_word(addr, value),
+            SP_REGS_START..=SP_REGS_END => match addr & 0x1F {
+                SP_STATUS => self.write_sp_status(value),
+                offset => self.sp.write_word(offset, value, &mut self.scheduler),
+            },
+            SP_PC_REGS_START..=SP_PC_REGS_END if addr & 0x7 == 0 => self.rsp.set_pc(value),
+            DP_REGS_START..=DP_REGS_END => {
+                self.dp.write_word(addr & 0x1F, value);
+                self.run_dp();
+            }
+            MI_REGS_START..=MI_REGS_END => self.mi.write_word(addr & 0xFF, value),
+            PI_REGS_START..=PI_REGS_END => {
+                self.pi.write_word(addr & 0xFF, value, &mut self.scheduler);
+                self.mi.set_line(MiInterrupt::Pi, self.pi.interrupt());
+            }
+            VI_REGS_START..=VI_REGS_END => {
+                self.vi.write_word(addr & 0xFF, value);
+                self.mi.set_line(MiInterrupt::Vi, self.vi.interrupt());
+            }
+            AI_REGS_START..=AI_REGS_END => {
+                self.ai.write_word(addr & 0xFF, value, &mut self.scheduler);
+                self.play_audio();
+                self.mi.set_line(MiInterrupt::Ai, self.ai.interrupt());
+            }
+            RI_REGS_START..=RI_REGS_END => self.ri.write_word(addr & 0xFF, value),
+            SI_REGS_START..=SI_REGS_END => {
+                self.si.write_word(addr & 0xFF, value, &mut self.scheduler);
+                self.mi.set_line(MiInterrupt::Si, self.si.interrupt());
+            }
+            CART_DOM2_ADDR2..=CART_DOM1_ADDR2_END => {
+                if let Some(cart) = &mut self.cart {
+                    cart.write_word(addr, value);
+                }
+            }
+            PIF_RAM_START..=PIF_RAM_END => self.pif.write_word(addr - PIF_RAM_START, value),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.scheduler.advance(cycles);
+        while let Some(event) = self.scheduler.pop_due() {
+            self.handle_event(event);
+        }
+        self.run_rsp(cycles);
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.mi.pending()
+    }
+
+    fn now(&self) -> u64 {
+        self.scheduler.now()
+    }
+
+    fn schedule_timer(&mut self, time: u64) {
+        self.scheduler.schedule_at(time, Event::CompareMatch);
+    }
+
+    fn take_timer_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.timer_interrupt)
+    }
+
+    fn watch_code_page(&mut self, page: u32) {
+        if let Some(watched) = self.watched_pages.get_mut(page as usize) {
+            *watched = true;
+        }
+    }
+
+    fn take_code_writes(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.written_pages)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -49,29 +636,343 @@ impl Default for ICacheLine {
     }
 }
 
+impl ICacheLine {
+    pub fn data(&self) -> [u32; 8] {
+        self.data
+    }
+
+    // The physical tag: bits 12-31 of the address the line holds.
+    pub fn tag(&self) -> u32 {
+        self.tag.into()
+    }
+
+    pub fn valid(&self) -> bool {
+        self.valid
+    }
+}
+
+// The cache is virtually indexed and physically tagged: index operations
+// pick a line by virtual address alone, and hit operations take both
+// addresses and only act when the line holds the physical one.
 pub trait InstructionCache: Default {
     type AddressSize;
-    type Output;
-    fn fetch(&self, addr: Self::AddressSize) -> Self::Output;
+    // The word at `vaddr`, if its line holds `paddr`.
+    fn fetch(&self, vaddr: Self::AddressSize, paddr: Self::AddressSize) -> Option<u32>;
+    // Replaces the line for `vaddr` with `data` read from `paddr`.
+    fn fill(&mut self, vaddr: Self::AddressSize, paddr: Self::AddressSize, data: [u32; 8]);
+    fn invalidate_index(&mut self, vaddr: Self::AddressSize);
+    // Reads and writes the line's tag in the layout of COP0 TagLo.
+    fn load_tag(&self, vaddr: Self::AddressSize) -> u32;
+    fn store_tag(&mut self, vaddr: Self::AddressSize, tag_lo: u32);
+    fn invalidate_hit(&mut self, vaddr: Self::AddressSize, paddr: Self::AddressSize);
+    fn hit_line(&self, vaddr: Self::AddressSize, paddr: Self::AddressSize) -> Option<[u32; 8]>;
 }
 
+const ICACHE_LINES: usize = 512;
+
+// TagLo holds the physical tag in bits 8-27 and the valid bit in bit 7.
+const TAG_LO_PTAG_SHIFT: u32 = 8;
+const TAG_LO_PTAG: u32 = 0x0FFF_FF00;
+const TAG_LO_VALID: u32 = 1 << 7;
+
 pub struct ICache {
-    memory: [ICacheLine; 512],
+    memory: [ICacheLine; ICACHE_LINES],
 }
 
 impl Default for ICache {
     fn default() -> ICache {
         ICache {
-            memory: [ICacheLine::default(); 512],
+            memory: [ICacheLine::default(); ICACHE_LINES],
         }
     }
 }
 
+// 16KB of 32-byte lines, indexed by bits 5-13 of the virtual address and
+// tagged with bits 12-31 of the physical one.
+impl ICache {
+    fn index(vaddr: u32) -> usize {
+        (vaddr >> 5) as usize & (ICACHE_LINES - 1)
+    }
+
+    fn tag(paddr: u32) -> u20 {
+        u20::new(paddr >> 12)
+    }
+
+    fn hit(&self, vaddr: u32, paddr: u32) -> Option<&ICacheLine> {
+        let line = &self.memory[Self::index(vaddr)];
+        if line.valid && line.tag == Self::tag(paddr) {
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    // The line `vaddr` indexes, whatever it holds.
+    pub fn line(&self, vaddr: u32) -> &ICacheLine {
+        &self.memory[Self::index(vaddr)]
+    }
+
+    pub fn lines(&self) -> &[ICacheLine] {
+        &self.memory
+    }
+}
+
 impl InstructionCache for ICache {
-    type AddressSize = u14;
-    type Output = ICacheLine;
-    fn fetch(&self, addr: Self::AddressSize) -> Self::Output {
-        self.memory[u32::from(addr >> 5) as usize]
+    type AddressSize = u32;
+
+    fn fetch(&self, vaddr: u32, paddr: u32) -> Option<u32> {
+        self.hit(vaddr, paddr)
+            .map(|line| line.data[(vaddr >> 2) as usize & 7])
+    }
+
+    fn fill(&mut self, vaddr: u32, paddr: u32, data: [u32; 8]) {
+        self.memory[Self::index(vaddr)] = ICacheLine {
+            data,
+            tag: Self::tag(paddr),
+            valid: true,
+        };
+    }
+
+    fn invalidate_index(&mut self, vaddr: u32) {
+        self.memory[Self::index(vaddr)].valid = false;
+    }
+
+    fn load_tag(&self, vaddr: u32) -> u32 {
+        let line = &self.memory[Self::index(vaddr)];
+        let valid = if line.valid { TAG_LO_VALID } else { 0 };
+        (u32::from(line.tag) << TAG_LO_PTAG_SHIFT) | valid
+    }
+
+    fn store_tag(&mut self, vaddr: u32, tag_lo: u32) {
+        let line = &mut self.memory[Self::index(vaddr)];
+        line.tag = u20::new((tag_lo & TAG_LO_PTAG) >> TAG_LO_PTAG_SHIFT);
+        line.valid = tag_lo & TAG_LO_VALID != 0;
+    }
+
+    fn invalidate_hit(&mut self, vaddr: u32, paddr: u32) {
+        if self.hit(vaddr, paddr).is_some() {
+            self.memory[Self::index(vaddr)].valid = false;
+        }
+    }
+
+    fn hit_line(&self, vaddr: u32, paddr: u32) -> Option<[u32; 8]> {
+        self.hit(vaddr, paddr).map(|line| line.data)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct DCacheLine {
+    data: [u32; 4],
+    tag: u20,
+    valid: bool,
+    dirty: bool,
+}
+
+impl Default for DCacheLine {
+    fn default() -> DCacheLine {
+        DCacheLine {
+            data: [0; 4],
+            tag: 0u16.into(),
+            valid: false,
+            dirty: false,
+        }
+    }
+}
+
+impl DCacheLine {
+    pub fn data(&self) -> [u32; 4] {
+        self.data
+    }
+
+    pub fn tag(&self) -> u32 {
+        self.tag.into()
+    }
+
+    pub fn valid(&self) -> bool {
+        self.valid
+    }
+
+    pub fn dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+// A line that has to be written back to memory: its physical address and
+// contents.
+pub type DirtyLine = (u32, [u32; 4]);
+
+// A write-back cache. Anything that replaces or drops a dirty line hands
+// it back to be written to memory; nothing else reaches memory, so DMA
+// sees only what has been written back.
+pub trait DataCache: Default {
+    type AddressSize;
+    // The word at `addr`, if its line is cached.
+    fn read(&self, addr: Self::AddressSize) -> Option<u32>;
+    // Merges the bits of `value` selected by `mask` into the word at
+    // `addr` and marks its line dirty. Returns false on a miss.
+    fn write(&mut self, addr: Self::AddressSize, value: u32, mask: u32) -> bool;
+    // Replaces the line for `addr` with `data` read from memory.
+    fn fill(&mut self, addr: Self::AddressSize, data: [u32; 4]) -> Option<DirtyLine>;
+    fn writeback_invalidate_index(&mut self, addr: Self::AddressSize) -> Option<DirtyLine>;
+    // Reads and writes the line's tag in the layout of COP0 TagLo.
+    fn load_tag(&self, addr: Self::AddressSize) -> u32;
+    fn store_tag(&mut self, addr: Self::AddressSize, tag_lo: u32);
+    // Claims the line for `addr` as dirty without reading memory.
+    fn create_dirty_exclusive(&mut self, addr: Self::AddressSize) -> Option<DirtyLine>;
+    fn invalidate_hit(&mut self, addr: Self::AddressSize);
+    fn writeback_invalidate_hit(&mut self, addr: Self::AddressSize) -> Option<DirtyLine>;
+    fn writeback_hit(&mut self, addr: Self::AddressSize) -> Option<DirtyLine>;
+}
+
+const DCACHE_LINES: usize = 512;
+
+// The data cache's PState field adds a dirty bit below the valid bit.
+const TAG_LO_DIRTY: u32 = 1 << 6;
+
+pub struct DCache {
+    memory: [DCacheLine; DCACHE_LINES],
+}
+
+impl Default for DCache {
+    fn default() -> DCache {
+        DCache {
+            memory: [DCacheLine::default(); DCACHE_LINES],
+        }
+    }
+}
+
+// 8KB of 16-byte lines, indexed by bits 4-12 and tagged with bits 12-31
+// of the address.
+impl DCache {
+    fn index(addr: u32) -> usize {
+        (addr >> 4) as usize & (DCACHE_LINES - 1)
+    }
+
+    fn tag(addr: u32) -> u20 {
+        u20::new(addr >> 12)
+    }
+
+    fn hit(&self, addr: u32) -> Option<usize> {
+        let index = Self::index(addr);
+        let line = &self.memory[index];
+        if line.valid && line.tag == Self::tag(addr) {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    // The line `addr` indexes, whatever it holds.
+    pub fn line(&self, addr: u32) -> &DCacheLine {
+        &self.memory[Self::index(addr)]
+    }
+
+    pub fn lines(&self) -> &[DCacheLine] {
+        &self.memory
+    }
+
+    // Takes the line at `index` out of the cache, returning it if it has
+    // to be written back.
+    fn evict(&mut self, index: usize) -> Option<DirtyLine> {
+        let line = &mut self.memory[index];
+        let dirty = line.valid && line.dirty;
+        line.valid = false;
+        line.dirty = false;
+        if dirty {
+            let addr = (u32::from(line.tag) << 12) | ((index as u32) << 4 & 0xFFF);
+            Some((addr, line.data))
+        } else {
+            None
+        }
+    }
+
+    fn replace(&mut self, addr: u32, data: [u32; 4], dirty: bool) -> Option<DirtyLine> {
+        let index = Self::index(addr);
+        let evicted = match self.hit(addr) {
+            Some(_) => None,
+            None => self.evict(index),
+        };
+        self.memory[index] = DCacheLine {
+            data,
+            tag: Self::tag(addr),
+            valid: true,
+            dirty,
+        };
+        evicted
+    }
+}
+
+impl DataCache for DCache {
+    type AddressSize = u32;
+
+    fn read(&self, addr: u32) -> Option<u32> {
+        self.hit(addr)
+            .map(|index| self.memory[index].data[(addr >> 2) as usize & 3])
+    }
+
+    fn write(&mut self, addr: u32, value: u32, mask: u32) -> bool {
+        match self.hit(addr) {
+            Some(index) => {
+                let line = &mut self.memory[index];
+                let word = &mut line.data[(addr >> 2) as usize & 3];
+                *word = (*word & !mask) | (value & mask);
+                line.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn fill(&mut self, addr: u32, data: [u32; 4]) -> Option<DirtyLine> {
+        self.replace(addr, data, false)
+    }
+
+    fn writeback_invalidate_index(&mut self, addr: u32) -> Option<DirtyLine> {
+        self.evict(Self::index(addr))
+    }
+
+    fn load_tag(&self, addr: u32) -> u32 {
+        let line = &self.memory[Self::index(addr)];
+        let valid = if line.valid { TAG_LO_VALID } else { 0 };
+        let dirty = if line.dirty { TAG_LO_DIRTY } else { 0 };
+        (u32::from(line.tag) << TAG_LO_PTAG_SHIFT) | valid | dirty
+    }
+
+    fn store_tag(&mut self, addr: u32, tag_lo: u32) {
+        let line = &mut self.memory[Self::index(addr)];
+        line.tag = u20::new((tag_lo & TAG_LO_PTAG) >> TAG_LO_PTAG_SHIFT);
+        line.valid = tag_lo & TAG_LO_VALID != 0;
+        line.dirty = tag_lo & TAG_LO_DIRTY != 0;
+    }
+
+    fn create_dirty_exclusive(&mut self, addr: u32) -> Option<DirtyLine> {
+        let data = match self.hit(addr) {
+            Some(index) => self.memory[index].data,
+            None => [0; 4],
+        };
+        self.replace(addr, data, true)
+    }
+
+    fn invalidate_hit(&mut self, addr: u32) {
+        if let Some(index) = self.hit(addr) {
+            self.memory[index].valid = false;
+            self.memory[index].dirty = false;
+        }
+    }
+
+    fn writeback_invalidate_hit(&mut self, addr: u32) -> Option<DirtyLine> {
+        self.hit(addr).and_then(|index| self.evict(index))
+    }
+
+    fn writeback_hit(&mut self, addr: u32) -> Option<DirtyLine> {
+        let index = self.hit(addr)?;
+        let line = &mut self.memory[index];
+        if line.dirty {
+            line.dirty = false;
+            Some((addr & !15, line.data))
+        } else {
+            None
+        }
     }
 }
 
@@ -80,39 +981,84 @@ enum CpuCommand {
     WaitMem(u8),
 }
 
-pub struct InterpCPU32bit<MB, IC, MM>
+pub struct InterpCPU32bit<MB, IC, DC, MM>
 where
     MB: MemoryBus,
     IC: InstructionCache,
+    DC: DataCache,
     MM: MMU,
 {
     pc: u32,
+    next_pc: u32,
+    delay_slot: bool,
+    gpr: [u64; 32],
+    hi: u64,
+    lo: u64,
+    llbit: bool,
+    fpr: [u64; 32],
+    fcr31: u32,
+    cop0: Cop0,
     bus: MB,
     icache: IC,
+    dcache: DC,
     mmu: MM,
+    // Cycles the current instruction spent stalled on memory or interlocks.
+    stall: u64,
+    // Set when pipeline timing is in use.
+    pipeline: Option<Pipeline>,
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    jit: Option<jit::Jit>,
     cmd_queue: VecDeque<ArrayDeque<[CpuCommand; 8], Saturating>>,
 }
 
 pub trait CPU {
     fn new(pifrom_src: &mut impl std::io::Read) -> Self;
+    fn step(&mut self);
     fn run(self);
 }
 
-const RESET_VECTOR_32: u32 = 0xBC00_0000;
-const MEM_WORD_DELAY: usize = 38;
+const RESET_VECTOR_32: u32 = 0xBFC0_0000;
+pub(crate) const MEM_WORD_DELAY: usize = 38;
+// A refill pays the latency of the first word, then streams in the rest.
+pub(crate) const ICACHE_REFILL_DELAY: u64 = MEM_WORD_DELAY as u64 + 7 * 2;
+pub(crate) const DCACHE_REFILL_DELAY: u64 = MEM_WORD_DELAY as u64 + 3 * 2;
+pub(crate) const DCACHE_WRITEBACK_DELAY: u64 = 4 * 2;
 
-impl<MB: MemoryBus, IC: InstructionCache, MM: MMU> CPU for InterpCPU32bit<MB, IC, MM>
+impl<MB, IC, DC, MM> CPU for InterpCPU32bit<MB, IC, DC, MM>
 where
+    MB: MemoryBus,
+    IC: InstructionCache,
+    DC: DataCache,
+    MM: MMU,
+    IC::AddressSize: From<u32>,
+    DC::AddressSize: From<u32>,
     MM::AddressSize: From<u32>,
 {
     fn new(pifrom_src: &mut impl std::io::Read) -> Self {
-        InterpCPU32bit {
+        let mut cpu = InterpCPU32bit {
             pc: RESET_VECTOR_32,
+            next_pc: RESET_VECTOR_32 + 4,
+            delay_slot: false,
+            gpr: [0; 32],
+            hi: 0,
+            lo: 0,
+            llbit: false,
+            fpr: [0; 32],
+            fcr31: 0,
+            cop0: Cop0::default(),
             bus: MB::new(pifrom_src),
             icache: IC::default(),
+            dcache: DC::default(),
             mmu: MM::default(),
+            stall: 0,
+            pipeline: None,
+            #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+            jit: None,
             cmd_queue: VecDeque::with_capacity(64),
-        }
+        };
+        let now = cpu.bus.now();
+        cpu.bus.schedule_timer(cpu.cop0.compare_time(now));
+        cpu
     }
 
     /*fn instruction_fetch(&mut self, _: CpuCommand) {
@@ -122,6 +1068,22 @@ where
         }
     }*/
 
+    fn step(&mut self) {
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        {
+            if let Some(cycles) = self.step_jit() {
+                self.bus.tick(cycles);
+                return;
+            }
+        }
+        self.step_instruction();
+        let stall = std::mem::take(&mut self.stall);
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.elapse(1 + stall);
+        }
+        self.bus.tick(1 + stall);
+    }
+
     fn run(mut self) {
         loop {
             while let Some(cmd) = self.cmd_queue.pop_front() {
@@ -129,19 +1091,46 @@ where
                     InstructionFetch => {}
                 }
             }
+            self.step();
         }
     }
 }
 
-impl<MB: MemoryBus, IC: InstructionCache, MM: MMU> InterpCPU32bit<MB, IC, MM> {
-    fn icache_fetch(icache: &IC, addr: IC::AddressSize) -> IC::Output {
-        icache.fetch(addr)
+impl<MB: MemoryBus, IC: InstructionCache, DC: DataCache, MM: MMU> InterpCPU32bit<MB, IC, DC, MM> {
+    pub fn bus_mut(&mut self) -> &mut MB {
+        &mut self.bus
+    }
+
+    // Pipeline timing is slower to run, so it's off unless asked for.
+    pub fn set_pipelined(&mut self, pipelined: bool) {
+        self.pipeline = if pipelined {
+            Some(Pipeline::default())
+        } else {
+            None
+        };
+    }
+
+    // Runs translated code where it can. In differential mode every block
+    // is also run by the interpreter, which panics if they disagree.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    pub fn enable_jit(&mut self, differential: bool) {
+        self.jit = Some(jit::Jit::new(differential));
+    }
+
+    // The caches, for debuggers to inspect.
+    pub fn icache(&self) -> &IC {
+        &self.icache
+    }
+
+    pub fn dcache(&self) -> &DC {
+        &self.dcache
     }
 }
 
 pub trait MMU: Default {
     type AddressSize;
     fn is_cached(addr: Self::AddressSize) -> bool;
+    fn translate(addr: Self::AddressSize) -> Option<u32>;
 }
 
 pub struct MMU32Bit {}
@@ -155,8 +1144,17 @@ impl Default for MMU32Bit {
 impl MMU for MMU32Bit {
     type AddressSize = u32;
 
+    // kseg1 is the only unmapped segment that bypasses the caches.
     fn is_cached(addr: u32) -> bool {
-        addr >= 0xA000_0000 && addr < 0xC000_0000
+        !(0xA000_0000..0xC000_0000).contains(&addr)
+    }
+
+    // kseg0 and kseg1 are direct mapped; everything else needs the TLB.
+    fn translate(addr: u32) -> Option<u32> {
+        match addr {
+            0x8000_0000..=0xBFFF_FFFF => Some(addr & 0x1FFF_FFFF),
+            _ => None,
+        }
     }
 }
 
@@ -179,6 +1177,177 @@ mod tests {
         assert_eq!(opts.pifrom_path, "pifdata.bin");
     }
 
+    #[test]
+    fn rdp_replay_options() {
+        let args: &[&str] = &["capture.rdp", "frame.ppm"];
+        let opts = RdpReplayOptions::parse_args(args, ParsingStyle::AllOptions).unwrap();
+        assert_eq!(opts.capture_path, "capture.rdp");
+        assert_eq!(opts.image_path, "frame.ppm");
+        assert!(RdpReplayOptions::parse_args(&["capture.rdp"], ParsingStyle::AllOptions).is_err());
+    }
+
+    #[test]
+    fn save_type_option() {
+        let args: &[&str] = &[
+            "--pifrom=pifdata.bin",
+            "--rom=game.z64",
+            "--save-type=flashram",
+        ];
+        let opts = EmuOptions::parse_args(args, ParsingStyle::AllOptions).unwrap();
+        assert_eq!(opts.rom_path.as_deref(), Some("game.z64"));
+        assert_eq!(opts.save_type, Some(SaveType::FlashRam));
+    }
+
+    #[test]
+    fn pi_dma_copies_rom_to_rdram() {
+        let mut rom = vec![0u8; 0x2000];
+        rom[0x1000..0x1008].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut mem = Memory::new(&mut &[0u8; PIFROM_SIZE][..]);
+        mem.insert_cartridge(Cartridge::new(&mut rom.as_slice()));
+        mem.write_word(PI_REGS_START, 0x0000_0100);
+        mem.write_word(PI_REGS_START + 0x04, 0x1000_1000);
+        mem.write_word(PI_REGS_START + 0x0C, 0x0000_0007);
+        assert_eq!(mem.read_word(0x0000_0100), 0);
+        assert!(mem.scheduler().is_scheduled(Event::PiDmaComplete));
+        mem.tick(1_000);
+        assert_eq!(mem.read_word(0x0000_0100), 0x0102_0304);
+        assert_eq!(mem.read_word(0x0000_0104), 0x0506_0708);
+    }
+
+    #[test]
+    fn mi_interrupt_enters_exception_vector() {
+        let mut cpu: InterpCPU32bit<Memory, ICache, DCache, MMU32Bit> =
+            InterpCPU32bit::new(&mut &[0u8; PIFROM_SIZE][..]);
+        cpu.cop0.write(
+            cop0::COP0_STATUS,
+            cop0::STATUS_BEV | cop0::CAUSE_IP2 | cop0::STATUS_IE,
+        );
+        cpu.step();
+        let pc = cpu.pc;
+        assert_eq!(pc, RESET_VECTOR_32 + 4);
+
+        // Set the PI bit in MI_INTR_MASK, then raise a PI interrupt.
+        let set_pi_mask = 2 << (MiInterrupt::Pi as u32 * 2);
+        cpu.bus.write_word(MI_REGS_START + 0x0C, set_pi_mask);
+        cpu.bus.mi.raise(MiInterrupt::Pi);
+        cpu.step();
+        assert_eq!(cpu.pc, 0xBFC0_0380);
+        assert_eq!(cpu.cop0.read(cop0::COP0_EPC) as u32, pc);
+        assert_ne!(cpu.cop0.read(cop0::COP0_CAUSE) & cop0::CAUSE_IP2, 0);
+        assert_ne!(cpu.cop0.status() & cop0::STATUS_EXL, 0);
+    }
+
+    #[test]
+    fn si_dma_runs_joybus() {
+        let mut mem = Memory::new(&mut &[0u8; PIFROM_SIZE][..]);
+        mem.write_word(0x0000_1000, 0x0103_00FF);
+        mem.write_word(0x0000_1004, 0xFFFF_FE00);
+        mem.write_word(0x0000_103C, 0x0000_0001);
+        mem.write_word(SI_REGS_START, 0x0000_1000);
+        mem.write_word(SI_REGS_START + 0x10, PIF_RAM_START);
+        mem.tick(6_000);
+        assert!(mem.mi.read_word(0x08) & (1 << MiInterrupt::Si as u32) != 0);
+        mem.write_word(SI_REGS_START + 0x18, 0);
+
+        mem.write_word(SI_REGS_START, 0x0000_2000);
+        mem.write_word(SI_REGS_START + 0x04, PIF_RAM_START);
+        mem.tick(6_000);
+        assert_eq!(mem.read_word(0x0000_2000), 0x0103_0005);
+        assert_eq!(mem.read_word(0x0000_2004), 0x0002_FE00);
+    }
+
+    #[test]
+    fn sp_dma_loads_microcode_and_interrupts() {
+        let mut mem = Memory::new(&mut &[0u8; PIFROM_SIZE][..]);
+        mem.write_word(0x0000_2000, 0x3401_0055); // ori $1, $0, 0x55
+        mem.write_word(0x0000_2004, 0xAC01_0000); // sw $1, 0($0)
+        mem.write_word(0x0000_2008, 0x0000_000D); // break
+        mem.write_word(SP_REGS_START, 0x1000);
+        mem.write_word(SP_REGS_START + 0x04, 0x2000);
+        mem.write_word(SP_REGS_START + 0x08, 15);
+        assert_ne!(mem.read_word(SP_REGS_START + SP_STATUS) & 0x4, 0);
+        mem.tick(100);
+        assert_eq!(mem.read_word(SP_MEM_START + 0x1000), 0x3401_0055);
+        assert_eq!(mem.read_word(SP_REGS_START + SP_STATUS) & 0x4, 0);
+
+        // Clear halt and broke, interrupt on break.
+        mem.write_word(SP_PC_REGS_START, 0);
+        mem.write_word(SP_REGS_START + SP_STATUS, 0x105);
+        mem.tick(100);
+        assert_eq!(mem.read_word(SP_MEM_START), 0x55);
+        assert_eq!(mem.read_word(SP_REGS_START + SP_STATUS) & 0x3, 0x3);
+        assert_eq!(mem.read_word(SP_PC_REGS_START), 0xC);
+        assert!(mem.mi.read_word(0x08) & (1 << MiInterrupt::Sp as u32) != 0);
+        mem.write_word(SP_REGS_START + SP_STATUS, 1 << 3);
+        assert!(mem.mi.read_word(0x08) & (1 << MiInterrupt::Sp as u32) == 0);
+    }
+
+    #[test]
+    fn dp_runs_command_list_and_interrupts() {
+        let mut mem = Memory::new(&mut &[0u8; PIFROM_SIZE][..]);
+        let commands: [u64; 6] = [
+            0x3F10_0007_0000_2000, // 16-bit colour image, 8 wide, at 0x2000
+            0x2D00_0000_0020_0020, // scissor (0, 0)-(8, 8)
+            0x2F30_0000_0000_0000, // fill mode
+            0x3700_0000_1234_5678, // fill colour
+            0x3600_C004_0000_0004, // fill (0, 1)-(3, 1)
+            0x2900_0000_0000_0000, // sync full
+        ];
+        for (i, command) in commands.iter().enumerate() {
+            let addr = 0x1000 + i as u32 * 8;
+            mem.write_word(addr, (command >> 32) as u32);
+            mem.write_word(addr + 4, *command as u32);
+        }
+        mem.write_word(DP_REGS_START, 0x1000);
+        mem.write_word(DP_REGS_START + 0x04, 0x1000 + 5 * 8);
+        assert!(mem.mi.read_word(0x08) & (1 << MiInterrupt::Dp as u32) == 0);
+        assert_eq!(mem.read_word(0x2010), 0x1234_5678);
+        assert_eq!(mem.read_word(0x2014), 0x1234_5678);
+        assert_eq!(mem.read_word(0x2018), 0);
+
+        mem.write_word(DP_REGS_START + 0x04, 0x1000 + 6 * 8);
+        assert_eq!(mem.read_word(DP_REGS_START + 0x08), 0x1030);
+        assert!(mem.mi.read_word(0x08) & (1 << MiInterrupt::Dp as u32) != 0);
+        mem.write_word(MI_REGS_START, 1 << 11);
+        assert!(mem.mi.read_word(0x08) & (1 << MiInterrupt::Dp as u32) == 0);
+    }
+
+    #[test]
+    fn icache_is_virtually_indexed_and_physically_tagged() {
+        let mut icache = ICache::default();
+        let data = [0, 0x1234_5678, 0, 0, 0, 0, 0, 0];
+        icache.fill(0x0000_2040, 0x0040_0040, data);
+        let line = icache.line(0x0000_2040);
+        assert!(line.valid());
+        assert_eq!(line.tag(), 0x00400);
+        assert_eq!(line.data(), data);
+        assert!(!icache.line(0x0040_0040).valid());
+        assert_eq!(icache.lines().iter().filter(|line| line.valid()).count(), 1);
+
+        assert_eq!(icache.fetch(0x0000_2044, 0x0040_0044), Some(0x1234_5678));
+        assert_eq!(icache.fetch(0x0000_2044, 0x0000_2044), None);
+        icache.invalidate_hit(0x0000_2040, 0x0000_2040);
+        assert!(icache.line(0x0000_2040).valid());
+        icache.invalidate_hit(0x0000_2040, 0x0040_0040);
+        assert!(!icache.line(0x0000_2040).valid());
+    }
+
+    #[test]
+    fn expansion_pak_rdram() {
+        let args: &[&str] = &["--pifrom=pifdata.bin", "--rdram=8M"];
+        let opts = EmuOptions::parse_args(args, ParsingStyle::AllOptions).unwrap();
+        assert_eq!(opts.rdram, Some(RdramSize::Size8M));
+
+        let mut mem = Memory::new(&mut &[0u8; PIFROM_SIZE][..]);
+        mem.write_word(0x0060_0000, 0x1234_5678);
+        assert_eq!(mem.read_word(0x0060_0000), 0);
+        assert_eq!(mem.read_word(RDRAM_REGS_START + 0x1800), 0);
+        mem.set_rdram_size(RdramSize::Size8M);
+        mem.write_word(0x0060_0000, 0x1234_5678);
+        assert_eq!(mem.read_word(0x0060_0000), 0x1234_5678);
+        assert_ne!(mem.read_word(RDRAM_REGS_START + 0x1800), 0);
+    }
+
     #[test]
     fn help_no_pifrom() {
         let args: &[&str] = &["--help"];
@@ -0,0 +1,159 @@
+pub const MI_REGS_START: u32 = 0x0430_0000;
+pub const MI_REGS_END: u32 = 0x043F_FFFF;
+
+const MI_MODE: u32 = 0x00;
+const MI_VERSION: u32 = 0x04;
+const MI_INTR: u32 = 0x08;
+const MI_INTR_MASK: u32 = 0x0C;
+
+// RSP 2.0, RDP 2.0, RAC 1.0, IO 2.0
+const MI_VERSION_VALUE: u32 = 0x0202_0102;
+
+const MI_MODE_INIT_LENGTH: u32 = 0x7F;
+const MI_MODE_CLEAR_INIT: u32 = 1 << 7;
+const MI_MODE_SET_INIT: u32 = 1 << 8;
+const MI_MODE_CLEAR_EBUS: u32 = 1 << 9;
+const MI_MODE_SET_EBUS: u32 = 1 << 10;
+const MI_MODE_CLEAR_DP_INTR: u32 = 1 << 11;
+const MI_MODE_CLEAR_RDRAM: u32 = 1 << 12;
+const MI_MODE_SET_RDRAM: u32 = 1 << 13;
+
+const MI_MODE_INIT: u32 = 1 << 7;
+const MI_MODE_EBUS: u32 = 1 << 8;
+const MI_MODE_RDRAM: u32 = 1 << 9;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MiInterrupt {
+    Sp = 0,
+    Si = 1,
+    Ai = 2,
+    Vi = 3,
+    Pi = 4,
+    Dp = 5,
+}
+
+#[derive(Default)]
+pub struct Mi {
+    mode: u32,
+    intr: u32,
+    intr_mask: u32,
+}
+
+impl Mi {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            MI_MODE => self.mode,
+            MI_VERSION => MI_VERSION_VALUE,
+            MI_INTR => self.intr,
+            MI_INTR_MASK => self.intr_mask,
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        match offset {
+            MI_MODE => {
+                self.mode = (self.mode & !MI_MODE_INIT_LENGTH) | (value & MI_MODE_INIT_LENGTH);
+                set_clear(
+                    &mut self.mode,
+                    value,
+                    MI_MODE_SET_INIT,
+                    MI_MODE_CLEAR_INIT,
+                    MI_MODE_INIT,
+                );
+                set_clear(
+                    &mut self.mode,
+                    value,
+                    MI_MODE_SET_EBUS,
+                    MI_MODE_CLEAR_EBUS,
+                    MI_MODE_EBUS,
+                );
+                set_clear(
+                    &mut self.mode,
+                    value,
+                    MI_MODE_SET_RDRAM,
+                    MI_MODE_CLEAR_RDRAM,
+                    MI_MODE_RDRAM,
+                );
+                if value & MI_MODE_CLEAR_DP_INTR != 0 {
+                    self.clear(MiInterrupt::Dp);
+                }
+            }
+            MI_INTR_MASK => {
+                // Each interrupt has a clear bit followed by a set bit.
+                for line in 0..6 {
+                    set_clear(
+                        &mut self.intr_mask,
+                        value,
+                        2 << (line * 2),
+                        1 << (line * 2),
+                        1 << line,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn raise(&mut self, line: MiInterrupt) {
+        self.intr |= 1 << line as u32;
+    }
+
+    pub fn clear(&mut self, line: MiInterrupt) {
+        self.intr &= !(1 << line as u32);
+    }
+
+    pub fn set_line(&mut self, line: MiInterrupt, level: bool) {
+        if level {
+            self.raise(line);
+        } else {
+            self.clear(line);
+        }
+    }
+
+    // The MI drives the CPU's external interrupt 0, Cause.IP2.
+    pub fn pending(&self) -> bool {
+        self.intr & self.intr_mask != 0
+    }
+
+    pub fn rdram_reg_mode(&self) -> bool {
+        self.mode & MI_MODE_RDRAM != 0
+    }
+}
+
+fn set_clear(reg: &mut u32, value: u32, set: u32, clear: u32, bit: u32) {
+    if value & clear != 0 {
+        *reg &= !bit;
+    }
+    if value & set != 0 {
+        *reg |= bit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masked_interrupts() {
+        let mut mi = Mi::default();
+        mi.raise(MiInterrupt::Pi);
+        assert!(!mi.pending());
+        mi.write_word(MI_INTR_MASK, 2 << (MiInterrupt::Pi as u32 * 2));
+        assert_eq!(mi.read_word(MI_INTR_MASK), 1 << MiInterrupt::Pi as u32);
+        assert!(mi.pending());
+        mi.write_word(MI_INTR_MASK, 1 << (MiInterrupt::Pi as u32 * 2));
+        assert!(!mi.pending());
+    }
+
+    #[test]
+    fn mode_clears_dp_interrupt() {
+        let mut mi = Mi::default();
+        mi.raise(MiInterrupt::Dp);
+        mi.raise(MiInterrupt::Vi);
+        mi.write_word(MI_MODE, MI_MODE_CLEAR_DP_INTR | MI_MODE_SET_RDRAM | 0x0F);
+        assert_eq!(mi.read_word(MI_INTR), 1 << MiInterrupt::Vi as u32);
+        assert_eq!(mi.read_word(MI_MODE), MI_MODE_RDRAM | 0x0F);
+        assert_eq!(mi.read_word(MI_VERSION), MI_VERSION_VALUE);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::scheduler::{rcp_to_cpu_cycles, Event, Scheduler};
+
+pub const PI_REGS_START: u32 = 0x0460_0000;
+pub const PI_REGS_END: u32 = 0x046F_FFFF;
+
+const PI_DRAM_ADDR: u32 = 0x00;
+const PI_CART_ADDR: u32 = 0x04;
+const PI_RD_LEN: u32 = 0x08;
+const PI_WR_LEN: u32 = 0x0C;
+const PI_STATUS: u32 = 0x10;
+const PI_BSD_DOM1_LAT: u32 = 0x14;
+const PI_BSD_DOM1_PWD: u32 = 0x18;
+const PI_BSD_DOM1_PGS: u32 = 0x1C;
+const PI_BSD_DOM1_RLS: u32 = 0x20;
+const PI_BSD_DOM2_LAT: u32 = 0x24;
+const PI_BSD_DOM2_PWD: u32 = 0x28;
+const PI_BSD_DOM2_PGS: u32 = 0x2C;
+const PI_BSD_DOM2_RLS: u32 = 0x30;
+
+const PI_STATUS_DMA_BUSY: u32 = 0x01;
+const PI_STATUS_IO_BUSY: u32 = 0x02;
+const PI_STATUS_ERROR: u32 = 0x04;
+const PI_STATUS_INTERRUPT: u32 = 0x08;
+const PI_STATUS_RESET: u32 = 0x01;
+const PI_STATUS_CLEAR_INTERRUPT: u32 = 0x02;
+
+// Domain 2 is the 64DD registers (address 1) and SRAM/FlashRAM (address 2).
+// The 64DD IPL ROM between them, 0x0600_0000-0x07FF_FFFF, is domain 1.
+const DOM2_ADDR1_START: u32 = 0x0500_0000;
+const DOM2_ADDR1_END: u32 = 0x05FF_FFFF;
+const DOM2_ADDR2_START: u32 = 0x0800_0000;
+const DOM2_ADDR2_END: u32 = 0x0FFF_FFFF;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PiDmaDirection {
+    // PI_RD_LEN: RDRAM to cartridge
+    ToCart,
+    // PI_WR_LEN: cartridge to RDRAM
+    ToDram,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PiDma {
+    pub dram_addr: u32,
+    pub cart_addr: u32,
+    pub len: u32,
+    pub direction: PiDmaDirection,
+}
+
+// Per-domain bus timing, in RCP cycles. The registers hold one less than the
+// number of cycles except for the page size, which is a power of two.
+#[derive(Debug, Copy, Clone, Default)]
+struct DomainTiming {
+    latency: u8,
+    pulse_width: u8,
+    page_size: u8,
+    release: u8,
+}
+
+impl DomainTiming {
+    // Each page pays the latency once; each 16-bit transfer within a page
+    // costs a read/write pulse plus the release time.
+    fn transfer_cycles(&self, len: u32) -> u64 {
+        let page_bytes = 1u64 << (u32::from(self.page_size) + 2);
+        let pages = u64::from(len).div_ceil(page_bytes);
+        let halfwords = u64::from(len).div_ceil(2);
+        pages * (u64::from(self.latency) + 1)
+            + halfwords * (u64::from(self.pulse_width) + 1 + u64::from(self.release) + 1)
+    }
+}
+
+#[derive(Default)]
+pub struct Pi {
+    dram_addr: u32,
+    cart_addr: u32,
+    status: u32,
+    domains: [DomainTiming; 2],
+    pending: Option<PiDma>,
+}
+
+impl Pi {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            PI_DRAM_ADDR => self.dram_addr,
+            PI_CART_ADDR => self.cart_addr,
+            PI_RD_LEN | PI_WR_LEN => 0x7F,
+            PI_STATUS => self.status,
+            PI_BSD_DOM1_LAT => self.domains[0].latency.into(),
+            PI_BSD_DOM1_PWD => self.domains[0].pulse_width.into(),
+            PI_BSD_DOM1_PGS => self.domains[0].page_size.into(),
+            PI_BSD_DOM1_RLS => self.domains[0].release.into(),
+            PI_BSD_DOM2_LAT => self.domains[1].latency.into(),
+            PI_BSD_DOM2_PWD => self.domains[1].pulse_width.into(),
+            PI_BSD_DOM2_PGS => self.domains[1].page_size.into(),
+            PI_BSD_DOM2_RLS => self.domains[1].release.into(),
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32, scheduler: &mut Scheduler) {
+        match offset {
+            PI_DRAM_ADDR => self.dram_addr = value & 0x00FF_FFFE,
+            PI_CART_ADDR => self.cart_addr = value & 0xFFFF_FFFE,
+            PI_RD_LEN => self.start_dma(value, PiDmaDirection::ToCart, scheduler),
+            PI_WR_LEN => self.start_dma(value, PiDmaDirection::ToDram, scheduler),
+            PI_STATUS => {
+                if value & PI_STATUS_RESET != 0 {
+                    self.pending = None;
+                    scheduler.cancel(Event::PiDmaComplete);
+                    self.status &= !(PI_STATUS_DMA_BUSY | PI_STATUS_IO_BUSY | PI_STATUS_ERROR);
+                }
+                if value & PI_STATUS_CLEAR_INTERRUPT != 0 {
+                    self.status &= !PI_STATUS_INTERRUPT;
+                }
+            }
+            PI_BSD_DOM1_LAT => self.domains[0].latency = value as u8,
+            PI_BSD_DOM1_PWD => self.domains[0].pulse_width = value as u8,
+            PI_BSD_DOM1_PGS => self.domains[0].page_size = (value & 0xF) as u8,
+            PI_BSD_DOM1_RLS => self.domains[0].release = (value & 0x3) as u8,
+            PI_BSD_DOM2_LAT => self.domains[1].latency = value as u8,
+            PI_BSD_DOM2_PWD => self.domains[1].pulse_width = value as u8,
+            PI_BSD_DOM2_PGS => self.domains[1].page_size = (value & 0xF) as u8,
+            PI_BSD_DOM2_RLS => self.domains[1].release = (value & 0x3) as u8,
+            _ => {}
+        }
+    }
+
+    fn start_dma(&mut self, len: u32, direction: PiDmaDirection, scheduler: &mut Scheduler) {
+        if self.status & PI_STATUS_DMA_BUSY != 0 {
+            self.status |= PI_STATUS_ERROR;
+            return;
+        }
+        let dma = PiDma {
+            dram_addr: self.dram_addr,
+            cart_addr: self.cart_addr,
+            len: (len & 0x00FF_FFFF) + 1,
+            direction,
+        };
+        let domain = match self.cart_addr {
+            DOM2_ADDR1_START..=DOM2_ADDR1_END | DOM2_ADDR2_START..=DOM2_ADDR2_END => {
+                &self.domains[1]
+            }
+            _ => &self.domains[0],
+        };
+        scheduler.schedule(
+            rcp_to_cpu_cycles(domain.transfer_cycles(dma.len)),
+            Event::PiDmaComplete,
+        );
+        self.pending = Some(dma);
+        self.status |= PI_STATUS_DMA_BUSY;
+    }
+
+    // Called when Event::PiDmaComplete fires. The transfer is handed back to
+    // the bus to perform the copy.
+    pub fn complete_dma(&mut self) -> Option<PiDma> {
+        let dma = self.pending.take()?;
+        let len = (dma.len + 1) & !1;
+        self.dram_addr = (self.dram_addr + len) & 0x00FF_FFFE;
+        self.cart_addr = self.cart_addr.wrapping_add(len);
+        self.status &= !(PI_STATUS_DMA_BUSY | PI_STATUS_IO_BUSY);
+        self.status |= PI_STATUS_INTERRUPT;
+        Some(dma)
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.status & PI_STATUS_INTERRUPT != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dma_takes_domain_time() {
+        let mut pi = Pi::default();
+        let mut scheduler = Scheduler::default();
+        // Typical IPL3 domain 1 settings: LAT=0x40, PWD=0x12, PGS=7, RLS=3.
+        pi.write_word(PI_BSD_DOM1_LAT, 0x40, &mut scheduler);
+        pi.write_word(PI_BSD_DOM1_PWD, 0x12, &mut scheduler);
+        pi.write_word(PI_BSD_DOM1_PGS, 0x07, &mut scheduler);
+        pi.write_word(PI_BSD_DOM1_RLS, 0x03, &mut scheduler);
+        pi.write_word(PI_DRAM_ADDR, 0x0000_0400, &mut scheduler);
+        pi.write_word(PI_CART_ADDR, 0x1000_1000, &mut scheduler);
+        pi.write_word(PI_WR_LEN, 0x0000_01FF, &mut scheduler);
+        assert_eq!(
+            pi.read_word(PI_STATUS) & PI_STATUS_DMA_BUSY,
+            PI_STATUS_DMA_BUSY
+        );
+
+        // 512 bytes = one 512 byte page of 256 halfword transfers.
+        let expected = (65 + 256 * (19 + 4)) * 3 / 2;
+        assert_eq!(scheduler.time_of(Event::PiDmaComplete), Some(expected));
+        scheduler.advance(expected - 1);
+        assert_eq!(scheduler.pop_due(), None);
+        scheduler.advance(1);
+        assert_eq!(scheduler.pop_due(), Some(Event::PiDmaComplete));
+        let dma = pi.complete_dma().unwrap();
+        assert_eq!(dma.len, 0x200);
+        assert_eq!(dma.direction, PiDmaDirection::ToDram);
+        assert_eq!(pi.read_word(PI_STATUS), PI_STATUS_INTERRUPT);
+        assert_eq!(pi.read_word(PI_DRAM_ADDR), 0x0000_0600);
+        assert_eq!(pi.read_word(PI_CART_ADDR), 0x1000_1200);
+
+        pi.write_word(PI_STATUS, PI_STATUS_CLEAR_INTERRUPT, &mut scheduler);
+        assert!(!pi.interrupt());
+    }
+
+    #[test]
+    fn dma_picks_domain_by_address() {
+        for &(cart_addr, domain2) in &[
+            (0x0500_0000, true),
+            (0x0600_0000, false),
+            (0x07FF_FFFE, false),
+            (0x0800_0000, true),
+            (0x1000_0000, false),
+        ] {
+            let mut pi = Pi::default();
+            let mut scheduler = Scheduler::default();
+            pi.write_word(PI_BSD_DOM2_LAT, 0x40, &mut scheduler);
+            pi.write_word(PI_CART_ADDR, cart_addr, &mut scheduler);
+            pi.write_word(PI_WR_LEN, 0x1, &mut scheduler);
+            // One halfword: the latency plus a two-cycle pulse and release.
+            let latency = if domain2 { 0x41 } else { 1 };
+            assert_eq!(
+                scheduler.time_of(Event::PiDmaComplete),
+                Some(rcp_to_cpu_cycles(latency + 2)),
+                "{:#010x}",
+                cart_addr
+            );
+        }
+    }
+
+    #[test]
+    fn dma_while_busy_is_an_error() {
+        let mut pi = Pi::default();
+        let mut scheduler = Scheduler::default();
+        pi.write_word(PI_WR_LEN, 0x7, &mut scheduler);
+        pi.write_word(PI_WR_LEN, 0x7, &mut scheduler);
+        assert_ne!(pi.read_word(PI_STATUS) & PI_STATUS_ERROR, 0);
+        pi.write_word(PI_STATUS, PI_STATUS_RESET, &mut scheduler);
+        assert_eq!(pi.read_word(PI_STATUS), 0);
+        assert!(!scheduler.is_scheduled(Event::PiDmaComplete));
+        assert_eq!(pi.complete_dma(), None);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::save::{ControllerPak, Save};
+
+pub const PIF_RAM_SIZE: usize = 64;
+const PIF_COMMAND_BYTE: usize = PIF_RAM_SIZE - 1;
+const JOYBUS_CHANNELS: usize = 5;
+const EEPROM_CHANNEL: usize = 4;
+
+const JOYBUS_SKIP_CHANNEL: u8 = 0x00;
+const JOYBUS_END: u8 = 0xFE;
+const JOYBUS_PADDING: u8 = 0xFF;
+const JOYBUS_RESET: u8 = 0xFD;
+const JOYBUS_NO_RESPONSE: u8 = 0x80;
+
+pub struct Controller {
+    pub buttons: u16,
+    pub stick_x: i8,
+    pub stick_y: i8,
+    pub pak: Option<ControllerPak>,
+}
+
+impl Controller {
+    pub fn new(pak: Option<ControllerPak>) -> Self {
+        Controller {
+            buttons: 0,
+            stick_x: 0,
+            stick_y: 0,
+            pak,
+        }
+    }
+
+    fn joybus_command(&mut self, tx: &[u8], rx: &mut [u8]) -> bool {
+        match tx.first() {
+            Some(0x00) | Some(0xFF) if rx.len() >= 3 => {
+                rx[0] = 0x05;
+                rx[1] = 0x00;
+                rx[2] = if self.pak.is_some() { 0x01 } else { 0x02 };
+                true
+            }
+            Some(0x01) if rx.len() >= 4 => {
+                rx[..2].copy_from_slice(&self.buttons.to_be_bytes());
+                rx[2] = self.stick_x as u8;
+                rx[3] = self.stick_y as u8;
+                true
+            }
+            Some(0x02) if tx.len() >= 3 && rx.len() >= 33 => match &mut self.pak {
+                Some(pak) => {
+                    pak.read(tx, rx);
+                    true
+                }
+                None => false,
+            },
+            Some(0x03) if tx.len() >= 35 && !rx.is_empty() => match &mut self.pak {
+                Some(pak) => {
+                    pak.write(tx, rx);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+pub struct Pif {
+    pub ram: [u8; PIF_RAM_SIZE],
+    pub controllers: [Option<Controller>; 4],
+}
+
+impl Default for Pif {
+    fn default() -> Self {
+        Pif {
+            ram: [0; PIF_RAM_SIZE],
+            controllers: [Some(Controller::new(None)), None, None, None],
+        }
+    }
+}
+
+impl Pif {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        let offset = (offset as usize) & (PIF_RAM_SIZE - 4);
+        u32::from_be_bytes([
+            self.ram[offset],
+            self.ram[offset + 1],
+            self.ram[offset + 2],
+            self.ram[offset + 3],
+        ])
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        let offset = (offset as usize) & (PIF_RAM_SIZE - 4);
+        self.ram[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    // Walks the command blocks in PIF RAM, dispatching each one to the
+    // device on its channel and writing the response back in place.
+    pub fn process_joybus(&mut self, save: &mut Save) {
+        if self.ram[PIF_COMMAND_BYTE] & 0x01 == 0 {
+            return;
+        }
+        self.ram[PIF_COMMAND_BYTE] &= !0x01;
+
+        let mut channel = 0;
+        let mut i = 0;
+        while i < PIF_COMMAND_BYTE && channel < JOYBUS_CHANNELS {
+            let tx_len = self.ram[i];
+            match tx_len {
+                JOYBUS_SKIP_CHANNEL => {
+                    channel += 1;
+                    i += 1;
+                    continue;
+                }
+                JOYBUS_END => break,
+                JOYBUS_PADDING | JOYBUS_RESET => {
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            if i + 1 >= PIF_COMMAND_BYTE {
+                break;
+            }
+            let tx_len = (tx_len & 0x3F) as usize;
+            let rx_len = (self.ram[i + 1] & 0x3F) as usize;
+            let tx_start = i + 2;
+            let rx_start = tx_start + tx_len;
+            let end = rx_start + rx_len;
+            if end > PIF_COMMAND_BYTE {
+                break;
+            }
+
+            let (head, tail) = self.ram.split_at_mut(rx_start);
+            let tx = &head[tx_start..];
+            let rx = &mut tail[..rx_len];
+            let handled = if channel == EEPROM_CHANNEL {
+                match save {
+                    Save::Eeprom(eeprom) => eeprom.joybus_command(tx, rx),
+                    _ => false,
+                }
+            } else {
+                match &mut self.controllers[channel] {
+                    Some(controller) => controller.joybus_command(tx, rx),
+                    None => false,
+                }
+            };
+            if !handled {
+                self.ram[i + 1] |= JOYBUS_NO_RESPONSE;
+            }
+
+            channel += 1;
+            i = end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::SaveType;
+
+    #[test]
+    fn controller_status() {
+        let mut pif = Pif::default();
+        pif.ram[..6].copy_from_slice(&[0x01, 0x03, 0x00, 0xFF, 0xFF, 0xFF]);
+        pif.ram[6] = JOYBUS_END;
+        pif.ram[PIF_COMMAND_BYTE] = 0x01;
+        pif.process_joybus(&mut Save::None);
+        assert_eq!(pif.ram[..6], [0x01, 0x03, 0x00, 0x05, 0x00, 0x02]);
+        assert_eq!(pif.ram[PIF_COMMAND_BYTE], 0x00);
+    }
+
+    #[test]
+    fn missing_controller() {
+        let mut pif = Pif::default();
+        pif.ram[..7].copy_from_slice(&[0x00, 0x01, 0x03, 0x00, 0xFF, 0xFF, 0xFF]);
+        pif.ram[7] = JOYBUS_END;
+        pif.ram[PIF_COMMAND_BYTE] = 0x01;
+        pif.process_joybus(&mut Save::None);
+        assert_eq!(pif.ram[2], 0x03 | JOYBUS_NO_RESPONSE);
+    }
+
+    #[test]
+    fn eeprom_on_channel_four() {
+        let mut pif = Pif::default();
+        let mut save = Save::new(None, SaveType::Eeprom16K);
+        pif.ram[..4].copy_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        pif.ram[4..9].copy_from_slice(&[0x01, 0x03, 0x00, 0xFF, 0xFF]);
+        pif.ram[9] = 0xFF;
+        pif.ram[10] = JOYBUS_END;
+        pif.ram[PIF_COMMAND_BYTE] = 0x01;
+        pif.process_joybus(&mut save);
+        assert_eq!(pif.ram[7..10], [0x00, 0xC0, 0x00]);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::decoder::{decode_vr4300, CpuInstrVR4300, CpuInstrVR4300::*};
+
+// Cycles from a multiply or divide entering EX until HI and LO hold its
+// result.
+const MULT_LATENCY: u64 = 5;
+const DMULT_LATENCY: u64 = 8;
+const DIV_LATENCY: u64 = 37;
+const DDIV_LATENCY: u64 = 69;
+
+// A load's result is ready at the end of DC, one cycle too late for an
+// instruction in EX right behind it.
+const LOAD_USE_STALL: u64 = 1;
+
+// MTC0 writes in WB, so interrupts are still sampled with the old Status
+// for the two instructions behind it.
+pub const COP0_HAZARD: u32 = 2;
+
+fn rs(iw: u32) -> Option<usize> {
+    Some(((iw >> 21) & 0x1F) as usize)
+}
+
+fn rt(iw: u32) -> Option<usize> {
+    Some(((iw >> 16) & 0x1F) as usize)
+}
+
+// The general purpose registers an instruction reads in EX.
+fn sources(instr: CpuInstrVR4300, iw: u32) -> [Option<usize>; 2] {
+    match instr {
+        ADD | ADDU | SUB | SUBU | DADD | DADDU | DSUB | DSUBU | AND | OR | XOR | NOR | SLT
+        | SLTU | SLLV | SRLV | SRAV | DSLLV | DSRLV | DSRAV | MULT | MULTU | DIV | DIVU | DMULT
+        | DMULTU | DDIV | DDIVU | TGE | TGEU | TLT | TLTU | TEQ | TNE | BEQ | BNE | BEQL | BNEL
+        | SB | SH | SW | SD | SWL | SWR | SDL | SDR | SC | SCD | LWL | LWR | LDL | LDR => {
+            [rs(iw), rt(iw)]
+        }
+        SLL | SRL | SRA | DSLL | DSRL | DSRA | DSLL32 | DSRL32 | DSRA32 | MTC0 | DMTC0 | MTC1
+        | DMTC1 | CTC1 => [rt(iw), None],
+        ADDI | ADDIU | DADDI | DADDIU | SLTI | SLTIU | ANDI | ORI | XORI | LB | LBU | LH | LHU
+        | LW | LWU | LD | LL | LLD | LWC1 | LDC1 | SWC1 | SDC1 | CACHE | JR | JALR | MTHI
+        | MTLO | BLEZ | BGTZ | BLEZL | BGTZL | BLTZ | BGEZ | BLTZL | BGEZL | BLTZAL | BGEZAL
+        | BLTZALL | BGEZALL | TGEI | TGEIU | TLTI | TLTIU | TEQI | TNEI => [rs(iw), None],
+        _ => [None, None],
+    }
+}
+
+// The register an instruction writes late, at the end of DC.
+fn late_result(instr: CpuInstrVR4300, iw: u32) -> Option<usize> {
+    match instr {
+        LB | LBU | LH | LHU | LW | LWU | LWL | LWR | LD | LDL | LDR | LL | LLD | MFC0 | DMFC0
+        | MFC1 | DMFC1 | CFC1 => rt(iw),
+        _ => None,
+    }
+}
+
+fn hilo_latency(instr: CpuInstrVR4300) -> Option<u64> {
+    match instr {
+        MULT | MULTU => Some(MULT_LATENCY),
+        DMULT | DMULTU => Some(DMULT_LATENCY),
+        DIV | DIVU => Some(DIV_LATENCY),
+        DDIV | DDIVU => Some(DDIV_LATENCY),
+        _ => None,
+    }
+}
+
+// Timing for the VR4300's five-stage pipeline (IC, RF, EX, DC, WB). The
+// interpreter still runs an instruction at a time; this works out how long
+// each one holds up the pipeline through load-use interlocks and waits on
+// the multiplier, and how long COP0 writes take to affect interrupts.
+// Cache misses are charged by the interpreter, and FPU latencies aren't
+// modelled.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    // The register the previous instruction loads.
+    loading: Option<usize>,
+    // Cycles until the multiplier is done with HI and LO.
+    hilo_busy: u64,
+    // Instructions left that sample interrupts with the old Status.
+    cop0_hazard: u32,
+    stale_status: u64,
+}
+
+impl Pipeline {
+    // Issues `iw` and returns the cycles it waits in RF before it can
+    // enter EX.
+    pub fn issue(&mut self, iw: u32) -> u64 {
+        let instr = decode_vr4300(iw);
+        let mut stall = 0;
+        if let Some(reg) = self.loading.take() {
+            if reg != 0 && sources(instr, iw).contains(&Some(reg)) {
+                stall += LOAD_USE_STALL;
+            }
+        }
+        let hilo = hilo_latency(instr);
+        if hilo.is_some() || matches!(instr, MFHI | MFLO) {
+            stall += std::mem::take(&mut self.hilo_busy);
+        }
+        self.hilo_busy = hilo.unwrap_or(self.hilo_busy);
+        self.loading = late_result(instr, iw);
+        stall
+    }
+
+    // Counts down the multiplier for an instruction that took `cycles`.
+    pub fn elapse(&mut self, cycles: u64) {
+        self.hilo_busy = self.hilo_busy.saturating_sub(cycles);
+    }
+
+    // Called before an MTC0 to Status with the value it replaces.
+    pub fn status_write(&mut self, status: u64) {
+        if self.cop0_hazard == 0 {
+            self.stale_status = status;
+        }
+        self.cop0_hazard = COP0_HAZARD + 1;
+    }
+
+    // The Status interrupts are sampled with at the next instruction
+    // boundary, if a recent write to it hasn't landed yet.
+    pub fn stale_status(&mut self) -> Option<u64> {
+        if self.cop0_hazard == 0 {
+            return None;
+        }
+        self.cop0_hazard -= 1;
+        if self.cop0_hazard == 0 {
+            None
+        } else {
+            Some(self.stale_status)
+        }
+    }
+
+    // Exceptions flush the pipeline.
+    pub fn flush(&mut self) {
+        self.loading = None;
+        self.cop0_hazard = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i_type(op: u32, rs: u32, rt: u32, imm: u16) -> u32 {
+        op << 26 | rs << 21 | rt << 16 | u32::from(imm)
+    }
+
+    #[test]
+    fn load_use_interlock() {
+        let mut pipeline = Pipeline::default();
+        assert_eq!(pipeline.issue(i_type(0x23, 4, 8, 0)), 0); // lw t0, 0(a0)
+        assert_eq!(pipeline.issue(i_type(0x09, 8, 9, 1)), 1); // addiu t1, t0, 1
+        assert_eq!(pipeline.issue(i_type(0x23, 4, 8, 0)), 0); // lw t0, 0(a0)
+        assert_eq!(pipeline.issue(i_type(0x09, 10, 9, 1)), 0); // addiu t1, t2, 1
+        assert_eq!(pipeline.issue(i_type(0x09, 8, 9, 1)), 0); // addiu t1, t0, 1
+
+        // A load into $zero never interlocks.
+        assert_eq!(pipeline.issue(i_type(0x23, 4, 0, 0)), 0); // lw zero, 0(a0)
+        assert_eq!(pipeline.issue(i_type(0x09, 0, 9, 1)), 0); // addiu t1, zero, 1
+    }
+
+    #[test]
+    fn hilo_waits_for_multiplier() {
+        let mut pipeline = Pipeline::default();
+        assert_eq!(pipeline.issue(0x0109_0018), 0); // mult t0, t1
+        pipeline.elapse(1);
+        assert_eq!(pipeline.issue(0), 0); // nop
+        pipeline.elapse(1);
+        assert_eq!(pipeline.issue(0x0000_5012), MULT_LATENCY - 2); // mflo t2
+        pipeline.elapse(1 + MULT_LATENCY - 2);
+        assert_eq!(pipeline.issue(0x0000_5010), 0); // mfhi t2
+
+        // A divide behind a divide waits for the first to finish.
+        assert_eq!(pipeline.issue(0x0109_001A), 0); // div t0, t1
+        pipeline.elapse(1);
+        assert_eq!(pipeline.issue(0x0109_001A), DIV_LATENCY - 1);
+    }
+
+    #[test]
+    fn cop0_writes_land_after_hazard() {
+        let mut pipeline = Pipeline::default();
+        assert_eq!(pipeline.stale_status(), None);
+        pipeline.status_write(1);
+        pipeline.status_write(2);
+        for _ in 0..COP0_HAZARD {
+            assert_eq!(pipeline.stale_status(), Some(1));
+        }
+        assert_eq!(pipeline.stale_status(), None);
+    }
+}
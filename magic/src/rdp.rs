@@ -0,0 +1,1446 @@
+// A software implementation of the RDP. Commands are 64-bit words; most are
+// a single word, but triangles and texture rectangles carry their
+// coefficients in the words that follow.
+//
+// Pixels are rendered with full coverage: there is no antialiasing, no
+// dithering and no colour keying, and bilinear filtering takes four taps
+// rather than the hardware's three.
+
+use crate::dp::RdpBackend;
+use crate::vi::Frame;
+
+pub const TMEM_SIZE: usize = 4096;
+
+// TLUT entries live in the upper half of TMEM, each one repeated across
+// the four banks.
+const TLUT_BASE: usize = 0x800;
+
+const FORMAT_CI: u32 = 2;
+const FORMAT_IA: u32 = 3;
+
+const SIZE_4: u32 = 0;
+const SIZE_8: u32 = 1;
+const SIZE_16: u32 = 2;
+const SIZE_32: u32 = 3;
+
+const Z_MAX: u32 = 0x3FFFF;
+
+// RGBA with 8 bits per channel, widened for the combiner arithmetic.
+type Color = [i32; 4];
+
+// The number of words in the command starting with `first`.
+pub fn command_len(first: u64) -> usize {
+    match (first >> 56) & 0x3F {
+        id @ 0x08..=0x0F => {
+            let mut len = 4;
+            if id & 0x4 != 0 {
+                len += 8;
+            }
+            if id & 0x2 != 0 {
+                len += 8;
+            }
+            if id & 0x1 != 0 {
+                len += 2;
+            }
+            len
+        }
+        0x24 | 0x25 => 2,
+        _ => 1,
+    }
+}
+
+fn bits(word: u64, shift: u32, width: u32) -> u32 {
+    ((word >> shift) & ((1 << width) - 1)) as u32
+}
+
+fn sign_extend(value: u32, width: u32) -> i32 {
+    ((value << (32 - width)) as i32) >> (32 - width)
+}
+
+fn rgba16(value: u16) -> Color {
+    let channel = |shift: u16| {
+        let c = i32::from((value >> shift) & 0x1F);
+        (c << 3) | (c >> 2)
+    };
+    let alpha = if value & 1 != 0 { 0xFF } else { 0 };
+    [channel(11), channel(6), channel(1), alpha]
+}
+
+fn ia16(value: u16) -> Color {
+    let i = i32::from(value >> 8);
+    [i, i, i, i32::from(value & 0xFF)]
+}
+
+fn rgba32(value: u32) -> Color {
+    let [r, g, b, a] = value.to_be_bytes();
+    [r.into(), g.into(), b.into(), a.into()]
+}
+
+// Z is stored in RDRAM as a 3-bit exponent and 11-bit mantissa, followed
+// by two bits of the depth slope which we leave clear.
+const Z_EXPONENT_BASE: [u32; 8] = [
+    0x00000, 0x20000, 0x30000, 0x38000, 0x3C000, 0x3E000, 0x3F000, 0x3F800,
+];
+const Z_MANTISSA_SHIFT: [u32; 8] = [6, 5, 4, 3, 2, 1, 0, 0];
+
+fn compress_z(z: u32) -> u16 {
+    let z = z.min(Z_MAX);
+    let exponent = Z_EXPONENT_BASE
+        .iter()
+        .rposition(|&base| z >= base)
+        .unwrap_or(0);
+    let mantissa = ((z - Z_EXPONENT_BASE[exponent]) >> Z_MANTISSA_SHIFT[exponent]) & 0x7FF;
+    ((exponent as u16) << 13) | ((mantissa as u16) << 2)
+}
+
+fn decompress_z(value: u16) -> u32 {
+    let exponent = usize::from(value >> 13);
+    let mantissa = u32::from((value >> 2) & 0x7FF);
+    Z_EXPONENT_BASE[exponent] + (mantissa << Z_MANTISSA_SHIFT[exponent])
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+enum CycleType {
+    #[default]
+    OneCycle,
+    TwoCycle,
+    Copy,
+    Fill,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+enum ZMode {
+    #[default]
+    Opaque,
+    Interpenetrating,
+    Transparent,
+    Decal,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct OtherModes {
+    cycle_type: CycleType,
+    persp_tex: bool,
+    en_tlut: bool,
+    tlut_ia: bool,
+    bilinear: bool,
+    // Blender mux selects for each cycle: P, A, M, B.
+    blend: [[u32; 4]; 2],
+    force_blend: bool,
+    z_mode: ZMode,
+    z_update: bool,
+    z_compare: bool,
+    z_source_prim: bool,
+    alpha_compare: bool,
+}
+
+impl OtherModes {
+    fn from_word(w: u64) -> Self {
+        let blend = |cycle: u32| {
+            [
+                bits(w, 30 - cycle * 2, 2),
+                bits(w, 26 - cycle * 2, 2),
+                bits(w, 22 - cycle * 2, 2),
+                bits(w, 18 - cycle * 2, 2),
+            ]
+        };
+        OtherModes {
+            cycle_type: match bits(w, 52, 2) {
+                0 => CycleType::OneCycle,
+                1 => CycleType::TwoCycle,
+                2 => CycleType::Copy,
+                _ => CycleType::Fill,
+            },
+            persp_tex: bits(w, 51, 1) != 0,
+            en_tlut: bits(w, 47, 1) != 0,
+            tlut_ia: bits(w, 46, 1) != 0,
+            bilinear: bits(w, 45, 1) != 0,
+            blend: [blend(0), blend(1)],
+            force_blend: bits(w, 14, 1) != 0,
+            z_mode: match bits(w, 10, 2) {
+                0 => ZMode::Opaque,
+                1 => ZMode::Interpenetrating,
+                2 => ZMode::Transparent,
+                _ => ZMode::Decal,
+            },
+            z_update: bits(w, 5, 1) != 0,
+            z_compare: bits(w, 4, 1) != 0,
+            z_source_prim: bits(w, 2, 1) != 0,
+            alpha_compare: bits(w, 0, 1) != 0,
+        }
+    }
+}
+
+// Combiner mux selects for one cycle, as (A - B) * C + D.
+#[derive(Debug, Copy, Clone, Default)]
+struct CombineCycle {
+    rgb: [u32; 4],
+    alpha: [u32; 4],
+}
+
+#[rustfmt::skip]
+fn combine_modes(w: u64) -> [CombineCycle; 2] {
+    [
+        CombineCycle {
+            rgb: [bits(w, 52, 4), bits(w, 28, 4), bits(w, 47, 5), bits(w, 15, 3)],
+            alpha: [bits(w, 44, 3), bits(w, 12, 3), bits(w, 41, 3), bits(w, 9, 3)],
+        },
+        CombineCycle {
+            rgb: [bits(w, 37, 4), bits(w, 24, 4), bits(w, 32, 5), bits(w, 6, 3)],
+            alpha: [bits(w, 21, 3), bits(w, 3, 3), bits(w, 18, 3), bits(w, 0, 3)],
+        },
+    ]
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Image {
+    size: u32,
+    width: u32,
+    addr: u32,
+}
+
+impl Image {
+    fn from_word(w: u64) -> Self {
+        Image {
+            size: bits(w, 51, 2),
+            width: bits(w, 32, 10) + 1,
+            addr: bits(w, 0, 26),
+        }
+    }
+}
+
+// The wrapping controls for one texture axis, plus the tile's extent along
+// it in 10.2 fixed point.
+#[derive(Debug, Copy, Clone, Default)]
+struct TileAxis {
+    clamp: bool,
+    mirror: bool,
+    mask: u32,
+    shift: u32,
+    lo: u32,
+    hi: u32,
+}
+
+impl TileAxis {
+    // Takes a 10.5 texture coordinate to one relative to the tile.
+    fn adjust(&self, coord: i32) -> i32 {
+        let coord = match self.shift {
+            0 => coord,
+            1..=10 => coord >> self.shift,
+            _ => coord << (16 - self.shift),
+        };
+        coord - ((self.lo as i32) << 3)
+    }
+
+    fn wrap(&self, texel: i32) -> u32 {
+        let mut texel = texel;
+        if self.clamp || self.mask == 0 {
+            let max = (self.hi.saturating_sub(self.lo) >> 2) as i32;
+            texel = texel.clamp(0, max);
+        }
+        if self.mask == 0 {
+            return texel as u32;
+        }
+        let mask = self.mask.min(10);
+        if self.mirror && (texel >> mask) & 1 != 0 {
+            texel = !texel;
+        }
+        (texel & ((1 << mask) - 1)) as u32
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Tile {
+    format: u32,
+    size: u32,
+    // Row pitch and base address, both in 64-bit words.
+    line: u32,
+    tmem: u32,
+    palette: u32,
+    s: TileAxis,
+    t: TileAxis,
+}
+
+// Shade, texture or depth coefficients for a triangle: a start value on
+// the major edge and its change per pixel along x and per scanline along
+// that edge. All are 16.16 fixed point.
+#[derive(Debug, Copy, Clone, Default)]
+struct Coefficients {
+    start: [i32; 4],
+    dx: [i32; 4],
+    de: [i32; 4],
+}
+
+impl Coefficients {
+    // Integer and fraction halves are stored in separate words.
+    fn from_words(w: &[u64]) -> Self {
+        let field = |int: u64, frac: u64, i: u32| {
+            ((bits(int, 48 - i * 16, 16) << 16) | bits(frac, 48 - i * 16, 16)) as i32
+        };
+        let mut c = Coefficients::default();
+        for i in 0..4 {
+            c.start[i] = field(w[0], w[2], i as u32);
+            c.dx[i] = field(w[1], w[3], i as u32);
+            c.de[i] = field(w[4], w[6], i as u32);
+        }
+        c
+    }
+
+    fn z_from_words(w: &[u64]) -> Self {
+        let mut c = Coefficients::default();
+        c.start[0] = (w[0] >> 32) as i32;
+        c.dx[0] = w[0] as i32;
+        c.de[0] = (w[1] >> 32) as i32;
+        c
+    }
+
+    // The value at `scanlines` (in quarter lines) down the major edge and
+    // `dx` (16.16) pixels across from it.
+    fn at(&self, scanlines: i64, dx: i64) -> [i32; 4] {
+        let mut out = [0; 4];
+        for (i, value) in out.iter_mut().enumerate() {
+            let edge = i64::from(self.start[i]) + i64::from(self.de[i]) * scanlines / 4;
+            *value = (edge + ((i64::from(self.dx[i]) * dx) >> 16)) as i32;
+        }
+        out
+    }
+}
+
+// What the rasteriser hands the pixel pipeline for each pixel.
+#[derive(Debug, Copy, Clone, Default)]
+struct Fragment {
+    shade: Color,
+    // Texture coordinates in 10.5 fixed point.
+    s: i32,
+    t: i32,
+    z: u32,
+}
+
+pub struct Rdp {
+    pub tmem: [u8; TMEM_SIZE],
+    modes: OtherModes,
+    combine: [CombineCycle; 2],
+    color_image: Image,
+    texture_image: Image,
+    z_image: u32,
+    tiles: [Tile; 8],
+    // Scissor box in 10.2 fixed point; the low corner is exclusive.
+    scissor: [u32; 4],
+    fill_color: u32,
+    fog_color: Color,
+    blend_color: Color,
+    prim_color: Color,
+    env_color: Color,
+    prim_lod_frac: i32,
+    prim_z: u32,
+    noise: u32,
+    full_sync: bool,
+}
+
+impl Default for Rdp {
+    fn default() -> Self {
+        Rdp {
+            tmem: [0; TMEM_SIZE],
+            modes: OtherModes::default(),
+            combine: [CombineCycle::default(); 2],
+            color_image: Image::default(),
+            texture_image: Image::default(),
+            z_image: 0,
+            tiles: [Tile::default(); 8],
+            scissor: [0; 4],
+            fill_color: 0,
+            fog_color: [0; 4],
+            blend_color: [0; 4],
+            prim_color: [0; 4],
+            env_color: [0; 4],
+            prim_lod_frac: 0,
+            prim_z: 0,
+            noise: 1,
+            full_sync: false,
+        }
+    }
+}
+
+impl Rdp {
+    // Runs every complete command in `words` and returns how many words
+    // were used. A trailing partial command is left for the next call.
+    pub fn run(&mut self, words: &[u64], rdram: &mut [u8]) -> usize {
+        let mut pos = 0;
+        while pos < words.len() {
+            let len = command_len(words[pos]);
+            if pos + len > words.len() {
+                break;
+            }
+            self.execute(&words[pos..pos + len], rdram);
+            pos += len;
+        }
+        pos
+    }
+
+    // Returns whether a SYNC_FULL has been run since the last call.
+    pub fn take_full_sync(&mut self) -> bool {
+        std::mem::take(&mut self.full_sync)
+    }
+
+    pub fn execute(&mut self, command: &[u64], rdram: &mut [u8]) {
+        let w = command[0];
+        match (w >> 56) & 0x3F {
+            id @ 0x08..=0x0F => self.triangle(command, id as u32, rdram),
+            0x24 => self.texture_rectangle(command, false, rdram),
+            0x25 => self.texture_rectangle(command, true, rdram),
+            0x29 => self.full_sync = true,
+            0x2D => {
+                self.scissor = [
+                    bits(w, 44, 12),
+                    bits(w, 32, 12),
+                    bits(w, 12, 12),
+                    bits(w, 0, 12),
+                ]
+            }
+            0x2E => {
+                self.prim_z = bits(w, 16, 16);
+            }
+            0x2F => self.modes = OtherModes::from_word(w),
+            0x30 => self.load_tlut(w, rdram),
+            0x32 => {
+                let tile = &mut self.tiles[bits(w, 24, 3) as usize];
+                tile.s.lo = bits(w, 44, 12);
+                tile.t.lo = bits(w, 32, 12);
+                tile.s.hi = bits(w, 12, 12);
+                tile.t.hi = bits(w, 0, 12);
+            }
+            0x33 => self.load_block(w, rdram),
+            0x34 => self.load_tile(w, rdram),
+            0x35 => {
+                self.tiles[bits(w, 24, 3) as usize] = Tile {
+                    format: bits(w, 53, 3),
+                    size: bits(w, 51, 2),
+                    line: bits(w, 41, 9),
+                    tmem: bits(w, 32, 9),
+                    palette: bits(w, 20, 4),
+                    t: TileAxis {
+                        clamp: bits(w, 19, 1) != 0,
+                        mirror: bits(w, 18, 1) != 0,
+                        mask: bits(w, 14, 4),
+                        shift: bits(w, 10, 4),
+                        ..self.tiles[bits(w, 24, 3) as usize].t
+                    },
+                    s: TileAxis {
+                        clamp: bits(w, 9, 1) != 0,
+                        mirror: bits(w, 8, 1) != 0,
+                        mask: bits(w, 4, 4),
+                        shift: bits(w, 0, 4),
+                        ..self.tiles[bits(w, 24, 3) as usize].s
+                    },
+                }
+            }
+            0x36 => self.fill_rectangle(w, rdram),
+            0x37 => self.fill_color = w as u32,
+            0x38 => self.fog_color = rgba32(w as u32),
+            0x39 => self.blend_color = rgba32(w as u32),
+            0x3A => {
+                self.prim_color = rgba32(w as u32);
+                self.prim_lod_frac = bits(w, 32, 8) as i32;
+            }
+            0x3B => self.env_color = rgba32(w as u32),
+            0x3C => self.combine = combine_modes(w),
+            0x3D => self.texture_image = Image::from_word(w),
+            0x3E => self.z_image = bits(w, 0, 26),
+            0x3F => self.color_image = Image::from_word(w),
+            // No-op, the other syncs, keying and YUV conversion.
+            _ => {}
+        }
+    }
+
+    fn triangle(&mut self, command: &[u64], id: u32, rdram: &mut [u8]) {
+        let w = command[0];
+        let tile = bits(w, 48, 3) as usize;
+        let yl = sign_extend(bits(w, 32, 14), 14);
+        let ym = sign_extend(bits(w, 16, 14), 14);
+        let yh = sign_extend(bits(w, 0, 14), 14);
+        let edge = |w: u64| (i64::from((w >> 32) as i32), i64::from(w as i32));
+        let (xl, dxldy) = edge(command[1]);
+        let (xh, dxhdy) = edge(command[2]);
+        let (xm, dxmdy) = edge(command[3]);
+
+        let mut next = 4;
+        let shade = if id & 0x4 != 0 {
+            next += 8;
+            Some(Coefficients::from_words(&command[next - 8..next]))
+        } else {
+            None
+        };
+        let texture = if id & 0x2 != 0 {
+            next += 8;
+            Some(Coefficients::from_words(&command[next - 8..next]))
+        } else {
+            None
+        };
+        let depth = if id & 0x1 != 0 {
+            Some(Coefficients::z_from_words(&command[next..next + 2]))
+        } else {
+            None
+        };
+
+        let (clip_y0, clip_y1) = (self.scissor[1] as i32 >> 2, self.scissor[3] as i32 >> 2);
+        let y0 = (yh >> 2).max(clip_y0);
+        let y1 = ((yl + 3) >> 2).min(clip_y1);
+        let top = yh & !3;
+        for y in y0..y1 {
+            // Sample each scanline through its middle.
+            let yq = y * 4 + 2;
+            if yq < yh || yq >= yl {
+                continue;
+            }
+            let scanlines = i64::from(yq - top);
+            let major = xh + dxhdy * scanlines / 4;
+            let minor = if yq < ym {
+                xm + dxmdy * scanlines / 4
+            } else {
+                xl + dxldy * i64::from(yq - ym) / 4
+            };
+            let (left, right) = (major.min(minor), major.max(minor));
+            let first_x = (left - 0x8000 + 0xFFFF) >> 16;
+            let last_x = (right - 0x8000 + 0xFFFF) >> 16;
+            let x0 = first_x.max(i64::from(self.scissor[0] >> 2));
+            let x1 = last_x.min(i64::from(self.scissor[2] >> 2));
+            for x in x0..x1 {
+                let dx = (x << 16) + 0x8000 - major;
+                let mut fragment = Fragment::default();
+                if let Some(shade) = &shade {
+                    let rgba = shade.at(scanlines, dx);
+                    for (channel, value) in fragment.shade.iter_mut().zip(rgba.iter()) {
+                        *channel = (value >> 16).clamp(0, 0xFF);
+                    }
+                }
+                if let Some(texture) = &texture {
+                    let [s, t, w, _] = texture.at(scanlines, dx);
+                    let (s, t) = self.perspective(s, t, w);
+                    fragment.s = s;
+                    fragment.t = t;
+                }
+                if let Some(depth) = &depth {
+                    let z = depth.at(scanlines, dx)[0] >> 13;
+                    fragment.z = z.clamp(0, Z_MAX as i32) as u32;
+                }
+                self.pixel(x as u32, y as u32, tile, &fragment, rdram);
+            }
+        }
+    }
+
+    // Takes interpolated S, T and W to 10.5 texture coordinates.
+    fn perspective(&self, s: i32, t: i32, w: i32) -> (i32, i32) {
+        if !self.modes.persp_tex {
+            return (s >> 16, t >> 16);
+        }
+        // W is 1.0 at 0x7FFF in its integer half.
+        let w = i64::from(w.max(1));
+        let divide = |c: i32| (i64::from(c) * 0x8000 / w).clamp(-0x8000, 0x7FFF) as i32;
+        (divide(s), divide(t))
+    }
+
+    // The rectangle covered by a rectangle command, after scissoring. Fill
+    // and copy modes include the right and bottom edges.
+    fn rectangle_bounds(&self, w: u64) -> (u32, u32, u32, u32) {
+        let inclusive = match self.modes.cycle_type {
+            CycleType::Fill | CycleType::Copy => 1,
+            _ => 0,
+        };
+        let x0 = (bits(w, 12, 12) >> 2).max(self.scissor[0] >> 2);
+        let y0 = (bits(w, 0, 12) >> 2).max(self.scissor[1] >> 2);
+        let x1 = ((bits(w, 44, 12) >> 2) + inclusive).min(self.scissor[2] >> 2);
+        let y1 = ((bits(w, 32, 12) >> 2) + inclusive).min(self.scissor[3] >> 2);
+        (x0, y0, x1, y1)
+    }
+
+    fn fill_rectangle(&mut self, w: u64, rdram: &mut [u8]) {
+        let (x0, y0, x1, y1) = self.rectangle_bounds(w);
+        let fragment = Fragment {
+            z: self.prim_z << 3,
+            ..Fragment::default()
+        };
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.pixel(x, y, 0, &fragment, rdram);
+            }
+        }
+    }
+
+    fn texture_rectangle(&mut self, command: &[u64], flip: bool, rdram: &mut [u8]) {
+        let (w0, w1) = (command[0], command[1]);
+        let tile = bits(w0, 24, 3) as usize;
+        let (x0, y0, x1, y1) = self.rectangle_bounds(w0);
+        let (xh, yh) = (bits(w0, 12, 12) >> 2, bits(w0, 0, 12) >> 2);
+        // S and T are 10.5 and their steps 5.10; copy mode writes four
+        // pixels per step.
+        let s = sign_extend(bits(w1, 48, 16), 16) << 5;
+        let t = sign_extend(bits(w1, 32, 16), 16) << 5;
+        let mut dsdx = sign_extend(bits(w1, 16, 16), 16);
+        let dtdy = sign_extend(bits(w1, 0, 16), 16);
+        if self.modes.cycle_type == CycleType::Copy {
+            dsdx >>= 2;
+        }
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (dx, dy) = ((x - xh) as i32, (y - yh) as i32);
+                let (dx, dy) = if flip { (dy, dx) } else { (dx, dy) };
+                let fragment = Fragment {
+                    s: (s + dsdx * dx) >> 5,
+                    t: (t + dtdy * dy) >> 5,
+                    z: self.prim_z << 3,
+                    ..Fragment::default()
+                };
+                self.pixel(x, y, tile, &fragment, rdram);
+            }
+        }
+    }
+
+    fn pixel(&mut self, x: u32, y: u32, tile: usize, fragment: &Fragment, rdram: &mut [u8]) {
+        if x >= self.color_image.width {
+            return;
+        }
+        match self.modes.cycle_type {
+            CycleType::Fill => self.fill_pixel(x, y, rdram),
+            CycleType::Copy => {
+                let texel = self.texel(tile, fragment.s, fragment.t, false);
+                if self.modes.alpha_compare && texel[3] == 0 {
+                    return;
+                }
+                self.write_color(x, y, texel, rdram);
+            }
+            CycleType::OneCycle | CycleType::TwoCycle => {
+                self.shade_pixel(x, y, tile, fragment, rdram)
+            }
+        }
+    }
+
+    fn shade_pixel(&mut self, x: u32, y: u32, tile: usize, fragment: &Fragment, rdram: &mut [u8]) {
+        let z = if self.modes.z_source_prim {
+            self.prim_z << 3
+        } else {
+            fragment.z
+        };
+        if self.modes.z_compare && !self.depth_test(z, self.read_z(x, y, rdram)) {
+            return;
+        }
+
+        let two_cycle = self.modes.cycle_type == CycleType::TwoCycle;
+        let bilinear = self.modes.bilinear;
+        let tex0 = self.texel(tile, fragment.s, fragment.t, bilinear);
+        let tex1 = if two_cycle {
+            self.texel((tile + 1) & 7, fragment.s, fragment.t, bilinear)
+        } else {
+            tex0
+        };
+        // One-cycle mode uses the second combiner cycle but the first
+        // blender cycle.
+        let mut combined = [0; 4];
+        let combine_cycles = if two_cycle { 0..2 } else { 1..2 };
+        for cycle in combine_cycles {
+            combined = self.combine_cycle(cycle, combined, tex0, tex1, fragment.shade);
+        }
+        if self.modes.alpha_compare && combined[3] < self.blend_color[3] {
+            return;
+        }
+
+        let memory = self.read_color(x, y, rdram);
+        let mut color = combined;
+        let last = two_cycle as usize;
+        for cycle in 0..=last {
+            let blend = cycle != last || self.modes.force_blend;
+            color = self.blend_cycle(cycle, color, combined[3], memory, fragment.shade[3], blend);
+        }
+        self.write_color(x, y, color, rdram);
+        if self.modes.z_update {
+            self.write_z(x, y, z, rdram);
+        }
+    }
+
+    fn depth_test(&self, z: u32, memory_z: u32) -> bool {
+        match self.modes.z_mode {
+            ZMode::Decal => z.abs_diff(memory_z) <= 0x100,
+            _ => z < memory_z,
+        }
+    }
+
+    fn next_noise(&mut self) -> i32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        (self.noise & 0xFF) as i32
+    }
+
+    fn combine_cycle(
+        &mut self,
+        cycle: usize,
+        combined: Color,
+        tex0: Color,
+        tex1: Color,
+        shade: Color,
+    ) -> Color {
+        let mode = self.combine[cycle];
+        let noise = self.next_noise();
+        let common = |sel: u32| match sel {
+            0 => Some(combined),
+            1 => Some(tex0),
+            2 => Some(tex1),
+            3 => Some(self.prim_color),
+            4 => Some(shade),
+            5 => Some(self.env_color),
+            _ => None,
+        };
+        let a = common(mode.rgb[0]).unwrap_or(match mode.rgb[0] {
+            6 => [0x100; 4],
+            7 => [noise; 4],
+            _ => [0; 4],
+        });
+        let b = common(mode.rgb[1]).unwrap_or([0; 4]);
+        let c = common(mode.rgb[2]).unwrap_or(match mode.rgb[2] {
+            7 => [combined[3]; 4],
+            8 => [tex0[3]; 4],
+            9 => [tex1[3]; 4],
+            10 => [self.prim_color[3]; 4],
+            11 => [shade[3]; 4],
+            12 => [self.env_color[3]; 4],
+            14 => [self.prim_lod_frac; 4],
+            _ => [0; 4],
+        });
+        let d = common(mode.rgb[3]).unwrap_or(match mode.rgb[3] {
+            6 => [0x100; 4],
+            _ => [0; 4],
+        });
+
+        let alpha = |sel: u32, one: i32| {
+            common(sel).map(|c| c[3]).unwrap_or(match sel {
+                6 => one,
+                _ => 0,
+            })
+        };
+        let alpha_c = match mode.alpha[2] {
+            0 => 0,
+            6 => self.prim_lod_frac,
+            sel => alpha(sel, 0),
+        };
+
+        let equation =
+            |a: i32, b: i32, c: i32, d: i32| (((a - b) * c + (d << 8) + 0x80) >> 8).clamp(0, 0xFF);
+        let mut out = [0; 4];
+        for i in 0..3 {
+            out[i] = equation(a[i], b[i], c[i], d[i]);
+        }
+        out[3] = equation(
+            alpha(mode.alpha[0], 0x100),
+            alpha(mode.alpha[1], 0x100),
+            alpha_c,
+            alpha(mode.alpha[3], 0x100),
+        );
+        out
+    }
+
+    // Outside the first of two cycles, the blender only blends when forced
+    // to; otherwise P passes straight through.
+    fn blend_cycle(
+        &self,
+        cycle: usize,
+        pixel: Color,
+        pixel_alpha: i32,
+        memory: Color,
+        shade_alpha: i32,
+        blend: bool,
+    ) -> Color {
+        let [p, a, m, b] = self.modes.blend[cycle];
+        let color = |sel: u32| match sel {
+            0 => pixel,
+            1 => memory,
+            2 => self.blend_color,
+            _ => self.fog_color,
+        };
+        let p = color(p);
+        if !blend {
+            return p;
+        }
+        let a = match a {
+            0 => pixel_alpha,
+            1 => self.fog_color[3],
+            2 => shade_alpha,
+            _ => 0,
+        };
+        let b = match b {
+            0 => 0xFF - a,
+            1 => memory[3],
+            2 => 0xFF,
+            _ => 0,
+        };
+        let m = color(m);
+        let mut out = [0; 4];
+        for i in 0..3 {
+            out[i] = ((p[i] * a + m[i] * b + 0x7F) / 0xFF).clamp(0, 0xFF);
+        }
+        out[3] = pixel_alpha;
+        out
+    }
+
+    fn texel(&self, tile: usize, s: i32, t: i32, bilinear: bool) -> Color {
+        let tile = &self.tiles[tile];
+        let (s, t) = (tile.s.adjust(s), tile.t.adjust(t));
+        let (si, ti) = (s >> 5, t >> 5);
+        if !bilinear {
+            return self.fetch(tile, tile.s.wrap(si), tile.t.wrap(ti));
+        }
+        let (sf, tf) = (s & 0x1F, t & 0x1F);
+        let (s0, s1) = (tile.s.wrap(si), tile.s.wrap(si + 1));
+        let (t0, t1) = (tile.t.wrap(ti), tile.t.wrap(ti + 1));
+        let taps = [
+            (self.fetch(tile, s0, t0), (32 - sf) * (32 - tf)),
+            (self.fetch(tile, s1, t0), sf * (32 - tf)),
+            (self.fetch(tile, s0, t1), (32 - sf) * tf),
+            (self.fetch(tile, s1, t1), sf * tf),
+        ];
+        let mut out = [0; 4];
+        for (i, channel) in out.iter_mut().enumerate() {
+            let sum: i32 = taps.iter().map(|(texel, weight)| texel[i] * weight).sum();
+            *channel = (sum + 0x200) >> 10;
+        }
+        out
+    }
+
+    // Odd rows of TMEM have the 32-bit halves of each 64-bit word swapped,
+    // so that adjacent rows land in different banks.
+    fn tmem_address(tile: &Tile, t: u32, offset: u32) -> usize {
+        let swap = if t & 1 != 0 { 4 } else { 0 };
+        ((tile.tmem * 8 + t * tile.line * 8 + offset) ^ swap) as usize
+    }
+
+    fn fetch(&self, tile: &Tile, s: u32, t: u32) -> Color {
+        let byte = |offset: u32| self.tmem[Self::tmem_address(tile, t, offset) & (TMEM_SIZE - 1)];
+        let half = |offset: u32| u16::from_be_bytes([byte(offset), byte(offset + 1)]);
+        let indexed = self.modes.en_tlut || tile.format == FORMAT_CI;
+        match tile.size {
+            SIZE_4 => {
+                let shift = if s & 1 == 0 { 4 } else { 0 };
+                let value = (byte(s / 2) >> shift) & 0xF;
+                if indexed {
+                    return self.tlut((tile.palette << 4) as usize | value as usize);
+                }
+                if tile.format == FORMAT_IA {
+                    let i = i32::from(value >> 1);
+                    let a = if value & 1 != 0 { 0xFF } else { 0 };
+                    let i = (i << 5) | (i << 2) | (i >> 1);
+                    return [i, i, i, a];
+                }
+                let i = i32::from(value) * 0x11;
+                [i, i, i, i]
+            }
+            SIZE_8 => {
+                let value = byte(s);
+                if indexed {
+                    return self.tlut(value as usize);
+                }
+                if tile.format == FORMAT_IA {
+                    let i = i32::from(value >> 4) * 0x11;
+                    return [i, i, i, i32::from(value & 0xF) * 0x11];
+                }
+                let i = i32::from(value);
+                [i, i, i, i]
+            }
+            SIZE_16 => {
+                let value = half(s * 2);
+                match tile.format {
+                    FORMAT_IA => ia16(value),
+                    _ => rgba16(value),
+                }
+            }
+            // 32-bit texels keep red and green in the low half of TMEM and
+            // blue and alpha in the high half.
+            _ => {
+                let address = Self::tmem_address(tile, t, s * 2) & (TLUT_BASE - 1);
+                let rg = [self.tmem[address], self.tmem[address + 1]];
+                let ba = [
+                    self.tmem[address | TLUT_BASE],
+                    self.tmem[(address + 1) | TLUT_BASE],
+                ];
+                [rg[0].into(), rg[1].into(), ba[0].into(), ba[1].into()]
+            }
+        }
+    }
+
+    fn tlut(&self, index: usize) -> Color {
+        let address = TLUT_BASE + (index & 0xFF) * 8;
+        let value = u16::from_be_bytes([self.tmem[address], self.tmem[address + 1]]);
+        if self.modes.tlut_ia {
+            ia16(value)
+        } else {
+            rgba16(value)
+        }
+    }
+
+    fn texture_bytes_per_texel(&self) -> u32 {
+        match self.texture_image.size {
+            SIZE_32 => 4,
+            SIZE_16 => 2,
+            _ => 1,
+        }
+    }
+
+    // Writes `bytes` from the texture image to TMEM at `offset` within a
+    // row. 32-bit texels are split across the two halves of TMEM.
+    fn load_texels(&mut self, tile: &Tile, row: u32, offset: u32, bytes: &[u8]) {
+        if self.texture_image.size == SIZE_32 {
+            for (i, texel) in bytes.chunks(4).enumerate() {
+                let texel_offset = offset / 2 + i as u32 * 2;
+                for (j, &byte) in texel.iter().enumerate() {
+                    let address = Self::tmem_address(tile, row, texel_offset + (j as u32 & 1))
+                        & (TLUT_BASE - 1);
+                    let half = if j < 2 { 0 } else { TLUT_BASE };
+                    self.tmem[address | half] = byte;
+                }
+            }
+        } else {
+            for (i, &byte) in bytes.iter().enumerate() {
+                let address = Self::tmem_address(tile, row, offset + i as u32);
+                self.tmem[address & (TMEM_SIZE - 1)] = byte;
+            }
+        }
+    }
+
+    fn texture_image_bytes(&self, rdram: &[u8], texel: u32, count: u32) -> Vec<u8> {
+        let (start, len) = match self.texture_image.size {
+            SIZE_4 => (texel / 2, count.div_ceil(2)),
+            _ => {
+                let bpt = self.texture_bytes_per_texel();
+                (texel * bpt, count * bpt)
+            }
+        };
+        let start = (self.texture_image.addr + start) as usize;
+        (start..start + len as usize)
+            .map(|i| rdram.get(i).copied().unwrap_or(0))
+            .collect()
+    }
+
+    fn load_tile(&mut self, w: u64, rdram: &[u8]) {
+        let index = bits(w, 24, 3) as usize;
+        let (sl, tl, sh, th) = (
+            bits(w, 44, 12),
+            bits(w, 32, 12),
+            bits(w, 12, 12),
+            bits(w, 0, 12),
+        );
+        self.tiles[index].s.lo = sl;
+        self.tiles[index].t.lo = tl;
+        self.tiles[index].s.hi = sh;
+        self.tiles[index].t.hi = th;
+        let tile = self.tiles[index];
+        let (s0, t0, s1, t1) = (sl >> 2, tl >> 2, sh >> 2, th >> 2);
+        if s1 < s0 || t1 < t0 {
+            return;
+        }
+        for t in t0..=t1 {
+            let first = t * self.texture_image.width + s0;
+            let bytes = self.texture_image_bytes(rdram, first, s1 - s0 + 1);
+            self.load_texels(&tile, t - t0, 0, &bytes);
+        }
+    }
+
+    // Loads a run of texels as if it were one long row, switching TMEM
+    // rows every time the DXT accumulator ticks over.
+    fn load_block(&mut self, w: u64, rdram: &[u8]) {
+        let tile = self.tiles[bits(w, 24, 3) as usize];
+        let (sl, tl, sh, dxt) = (
+            bits(w, 44, 12),
+            bits(w, 32, 12),
+            bits(w, 12, 12),
+            bits(w, 0, 12),
+        );
+        if sh < sl {
+            return;
+        }
+        let first = tl * self.texture_image.width + sl;
+        let bytes = self.texture_image_bytes(rdram, first, sh - sl + 1);
+        // Each TMEM word holds four 32-bit texels, split across the halves.
+        let word_bytes = if self.texture_image.size == SIZE_32 {
+            16
+        } else {
+            8
+        };
+        let row_tile = Tile { line: 0, ..tile };
+        for (word, chunk) in bytes.chunks(word_bytes).enumerate() {
+            let row = (word as u32 * dxt) >> 11;
+            self.load_texels(&row_tile, row & 1, (word * word_bytes) as u32, chunk);
+        }
+    }
+
+    // TLUT entries are 16 bits and are copied to all four banks.
+    fn load_tlut(&mut self, w: u64, rdram: &[u8]) {
+        let tile = self.tiles[bits(w, 24, 3) as usize];
+        let (sl, tl, sh) = (
+            bits(w, 44, 12) >> 2,
+            bits(w, 32, 12) >> 2,
+            bits(w, 12, 12) >> 2,
+        );
+        if sh < sl {
+            return;
+        }
+        let first = (tl * self.texture_image.width + sl) * 2 + self.texture_image.addr;
+        for i in 0..=(sh - sl) {
+            let src = (first + i * 2) as usize;
+            let entry = [
+                rdram.get(src).copied().unwrap_or(0),
+                rdram.get(src + 1).copied().unwrap_or(0),
+            ];
+            for bank in 0..4 {
+                let address = (tile.tmem * 8 + i * 8 + bank * 2) as usize & (TMEM_SIZE - 1);
+                self.tmem[address..address + 2].copy_from_slice(&entry);
+            }
+        }
+    }
+
+    fn color_address(&self, x: u32, y: u32) -> usize {
+        let bytes = match self.color_image.size {
+            SIZE_32 => 4,
+            SIZE_16 => 2,
+            _ => 1,
+        };
+        (self.color_image.addr + (y * self.color_image.width + x) * bytes) as usize
+    }
+
+    fn fill_pixel(&self, x: u32, y: u32, rdram: &mut [u8]) {
+        let address = self.color_address(x, y);
+        let fill = self.fill_color.to_be_bytes();
+        let bytes: &[u8] = match self.color_image.size {
+            SIZE_32 => &fill,
+            SIZE_16 => {
+                let half = (x as usize & 1) * 2;
+                &fill[half..half + 2]
+            }
+            _ => {
+                let byte = x as usize & 3;
+                &fill[byte..=byte]
+            }
+        };
+        if let Some(dest) = rdram.get_mut(address..address + bytes.len()) {
+            dest.copy_from_slice(bytes);
+        }
+    }
+
+    fn read_color(&self, x: u32, y: u32, rdram: &[u8]) -> Color {
+        let address = self.color_address(x, y);
+        match self.color_image.size {
+            SIZE_32 => match rdram.get(address..address + 4) {
+                Some(p) => [p[0].into(), p[1].into(), p[2].into(), 0xFF],
+                None => [0; 4],
+            },
+            SIZE_16 => match rdram.get(address..address + 2) {
+                Some(p) => {
+                    let [r, g, b, _] = rgba16(u16::from_be_bytes([p[0], p[1]]));
+                    [r, g, b, 0xFF]
+                }
+                None => [0; 4],
+            },
+            _ => [0; 4],
+        }
+    }
+
+    // The alpha bits of the colour image hold coverage, which is always
+    // full here.
+    fn write_color(&self, x: u32, y: u32, color: Color, rdram: &mut [u8]) {
+        let address = self.color_address(x, y);
+        let [r, g, b, _] = color;
+        match self.color_image.size {
+            SIZE_32 => {
+                if let Some(dest) = rdram.get_mut(address..address + 4) {
+                    dest.copy_from_slice(&[r as u8, g as u8, b as u8, 0xE0]);
+                }
+            }
+            SIZE_16 => {
+                let value =
+                    ((r as u16 >> 3) << 11) | ((g as u16 >> 3) << 6) | ((b as u16 >> 3) << 1) | 1;
+                if let Some(dest) = rdram.get_mut(address..address + 2) {
+                    dest.copy_from_slice(&value.to_be_bytes());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The colour image down to the bottom of the scissor box, as RGB.
+    pub fn color_frame(&self, rdram: &[u8]) -> Option<Frame> {
+        let width = self.color_image.width;
+        let height = (self.scissor[3] + 3) >> 2;
+        if height == 0 || !matches!(self.color_image.size, SIZE_16 | SIZE_32) {
+            return None;
+        }
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b, _] = self.read_color(x, y, rdram);
+                pixels.extend_from_slice(&[r as u8, g as u8, b as u8]);
+            }
+        }
+        Some(Frame {
+            width: width as usize,
+            height: height as usize,
+            pixels,
+        })
+    }
+
+    fn z_address(&self, x: u32, y: u32) -> usize {
+        (self.z_image + (y * self.color_image.width + x) * 2) as usize
+    }
+
+    fn read_z(&self, x: u32, y: u32, rdram: &[u8]) -> u32 {
+        let address = self.z_address(x, y);
+        match rdram.get(address..address + 2) {
+            Some(z) => decompress_z(u16::from_be_bytes([z[0], z[1]])),
+            None => Z_MAX,
+        }
+    }
+
+    fn write_z(&self, x: u32, y: u32, z: u32, rdram: &mut [u8]) {
+        let address = self.z_address(x, y);
+        if let Some(dest) = rdram.get_mut(address..address + 2) {
+            dest.copy_from_slice(&compress_z(z).to_be_bytes());
+        }
+    }
+}
+
+impl RdpBackend for Rdp {
+    fn process(&mut self, commands: &[u64], rdram: &mut [u8]) -> usize {
+        self.run(commands, rdram)
+    }
+
+    fn take_full_sync(&mut self) -> bool {
+        Rdp::take_full_sync(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR_IMAGE: u32 = 0x1000;
+    const Z_IMAGE: u32 = 0x8000;
+    const TEXTURE: u32 = 0x10000;
+
+    fn command(id: u64, low: u64) -> u64 {
+        (id << 56) | low
+    }
+
+    // A 16-bit, 32 pixel wide colour image with the scissor covering it.
+    fn setup(rdp: &mut Rdp, rdram: &mut [u8], cycle_type: u64) {
+        let commands = [
+            command(0x3F, (SIZE_16 as u64) << 51 | 31 << 32 | COLOR_IMAGE as u64),
+            command(0x3E, Z_IMAGE as u64),
+            command(0x2D, (32 << 2) << 12 | (32 << 2)),
+            command(0x2F, cycle_type << 52),
+        ];
+        assert_eq!(rdp.run(&commands, rdram), commands.len());
+    }
+
+    fn pixel(rdram: &[u8], x: u32, y: u32) -> u16 {
+        let address = (COLOR_IMAGE + (y * 32 + x) * 2) as usize;
+        u16::from_be_bytes([rdram[address], rdram[address + 1]])
+    }
+
+    #[test]
+    fn fill_rectangle_is_inclusive() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        setup(&mut rdp, &mut rdram, 3);
+        let commands = [
+            command(0x37, 0xF801_07C1),
+            command(
+                0x36,
+                (5 << 2) << 44 | (3 << 2) << 32 | (2 << 2) << 12 | (1 << 2),
+            ),
+            command(0x29, 0),
+        ];
+        rdp.run(&commands, &mut rdram);
+        assert_eq!(pixel(&rdram, 2, 1), 0xF801);
+        assert_eq!(pixel(&rdram, 3, 1), 0x07C1);
+        assert_eq!(pixel(&rdram, 5, 3), 0x07C1);
+        assert_eq!(pixel(&rdram, 6, 3), 0);
+        assert_eq!(pixel(&rdram, 2, 4), 0);
+        assert!(rdp.take_full_sync());
+        assert!(!rdp.take_full_sync());
+    }
+
+    #[test]
+    fn partial_commands_are_left_for_later() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        let triangle = command(0x0C, 0);
+        assert_eq!(command_len(triangle), 12);
+        assert_eq!(rdp.run(&[command(0x27, 0), triangle, 0, 0], &mut rdram), 1);
+    }
+
+    // Draws a right triangle with corners (0, 0), (16, 0) and (0, 16) with
+    // shade and Z, the combiner passing shade through.
+    fn shaded_triangle(rgba: [u16; 4], z: i32) -> Vec<u64> {
+        let mut words = vec![
+            command(0x0D, 1 << 55 | (16 << 2) << 32 | (16 << 2) << 16),
+            // XL, the minor edge below YM, and XH, the major edge.
+            0,
+            0,
+            // XM runs from x=16 in to x=0.
+            (16 << 16) << 32 | (-(1 << 16) as u32 as u64),
+        ];
+        let shade_int = rgba.iter().fold(0u64, |acc, &c| (acc << 16) | u64::from(c));
+        words.push(shade_int);
+        words.extend_from_slice(&[0; 7]);
+        words.push((z as u32 as u64) << 32);
+        words.push(0);
+        words
+    }
+
+    #[test]
+    fn triangle_with_shade_and_z() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        setup(&mut rdp, &mut rdram, 0);
+        // Shade through both cycles; compare and update Z.
+        let combine = 4 << 15 | 4 << 9 | 4 << 6 | 4;
+        let modes = 0x30 | (1 << 14) | (2 << 26);
+        let mut commands = vec![
+            command(0x3C, combine),
+            command(0x2F, modes),
+            command(0x37, 0xFFFF_FFFF),
+        ];
+        // Clear Z to the far plane through a fill of the Z image.
+        commands.push(command(
+            0x3F,
+            (SIZE_16 as u64) << 51 | 31 << 32 | Z_IMAGE as u64,
+        ));
+        commands.push(command(0x2F, 3 << 52));
+        commands.push(command(0x36, (31 << 2) << 44 | (31 << 2) << 32));
+        commands.push(command(
+            0x3F,
+            (SIZE_16 as u64) << 51 | 31 << 32 | COLOR_IMAGE as u64,
+        ));
+        commands.push(command(0x2F, modes));
+        commands.extend(shaded_triangle([0xFF, 0, 0, 0xFF], 0x100 << 16));
+        // A green triangle behind the red one is hidden.
+        commands.extend(shaded_triangle([0, 0xFF, 0, 0xFF], 0x200 << 16));
+        assert_eq!(rdp.run(&commands, &mut rdram), commands.len());
+
+        assert_eq!(pixel(&rdram, 0, 0), 0xF801);
+        assert_eq!(pixel(&rdram, 7, 7), 0xF801);
+        assert_eq!(pixel(&rdram, 8, 8), 0);
+        assert_eq!(pixel(&rdram, 14, 0), 0xF801);
+        assert_eq!(pixel(&rdram, 15, 0), 0);
+        assert_eq!(pixel(&rdram, 0, 16), 0);
+        let z = u16::from_be_bytes([rdram[Z_IMAGE as usize], rdram[Z_IMAGE as usize + 1]]);
+        assert_eq!(decompress_z(z), 0x800);
+    }
+
+    #[test]
+    fn copy_mode_texture_rectangle_from_load_tile() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        setup(&mut rdp, &mut rdram, 2);
+        // A 4x2 RGBA16 texture, each texel its own index.
+        for i in 0..8u16 {
+            let address = TEXTURE as usize + i as usize * 2;
+            rdram[address..address + 2].copy_from_slice(&((i << 1) | 1).to_be_bytes());
+        }
+        let commands = [
+            command(0x3D, (SIZE_16 as u64) << 51 | 3 << 32 | TEXTURE as u64),
+            command(0x35, (SIZE_16 as u64) << 51 | 1 << 41),
+            command(0x34, (3 << 2) << 12 | (1 << 2)),
+            // Copy to (10, 10)-(13, 11), with DsDx four texels per step.
+            command(
+                0x24,
+                (13 << 2) << 44 | (11 << 2) << 32 | (10 << 2) << 12 | (10 << 2),
+            ),
+            (4 << 10) << 16 | (1 << 10),
+        ];
+        rdp.run(&commands, &mut rdram);
+        for i in 0..8u32 {
+            assert_eq!(pixel(&rdram, 10 + i % 4, 10 + i / 4), ((i << 1) | 1) as u16);
+        }
+        assert_eq!(pixel(&rdram, 14, 10), 0);
+    }
+
+    #[test]
+    fn combiner_modulates_texture_by_primitive_color() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        setup(&mut rdp, &mut rdram, 0);
+        // One I8 texel, loaded by block: TEXEL0 * PRIMITIVE.
+        rdram[TEXTURE as usize] = 0x80;
+        let combine = 1 << 37 | 3 << 32 | 15 << 24 | 7 << 6;
+        let commands = [
+            command(0x3D, (SIZE_8 as u64) << 51 | TEXTURE as u64),
+            command(0x35, 4 << 53 | (SIZE_8 as u64) << 51),
+            command(0x33, 0),
+            command(0x3A, 0x00FF_80FF),
+            command(0x3C, combine),
+            command(0x24, (1 << 2) << 44 | (1 << 2) << 32),
+            0,
+        ];
+        rdp.run(&commands, &mut rdram);
+        let [r, g, b, _] = rgba16(pixel(&rdram, 0, 0));
+        assert_eq!((r, g, b), (0, 0x84, 0x42));
+    }
+
+    #[test]
+    fn load_block_swaps_odd_rows_by_dxt() {
+        let mut rdram = vec![0; 0x20000];
+        // Eight RGBA16 texels, two TMEM words' worth.
+        let texel = |i: u16| (i << 11) | 1;
+        for i in 0..8u16 {
+            let address = TEXTURE as usize + i as usize * 2;
+            rdram[address..address + 2].copy_from_slice(&texel(i).to_be_bytes());
+        }
+        let mut load = |dxt: u64| {
+            let mut rdp = Rdp::default();
+            let commands = [
+                command(0x3D, (SIZE_16 as u64) << 51 | TEXTURE as u64),
+                command(0x35, (SIZE_16 as u64) << 51 | 1 << 41),
+                command(0x33, 7 << 12 | dxt),
+            ];
+            rdp.run(&commands, &mut rdram);
+            rdp
+        };
+
+        // A DXT of one row per word puts the second word on an odd row,
+        // with its 32-bit halves swapped.
+        let rdp = load(0x800);
+        let half = |rdp: &Rdp, i: usize| u16::from_be_bytes([rdp.tmem[i * 2], rdp.tmem[i * 2 + 1]]);
+        let halves: Vec<u16> = (0..8).map(|i| half(&rdp, i)).collect();
+        let swapped: Vec<u16> = [0, 1, 2, 3, 6, 7, 4, 5].iter().map(|&i| texel(i)).collect();
+        assert_eq!(halves, swapped);
+        let tile = rdp.tiles[0];
+        assert_eq!(rdp.fetch(&tile, 1, 1), rgba16(texel(5)));
+
+        // With no DXT everything stays on the first row.
+        let rdp = load(0);
+        let halves: Vec<u16> = (0..8).map(|i| half(&rdp, i)).collect();
+        let straight: Vec<u16> = (0..8).map(texel).collect();
+        assert_eq!(halves, straight);
+    }
+
+    #[test]
+    fn load_tlut_fills_every_bank() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        let entries = [0xF801u16, 0x07C1, 0x003F, 0xFFFF];
+        for (i, entry) in entries.iter().enumerate() {
+            let address = TEXTURE as usize + i * 2;
+            rdram[address..address + 2].copy_from_slice(&entry.to_be_bytes());
+        }
+        let commands = [
+            command(0x3D, (SIZE_16 as u64) << 51 | TEXTURE as u64),
+            // Tile 7 at the start of the TLUT half of TMEM.
+            command(0x35, 0x100 << 32 | 7 << 24),
+            command(0x30, 7 << 24 | (3 << 2) << 12),
+            // An 8-bit colour-indexed tile 0 drawing through the TLUT.
+            command(0x35, (FORMAT_CI as u64) << 53 | (SIZE_8 as u64) << 51),
+            command(0x2F, 1 << 47),
+        ];
+        rdp.run(&commands, &mut rdram);
+        for (i, entry) in entries.iter().enumerate() {
+            for bank in 0..4 {
+                let address = TLUT_BASE + i * 8 + bank * 2;
+                let stored = u16::from_be_bytes([rdp.tmem[address], rdp.tmem[address + 1]]);
+                assert_eq!(stored, *entry);
+            }
+        }
+        rdp.tmem[..4].copy_from_slice(&[3, 2, 1, 0]);
+        let tile = rdp.tiles[0];
+        assert_eq!(rdp.fetch(&tile, 1, 0), rgba16(0x003F));
+        assert_eq!(rdp.fetch(&tile, 3, 0), rgba16(0xF801));
+    }
+
+    #[test]
+    fn blender_mixes_pixel_and_memory() {
+        let mut rdp = Rdp::default();
+        let mut rdram = vec![0; 0x20000];
+        rdp.run(
+            &[command(0x38, 0x0000_FF40), command(0x39, 0x00FF_0000)],
+            &mut rdram,
+        );
+        let pixel = [0xFF, 0, 0, 0x80];
+        let memory = [0, 0, 0xFF, 0xFF];
+        let blend = |rdp: &mut Rdp, selects: [u64; 4], force: bool| {
+            let [p, a, m, b] = selects;
+            let modes = p << 30 | a << 26 | m << 22 | b << 18;
+            rdp.execute(&[command(0x2F, modes)], &mut []);
+            rdp.blend_cycle(0, pixel, pixel[3], memory, 0x40, force)
+        };
+
+        // Pixel over memory by the pixel's alpha.
+        assert_eq!(blend(&mut rdp, [0, 0, 1, 0], true), [0x80, 0, 0x7F, 0x80]);
+        // Unless the blend is skipped, when P passes through untouched.
+        assert_eq!(blend(&mut rdp, [0, 0, 1, 0], false), pixel);
+        // Fog over the blend colour by fog alpha, with B one.
+        assert_eq!(blend(&mut rdp, [3, 1, 2, 2], true), [0, 0xFF, 0x40, 0x80]);
+        // Shade alpha against zero.
+        assert_eq!(blend(&mut rdp, [1, 2, 0, 3], true), [0, 0, 0x40, 0x80]);
+    }
+
+    #[test]
+    fn two_cycle_mode_chains_the_combiner() {
+        // PRIMITIVE * ENV_ALPHA in the first cycle, COMBINED * ENV_ALPHA
+        // in the second.
+        let combine = 3 << 52 | 12 << 47 | 15 << 28 | 7 << 15 | 12 << 32 | 15 << 24 | 7 << 6;
+        let draw = |cycle_type: u64| {
+            let mut rdp = Rdp::default();
+            let mut rdram = vec![0; 0x20000];
+            setup(&mut rdp, &mut rdram, cycle_type);
+            let commands = [
+                command(0x3A, 0xFF80_40FF),
+                command(0x3B, 0x0000_0080),
+                command(0x3C, combine),
+                command(0x24, (1 << 2) << 44 | (1 << 2) << 32),
+                0,
+            ];
+            rdp.run(&commands, &mut rdram);
+            pixel(&rdram, 0, 0)
+        };
+        let [r, g, b, _] = rgba16(draw(1));
+        assert_eq!((r, g, b), (0x42, 0x21, 0x10));
+        // One cycle only runs the second cycle, with nothing combined yet.
+        assert_eq!(draw(0), 0x0001);
+    }
+
+    #[test]
+    fn z_compare_modes() {
+        // Memory Z equal to, a little behind, in front of and far behind
+        // the primitive's depth of 0x1000.
+        let memory_z = [0x1000, 0x1040, 0x0800, 0x2000];
+        let draw = |z_mode: u64| {
+            let mut rdp = Rdp::default();
+            let mut rdram = vec![0; 0x20000];
+            setup(&mut rdp, &mut rdram, 0);
+            for (x, &z) in memory_z.iter().enumerate() {
+                rdp.write_z(x as u32, 0, z, &mut rdram);
+            }
+            let commands = [
+                command(0x3A, 0xFF00_00FF),
+                command(0x3C, 3 << 6),
+                command(0x2E, 0x200 << 16),
+                command(0x2F, z_mode << 10 | 0x10),
+                command(0x36, (4 << 2) << 44 | (1 << 2) << 32),
+            ];
+            rdp.run(&commands, &mut rdram);
+            let drawn: Vec<bool> = (0..4).map(|x| pixel(&rdram, x, 0) != 0).collect();
+            // Z is only compared, never updated.
+            assert_eq!(rdp.read_z(0, 0, &rdram), 0x1000);
+            drawn
+        };
+        assert_eq!(draw(0), [false, true, false, true]);
+        assert_eq!(draw(1), [false, true, false, true]);
+        assert_eq!(draw(2), [false, true, false, true]);
+        assert_eq!(draw(3), [true, true, false, false]);
+    }
+
+    #[test]
+    fn z_compression_round_trips() {
+        for &z in &[0, 0x1FFC0, 0x20000, 0x3F800, 0x3FFFF] {
+            let packed = compress_z(z);
+            assert!(decompress_z(packed) <= z);
+            assert!(z - decompress_z(packed) < 64);
+        }
+        assert_eq!(compress_z(Z_MAX) & 0xFFFC, 0xFFFC);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::dp::RdpBackend;
+use crate::rdp::{command_len, Rdp};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+// A capture is this magic followed by records, each a tag byte and then
+// either a region of RDRAM (address, length, bytes) or a run of command
+// words (count, words). All numbers are big-endian.
+const CAPTURE_MAGIC: &[u8; 8] = b"RDPCAP01";
+const RECORD_MEMORY: u8 = 1;
+const RECORD_COMMANDS: u8 = 2;
+
+// Replays get the largest RDRAM a console can have.
+const REPLAY_RDRAM_SIZE: usize = 0x80_0000;
+
+#[derive(Debug, Copy, Clone, Default)]
+struct TrackedImage {
+    addr: u32,
+    size: u32,
+    width: u32,
+}
+
+impl TrackedImage {
+    fn from_word(w: u64) -> Self {
+        TrackedImage {
+            addr: (w & 0x03FF_FFFF) as u32,
+            size: ((w >> 51) & 0x3) as u32,
+            width: ((w >> 32) & 0x3FF) as u32 + 1,
+        }
+    }
+
+    // The bytes holding texels `first` to `last` inclusive.
+    fn span(&self, first: u32, last: u32) -> (u32, u32) {
+        let bits = 4 << self.size;
+        let start = first * bits / 8;
+        let end = ((last + 1) * bits).div_ceil(8);
+        (self.addr + start, end - start)
+    }
+}
+
+// Follows just enough RDP state to know which parts of RDRAM a command
+// list reads: texture loads, and the colour and Z images it draws over.
+#[derive(Default)]
+struct Tracker {
+    texture: TrackedImage,
+    color: TrackedImage,
+    z_image: Option<u32>,
+    scissor_bottom: u32,
+    color_captured: bool,
+    z_captured: bool,
+}
+
+impl Tracker {
+    fn regions(&mut self, command: u64, regions: &mut Vec<(u32, u32)>) {
+        let field = |shift: u32| ((command >> shift) & 0xFFF) as u32;
+        match (command >> 56) & 0x3F {
+            0x08..=0x0F | 0x24 | 0x25 | 0x36 => {
+                let height = (self.scissor_bottom + 3) >> 2;
+                let pixels = self.color.width * height;
+                if !self.color_captured {
+                    regions.push((self.color.addr, pixels << self.color.size >> 1));
+                    self.color_captured = true;
+                }
+                if let (Some(addr), false) = (self.z_image, self.z_captured) {
+                    regions.push((addr, pixels * 2));
+                    self.z_captured = true;
+                }
+            }
+            0x2D => self.scissor_bottom = field(0),
+            0x30 => {
+                let tlut = TrackedImage {
+                    size: 2,
+                    ..self.texture
+                };
+                let first = (field(32) >> 2) * tlut.width + (field(44) >> 2);
+                let count = (field(12) >> 2).saturating_sub(field(44) >> 2);
+                regions.push(tlut.span(first, first + count));
+            }
+            0x33 => {
+                let first = field(32) * self.texture.width + field(44);
+                let count = field(12).saturating_sub(field(44));
+                regions.push(self.texture.span(first, first + count));
+            }
+            0x34 => {
+                let width = self.texture.width;
+                let first = (field(32) >> 2) * width + (field(44) >> 2);
+                let last = (field(0) >> 2) * width + (field(12) >> 2);
+                regions.push(self.texture.span(first, last.max(first)));
+            }
+            0x3D => self.texture = TrackedImage::from_word(command),
+            0x3E => {
+                self.z_image = Some((command & 0x03FF_FFFF) as u32);
+                self.z_captured = false;
+            }
+            0x3F => {
+                self.color = TrackedImage::from_word(command);
+                self.color_captured = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Records every command the wrapped backend runs, along with the RDRAM
+// it reads as it stood beforehand, so the lists can be replayed on their
+// own.
+pub struct RdpCapture<W: Write> {
+    out: W,
+    inner: Box<dyn RdpBackend>,
+    tracker: Tracker,
+}
+
+impl RdpCapture<BufWriter<File>> {
+    pub fn create(path: &Path, inner: Box<dyn RdpBackend>) -> Self {
+        match File::create(path) {
+            Err(why) => panic!("Couldn't create capture file {}: {}", path.display(), why),
+            Ok(file) => RdpCapture::new(BufWriter::new(file), inner),
+        }
+    }
+}
+
+impl<W: Write> RdpCapture<W> {
+    pub fn new(mut out: W, inner: Box<dyn RdpBackend>) -> Self {
+        if let Err(why) = out.write_all(CAPTURE_MAGIC) {
+            panic!("Couldn't write capture header: {}", why);
+        }
+        RdpCapture {
+            out,
+            inner,
+            tracker: Tracker::default(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    fn write_records(&mut self, snapshots: &[(u32, Vec<u8>)], commands: &[u64]) {
+        let mut record = Vec::new();
+        for (addr, bytes) in snapshots {
+            record.push(RECORD_MEMORY);
+            record.extend_from_slice(&addr.to_be_bytes());
+            record.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            record.extend_from_slice(bytes);
+        }
+        record.push(RECORD_COMMANDS);
+        record.extend_from_slice(&(commands.len() as u32).to_be_bytes());
+        for word in commands {
+            record.extend_from_slice(&word.to_be_bytes());
+        }
+        let result = self.out.write_all(&record).and_then(|_| self.out.flush());
+        if let Err(why) = result {
+            panic!("Couldn't write capture: {}", why);
+        }
+    }
+}
+
+impl<W: Write> RdpBackend for RdpCapture<W> {
+    fn process(&mut self, commands: &[u64], rdram: &mut [u8]) -> usize {
+        let mut regions = Vec::new();
+        let mut pos = 0;
+        while pos < commands.len() && pos + command_len(commands[pos]) <= commands.len() {
+            self.tracker.regions(commands[pos], &mut regions);
+            pos += command_len(commands[pos]);
+        }
+        if pos == 0 {
+            return 0;
+        }
+        // Regions are taken before the commands can draw over them.
+        let snapshots: Vec<(u32, Vec<u8>)> = regions
+            .iter()
+            .map(|&(addr, len)| {
+                let start = (addr as usize).min(rdram.len());
+                let end = (start + len as usize).min(rdram.len());
+                (start as u32, rdram[start..end].to_vec())
+            })
+            .collect();
+        let used = self.inner.process(&commands[..pos], rdram);
+        self.write_records(&snapshots, &commands[..used]);
+        used
+    }
+
+    fn take_full_sync(&mut self) -> bool {
+        self.inner.take_full_sync()
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+// Runs a capture through the software rasteriser, returning it and the
+// RDRAM it drew into.
+pub fn replay(input: &mut impl Read) -> std::io::Result<(Rdp, Vec<u8>)> {
+    let invalid = |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, what);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != CAPTURE_MAGIC {
+        return Err(invalid("not an RDP capture"));
+    }
+    let mut rdp = Rdp::default();
+    let mut rdram = vec![0; REPLAY_RDRAM_SIZE];
+    let mut pending = Vec::new();
+    let mut tag = [0; 1];
+    while input.read(&mut tag)? == 1 {
+        match tag[0] {
+            RECORD_MEMORY => {
+                let addr = read_u32(input)? as usize;
+                let len = read_u32(input)? as usize;
+                let mut bytes = vec![0; len];
+                input.read_exact(&mut bytes)?;
+                match rdram.get_mut(addr..addr + len) {
+                    Some(dest) => dest.copy_from_slice(&bytes),
+                    None => return Err(invalid("memory record outside RDRAM")),
+                }
+            }
+            RECORD_COMMANDS => {
+                let count = read_u32(input)?;
+                for _ in 0..count {
+                    let mut word = [0; 8];
+                    input.read_exact(&mut word)?;
+                    pending.push(u64::from_be_bytes(word));
+                }
+                let used = rdp.run(&pending, &mut rdram);
+                pending.drain(..used);
+            }
+            _ => return Err(invalid("unknown capture record")),
+        }
+    }
+    Ok((rdp, rdram))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_matches_live_rendering() {
+        let mut rdram = vec![0u8; 0x20000];
+        // A 2x2 RGBA16 texture at 0x8000 and a 4x4 colour image at 0x1000
+        // with stale contents that the copy leaves partly visible.
+        rdram[0x8000..0x8008].copy_from_slice(&[0xF8, 0x01, 0x07, 0xC1, 0x00, 0x3F, 0xFF, 0xFF]);
+        for byte in rdram[0x1000..0x1020].iter_mut() {
+            *byte = 0x55;
+        }
+        let commands = [
+            0x3F10_0003_0000_1000, // 16-bit colour image, 4 wide
+            0x2D00_0000_0010_0010, // scissor (0, 0)-(4, 4)
+            0x2F20_0000_0000_0000, // copy mode
+            0x3D10_0001_0000_8000, // 16-bit texture image, 2 wide
+            0x3510_0200_0000_0000, // tile 0 at TMEM 0, one word per line
+            0x3400_0000_0000_4004, // load tile (0, 0)-(1, 1)
+            0x2400_4004_0000_0000, // copy to (0, 0)-(1, 1)
+            0x0000_0000_1000_0400, // S = T = 0, DsDx = 4, DtDy = 1
+            0x2900_0000_0000_0000,
+        ];
+
+        let mut live = RdpCapture::new(Vec::new(), Box::new(Rdp::default()));
+        // Split mid-command to check partial commands are carried over.
+        assert_eq!(live.process(&commands[..7], &mut rdram), 6);
+        assert_eq!(live.process(&commands[6..], &mut rdram), 3);
+        assert!(live.take_full_sync());
+
+        let (rdp, replayed) = replay(&mut live.get_ref().as_slice()).unwrap();
+        let frame = rdp.color_frame(&replayed).unwrap();
+        assert_eq!((frame.width, frame.height), (4, 4));
+        assert_eq!(&replayed[0x1000..0x1020], &rdram[0x1000..0x1020]);
+        assert_eq!(&frame.pixels[0..3], &[0xFF, 0, 0]);
+        assert_eq!(&frame.pixels[4 * 3..4 * 3 + 3], &[0, 0, 0xFF]);
+        assert_eq!(&frame.pixels[2 * 3..2 * 3 + 3], &[0x52, 0xAD, 0x52]);
+    }
+
+    #[test]
+    fn replay_rejects_other_files() {
+        assert!(replay(&mut &b"P6\n4 4\n255\n"[..]).is_err());
+    }
+}
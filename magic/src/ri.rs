@@ -0,0 +1,206 @@
+use std::str::FromStr;
+
+pub const RI_REGS_START: u32 = 0x0470_0000;
+pub const RI_REGS_END: u32 = 0x047F_FFFF;
+pub const RDRAM_REGS_START: u32 = 0x03F0_0000;
+pub const RDRAM_REGS_END: u32 = 0x03FF_FFFF;
+
+const RI_MODE: u32 = 0x00;
+const RI_CONFIG: u32 = 0x04;
+const RI_CURRENT_LOAD: u32 = 0x08;
+const RI_SELECT: u32 = 0x0C;
+const RI_REFRESH: u32 = 0x10;
+const RI_LATENCY: u32 = 0x14;
+const RI_RERROR: u32 = 0x18;
+const RI_WERROR: u32 = 0x1C;
+
+const RDRAM_DEVICE_TYPE: usize = 0;
+const RDRAM_DEVICE_ID: usize = 1;
+const RDRAM_DEVICE_MANUF: usize = 9;
+const RDRAM_REG_COUNT: usize = 10;
+
+// Address bit 19 broadcasts a register write to every chip; bits 18:10
+// select the chip by its device id.
+const RDRAM_BROADCAST: u32 = 1 << 19;
+const RDRAM_ID_SHIFT: u32 = 10;
+const RDRAM_ID_MASK: u32 = 0x1FF;
+
+// 18Mbit (2MB) RDRAM, 9 bits per byte, made by NEC.
+const RDRAM_DEVICE_TYPE_VALUE: u32 = 0xB419_0010;
+const RDRAM_DEVICE_MANUF_VALUE: u32 = 0x0000_0500;
+pub const RDRAM_CHIP_SIZE: usize = 0x20_0000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum RdramSize {
+    #[default]
+    Size4M,
+    Size8M,
+}
+
+impl RdramSize {
+    pub fn bytes(self) -> usize {
+        match self {
+            RdramSize::Size4M => 0x40_0000,
+            RdramSize::Size8M => 0x80_0000,
+        }
+    }
+
+    pub fn chips(self) -> usize {
+        self.bytes() / RDRAM_CHIP_SIZE
+    }
+}
+
+impl FromStr for RdramSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "4M" | "4MB" => Ok(RdramSize::Size4M),
+            "8M" | "8MB" => Ok(RdramSize::Size8M),
+            _ => Err(format!("unknown RDRAM size: {} (expected 4M or 8M)", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Ri {
+    mode: u32,
+    config: u32,
+    select: u32,
+    refresh: u32,
+    latency: u32,
+    rerror: u32,
+    werror: u32,
+}
+
+impl Ri {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            RI_MODE => self.mode,
+            RI_CONFIG => self.config,
+            RI_SELECT => self.select,
+            RI_REFRESH => self.refresh,
+            RI_LATENCY => self.latency,
+            RI_RERROR => self.rerror,
+            RI_WERROR => self.werror,
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        match offset {
+            RI_MODE => self.mode = value & 0xF,
+            RI_CONFIG => self.config = value & 0x7F,
+            // Latches the current control value into the RDRAMs; nothing to
+            // model.
+            RI_CURRENT_LOAD => {}
+            RI_SELECT => self.select = value & 0xFF,
+            RI_REFRESH => self.refresh = value & 0x000F_FFFF,
+            RI_LATENCY => self.latency = value & 0xF,
+            // Writing either error register clears both.
+            RI_RERROR | RI_WERROR => {
+                self.rerror = 0;
+                self.werror = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+// The device id register holds the chip's base address in 1MB units in its
+// top bits.
+fn device_id(value: u32) -> u32 {
+    (value >> 26) & RDRAM_ID_MASK
+}
+
+struct RdramChip {
+    regs: [u32; RDRAM_REG_COUNT],
+}
+
+// The register side of the RDRAM chips. IPL3 moves each chip to its final
+// base by writing its device id, then sizes memory by probing which chips
+// answer.
+pub struct Rdram {
+    chips: Vec<RdramChip>,
+}
+
+impl Rdram {
+    pub fn new(size: RdramSize) -> Self {
+        let chips = (0..size.chips())
+            .map(|i| {
+                let mut regs = [0; RDRAM_REG_COUNT];
+                regs[RDRAM_DEVICE_TYPE] = RDRAM_DEVICE_TYPE_VALUE;
+                regs[RDRAM_DEVICE_ID] = ((i * RDRAM_CHIP_SIZE / 0x10_0000) as u32) << 26;
+                regs[RDRAM_DEVICE_MANUF] = RDRAM_DEVICE_MANUF_VALUE;
+                RdramChip { regs }
+            })
+            .collect();
+        Rdram { chips }
+    }
+
+    pub fn read_word(&self, offset: u32) -> u32 {
+        let reg = ((offset & 0x3FF) >> 2) as usize;
+        let id = (offset >> RDRAM_ID_SHIFT) & RDRAM_ID_MASK;
+        self.chips
+            .iter()
+            .find(|chip| device_id(chip.regs[RDRAM_DEVICE_ID]) == id)
+            .and_then(|chip| chip.regs.get(reg).copied())
+            .unwrap_or(0)
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        let reg = ((offset & 0x3FF) >> 2) as usize;
+        if reg >= RDRAM_REG_COUNT || reg == RDRAM_DEVICE_TYPE || reg == RDRAM_DEVICE_MANUF {
+            return;
+        }
+        let id = (offset >> RDRAM_ID_SHIFT) & RDRAM_ID_MASK;
+        for chip in self.chips.iter_mut() {
+            if offset & RDRAM_BROADCAST != 0 || device_id(chip.regs[RDRAM_DEVICE_ID]) == id {
+                chip.regs[reg] = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rdram_size_option() {
+        assert_eq!("8M".parse::<RdramSize>(), Ok(RdramSize::Size8M));
+        assert_eq!("4m".parse::<RdramSize>(), Ok(RdramSize::Size4M));
+        assert!("2M".parse::<RdramSize>().is_err());
+        assert_eq!(RdramSize::Size8M.chips(), 4);
+    }
+
+    #[test]
+    fn chips_answer_at_their_device_id() {
+        let mut rdram = Rdram::new(RdramSize::Size4M);
+        assert_eq!(rdram.read_word(0x0000), RDRAM_DEVICE_TYPE_VALUE);
+        assert_eq!(rdram.read_word(0x0800 + 0x24), RDRAM_DEVICE_MANUF_VALUE);
+        // No third chip without the Expansion Pak.
+        assert_eq!(rdram.read_word(0x1000), 0);
+
+        // Move the second chip up to 6MB and set its delay by broadcast.
+        rdram.write_word(0x0800 + 0x04, 6 << 26);
+        rdram.write_word(RDRAM_BROADCAST + 0x08, 0x1808_2838);
+        assert_eq!(rdram.read_word(0x0800), 0);
+        assert_eq!(rdram.read_word(0x1800 + 0x08), 0x1808_2838);
+        assert_eq!(rdram.read_word(0x08), 0x1808_2838);
+    }
+
+    #[test]
+    fn ri_error_registers_clear_together() {
+        let mut ri = Ri {
+            rerror: 1,
+            werror: 1,
+            ..Ri::default()
+        };
+        ri.write_word(RI_SELECT, 0x14);
+        ri.write_word(RI_WERROR, 0);
+        assert_eq!(ri.read_word(RI_SELECT), 0x14);
+        assert_eq!(ri.read_word(RI_RERROR), 0);
+        assert_eq!(ri.read_word(RI_WERROR), 0);
+    }
+}
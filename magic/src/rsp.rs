@@ -0,0 +1,448 @@
+use crate::rsp_decoder::{decode_rsp, CpuInstrRSP::*};
+use crate::vu::Vu;
+
+pub const SP_MEM_START: u32 = 0x0400_0000;
+pub const SP_MEM_END: u32 = 0x0403_FFFF;
+pub const SP_MEM_SIZE: usize = 0x1000;
+
+pub const SP_STATUS_HALT: u32 = 1 << 0;
+pub const SP_STATUS_BROKE: u32 = 1 << 1;
+pub const SP_STATUS_DMA_BUSY: u32 = 1 << 2;
+pub const SP_STATUS_DMA_FULL: u32 = 1 << 3;
+pub const SP_STATUS_IO_FULL: u32 = 1 << 4;
+pub const SP_STATUS_SSTEP: u32 = 1 << 5;
+pub const SP_STATUS_INTR_BREAK: u32 = 1 << 6;
+pub const SP_STATUS_SIGNALS: u32 = 0xFF << 7;
+pub const SP_STATUS_TASKDONE: u32 = 1 << 9;
+
+const SP_CLEAR_HALT: u32 = 1 << 0;
+const SP_SET_HALT: u32 = 1 << 1;
+const SP_CLEAR_BROKE: u32 = 1 << 2;
+const SP_CLEAR_INTR: u32 = 1 << 3;
+const SP_SET_INTR: u32 = 1 << 4;
+const SP_CLEAR_SSTEP: u32 = 1 << 5;
+const SP_SET_SSTEP: u32 = 1 << 6;
+const SP_CLEAR_INTR_BREAK: u32 = 1 << 7;
+const SP_SET_INTR_BREAK: u32 = 1 << 8;
+
+// COP0 register 4 is SP_STATUS, which lives in the core itself.
+const RSP_COP0_SP_STATUS: usize = 4;
+
+// The RSP's COP0 registers are the SP (0-7) and DP command (8-15) register
+// blocks, reached from the CPU through the memory map.
+pub trait RspCop0 {
+    fn read_cop0(&mut self, reg: usize) -> u32;
+    fn write_cop0(&mut self, reg: usize, value: u32);
+}
+
+// The RSP scalar unit: a 32-bit MIPS core without exceptions, multiply or
+// divide, executing from its own 4KB IMEM and accessing only DMEM.
+pub struct Rsp {
+    pub dmem: [u8; SP_MEM_SIZE],
+    pub imem: [u8; SP_MEM_SIZE],
+    pc: u32,
+    next_pc: u32,
+    gpr: [u32; 32],
+    status: u32,
+    interrupt: bool,
+    vu: Vu,
+}
+
+impl Default for Rsp {
+    fn default() -> Self {
+        Rsp {
+            dmem: [0; SP_MEM_SIZE],
+            imem: [0; SP_MEM_SIZE],
+            pc: 0,
+            next_pc: 4,
+            gpr: [0; 32],
+            status: SP_STATUS_HALT,
+            interrupt: false,
+            vu: Vu::default(),
+        }
+    }
+}
+
+impl Rsp {
+    // DMEM and IMEM are mirrored across the SP memory range.
+    pub fn read_mem_word(&self, offset: u32) -> u32 {
+        let mem = self.mem(offset);
+        let i = offset as usize & (SP_MEM_SIZE - 4);
+        u32::from_be_bytes([mem[i], mem[i + 1], mem[i + 2], mem[i + 3]])
+    }
+
+    pub fn write_mem_word(&mut self, offset: u32, value: u32) {
+        let i = offset as usize & (SP_MEM_SIZE - 4);
+        let mem = if offset & 0x1000 == 0 {
+            &mut self.dmem
+        } else {
+            &mut self.imem
+        };
+        mem[i..i + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn mem(&self, offset: u32) -> &[u8; SP_MEM_SIZE] {
+        if offset & 0x1000 == 0 {
+            &self.dmem
+        } else {
+            &self.imem
+        }
+    }
+
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc & 0xFFC;
+        self.next_pc = (self.pc + 4) & 0xFFC;
+    }
+
+    pub fn gpr(&self, reg: usize) -> u32 {
+        self.gpr[reg]
+    }
+
+    pub fn vu(&self) -> &Vu {
+        &self.vu
+    }
+
+    pub fn status(&self) -> u32 {
+        self.status
+    }
+
+    pub fn halted(&self) -> bool {
+        self.status & SP_STATUS_HALT != 0
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+
+    // Writes to SP_STATUS, from either the CPU or the RSP itself, are pairs
+    // of clear and set bits.
+    pub fn write_status(&mut self, value: u32) {
+        set_clear(
+            &mut self.status,
+            value,
+            SP_SET_HALT,
+            SP_CLEAR_HALT,
+            SP_STATUS_HALT,
+        );
+        if value & SP_CLEAR_BROKE != 0 {
+            self.status &= !SP_STATUS_BROKE;
+        }
+        if value & SP_CLEAR_INTR != 0 {
+            self.interrupt = false;
+        }
+        if value & SP_SET_INTR != 0 {
+            self.interrupt = true;
+        }
+        set_clear(
+            &mut self.status,
+            value,
+            SP_SET_SSTEP,
+            SP_CLEAR_SSTEP,
+            SP_STATUS_SSTEP,
+        );
+        set_clear(
+            &mut self.status,
+            value,
+            SP_SET_INTR_BREAK,
+            SP_CLEAR_INTR_BREAK,
+            SP_STATUS_INTR_BREAK,
+        );
+        for signal in 0..8 {
+            set_clear(
+                &mut self.status,
+                value,
+                1 << (10 + signal * 2),
+                1 << (9 + signal * 2),
+                1 << (7 + signal),
+            );
+        }
+    }
+
+    fn break_(&mut self) {
+        self.status |= SP_STATUS_HALT | SP_STATUS_BROKE;
+        if self.status & SP_STATUS_INTR_BREAK != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    // Ends a task run outside the RSP the way the microcode would: signal 2
+    // (task done) and a break.
+    pub fn finish_task(&mut self) {
+        self.status |= SP_STATUS_TASKDONE;
+        self.break_();
+    }
+
+    // Runs until the RSP halts or the cycle budget is spent, returning the
+    // number of cycles used. The RSP retires one instruction per cycle.
+    pub fn run(&mut self, cop0: &mut impl RspCop0, cycles: u64) -> u64 {
+        let mut used = 0;
+        while used < cycles && !self.halted() {
+            self.step(cop0);
+            used += 1;
+        }
+        used
+    }
+
+    pub fn step(&mut self, cop0: &mut impl RspCop0) {
+        let pc = self.pc;
+        let i = pc as usize;
+        let iw = u32::from_be_bytes([
+            self.imem[i],
+            self.imem[i + 1],
+            self.imem[i + 2],
+            self.imem[i + 3],
+        ]);
+        self.pc = self.next_pc;
+        self.next_pc = (self.pc + 4) & 0xFFC;
+        self.execute(iw, pc, cop0);
+        self.gpr[0] = 0;
+        if self.status & SP_STATUS_SSTEP != 0 {
+            self.status |= SP_STATUS_HALT;
+        }
+    }
+
+    fn execute(&mut self, iw: u32, pc: u32, cop0: &mut impl RspCop0) {
+        let rs = ((iw >> 21) & 0x1F) as usize;
+        let rt = ((iw >> 16) & 0x1F) as usize;
+        let rd = ((iw >> 11) & 0x1F) as usize;
+        let sa = (iw >> 6) & 0x1F;
+        let imm = iw & 0xFFFF;
+        let simm = imm as i16 as u32;
+        let target = pc.wrapping_add(4).wrapping_add(simm << 2) & 0xFFC;
+        let addr = self.gpr[rs].wrapping_add(simm);
+
+        let instr = decode_rsp(iw);
+        match instr {
+            SLL => self.gpr[rd] = self.gpr[rt] << sa,
+            SRL => self.gpr[rd] = self.gpr[rt] >> sa,
+            SRA => self.gpr[rd] = ((self.gpr[rt] as i32) >> sa) as u32,
+            SLLV => self.gpr[rd] = self.gpr[rt] << (self.gpr[rs] & 0x1F),
+            SRLV => self.gpr[rd] = self.gpr[rt] >> (self.gpr[rs] & 0x1F),
+            SRAV => self.gpr[rd] = ((self.gpr[rt] as i32) >> (self.gpr[rs] & 0x1F)) as u32,
+            JR => self.next_pc = self.gpr[rs] & 0xFFC,
+            JALR => {
+                let link = (pc + 8) & 0xFFC;
+                self.next_pc = self.gpr[rs] & 0xFFC;
+                self.gpr[rd] = link;
+            }
+            BREAK => self.break_(),
+            // There are no overflow exceptions on the RSP.
+            ADD | ADDU => self.gpr[rd] = self.gpr[rs].wrapping_add(self.gpr[rt]),
+            SUB | SUBU => self.gpr[rd] = self.gpr[rs].wrapping_sub(self.gpr[rt]),
+            AND => self.gpr[rd] = self.gpr[rs] & self.gpr[rt],
+            OR => self.gpr[rd] = self.gpr[rs] | self.gpr[rt],
+            XOR => self.gpr[rd] = self.gpr[rs] ^ self.gpr[rt],
+            NOR => self.gpr[rd] = !(self.gpr[rs] | self.gpr[rt]),
+            SLT => self.gpr[rd] = ((self.gpr[rs] as i32) < (self.gpr[rt] as i32)) as u32,
+            SLTU => self.gpr[rd] = (self.gpr[rs] < self.gpr[rt]) as u32,
+            BLTZ => self.branch((self.gpr[rs] as i32) < 0, target),
+            BGEZ => self.branch((self.gpr[rs] as i32) >= 0, target),
+            BLTZAL => {
+                let taken = (self.gpr[rs] as i32) < 0;
+                self.gpr[31] = (pc + 8) & 0xFFC;
+                self.branch(taken, target);
+            }
+            BGEZAL => {
+                let taken = (self.gpr[rs] as i32) >= 0;
+                self.gpr[31] = (pc + 8) & 0xFFC;
+                self.branch(taken, target);
+            }
+            J => self.next_pc = (iw << 2) & 0xFFC,
+            JAL => {
+                self.gpr[31] = (pc + 8) & 0xFFC;
+                self.next_pc = (iw << 2) & 0xFFC;
+            }
+            BEQ => self.branch(self.gpr[rs] == self.gpr[rt], target),
+            BNE => self.branch(self.gpr[rs] != self.gpr[rt], target),
+            BLEZ => self.branch(self.gpr[rs] as i32 <= 0, target),
+            BGTZ => self.branch(self.gpr[rs] as i32 > 0, target),
+            ADDI | ADDIU => self.gpr[rt] = self.gpr[rs].wrapping_add(simm),
+            SLTI => self.gpr[rt] = ((self.gpr[rs] as i32) < (simm as i32)) as u32,
+            SLTIU => self.gpr[rt] = (self.gpr[rs] < simm) as u32,
+            ANDI => self.gpr[rt] = self.gpr[rs] & imm,
+            ORI => self.gpr[rt] = self.gpr[rs] | imm,
+            XORI => self.gpr[rt] = self.gpr[rs] ^ imm,
+            LUI => self.gpr[rt] = imm << 16,
+            MFC0 => {
+                self.gpr[rt] = match rd & 0xF {
+                    // The DMA engine supplies the busy and full bits.
+                    RSP_COP0_SP_STATUS => self.status | cop0.read_cop0(RSP_COP0_SP_STATUS),
+                    reg => cop0.read_cop0(reg),
+                }
+            }
+            MTC0 => match rd & 0xF {
+                RSP_COP0_SP_STATUS => self.write_status(self.gpr[rt]),
+                reg => cop0.write_cop0(reg, self.gpr[rt]),
+            },
+            LB => self.gpr[rt] = self.load(addr, 1) as i8 as u32,
+            LH => self.gpr[rt] = self.load(addr, 2) as i16 as u32,
+            LW => self.gpr[rt] = self.load(addr, 4),
+            LBU => self.gpr[rt] = self.load(addr, 1),
+            LHU => self.gpr[rt] = self.load(addr, 2),
+            SB => self.store(addr, 1, self.gpr[rt]),
+            SH => self.store(addr, 2, self.gpr[rt]),
+            SW => self.store(addr, 4, self.gpr[rt]),
+            MFC2 => self.gpr[rt] = self.vu.mfc2(iw),
+            MTC2 => self.vu.mtc2(iw, self.gpr[rt]),
+            CFC2 => self.gpr[rt] = self.vu.cfc2(iw),
+            CTC2 => self.vu.ctc2(iw, self.gpr[rt]),
+            LBV | LSV | LLV | LDV | LQV | LRV | LPV | LUV | LHV | LFV | LTV => {
+                self.vu.load(instr, iw, self.gpr[rs], &self.dmem)
+            }
+            SBV | SSV | SLV | SDV | SQV | SRV | SPV | SUV | SHV | SFV | SWV | STV => {
+                self.vu.store(instr, iw, self.gpr[rs], &mut self.dmem)
+            }
+            VMULF | VMULU | VRNDP | VMULQ | VMUDL | VMUDM | VMUDN | VMUDH | VMACF | VMACU
+            | VRNDN | VMACQ | VMADL | VMADM | VMADN | VMADH | VADD | VSUB | VABS | VADDC
+            | VSUBC | VSAR | VLT | VEQ | VNE | VGE | VCL | VCH | VCR | VMRG | VAND | VNAND
+            | VOR | VNOR | VXOR | VNXOR | VRCP | VRCPL | VRCPH | VMOV | VRSQ | VRSQL | VRSQH
+            | VNOP => self.vu.execute(instr, iw),
+            // Unknown opcodes do nothing.
+            Invalid => {}
+        }
+    }
+
+    fn branch(&mut self, taken: bool, target: u32) {
+        if taken {
+            self.next_pc = target;
+        }
+    }
+
+    // DMEM accesses need not be aligned and wrap around at the end.
+    fn load(&self, addr: u32, len: u32) -> u32 {
+        (0..len).fold(0, |value, i| {
+            let byte = self.dmem[(addr.wrapping_add(i) as usize) & (SP_MEM_SIZE - 1)];
+            (value << 8) | u32::from(byte)
+        })
+    }
+
+    fn store(&mut self, addr: u32, len: u32, value: u32) {
+        for i in 0..len {
+            let byte = (value >> ((len - 1 - i) * 8)) as u8;
+            self.dmem[(addr.wrapping_add(i) as usize) & (SP_MEM_SIZE - 1)] = byte;
+        }
+    }
+}
+
+fn set_clear(reg: &mut u32, value: u32, set: u32, clear: u32, bit: u32) {
+    if value & clear != 0 {
+        *reg &= !bit;
+    }
+    if value & set != 0 {
+        *reg |= bit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Regs([u32; 16]);
+
+    impl RspCop0 for Regs {
+        fn read_cop0(&mut self, reg: usize) -> u32 {
+            self.0[reg]
+        }
+
+        fn write_cop0(&mut self, reg: usize, value: u32) {
+            self.0[reg] = value;
+        }
+    }
+
+    fn load_program(rsp: &mut Rsp, program: &[u32]) {
+        for (i, iw) in program.iter().enumerate() {
+            rsp.write_mem_word(0x1000 + i as u32 * 4, *iw);
+        }
+        rsp.set_pc(0);
+        rsp.write_status(SP_CLEAR_HALT | SP_CLEAR_BROKE);
+    }
+
+    #[test]
+    fn loop_then_break() {
+        let mut rsp = Rsp::default();
+        let mut regs = Regs::default();
+        load_program(
+            &mut rsp,
+            &[
+                0x2001_0005, // addi $1, $0, 5
+                0x0000_1025, // or $2, $0, $0
+                0x0041_1020, // add $2, $2, $1
+                0x2021_FFFF, // addi $1, $1, -1
+                0x1C20_FFFD, // bgtz $1, -3
+                0x0000_0000, // nop
+                0xAC02_0FFE, // sw $2, 0xFFE($0)
+                0x4082_4000, // mtc0 $2, $8
+                0x0000_000D, // break
+            ],
+        );
+        assert_eq!(rsp.run(&mut regs, 1000), 2 + 5 * 4 + 3);
+        assert_eq!(rsp.gpr(2), 15);
+        assert!(rsp.halted());
+        assert_ne!(rsp.status() & SP_STATUS_BROKE, 0);
+        assert!(!rsp.interrupt());
+        assert_eq!(regs.0[8], 15);
+        // The unaligned store wrapped around DMEM.
+        assert_eq!(&rsp.dmem[0xFFE..], &[0, 0]);
+        assert_eq!(&rsp.dmem[..2], &[0, 15]);
+        assert_eq!(rsp.pc(), 9 * 4);
+    }
+
+    #[test]
+    fn vector_add_through_dmem() {
+        let mut rsp = Rsp::default();
+        for i in 0..8 {
+            rsp.dmem[i * 2 + 1] = i as u8 + 1;
+            rsp.dmem[i * 2 + 17] = 0x10;
+        }
+        load_program(
+            &mut rsp,
+            &[
+                0xC801_2000, // lqv $v1[0], 0($0)
+                0xC802_2001, // lqv $v2[0], 16($0)
+                0x4A02_08D0, // vadd $v3, $v1, $v2
+                0xE803_2002, // sqv $v3[0], 32($0)
+                0x4804_1900, // mfc2 $4, $v3[2]
+                0x0000_000D, // break
+            ],
+        );
+        rsp.run(&mut Regs::default(), 100);
+        assert_eq!(rsp.dmem[0x21], 0x11);
+        assert_eq!(rsp.dmem[0x2F], 0x18);
+        assert_eq!(rsp.gpr(4), 0x12);
+    }
+
+    #[test]
+    fn break_interrupts_when_enabled() {
+        let mut rsp = Rsp::default();
+        load_program(&mut rsp, &[0x0000_000D]);
+        rsp.write_status(SP_SET_INTR_BREAK | (1 << 10) | (1 << 24));
+        assert_eq!(rsp.status() & SP_STATUS_SIGNALS, (1 << 7) | (1 << 14));
+        rsp.step(&mut Regs::default());
+        assert!(rsp.interrupt());
+        rsp.write_status(SP_CLEAR_INTR);
+        assert!(!rsp.interrupt());
+    }
+
+    #[test]
+    fn jal_links_within_imem() {
+        let mut rsp = Rsp::default();
+        let mut regs = Regs::default();
+        load_program(
+            &mut rsp,
+            &[
+                0x0C00_03FF, // jal 0xFFC
+                0x3403_1234, // ori $3, $0, 0x1234
+            ],
+        );
+        rsp.write_mem_word(0x1FFC, 0x0000_000D);
+        rsp.run(&mut regs, 10);
+        assert_eq!(rsp.gpr(31), 8);
+        assert_eq!(rsp.gpr(3), 0x1234);
+        assert_eq!(rsp.pc(), 0);
+    }
+}
@@ -0,0 +1,208 @@
+use magic_macros::rsp_instr_enum;
+
+rsp_instr_enum!(CpuInstrRSP);
+use CpuInstrRSP::*;
+
+// ============================================================================
+//  First-order opcode table.
+//
+//      31---------26---------------------------------------------------0
+//      |  OPCODE/6 |                                                   |
+//      ------6----------------------------------------------------------
+//      |--000--|--001--|--010--|--011--|--100--|--101--|--110--|--111--|
+//  000 | *SPEC | *RGIM | J     | JAL   | BEQ   | BNE   | BLEZ  | BGTZ  |
+//  001 | ADDI  | ADDIU | SLTI  | SLTIU | ANDI  | ORI   | XORI  | LUI   |
+//  010 | *COP0 |       | *COP2 |       |       |       |       |       |
+//  011 |       |       |       |       |       |       |       |       |
+//  100 | LB    | LH    |       | LW    | LBU   | LHU   |       |       |
+//  101 | SB    | SH    |       | SW    |       |       |       |       |
+//  110 |       |       | *LWC2 |       |       |       |       |       |
+//  111 |       |       | *SWC2 |       |       |       |       |       |
+//      |-------|-------|-------|-------|-------|-------|-------|-------|
+//
+// ============================================================================
+#[rustfmt::skip]
+const OPCODE_TABLE: [CpuInstrRSP; 64] = [
+    Invalid, Invalid, J,       JAL,
+    BEQ,     BNE,     BLEZ,    BGTZ,
+    ADDI,    ADDIU,   SLTI,    SLTIU,
+    ANDI,    ORI,     XORI,    LUI,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    LB,      LH,      Invalid, LW,
+    LBU,     LHU,     Invalid, Invalid,
+    SB,      SH,      Invalid, SW,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+];
+
+// ============================================================================
+//  Escaped opcode table: Special.
+//
+//      31---------26------------------------------------------5--------0
+//      | SPECIAL/6 |                                         |  FMT/6  |
+//      ------6----------------------------------------------------6-----
+//      |--000--|--001--|--010--|--011--|--100--|--101--|--110--|--111--|
+//  000 | SLL   |       | SRL   | SRA   | SLLV  |       | SRLV  | SRAV  |
+//  001 | JR    | JALR  |       |       |       | BREAK |       |       |
+//  010 |       |       |       |       |       |       |       |       |
+//  011 |       |       |       |       |       |       |       |       |
+//  100 | ADD   | ADDU  | SUB   | SUBU  | AND   | OR    | XOR   | NOR   |
+//  101 |       |       | SLT   | SLTU  |       |       |       |       |
+//  110 |       |       |       |       |       |       |       |       |
+//  111 |       |       |       |       |       |       |       |       |
+//      |-------|-------|-------|-------|-------|-------|-------|-------|
+//
+// ============================================================================
+#[rustfmt::skip]
+const SPECIAL_TABLE: [CpuInstrRSP; 64] = [
+    SLL,     Invalid, SRL,     SRA,
+    SLLV,    Invalid, SRLV,    SRAV,
+    JR,      JALR,    Invalid, Invalid,
+    Invalid, BREAK,   Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    ADD,     ADDU,    SUB,     SUBU,
+    AND,     OR,      XOR,     NOR,
+    Invalid, Invalid, SLT,     SLTU,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+];
+
+// ============================================================================
+//  Escaped opcode table: RegImm.
+//
+//      31---------26----------20-------16------------------------------0
+//      | = REGIMM  |          |  FMT/5  |                              |
+//      ------6---------------------5------------------------------------
+//      |--000--|--001--|--010--|--011--|--100--|--101--|--110--|--111--|
+//   00 | BLTZ  | BGEZ  |       |       |       |       |       |       |
+//   01 |       |       |       |       |       |       |       |       |
+//   10 | BLTZAL| BGEZAL|       |       |       |       |       |       |
+//   11 |       |       |       |       |       |       |       |       |
+//      |-------|-------|-------|-------|-------|-------|-------|-------|
+//
+// ============================================================================
+#[rustfmt::skip]
+const REGIMM_TABLE: [CpuInstrRSP; 32] = [
+    BLTZ,    BGEZ,    Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    BLTZAL,  BGEZAL,  Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+];
+
+// ============================================================================
+//  Escaped opcode table: COP2 vector operations (bit 25 set).
+//
+//      31---------26-25-----------------------------------------5------0
+//      |  COP2/6   |1|                                         |  FMT/6 |
+//      ------6-------1--------------------------------------------6------
+//      |--000--|--001--|--010--|--011--|--100--|--101--|--110--|--111--|
+//  000 | VMULF | VMULU | VRNDP | VMULQ | VMUDL | VMUDM | VMUDN | VMUDH |
+//  001 | VMACF | VMACU | VRNDN | VMACQ | VMADL | VMADM | VMADN | VMADH |
+//  010 | VADD  | VSUB  |       | VABS  | VADDC | VSUBC |       |       |
+//  011 |       |       |       |       |       | VSAR  |       |       |
+//  100 | VLT   | VEQ   | VNE   | VGE   | VCL   | VCH   | VCR   | VMRG  |
+//  101 | VAND  | VNAND | VOR   | VNOR  | VXOR  | VNXOR |       |       |
+//  110 | VRCP  | VRCPL | VRCPH | VMOV  | VRSQ  | VRSQL | VRSQH | VNOP  |
+//  111 |       |       |       |       |       |       |       |       |
+//      |-------|-------|-------|-------|-------|-------|-------|-------|
+//
+// ============================================================================
+#[rustfmt::skip]
+const VECTOR_TABLE: [CpuInstrRSP; 64] = [
+    VMULF,   VMULU,   VRNDP,   VMULQ,
+    VMUDL,   VMUDM,   VMUDN,   VMUDH,
+    VMACF,   VMACU,   VRNDN,   VMACQ,
+    VMADL,   VMADM,   VMADN,   VMADH,
+    VADD,    VSUB,    Invalid, VABS,
+    VADDC,   VSUBC,   Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, VSAR,    Invalid, Invalid,
+    VLT,     VEQ,     VNE,     VGE,
+    VCL,     VCH,     VCR,     VMRG,
+    VAND,    VNAND,   VOR,     VNOR,
+    VXOR,    VNXOR,   Invalid, Invalid,
+    VRCP,    VRCPL,   VRCPH,   VMOV,
+    VRSQ,    VRSQL,   VRSQH,   VNOP,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+];
+
+// ============================================================================
+//  Escaped opcode tables: LWC2 and SWC2, selected by bits 15:11.
+//
+//      31---------26--------------------15-----11----------------------0
+//      | LWC2/SWC2 |                    |  FMT/5 |                     |
+//      ------6------------------------------5---------------------------
+//      |--000--|--001--|--010--|--011--|--100--|--101--|--110--|--111--|
+//   00 | xBV   | xSV   | xLV   | xDV   | xQV   | xRV   | xPV   | xUV   |
+//   01 | xHV   | xFV   | SWV   | xTV   |       |       |       |       |
+//   10 |       |       |       |       |       |       |       |       |
+//   11 |       |       |       |       |       |       |       |       |
+//      |-------|-------|-------|-------|-------|-------|-------|-------|
+//
+// ============================================================================
+#[rustfmt::skip]
+const LWC2_TABLE: [CpuInstrRSP; 32] = [
+    LBV,     LSV,     LLV,     LDV,
+    LQV,     LRV,     LPV,     LUV,
+    LHV,     LFV,     Invalid, LTV,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+];
+
+#[rustfmt::skip]
+const SWC2_TABLE: [CpuInstrRSP; 32] = [
+    SBV,     SSV,     SLV,     SDV,
+    SQV,     SRV,     SPV,     SUV,
+    SHV,     SFV,     SWV,     STV,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+    Invalid, Invalid, Invalid, Invalid,
+];
+
+// Decoder for the scalar and vector units. Fields the RSP doesn't look at,
+// such as the shift amount of ADD, are ignored rather than making the
+// instruction invalid.
+pub fn decode_rsp(iw: u32) -> CpuInstrRSP {
+    match iw >> 26 {
+        0x00 => SPECIAL_TABLE[(iw & 0x3F) as usize],
+        0x01 => REGIMM_TABLE[((iw >> 16) & 0x1F) as usize],
+        0x10 => match (iw >> 21) & 0x1F {
+            0x00 => MFC0,
+            0x04 => MTC0,
+            _ => Invalid,
+        },
+        0x12 if iw & (1 << 25) != 0 => VECTOR_TABLE[(iw & 0x3F) as usize],
+        0x12 => match (iw >> 21) & 0x1F {
+            0x00 => MFC2,
+            0x02 => CFC2,
+            0x04 => MTC2,
+            0x06 => CTC2,
+            _ => Invalid,
+        },
+        0x32 => LWC2_TABLE[((iw >> 11) & 0x1F) as usize],
+        0x3A => SWC2_TABLE[((iw >> 11) & 0x1F) as usize],
+        opcode => OPCODE_TABLE[opcode as usize],
+    }
+}
@@ -0,0 +1,739 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const EEPROM_4K_SIZE: usize = 512;
+pub const EEPROM_16K_SIZE: usize = 2048;
+pub const EEPROM_BLOCK_SIZE: usize = 8;
+pub const SRAM_SIZE: usize = 0x8000;
+pub const FLASHRAM_SIZE: usize = 0x2_0000;
+pub const FLASHRAM_PAGE_SIZE: usize = 128;
+pub const FLASHRAM_SECTOR_SIZE: usize = 0x4000;
+pub const CONTROLLER_PAK_SIZE: usize = 0x8000;
+pub const CONTROLLER_PAK_BLOCK_SIZE: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SaveType {
+    None,
+    Eeprom4K,
+    Eeprom16K,
+    Sram,
+    FlashRam,
+}
+
+impl SaveType {
+    pub fn size(self) -> usize {
+        match self {
+            SaveType::None => 0,
+            SaveType::Eeprom4K => EEPROM_4K_SIZE,
+            SaveType::Eeprom16K => EEPROM_16K_SIZE,
+            SaveType::Sram => SRAM_SIZE,
+            SaveType::FlashRam => FLASHRAM_SIZE,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SaveType::None => "sav",
+            SaveType::Eeprom4K | SaveType::Eeprom16K => "eep",
+            SaveType::Sram => "sra",
+            SaveType::FlashRam => "fla",
+        }
+    }
+
+    // The game code in the ROM header is media type, two character game ID
+    // and region, e.g. "NSME". Only the game ID is significant for saves.
+    pub fn detect(game_code: &[u8; 4]) -> SaveType {
+        SAVE_TYPE_DATABASE
+            .iter()
+            .find(|(id, _)| id.as_bytes() == &game_code[1..3])
+            .map(|&(_, save_type)| save_type)
+            .unwrap_or(SaveType::None)
+    }
+}
+
+impl FromStr for SaveType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(SaveType::None),
+            "eeprom4k" => Ok(SaveType::Eeprom4K),
+            "eeprom16k" => Ok(SaveType::Eeprom16K),
+            "sram" => Ok(SaveType::Sram),
+            "flashram" => Ok(SaveType::FlashRam),
+            _ => Err(format!("unknown save type: {}", s)),
+        }
+    }
+}
+
+const SAVE_TYPE_DATABASE: &[(&str, SaveType)] = &[
+    ("TW", SaveType::Eeprom4K),
+    ("HF", SaveType::Eeprom4K),
+    ("OS", SaveType::Eeprom4K),
+    ("TC", SaveType::Eeprom4K),
+    ("ER", SaveType::Eeprom4K),
+    ("AG", SaveType::Eeprom4K),
+    ("AB", SaveType::Eeprom4K),
+    ("S3", SaveType::Eeprom4K),
+    ("TN", SaveType::Eeprom4K),
+    ("BN", SaveType::Eeprom4K),
+    ("BK", SaveType::Eeprom4K),
+    ("FH", SaveType::Eeprom4K),
+    ("MU", SaveType::Eeprom4K),
+    ("BC", SaveType::Eeprom4K),
+    ("BH", SaveType::Eeprom4K),
+    ("HA", SaveType::Eeprom4K),
+    ("BM", SaveType::Eeprom4K),
+    ("BV", SaveType::Eeprom4K),
+    ("BD", SaveType::Eeprom4K),
+    ("CT", SaveType::Eeprom4K),
+    ("CH", SaveType::Eeprom4K),
+    ("CG", SaveType::Eeprom4K),
+    ("P2", SaveType::Eeprom4K),
+    ("XO", SaveType::Eeprom4K),
+    ("CU", SaveType::Eeprom4K),
+    ("CX", SaveType::Eeprom4K),
+    ("DY", SaveType::Eeprom4K),
+    ("DQ", SaveType::Eeprom4K),
+    ("DR", SaveType::Eeprom4K),
+    ("N6", SaveType::Eeprom4K),
+    ("DU", SaveType::Eeprom4K),
+    ("JM", SaveType::Eeprom4K),
+    ("FW", SaveType::Eeprom4K),
+    ("F2", SaveType::Eeprom4K),
+    ("KA", SaveType::Eeprom4K),
+    ("FG", SaveType::Eeprom4K),
+    ("GL", SaveType::Eeprom4K),
+    ("GV", SaveType::Eeprom4K),
+    ("GE", SaveType::Eeprom4K),
+    ("HP", SaveType::Eeprom4K),
+    ("PG", SaveType::Eeprom4K),
+    ("IJ", SaveType::Eeprom4K),
+    ("IC", SaveType::Eeprom4K),
+    ("FY", SaveType::Eeprom4K),
+    ("KI", SaveType::Eeprom4K),
+    ("LL", SaveType::Eeprom4K),
+    ("LR", SaveType::Eeprom4K),
+    ("KT", SaveType::Eeprom4K),
+    ("LB", SaveType::Eeprom4K),
+    ("MW", SaveType::Eeprom4K),
+    ("ML", SaveType::Eeprom4K),
+    ("TM", SaveType::Eeprom4K),
+    ("MI", SaveType::Eeprom4K),
+    ("MG", SaveType::Eeprom4K),
+    ("MO", SaveType::Eeprom4K),
+    ("MS", SaveType::Eeprom4K),
+    ("MR", SaveType::Eeprom4K),
+    ("CR", SaveType::Eeprom4K),
+    ("EA", SaveType::Eeprom4K),
+    ("PW", SaveType::Eeprom4K),
+    ("PY", SaveType::Eeprom4K),
+    ("PT", SaveType::Eeprom4K),
+    ("RA", SaveType::Eeprom4K),
+    ("WQ", SaveType::Eeprom4K),
+    ("SU", SaveType::Eeprom4K),
+    ("SN", SaveType::Eeprom4K),
+    ("K2", SaveType::Eeprom4K),
+    ("SV", SaveType::Eeprom4K),
+    ("FX", SaveType::Eeprom4K),
+    ("FP", SaveType::Eeprom4K),
+    ("S6", SaveType::Eeprom4K),
+    ("NA", SaveType::Eeprom4K),
+    ("RS", SaveType::Eeprom4K),
+    ("SW", SaveType::Eeprom4K),
+    ("SC", SaveType::Eeprom4K),
+    ("SA", SaveType::Eeprom4K),
+    ("B6", SaveType::Eeprom4K),
+    ("SS", SaveType::Eeprom4K),
+    ("TX", SaveType::Eeprom4K),
+    ("T6", SaveType::Eeprom4K),
+    ("TP", SaveType::Eeprom4K),
+    ("TJ", SaveType::Eeprom4K),
+    ("RC", SaveType::Eeprom4K),
+    ("TR", SaveType::Eeprom4K),
+    ("TB", SaveType::Eeprom4K),
+    ("GU", SaveType::Eeprom4K),
+    ("IR", SaveType::Eeprom4K),
+    ("VL", SaveType::Eeprom4K),
+    ("VY", SaveType::Eeprom4K),
+    ("WR", SaveType::Eeprom4K),
+    ("WC", SaveType::Eeprom4K),
+    ("AD", SaveType::Eeprom4K),
+    ("WU", SaveType::Eeprom4K),
+    ("YK", SaveType::Eeprom4K),
+    ("MA", SaveType::Eeprom4K),
+    ("SM", SaveType::Eeprom4K),
+    ("WT", SaveType::Eeprom4K),
+    ("B7", SaveType::Eeprom16K),
+    ("GT", SaveType::Eeprom16K),
+    ("FU", SaveType::Eeprom16K),
+    ("CW", SaveType::Eeprom16K),
+    ("CZ", SaveType::Eeprom16K),
+    ("D6", SaveType::Eeprom16K),
+    ("DO", SaveType::Eeprom16K),
+    ("D2", SaveType::Eeprom16K),
+    ("3D", SaveType::Eeprom16K),
+    ("MX", SaveType::Eeprom16K),
+    ("GC", SaveType::Eeprom16K),
+    ("IM", SaveType::Eeprom16K),
+    ("NB", SaveType::Eeprom16K),
+    ("MV", SaveType::Eeprom16K),
+    ("M8", SaveType::Eeprom16K),
+    ("EV", SaveType::Eeprom16K),
+    ("PP", SaveType::Eeprom16K),
+    ("UB", SaveType::Eeprom16K),
+    ("PD", SaveType::Eeprom16K),
+    ("RZ", SaveType::Eeprom16K),
+    ("R7", SaveType::Eeprom16K),
+    ("EP", SaveType::Eeprom16K),
+    ("YS", SaveType::Eeprom16K),
+    ("TE", SaveType::Sram),
+    ("VB", SaveType::Sram),
+    ("B5", SaveType::Sram),
+    ("FZ", SaveType::Sram),
+    ("SI", SaveType::Sram),
+    ("G6", SaveType::Sram),
+    ("GP", SaveType::Sram),
+    ("YW", SaveType::Sram),
+    ("HY", SaveType::Sram),
+    ("IB", SaveType::Sram),
+    ("PS", SaveType::Sram),
+    ("PA", SaveType::Sram),
+    ("P4", SaveType::Sram),
+    ("J5", SaveType::Sram),
+    ("P6", SaveType::Sram),
+    ("PE", SaveType::Sram),
+    ("JG", SaveType::Sram),
+    ("ZL", SaveType::Sram),
+    ("KG", SaveType::Sram),
+    ("MF", SaveType::Sram),
+    ("RI", SaveType::Sram),
+    ("UT", SaveType::Sram),
+    ("UM", SaveType::Sram),
+    ("OB", SaveType::Sram),
+    ("PM", SaveType::Sram),
+    ("RE", SaveType::Sram),
+    ("AL", SaveType::Sram),
+    ("T3", SaveType::Sram),
+    ("S4", SaveType::Sram),
+    ("A2", SaveType::Sram),
+    ("VP", SaveType::Sram),
+    ("WL", SaveType::Sram),
+    ("W2", SaveType::Sram),
+    ("WX", SaveType::Sram),
+    ("CC", SaveType::FlashRam),
+    ("DA", SaveType::FlashRam),
+    ("AF", SaveType::FlashRam),
+    ("JF", SaveType::FlashRam),
+    ("KJ", SaveType::FlashRam),
+    ("ZS", SaveType::FlashRam),
+    ("M6", SaveType::FlashRam),
+    ("CK", SaveType::FlashRam),
+    ("MQ", SaveType::FlashRam),
+    ("PN", SaveType::FlashRam),
+    ("PF", SaveType::FlashRam),
+    ("PO", SaveType::FlashRam),
+    ("P3", SaveType::FlashRam),
+    ("RH", SaveType::FlashRam),
+    ("SQ", SaveType::FlashRam),
+    ("T9", SaveType::FlashRam),
+    ("W4", SaveType::FlashRam),
+    ("DP", SaveType::FlashRam),
+];
+
+// Backing store for any kind of backup memory. The host file is read once
+// when the save is created and written back whenever it has been modified,
+// at the latest when the save is dropped.
+pub struct SaveFile {
+    path: Option<PathBuf>,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl SaveFile {
+    pub fn new(path: Option<&Path>, size: usize, fill: u8) -> Self {
+        let mut data = vec![fill; size];
+        if let Some(path) = path {
+            if let Ok(mut file) = File::open(path) {
+                let mut contents = Vec::new();
+                if let Err(why) = file.read_to_end(&mut contents) {
+                    panic!("Couldn't read save file {}: {}", path.display(), why);
+                }
+                let len = contents.len().min(size);
+                data[..len].copy_from_slice(&contents[..len]);
+            }
+        }
+        SaveFile {
+            path: path.map(Path::to_path_buf),
+            data,
+            dirty: false,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.dirty = true;
+        &mut self.data
+    }
+
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        if let Some(path) = &self.path {
+            let mut file = match File::create(path) {
+                Err(why) => panic!("Couldn't create save file {}: {}", path.display(), why),
+                Ok(file) => file,
+            };
+            if let Err(why) = file.write_all(&self.data) {
+                panic!("Couldn't write save file {}: {}", path.display(), why);
+            }
+        }
+        self.dirty = false;
+    }
+}
+
+impl Drop for SaveFile {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+pub struct Eeprom {
+    file: SaveFile,
+    save_type: SaveType,
+}
+
+impl Eeprom {
+    pub fn new(path: Option<&Path>, save_type: SaveType) -> Self {
+        Eeprom {
+            file: SaveFile::new(path, save_type.size(), 0xFF),
+            save_type,
+        }
+    }
+
+    // Handles a single joybus command addressed to the EEPROM channel.
+    // Returns false if the command isn't one the EEPROM responds to.
+    pub fn joybus_command(&mut self, tx: &[u8], rx: &mut [u8]) -> bool {
+        match tx.first() {
+            Some(0x00) | Some(0xFF) if rx.len() >= 3 => {
+                rx[0] = 0x00;
+                rx[1] = if self.save_type == SaveType::Eeprom16K {
+                    0xC0
+                } else {
+                    0x80
+                };
+                rx[2] = 0x00;
+                true
+            }
+            Some(0x04) if tx.len() >= 2 && rx.len() >= EEPROM_BLOCK_SIZE => {
+                let offset = self.block_offset(tx[1]);
+                rx[..EEPROM_BLOCK_SIZE]
+                    .copy_from_slice(&self.file.data()[offset..offset + EEPROM_BLOCK_SIZE]);
+                true
+            }
+            Some(0x05) if tx.len() >= 2 + EEPROM_BLOCK_SIZE => {
+                let offset = self.block_offset(tx[1]);
+                self.file.data_mut()[offset..offset + EEPROM_BLOCK_SIZE]
+                    .copy_from_slice(&tx[2..2 + EEPROM_BLOCK_SIZE]);
+                if let Some(status) = rx.first_mut() {
+                    *status = 0x00;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn block_offset(&self, block: u8) -> usize {
+        (block as usize * EEPROM_BLOCK_SIZE) % self.save_type.size()
+    }
+
+    pub fn flush(&mut self) {
+        self.file.flush();
+    }
+}
+
+pub struct Sram {
+    file: SaveFile,
+}
+
+impl Sram {
+    pub fn new(path: Option<&Path>) -> Self {
+        Sram {
+            file: SaveFile::new(path, SRAM_SIZE, 0x00),
+        }
+    }
+
+    pub fn dma_read(&self, offset: u32, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = self.file.data()[(offset as usize + i) % SRAM_SIZE];
+        }
+    }
+
+    pub fn dma_write(&mut self, offset: u32, buf: &[u8]) {
+        let data = self.file.data_mut();
+        for (i, byte) in buf.iter().enumerate() {
+            data[(offset as usize + i) % SRAM_SIZE] = *byte;
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.file.flush();
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FlashRamMode {
+    Idle,
+    Read,
+    Status,
+    SectorErase,
+    ChipErase,
+    PageProgram,
+}
+
+const FLASHRAM_SILICON_ID: u64 = 0x1111_8001_00C2_001E;
+const FLASHRAM_STATUS_ERASE_BUSY: u64 = 0x0000_0008_0000_0000;
+const FLASHRAM_STATUS_PROGRAM_BUSY: u64 = 0x0000_0004_0000_0000;
+const FLASHRAM_COMMAND_OFFSET: u32 = 0x1_0000;
+
+pub struct FlashRam {
+    file: SaveFile,
+    mode: FlashRamMode,
+    status: u64,
+    offset: usize,
+    page_buffer: [u8; FLASHRAM_PAGE_SIZE],
+}
+
+impl FlashRam {
+    pub fn new(path: Option<&Path>) -> Self {
+        FlashRam {
+            file: SaveFile::new(path, FLASHRAM_SIZE, 0xFF),
+            mode: FlashRamMode::Idle,
+            status: FLASHRAM_SILICON_ID,
+            offset: 0,
+            page_buffer: [0xFF; FLASHRAM_PAGE_SIZE],
+        }
+    }
+
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match self.mode {
+            FlashRamMode::Status if offset & 4 == 0 => (self.status >> 32) as u32,
+            FlashRamMode::Status => self.status as u32,
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        if offset != FLASHRAM_COMMAND_OFFSET {
+            return;
+        }
+        let page = (value & 0xFFFF) as usize * FLASHRAM_PAGE_SIZE;
+        match value >> 24 {
+            0x4B => {
+                self.mode = FlashRamMode::SectorErase;
+                self.offset = (page & !(FLASHRAM_SECTOR_SIZE - 1)) % FLASHRAM_SIZE;
+            }
+            0x3C => {
+                self.mode = FlashRamMode::ChipErase;
+                self.offset = 0;
+            }
+            0x78 => {
+                self.status = FLASHRAM_SILICON_ID | FLASHRAM_STATUS_ERASE_BUSY;
+            }
+            0xB4 => {
+                self.mode = FlashRamMode::PageProgram;
+            }
+            0xA5 => {
+                self.offset = page % FLASHRAM_SIZE;
+                self.status = FLASHRAM_SILICON_ID | FLASHRAM_STATUS_PROGRAM_BUSY;
+            }
+            0xD2 => self.execute(),
+            0xE1 => {
+                self.mode = FlashRamMode::Status;
+            }
+            0xF0 => {
+                self.mode = FlashRamMode::Read;
+                self.status = FLASHRAM_SILICON_ID;
+            }
+            _ => {}
+        }
+    }
+
+    fn execute(&mut self) {
+        match self.mode {
+            FlashRamMode::SectorErase => {
+                let offset = self.offset;
+                for byte in &mut self.file.data_mut()[offset..offset + FLASHRAM_SECTOR_SIZE] {
+                    *byte = 0xFF;
+                }
+            }
+            FlashRamMode::ChipErase => {
+                for byte in self.file.data_mut().iter_mut() {
+                    *byte = 0xFF;
+                }
+            }
+            FlashRamMode::PageProgram => {
+                let offset = self.offset;
+                let page_buffer = self.page_buffer;
+                self.file.data_mut()[offset..offset + FLASHRAM_PAGE_SIZE]
+                    .copy_from_slice(&page_buffer);
+            }
+            _ => {}
+        }
+        self.status = FLASHRAM_SILICON_ID;
+    }
+
+    pub fn dma_read(&self, offset: u32, buf: &mut [u8]) {
+        match self.mode {
+            FlashRamMode::Read => {
+                // The flash chip sits on a 16-bit bus, so cart addresses
+                // are half the byte offset into the array.
+                let base = offset as usize * 2;
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    *byte = self.file.data()[(base + i) % FLASHRAM_SIZE];
+                }
+            }
+            FlashRamMode::Status => {
+                let status = self.status.to_be_bytes();
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    *byte = status[i % 8];
+                }
+            }
+            _ => {
+                for byte in buf.iter_mut() {
+                    *byte = 0;
+                }
+            }
+        }
+    }
+
+    pub fn dma_write(&mut self, _offset: u32, buf: &[u8]) {
+        if self.mode == FlashRamMode::PageProgram {
+            let len = buf.len().min(FLASHRAM_PAGE_SIZE);
+            self.page_buffer[..len].copy_from_slice(&buf[..len]);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.file.flush();
+    }
+}
+
+pub struct ControllerPak {
+    file: SaveFile,
+}
+
+impl ControllerPak {
+    pub fn new(path: Option<&Path>) -> Self {
+        ControllerPak {
+            file: SaveFile::new(path, CONTROLLER_PAK_SIZE, 0x00),
+        }
+    }
+
+    // Accessory read: tx is [0x02, addr_hi, addr_lo], rx is 32 bytes of
+    // data followed by the data CRC.
+    pub fn read(&mut self, tx: &[u8], rx: &mut [u8]) {
+        let address = u16::from_be_bytes([tx[1], tx[2]]);
+        let block = CONTROLLER_PAK_BLOCK_SIZE;
+        if address_crc(address) != (address & 0x1F) as u8 {
+            for byte in rx[..block].iter_mut() {
+                *byte = 0;
+            }
+            rx[block] = !data_crc(&rx[..block]);
+            return;
+        }
+        let offset = (address & 0xFFE0) as usize;
+        if offset < CONTROLLER_PAK_SIZE {
+            rx[..block].copy_from_slice(&self.file.data()[offset..offset + block]);
+        } else {
+            for byte in rx[..block].iter_mut() {
+                *byte = 0;
+            }
+        }
+        rx[block] = data_crc(&rx[..block]);
+    }
+
+    // Accessory write: tx is [0x03, addr_hi, addr_lo, data * 32], rx is
+    // the data CRC.
+    pub fn write(&mut self, tx: &[u8], rx: &mut [u8]) {
+        let address = u16::from_be_bytes([tx[1], tx[2]]);
+        let block = CONTROLLER_PAK_BLOCK_SIZE;
+        let data = &tx[3..3 + block];
+        if address_crc(address) != (address & 0x1F) as u8 {
+            rx[0] = !data_crc(data);
+            return;
+        }
+        let offset = (address & 0xFFE0) as usize;
+        if offset < CONTROLLER_PAK_SIZE {
+            self.file.data_mut()[offset..offset + block].copy_from_slice(data);
+        }
+        rx[0] = data_crc(data);
+    }
+
+    pub fn flush(&mut self) {
+        self.file.flush();
+    }
+}
+
+// 5-bit CRC over the upper 11 bits of an accessory address, as computed by
+// libultra's __osContAddressCrc.
+pub fn address_crc(address: u16) -> u8 {
+    let address = u32::from(address >> 5);
+    let mut crc: u32 = 0;
+    let mut bit = 0x400;
+    while bit != 0 {
+        crc <<= 1;
+        if address & bit != 0 {
+            if crc & 0x20 != 0 {
+                crc ^= 0x14;
+            } else {
+                crc += 1;
+            }
+        } else if crc & 0x20 != 0 {
+            crc ^= 0x15;
+        }
+        bit >>= 1;
+    }
+    for _ in 0..5 {
+        crc <<= 1;
+        if crc & 0x20 != 0 {
+            crc ^= 0x15;
+        }
+    }
+    (crc & 0x1F) as u8
+}
+
+// 8-bit CRC over an accessory data block, as computed by libultra's
+// __osContDataCrc.
+pub fn data_crc(data: &[u8]) -> u8 {
+    let mut crc: u32 = 0;
+    for byte in data {
+        let mut bit = 0x80;
+        while bit != 0 {
+            crc <<= 1;
+            if u32::from(*byte) & bit != 0 {
+                if crc & 0x100 != 0 {
+                    crc ^= 0x84;
+                } else {
+                    crc += 1;
+                }
+            } else if crc & 0x100 != 0 {
+                crc ^= 0x85;
+            }
+            bit >>= 1;
+        }
+    }
+    for _ in 0..8 {
+        crc <<= 1;
+        if crc & 0x100 != 0 {
+            crc ^= 0x85;
+        }
+    }
+    crc as u8
+}
+
+pub enum Save {
+    None,
+    Eeprom(Eeprom),
+    Sram(Sram),
+    FlashRam(FlashRam),
+}
+
+impl Save {
+    pub fn new(path: Option<&Path>, save_type: SaveType) -> Self {
+        match save_type {
+            SaveType::None => Save::None,
+            SaveType::Eeprom4K | SaveType::Eeprom16K => Save::Eeprom(Eeprom::new(path, save_type)),
+            SaveType::Sram => Save::Sram(Sram::new(path)),
+            SaveType::FlashRam => Save::FlashRam(FlashRam::new(path)),
+        }
+    }
+
+    pub fn flush(&mut self) {
+        match self {
+            Save::None => {}
+            Save::Eeprom(eeprom) => eeprom.flush(),
+            Save::Sram(sram) => sram.flush(),
+            Save::FlashRam(flash) => flash.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_save_types() {
+        assert_eq!(SaveType::detect(b"NSME"), SaveType::Eeprom4K);
+        assert_eq!(SaveType::detect(b"NZLP"), SaveType::Sram);
+        assert_eq!(SaveType::detect(b"CZLJ"), SaveType::Sram);
+        assert_eq!(SaveType::detect(b"NZSE"), SaveType::FlashRam);
+        assert_eq!(SaveType::detect(b"NYSE"), SaveType::Eeprom16K);
+        assert_eq!(SaveType::detect(b"N\0\0E"), SaveType::None);
+    }
+
+    #[test]
+    fn eeprom_round_trip() {
+        let mut eeprom = Eeprom::new(None, SaveType::Eeprom4K);
+        let mut rx = [0u8; 8];
+        assert!(eeprom.joybus_command(&[0x00], &mut rx[..3]));
+        assert_eq!(rx[..3], [0x00, 0x80, 0x00]);
+        let write = [0x05, 3, 1, 2, 3, 4, 5, 6, 7, 8];
+        assert!(eeprom.joybus_command(&write, &mut rx[..1]));
+        assert!(eeprom.joybus_command(&[0x04, 3], &mut rx));
+        assert_eq!(rx, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn flashram_program_and_erase() {
+        let mut flash = FlashRam::new(None);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xB400_0000);
+        flash.dma_write(0, &[0xAB; FLASHRAM_PAGE_SIZE]);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xA500_0002);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xD200_0000);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xF000_0000);
+        let mut buf = [0u8; 4];
+        flash.dma_read((2 * FLASHRAM_PAGE_SIZE / 2) as u32, &mut buf);
+        assert_eq!(buf, [0xAB; 4]);
+
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0x4B00_0000);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0x7800_0000);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xD200_0000);
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xF000_0000);
+        flash.dma_read((2 * FLASHRAM_PAGE_SIZE / 2) as u32, &mut buf);
+        assert_eq!(buf, [0xFF; 4]);
+
+        flash.write_word(FLASHRAM_COMMAND_OFFSET, 0xE100_0000);
+        assert_eq!(flash.read_word(0), 0x1111_8001);
+    }
+
+    #[test]
+    fn controller_pak_crcs() {
+        // Rumble pak probe addresses as sent by libultra.
+        assert_eq!(address_crc(0x8000), 0x01);
+        assert_eq!(address_crc(0xC000), 0x1B);
+        assert_eq!(data_crc(&[0u8; 32]), 0x00);
+    }
+
+    #[test]
+    fn controller_pak_round_trip() {
+        let mut pak = ControllerPak::new(None);
+        let address = 0x0040 | u16::from(address_crc(0x0040));
+        let mut tx = vec![0x03, (address >> 8) as u8, address as u8];
+        tx.extend_from_slice(&[0x5A; 32]);
+        let mut rx = [0u8; 33];
+        pak.write(&tx, &mut rx[..1]);
+        assert_eq!(rx[0], data_crc(&[0x5A; 32]));
+        pak.read(&tx[..3], &mut rx);
+        assert_eq!(rx[..32], [0x5A; 32]);
+        assert_eq!(rx[32], data_crc(&[0x5A; 32]));
+    }
+}
@@ -0,0 +1,116 @@
+// All device timing is expressed in CPU cycles at 93.75MHz. The RCP and
+// its interfaces run at 62.5MHz, two thirds of the CPU clock.
+pub const CPU_CLOCK_HZ: u64 = 93_750_000;
+pub const RCP_CLOCK_HZ: u64 = 62_500_000;
+
+pub fn rcp_to_cpu_cycles(rcp_cycles: u64) -> u64 {
+    rcp_cycles * CPU_CLOCK_HZ / RCP_CLOCK_HZ
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    AiBufferDone,
+    CompareMatch,
+    PiDmaComplete,
+    SiDmaComplete,
+    SpDmaComplete,
+    ViLine,
+}
+
+// Each kind of event is pending at most once; scheduling it again moves it.
+// There are only ever a handful of events in flight, so a sorted vector
+// beats anything cleverer.
+#[derive(Default)]
+pub struct Scheduler {
+    now: u64,
+    events: Vec<(u64, Event)>,
+}
+
+impl Scheduler {
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn schedule(&mut self, delay: u64, event: Event) {
+        let time = self.now + delay;
+        self.schedule_at(time, event);
+    }
+
+    pub fn schedule_at(&mut self, time: u64, event: Event) {
+        self.cancel(event);
+        let index = self
+            .events
+            .iter()
+            .position(|&(t, _)| t > time)
+            .unwrap_or(self.events.len());
+        self.events.insert(index, (time, event));
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|&(_, e)| e != event);
+    }
+
+    pub fn is_scheduled(&self, event: Event) -> bool {
+        self.events.iter().any(|&(_, e)| e == event)
+    }
+
+    pub fn time_of(&self, event: Event) -> Option<u64> {
+        self.events
+            .iter()
+            .find(|&&(_, e)| e == event)
+            .map(|&(t, _)| t)
+    }
+
+    pub fn next_event_time(&self) -> Option<u64> {
+        self.events.first().map(|&(t, _)| t)
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    // Returns the earliest event that is due, if any. Events due at the
+    // same time are returned in the order they were scheduled.
+    pub fn pop_due(&mut self) -> Option<Event> {
+        match self.events.first() {
+            Some(&(time, event)) if time <= self.now => {
+                self.events.remove(0);
+                Some(event)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_fire_in_time_order() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(100, Event::PiDmaComplete);
+        assert_eq!(scheduler.next_event_time(), Some(100));
+        scheduler.advance(99);
+        assert_eq!(scheduler.pop_due(), None);
+        scheduler.advance(1);
+        assert_eq!(scheduler.pop_due(), Some(Event::PiDmaComplete));
+        assert_eq!(scheduler.pop_due(), None);
+    }
+
+    #[test]
+    fn rescheduling_replaces() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(100, Event::PiDmaComplete);
+        scheduler.schedule(50, Event::PiDmaComplete);
+        assert_eq!(scheduler.time_of(Event::PiDmaComplete), Some(50));
+        scheduler.cancel(Event::PiDmaComplete);
+        assert!(!scheduler.is_scheduled(Event::PiDmaComplete));
+    }
+
+    #[test]
+    fn rcp_clock_conversion() {
+        assert_eq!(rcp_to_cpu_cycles(2), 3);
+        assert_eq!(rcp_to_cpu_cycles(RCP_CLOCK_HZ), CPU_CLOCK_HZ);
+    }
+}
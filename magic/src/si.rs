@@ -0,0 +1,112 @@
+use crate::scheduler::{Event, Scheduler};
+
+pub const SI_REGS_START: u32 = 0x0480_0000;
+pub const SI_REGS_END: u32 = 0x048F_FFFF;
+
+const SI_DRAM_ADDR: u32 = 0x00;
+const SI_PIF_ADDR_RD64B: u32 = 0x04;
+const SI_PIF_ADDR_WR64B: u32 = 0x10;
+const SI_STATUS: u32 = 0x18;
+
+const SI_STATUS_DMA_BUSY: u32 = 1 << 0;
+const SI_STATUS_IO_BUSY: u32 = 1 << 1;
+const SI_STATUS_DMA_ERROR: u32 = 1 << 3;
+const SI_STATUS_INTERRUPT: u32 = 1 << 12;
+
+// A 64 byte transfer takes about 6000 CPU cycles, most of it spent by the
+// PIF running the joybus.
+const SI_DMA_CYCLES: u64 = 6000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SiDmaDirection {
+    // SI_PIF_ADDR_RD64B: PIF RAM to RDRAM
+    ToDram,
+    // SI_PIF_ADDR_WR64B: RDRAM to PIF RAM
+    ToPif,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SiDma {
+    pub dram_addr: u32,
+    pub direction: SiDmaDirection,
+}
+
+#[derive(Default)]
+pub struct Si {
+    dram_addr: u32,
+    status: u32,
+    pending: Option<SiDma>,
+}
+
+impl Si {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            SI_DRAM_ADDR => self.dram_addr,
+            SI_STATUS => self.status,
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32, scheduler: &mut Scheduler) {
+        match offset {
+            SI_DRAM_ADDR => self.dram_addr = value & 0x00FF_FFF8,
+            SI_PIF_ADDR_RD64B => self.start_dma(SiDmaDirection::ToDram, scheduler),
+            SI_PIF_ADDR_WR64B => self.start_dma(SiDmaDirection::ToPif, scheduler),
+            // Any write to the status register acknowledges the interrupt.
+            SI_STATUS => self.status &= !SI_STATUS_INTERRUPT,
+            _ => {}
+        }
+    }
+
+    fn start_dma(&mut self, direction: SiDmaDirection, scheduler: &mut Scheduler) {
+        if self.status & SI_STATUS_DMA_BUSY != 0 {
+            self.status |= SI_STATUS_DMA_ERROR;
+            return;
+        }
+        self.pending = Some(SiDma {
+            dram_addr: self.dram_addr,
+            direction,
+        });
+        self.status |= SI_STATUS_DMA_BUSY;
+        scheduler.schedule(SI_DMA_CYCLES, Event::SiDmaComplete);
+    }
+
+    // Called when Event::SiDmaComplete fires. The transfer is handed back to
+    // the bus to perform the copy and run the PIF.
+    pub fn complete_dma(&mut self) -> Option<SiDma> {
+        let dma = self.pending.take()?;
+        self.status &= !(SI_STATUS_DMA_BUSY | SI_STATUS_IO_BUSY | SI_STATUS_DMA_ERROR);
+        self.status |= SI_STATUS_INTERRUPT;
+        Some(dma)
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.status & SI_STATUS_INTERRUPT != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dma_interrupts_after_latency() {
+        let mut si = Si::default();
+        let mut scheduler = Scheduler::default();
+        si.write_word(SI_DRAM_ADDR, 0x0000_1234, &mut scheduler);
+        si.write_word(SI_PIF_ADDR_WR64B, 0x1FC0_07C0, &mut scheduler);
+        assert_eq!(si.read_word(SI_STATUS), SI_STATUS_DMA_BUSY);
+        si.write_word(SI_PIF_ADDR_RD64B, 0x1FC0_07C0, &mut scheduler);
+        assert_ne!(si.read_word(SI_STATUS) & SI_STATUS_DMA_ERROR, 0);
+
+        scheduler.advance(SI_DMA_CYCLES);
+        assert_eq!(scheduler.pop_due(), Some(Event::SiDmaComplete));
+        let dma = si.complete_dma().unwrap();
+        assert_eq!(dma.dram_addr, 0x0000_1230);
+        assert_eq!(dma.direction, SiDmaDirection::ToPif);
+        assert_eq!(si.read_word(SI_STATUS), SI_STATUS_INTERRUPT);
+
+        si.write_word(SI_STATUS, 0, &mut scheduler);
+        assert!(!si.interrupt());
+    }
+}
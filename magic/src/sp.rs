@@ -0,0 +1,223 @@
+use crate::dp::Dp;
+use crate::rsp::{RspCop0, SP_MEM_SIZE, SP_STATUS_DMA_BUSY, SP_STATUS_DMA_FULL};
+use crate::scheduler::{rcp_to_cpu_cycles, Event, Scheduler};
+
+pub const SP_REGS_START: u32 = 0x0404_0000;
+pub const SP_REGS_END: u32 = 0x0407_FFFF;
+pub const SP_PC_REGS_START: u32 = 0x0408_0000;
+pub const SP_PC_REGS_END: u32 = 0x040F_FFFF;
+
+pub const SP_MEM_ADDR: u32 = 0x00;
+pub const SP_DRAM_ADDR: u32 = 0x04;
+pub const SP_RD_LEN: u32 = 0x08;
+pub const SP_WR_LEN: u32 = 0x0C;
+pub const SP_STATUS: u32 = 0x10;
+pub const SP_DMA_FULL: u32 = 0x14;
+pub const SP_DMA_BUSY: u32 = 0x18;
+pub const SP_SEMAPHORE: u32 = 0x1C;
+
+// Bit 12 of SP_MEM_ADDR selects IMEM; transfers are in 8-byte units.
+const SP_MEM_ADDR_MASK: u32 = 0x1FF8;
+const SP_DRAM_ADDR_MASK: u32 = 0x00FF_FFF8;
+
+// Fixed setup cost of a transfer, in RCP cycles, on top of one cycle per
+// 8 bytes moved.
+const SP_DMA_SETUP_CYCLES: u64 = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpDmaDirection {
+    // SP_RD_LEN: RDRAM to DMEM/IMEM
+    ToSp,
+    // SP_WR_LEN: DMEM/IMEM to RDRAM
+    ToDram,
+}
+
+// A transfer of `count` rows of `len` bytes. `skip` bytes of RDRAM are
+// stepped over between rows; the SP side is always contiguous and wraps
+// within the selected memory.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpDma {
+    pub mem_addr: u32,
+    pub dram_addr: u32,
+    pub len: u32,
+    pub count: u32,
+    pub skip: u32,
+    pub direction: SpDmaDirection,
+}
+
+impl SpDma {
+    fn from_len(mem_addr: u32, dram_addr: u32, value: u32, direction: SpDmaDirection) -> Self {
+        SpDma {
+            mem_addr,
+            dram_addr,
+            len: (value & 0xFF8) + 8,
+            count: ((value >> 12) & 0xFF) + 1,
+            skip: (value >> 20) & 0xFF8,
+            direction,
+        }
+    }
+
+    fn cycles(&self) -> u64 {
+        rcp_to_cpu_cycles(SP_DMA_SETUP_CYCLES + u64::from(self.len * self.count / 8))
+    }
+
+    // The address registers are left pointing just past the transfer.
+    fn end_addrs(&self) -> (u32, u32) {
+        let bytes = self.len * self.count;
+        let mem = (self.mem_addr & 0x1000) | ((self.mem_addr + bytes) & (SP_MEM_SIZE as u32 - 1));
+        let dram = self.dram_addr + (self.len + self.skip) * self.count;
+        (mem, dram & SP_DRAM_ADDR_MASK)
+    }
+}
+
+// The SP register block. SP_STATUS itself belongs to the RSP core, which
+// this block only supplies with the DMA busy and full bits.
+#[derive(Default)]
+pub struct Sp {
+    mem_addr: u32,
+    dram_addr: u32,
+    len: u32,
+    semaphore: bool,
+    current: Option<SpDma>,
+    queued: Option<SpDma>,
+}
+
+impl Sp {
+    pub fn read_word(&mut self, offset: u32) -> u32 {
+        match offset {
+            SP_MEM_ADDR => self.mem_addr,
+            SP_DRAM_ADDR => self.dram_addr,
+            SP_RD_LEN | SP_WR_LEN => self.len,
+            SP_STATUS => self.dma_status(),
+            SP_DMA_FULL => self.queued.is_some() as u32,
+            SP_DMA_BUSY => self.current.is_some() as u32,
+            // Reading the semaphore acquires it.
+            SP_SEMAPHORE => {
+                let value = self.semaphore as u32;
+                self.semaphore = true;
+                value
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32, scheduler: &mut Scheduler) {
+        match offset {
+            SP_MEM_ADDR => self.mem_addr = value & SP_MEM_ADDR_MASK,
+            SP_DRAM_ADDR => self.dram_addr = value & SP_DRAM_ADDR_MASK,
+            SP_RD_LEN => self.start_dma(value, SpDmaDirection::ToSp, scheduler),
+            SP_WR_LEN => self.start_dma(value, SpDmaDirection::ToDram, scheduler),
+            SP_SEMAPHORE => self.semaphore = false,
+            _ => {}
+        }
+    }
+
+    pub fn dma_status(&self) -> u32 {
+        let mut status = 0;
+        if self.current.is_some() {
+            status |= SP_STATUS_DMA_BUSY;
+        }
+        if self.queued.is_some() {
+            status |= SP_STATUS_DMA_FULL;
+        }
+        status
+    }
+
+    // One transfer runs while a second waits; a third request while both
+    // slots are taken is dropped, as libultra never makes one.
+    fn start_dma(&mut self, value: u32, direction: SpDmaDirection, scheduler: &mut Scheduler) {
+        let dma = SpDma::from_len(self.mem_addr, self.dram_addr, value, direction);
+        self.len = value;
+        if self.current.is_none() {
+            scheduler.schedule(dma.cycles(), Event::SpDmaComplete);
+            self.current = Some(dma);
+        } else if self.queued.is_none() {
+            self.queued = Some(dma);
+        }
+    }
+
+    // Called when Event::SpDmaComplete fires. The finished transfer is
+    // handed back to the bus to perform the copy, and the queued one, if
+    // any, is started.
+    pub fn complete_dma(&mut self, scheduler: &mut Scheduler) -> Option<SpDma> {
+        let dma = self.current.take()?;
+        let (mem_addr, dram_addr) = dma.end_addrs();
+        self.mem_addr = mem_addr;
+        self.dram_addr = dram_addr;
+        self.len = (dma.skip << 20) | 0xFF8;
+        if let Some(next) = self.queued.take() {
+            scheduler.schedule(next.cycles(), Event::SpDmaComplete);
+            self.current = Some(next);
+        }
+        Some(dma)
+    }
+}
+
+// The view of the SP and DP command registers the RSP gets through its
+// COP0 registers 0-7 and 8-15. SP_STATUS (register 4) is handled by the
+// core itself.
+pub struct SpCop0<'a> {
+    pub sp: &'a mut Sp,
+    pub dp: &'a mut Dp,
+    pub scheduler: &'a mut Scheduler,
+}
+
+impl RspCop0 for SpCop0<'_> {
+    fn read_cop0(&mut self, reg: usize) -> u32 {
+        match reg {
+            0..=7 => self.sp.read_word(reg as u32 * 4),
+            8..=15 => self.dp.read_word((reg as u32 - 8) * 4),
+            _ => 0,
+        }
+    }
+
+    fn write_cop0(&mut self, reg: usize, value: u32) {
+        match reg {
+            0..=7 => self.sp.write_word(reg as u32 * 4, value, self.scheduler),
+            8..=15 => self.dp.write_word((reg as u32 - 8) * 4, value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dma_queues_behind_busy_transfer() {
+        let mut sp = Sp::default();
+        let mut scheduler = Scheduler::default();
+        sp.write_word(SP_MEM_ADDR, 0x1000, &mut scheduler);
+        sp.write_word(SP_DRAM_ADDR, 0x0010_0000, &mut scheduler);
+        // Two rows of 16 bytes, skipping 8 bytes of RDRAM between them.
+        sp.write_word(SP_RD_LEN, (8 << 20) | (1 << 12) | 15, &mut scheduler);
+        sp.write_word(SP_WR_LEN, 7, &mut scheduler);
+        assert_eq!(sp.dma_status(), SP_STATUS_DMA_BUSY | SP_STATUS_DMA_FULL);
+        assert_eq!(sp.read_word(SP_DMA_FULL), 1);
+
+        let first = sp.complete_dma(&mut scheduler).unwrap();
+        assert_eq!(first.len, 16);
+        assert_eq!(first.count, 2);
+        assert_eq!(first.direction, SpDmaDirection::ToSp);
+        assert_eq!(sp.read_word(SP_MEM_ADDR), 0x1020);
+        assert_eq!(sp.read_word(SP_DRAM_ADDR), 0x0010_0030);
+        assert_eq!(sp.dma_status(), SP_STATUS_DMA_BUSY);
+        assert!(scheduler.is_scheduled(Event::SpDmaComplete));
+
+        let second = sp.complete_dma(&mut scheduler).unwrap();
+        assert_eq!(second.direction, SpDmaDirection::ToDram);
+        assert_eq!(second.mem_addr, 0x1000);
+        assert_eq!(sp.dma_status(), 0);
+    }
+
+    #[test]
+    fn semaphore_is_acquired_by_reading() {
+        let mut sp = Sp::default();
+        let mut scheduler = Scheduler::default();
+        assert_eq!(sp.read_word(SP_SEMAPHORE), 0);
+        assert_eq!(sp.read_word(SP_SEMAPHORE), 1);
+        sp.write_word(SP_SEMAPHORE, 0, &mut scheduler);
+        assert_eq!(sp.read_word(SP_SEMAPHORE), 0);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::scheduler::CPU_CLOCK_HZ;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+pub const VI_REGS_START: u32 = 0x0440_0000;
+pub const VI_REGS_END: u32 = 0x044F_FFFF;
+
+const VI_CONTROL: u32 = 0x00;
+const VI_ORIGIN: u32 = 0x04;
+const VI_WIDTH: u32 = 0x08;
+const VI_V_INTR: u32 = 0x0C;
+const VI_V_CURRENT: u32 = 0x10;
+const VI_BURST: u32 = 0x14;
+const VI_V_SYNC: u32 = 0x18;
+const VI_H_SYNC: u32 = 0x1C;
+const VI_H_SYNC_LEAP: u32 = 0x20;
+const VI_H_VIDEO: u32 = 0x24;
+const VI_V_VIDEO: u32 = 0x28;
+const VI_V_BURST: u32 = 0x2C;
+const VI_X_SCALE: u32 = 0x30;
+const VI_Y_SCALE: u32 = 0x34;
+
+const VI_CONTROL_TYPE: u32 = 0x3;
+const VI_CONTROL_TYPE_16: u32 = 0x2;
+const VI_CONTROL_TYPE_32: u32 = 0x3;
+const VI_CONTROL_SERRATE: u32 = 1 << 6;
+
+pub const VI_NTSC_CLOCK_HZ: u64 = 48_681_812;
+
+// NTSC defaults, used until the IPL programs the sync registers.
+const DEFAULT_H_SYNC: u32 = 0x0C15;
+const DEFAULT_V_SYNC: u32 = 0x020D;
+
+// An RGB image with 8 bits per channel.
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn write_ppm(&self, out: &mut impl Write) -> std::io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.pixels)
+    }
+}
+
+pub trait FrameSink {
+    fn frame(&mut self, number: u64, frame: &Frame);
+}
+
+// Writes every `interval`th frame to `dir` as a numbered PPM file.
+pub struct PpmDumper {
+    dir: PathBuf,
+    interval: u64,
+}
+
+impl PpmDumper {
+    pub fn new(dir: impl Into<PathBuf>, interval: u64) -> Self {
+        PpmDumper {
+            dir: dir.into(),
+            interval: interval.max(1),
+        }
+    }
+}
+
+impl FrameSink for PpmDumper {
+    fn frame(&mut self, number: u64, frame: &Frame) {
+        if number.checked_rem(self.interval) != Some(0) {
+            return;
+        }
+        let path = self.dir.join(format!("frame_{:06}.ppm", number));
+        let file = match File::create(&path) {
+            Err(why) => panic!("Couldn't create frame file {}: {}", path.display(), why),
+            Ok(file) => file,
+        };
+        if let Err(why) = frame.write_ppm(&mut BufWriter::new(file)) {
+            panic!("Couldn't write frame file {}: {}", path.display(), why);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Vi {
+    control: u32,
+    origin: u32,
+    width: u32,
+    v_intr: u32,
+    v_current: u32,
+    burst: u32,
+    v_sync: u32,
+    h_sync: u32,
+    h_sync_leap: u32,
+    h_video: u32,
+    v_video: u32,
+    v_burst: u32,
+    x_scale: u32,
+    y_scale: u32,
+    interrupt: bool,
+    frames: u64,
+}
+
+impl Vi {
+    pub fn read_word(&self, offset: u32) -> u32 {
+        match offset {
+            VI_CONTROL => self.control,
+            VI_ORIGIN => self.origin,
+            VI_WIDTH => self.width,
+            VI_V_INTR => self.v_intr,
+            VI_V_CURRENT => self.v_current,
+            VI_BURST => self.burst,
+            VI_V_SYNC => self.v_sync,
+            VI_H_SYNC => self.h_sync,
+            VI_H_SYNC_LEAP => self.h_sync_leap,
+            VI_H_VIDEO => self.h_video,
+            VI_V_VIDEO => self.v_video,
+            VI_V_BURST => self.v_burst,
+            VI_X_SCALE => self.x_scale,
+            VI_Y_SCALE => self.y_scale,
+            _ => 0,
+        }
+    }
+
+    pub fn write_word(&mut self, offset: u32, value: u32) {
+        match offset {
+            VI_CONTROL => self.control = value & 0x0001_FFFF,
+            VI_ORIGIN => self.origin = value & 0x00FF_FFFF,
+            VI_WIDTH => self.width = value & 0xFFF,
+            VI_V_INTR => self.v_intr = value & 0x3FF,
+            // Writing the current line acknowledges the interrupt.
+            VI_V_CURRENT => self.interrupt = false,
+            VI_BURST => self.burst = value & 0x3FFF_FFFF,
+            VI_V_SYNC => self.v_sync = value & 0x3FF,
+            VI_H_SYNC => self.h_sync = value & 0x001F_0FFF,
+            VI_H_SYNC_LEAP => self.h_sync_leap = value & 0x0FFF_0FFF,
+            VI_H_VIDEO => self.h_video = value & 0x03FF_03FF,
+            VI_V_VIDEO => self.v_video = value & 0x03FF_03FF,
+            VI_V_BURST => self.v_burst = value & 0x03FF_03FF,
+            VI_X_SCALE => self.x_scale = value & 0x0FFF_0FFF,
+            VI_Y_SCALE => self.y_scale = value & 0x0FFF_0FFF,
+            _ => {}
+        }
+    }
+
+    pub fn interrupt(&self) -> bool {
+        self.interrupt
+    }
+
+    // H_SYNC holds the line length in quarter VI clocks, less one.
+    pub fn line_cycles(&self) -> u64 {
+        let h_sync = match self.h_sync & 0xFFF {
+            0 => DEFAULT_H_SYNC,
+            h_sync => h_sync,
+        };
+        (u64::from(h_sync) + 1) * CPU_CLOCK_HZ / (VI_NTSC_CLOCK_HZ * 4)
+    }
+
+    // Moves scan-out to the next line. V_CURRENT counts half lines, with
+    // the low bit selecting the field when interlaced. Returns true when a
+    // new field begins.
+    pub fn next_line(&mut self) -> bool {
+        let v_sync = match self.v_sync {
+            0 => DEFAULT_V_SYNC,
+            v_sync => v_sync,
+        };
+        let field = self.v_current & 1;
+        let mut line = (self.v_current & !1) + 2;
+        let new_field = line >= v_sync;
+        if new_field {
+            line = 0;
+            self.frames += 1;
+        }
+        let field = if new_field && self.control & VI_CONTROL_SERRATE != 0 {
+            field ^ 1
+        } else {
+            field
+        };
+        self.v_current = line | field;
+        if self.v_current & !1 == self.v_intr & !1 {
+            self.interrupt = true;
+        }
+        new_field
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Converts the framebuffer in RDRAM into an image at the output
+    // resolution given by H_VIDEO and V_VIDEO, resampling with the scale
+    // registers. Returns None while the VI is blanked.
+    pub fn scan_out(&self, rdram: &[u8]) -> Option<Frame> {
+        let bytes_per_pixel = match self.control & VI_CONTROL_TYPE {
+            VI_CONTROL_TYPE_16 => 2,
+            VI_CONTROL_TYPE_32 => 4,
+            _ => return None,
+        };
+        let h_start = (self.h_video >> 16) & 0x3FF;
+        let h_end = self.h_video & 0x3FF;
+        let v_start = (self.v_video >> 16) & 0x3FF;
+        let v_end = self.v_video & 0x3FF;
+        let width = h_end.saturating_sub(h_start) as usize;
+        let height = (v_end.saturating_sub(v_start) / 2) as usize;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let x_scale = self.x_scale & 0xFFF;
+        let x_offset = (self.x_scale >> 16) & 0xFFF;
+        let y_scale = self.y_scale & 0xFFF;
+        let y_offset = (self.y_scale >> 16) & 0xFFF;
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height as u32 {
+            // The scale factors are 2.10 fixed point.
+            let src_y = (y_offset + y * y_scale) >> 10;
+            for x in 0..width as u32 {
+                let src_x = (x_offset + x * x_scale) >> 10;
+                let addr = (self.origin + (src_y * self.width + src_x) * bytes_per_pixel) as usize;
+                let rgb = match rdram.get(addr..addr + bytes_per_pixel as usize) {
+                    Some(&[hi, lo]) => {
+                        let pixel = u16::from_be_bytes([hi, lo]);
+                        [
+                            expand5(pixel >> 11),
+                            expand5(pixel >> 6),
+                            expand5(pixel >> 1),
+                        ]
+                    }
+                    Some(&[r, g, b, _]) => [r, g, b],
+                    _ => [0, 0, 0],
+                };
+                pixels.extend_from_slice(&rgb);
+            }
+        }
+        Some(Frame {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+fn expand5(component: u16) -> u8 {
+    let c = (component & 0x1F) as u8;
+    (c << 3) | (c >> 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_interrupt() {
+        let mut vi = Vi::default();
+        vi.write_word(VI_V_SYNC, 0x20D);
+        vi.write_word(VI_V_INTR, 0x2);
+        assert!(!vi.next_line() && vi.interrupt());
+        assert_eq!(vi.read_word(VI_V_CURRENT), 2);
+        vi.write_word(VI_V_CURRENT, 0);
+        assert!(!vi.interrupt());
+
+        let mut lines = 1;
+        while !vi.next_line() {
+            lines += 1;
+        }
+        assert_eq!(lines, 262);
+        assert_eq!(vi.read_word(VI_V_CURRENT), 0);
+        assert_eq!(vi.frames(), 1);
+    }
+
+    #[test]
+    fn ntsc_line_time() {
+        let mut vi = Vi::default();
+        vi.write_word(VI_H_SYNC, 0x0015_0C15);
+        assert_eq!(vi.line_cycles(), 1489);
+    }
+
+    #[test]
+    fn scan_out_scales_16bit_framebuffer() {
+        let mut rdram = vec![0u8; 0x1000];
+        // A 2x2 framebuffer at 0x100: red, green / blue, white.
+        rdram[0x100..0x108].copy_from_slice(&[0xF8, 0x01, 0x07, 0xC1, 0x00, 0x3F, 0xFF, 0xFF]);
+        let mut vi = Vi::default();
+        vi.write_word(VI_CONTROL, VI_CONTROL_TYPE_16);
+        vi.write_word(VI_ORIGIN, 0x100);
+        vi.write_word(VI_WIDTH, 2);
+        vi.write_word(VI_H_VIDEO, (100 << 16) | 104);
+        vi.write_word(VI_V_VIDEO, (30 << 16) | 38);
+        // Each framebuffer pixel covers two output pixels in both directions.
+        vi.write_word(VI_X_SCALE, 0x200);
+        vi.write_word(VI_Y_SCALE, 0x200);
+
+        let frame = vi.scan_out(&rdram).unwrap();
+        assert_eq!((frame.width, frame.height), (4, 4));
+        let pixel = |x: usize, y: usize| &frame.pixels[(y * 4 + x) * 3..(y * 4 + x) * 3 + 3];
+        assert_eq!(pixel(1, 1), &[0xFF, 0, 0]);
+        assert_eq!(pixel(2, 0), &[0, 0xFF, 0]);
+        assert_eq!(pixel(0, 3), &[0, 0, 0xFF]);
+        assert_eq!(pixel(3, 2), &[0xFF, 0xFF, 0xFF]);
+
+        let mut ppm = Vec::new();
+        frame.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+    }
+}
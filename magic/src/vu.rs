@@ -0,0 +1,1014 @@
+use crate::rsp::SP_MEM_SIZE;
+use crate::rsp_decoder::CpuInstrRSP::{self, *};
+
+// Reciprocal and inverse square root ROMs: the 9 bits below the leading one
+// of the input select a 16-bit fraction.
+fn rcp_rom(index: usize) -> u32 {
+    let a = index as u64 + 512;
+    let b = (1u64 << 34) / a;
+    // The first entry would be exactly 1.0, which saturates.
+    (((b + 1) >> 8) - 0x10000).min(0xFFFF) as u32
+}
+
+fn rsq_rom(index: usize) -> u32 {
+    let a = (index as u64 + 512) >> (index % 2);
+    let mut b = 1u64 << 17;
+    // The largest b with b < 1 / sqrt(a).
+    while a * (b + 1) * (b + 1) < (1u64 << 44) {
+        b += 1;
+    }
+    ((b >> 1) & 0xFFFF) as u32
+}
+
+// The element field of a computational instruction picks which lanes of vt
+// each lane reads: all eight, pairs, quarters or a single broadcast lane.
+fn select(e: usize, lane: usize) -> usize {
+    match e {
+        0..=1 => lane,
+        2..=3 => (lane & !1) | (e & 1),
+        4..=7 => (lane & !3) | (e & 3),
+        _ => e & 7,
+    }
+}
+
+fn clamp_i16(value: i64) -> u16 {
+    value.clamp(i64::from(i16::MIN), i64::from(i16::MAX)) as i16 as u16
+}
+
+// The vector unit: 32 registers of eight 16-bit lanes, a 48-bit accumulator
+// per lane and the VCO/VCC/VCE flag registers, kept as one bit per lane.
+#[derive(Default)]
+pub struct Vu {
+    pub vpr: [[u16; 8]; 32],
+    acc: [u64; 8],
+    vco_carry: u8,
+    vco_ne: u8,
+    vcc_lt: u8,
+    vcc_ge: u8,
+    vce: u8,
+    div_in: u16,
+    div_out: u16,
+    div_dp: bool,
+}
+
+impl Vu {
+    // Bytes are numbered big-endian across the register, as in DMEM.
+    fn byte(&self, reg: usize, index: usize) -> u8 {
+        let lane = self.vpr[reg][(index >> 1) & 7];
+        if index & 1 == 0 {
+            (lane >> 8) as u8
+        } else {
+            lane as u8
+        }
+    }
+
+    fn set_byte(&mut self, reg: usize, index: usize, value: u8) {
+        let lane = &mut self.vpr[reg][(index >> 1) & 7];
+        if index & 1 == 0 {
+            *lane = (*lane & 0x00FF) | (u16::from(value) << 8);
+        } else {
+            *lane = (*lane & 0xFF00) | u16::from(value);
+        }
+    }
+
+    pub fn acc(&self, lane: usize) -> u64 {
+        self.acc[lane]
+    }
+
+    fn acc_signed(&self, lane: usize) -> i64 {
+        ((self.acc[lane] << 16) as i64) >> 16
+    }
+
+    fn set_acc(&mut self, lane: usize, value: i64) {
+        self.acc[lane] = (value as u64) & 0xFFFF_FFFF_FFFF;
+    }
+
+    fn set_acc_lo(&mut self, lane: usize, value: u16) {
+        self.acc[lane] = (self.acc[lane] & !0xFFFF) | u64::from(value);
+    }
+
+    fn acc_lo(&self, lane: usize) -> u16 {
+        self.acc[lane] as u16
+    }
+
+    // Bits 47:16 of the accumulator clamped to a signed 16-bit result.
+    fn clamp_signed(&self, lane: usize) -> u16 {
+        clamp_i16(self.acc_signed(lane) >> 16)
+    }
+
+    fn clamp_unsigned(&self, lane: usize) -> u16 {
+        let value = self.acc_signed(lane) >> 16;
+        if value < 0 {
+            0
+        } else if value > 0x7FFF {
+            0xFFFF
+        } else {
+            value as u16
+        }
+    }
+
+    // The low accumulator slice, saturated when bits 47:16 overflow 16 bits.
+    fn clamp_low(&self, lane: usize) -> u16 {
+        let value = self.acc_signed(lane) >> 16;
+        if value < i64::from(i16::MIN) {
+            0
+        } else if value > i64::from(i16::MAX) {
+            0xFFFF
+        } else {
+            self.acc_lo(lane)
+        }
+    }
+
+    fn flag(flags: u8, lane: usize) -> bool {
+        flags & (1 << lane) != 0
+    }
+
+    fn set_flag(flags: &mut u8, lane: usize, value: bool) {
+        if value {
+            *flags |= 1 << lane;
+        } else {
+            *flags &= !(1 << lane);
+        }
+    }
+
+    pub fn mfc2(&self, iw: u32) -> u32 {
+        let vs = ((iw >> 11) & 0x1F) as usize;
+        let e = ((iw >> 7) & 0xF) as usize;
+        let value = (u16::from(self.byte(vs, e)) << 8) | u16::from(self.byte(vs, (e + 1) & 15));
+        value as i16 as u32
+    }
+
+    pub fn mtc2(&mut self, iw: u32, value: u32) {
+        let vs = ((iw >> 11) & 0x1F) as usize;
+        let e = ((iw >> 7) & 0xF) as usize;
+        self.set_byte(vs, e, (value >> 8) as u8);
+        if e < 15 {
+            self.set_byte(vs, e + 1, value as u8);
+        }
+    }
+
+    pub fn cfc2(&self, iw: u32) -> u32 {
+        let value = match (iw >> 11) & 0x3 {
+            0 => u16::from(self.vco_ne) << 8 | u16::from(self.vco_carry),
+            1 => u16::from(self.vcc_ge) << 8 | u16::from(self.vcc_lt),
+            _ => u16::from(self.vce),
+        };
+        value as i16 as u32
+    }
+
+    pub fn ctc2(&mut self, iw: u32, value: u32) {
+        match (iw >> 11) & 0x3 {
+            0 => {
+                self.vco_carry = value as u8;
+                self.vco_ne = (value >> 8) as u8;
+            }
+            1 => {
+                self.vcc_lt = value as u8;
+                self.vcc_ge = (value >> 8) as u8;
+            }
+            _ => self.vce = value as u8,
+        }
+    }
+
+    // LWC2: the offset is a signed 7-bit count of the access size.
+    pub fn load(&mut self, instr: CpuInstrRSP, iw: u32, base: u32, dmem: &[u8; SP_MEM_SIZE]) {
+        let vt = ((iw >> 16) & 0x1F) as usize;
+        let e = ((iw >> 7) & 0xF) as usize;
+        let offset = (((iw & 0x7F) << 25) as i32 >> 25) as u32;
+        let read = |addr: u32| dmem[addr as usize & (SP_MEM_SIZE - 1)];
+        let size = match instr {
+            LBV => 1,
+            LSV => 2,
+            LLV => 4,
+            LDV | LPV | LUV => 8,
+            _ => 16,
+        };
+        let addr = base.wrapping_add(offset.wrapping_mul(size));
+        match instr {
+            LBV | LSV | LLV | LDV => {
+                for i in 0..size as usize {
+                    if e + i < 16 {
+                        self.set_byte(vt, e + i, read(addr.wrapping_add(i as u32)));
+                    }
+                }
+            }
+            LQV => {
+                let len = 16 - (addr & 15) as usize;
+                for i in 0..len {
+                    if e + i < 16 {
+                        self.set_byte(vt, e + i, read(addr.wrapping_add(i as u32)));
+                    }
+                }
+            }
+            LRV => {
+                let start = e + 16 - (addr & 15) as usize;
+                let aligned = addr & !15;
+                for (i, index) in (start..16).enumerate() {
+                    self.set_byte(vt, index, read(aligned + i as u32));
+                }
+            }
+            LPV | LUV => {
+                let shift = if instr == LPV { 8 } else { 7 };
+                let index = (addr & 7).wrapping_sub(e as u32);
+                let aligned = addr & !7;
+                for lane in 0..8 {
+                    let byte = read(aligned + (index.wrapping_add(lane as u32) & 15));
+                    self.vpr[vt][lane] = u16::from(byte) << shift;
+                }
+            }
+            LHV => {
+                let index = (addr & 7).wrapping_sub(e as u32);
+                let aligned = addr & !7;
+                for lane in 0..8 {
+                    let byte = read(aligned + (index.wrapping_add(lane as u32 * 2) & 15));
+                    self.vpr[vt][lane] = u16::from(byte) << 7;
+                }
+            }
+            LFV => {
+                let index = (addr & 7).wrapping_sub(e as u32);
+                let aligned = addr & !7;
+                let mut tmp = Vu::default();
+                for lane in 0..4 {
+                    let lo = read(aligned + (index.wrapping_add(lane as u32 * 4) & 15));
+                    let hi = read(aligned + (index.wrapping_add(lane as u32 * 4 + 8) & 15));
+                    tmp.vpr[0][lane] = u16::from(lo) << 7;
+                    tmp.vpr[0][lane + 4] = u16::from(hi) << 7;
+                }
+                for index in e..(e + 8).min(16) {
+                    self.set_byte(vt, index, tmp.byte(0, index));
+                }
+            }
+            // Transposed load: one lane into each of a group of 8 registers.
+            LTV => {
+                let begin = addr & !7;
+                let start = (e as u32 + (addr & 8)) & 15;
+                let group = vt & !7;
+                let mut reg = e >> 1;
+                for lane in 0..8 {
+                    let at = start + lane as u32 * 2;
+                    let hi = read(begin + (at & 15));
+                    let lo = read(begin + ((at + 1) & 15));
+                    self.vpr[group + reg][lane] = (u16::from(hi) << 8) | u16::from(lo);
+                    reg = (reg + 1) & 7;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // SWC2, with the same addressing as the loads.
+    pub fn store(&self, instr: CpuInstrRSP, iw: u32, base: u32, dmem: &mut [u8; SP_MEM_SIZE]) {
+        let vt = ((iw >> 16) & 0x1F) as usize;
+        let e = ((iw >> 7) & 0xF) as usize;
+        let offset = (((iw & 0x7F) << 25) as i32 >> 25) as u32;
+        let mut write = |addr: u32, value: u8| dmem[addr as usize & (SP_MEM_SIZE - 1)] = value;
+        let size = match instr {
+            SBV => 1,
+            SSV => 2,
+            SLV => 4,
+            SDV | SPV | SUV => 8,
+            _ => 16,
+        };
+        let addr = base.wrapping_add(offset.wrapping_mul(size));
+        match instr {
+            SBV | SSV | SLV | SDV => {
+                for i in 0..size as usize {
+                    write(addr.wrapping_add(i as u32), self.byte(vt, (e + i) & 15));
+                }
+            }
+            SQV => {
+                let len = 16 - (addr & 15) as usize;
+                for i in 0..len {
+                    write(addr.wrapping_add(i as u32), self.byte(vt, (e + i) & 15));
+                }
+            }
+            SRV => {
+                let len = (addr & 15) as usize;
+                let aligned = addr & !15;
+                for i in 0..len {
+                    write(aligned + i as u32, self.byte(vt, (e + 16 - len + i) & 15));
+                }
+            }
+            // Packed stores write the upper 8 bits of each lane, either as
+            // the high byte (SPV) or shifted by 7 (SUV), swapping halves once
+            // the element wraps past 8.
+            SPV | SUV => {
+                for (i, index) in (e..e + 8).enumerate() {
+                    let high = (index & 15) < 8;
+                    let value = if high == (instr == SPV) {
+                        self.byte(vt, (index & 7) << 1)
+                    } else {
+                        (self.vpr[vt][index & 7] >> 7) as u8
+                    };
+                    write(addr.wrapping_add(i as u32), value);
+                }
+            }
+            SHV => {
+                let index = addr & 7;
+                let aligned = addr & !7;
+                for lane in 0..8 {
+                    let b = e + lane * 2;
+                    let value = (self.byte(vt, b & 15) << 1) | (self.byte(vt, (b + 1) & 15) >> 7);
+                    write(aligned + ((index + lane as u32 * 2) & 15), value);
+                }
+            }
+            SFV => {
+                let index = addr & 7;
+                let aligned = addr & !7;
+                let lanes = match e {
+                    0 | 15 => Some([0, 1, 2, 3]),
+                    1 => Some([6, 7, 4, 5]),
+                    4 => Some([1, 2, 3, 0]),
+                    5 => Some([7, 4, 5, 6]),
+                    8 => Some([4, 5, 6, 7]),
+                    11 => Some([3, 0, 1, 2]),
+                    12 => Some([5, 6, 7, 4]),
+                    _ => None,
+                };
+                for i in 0..4 {
+                    let value = match lanes {
+                        Some(lanes) => (self.vpr[vt][lanes[i]] >> 7) as u8,
+                        None => 0,
+                    };
+                    write(aligned + ((index + i as u32 * 4) & 15), value);
+                }
+            }
+            SWV => {
+                let aligned = addr & !15;
+                for (i, index) in (e..e + 16).enumerate() {
+                    write(
+                        aligned + ((addr + i as u32) & 15),
+                        self.byte(vt, index & 15),
+                    );
+                }
+            }
+            // Transposed store: one lane from each of a group of 8 registers.
+            STV => {
+                let group = vt & !7;
+                let aligned = addr & !15;
+                for i in 0..8 {
+                    let lane = (i + 8 - (e >> 1)) & 7;
+                    let at = (addr & 15) as usize + lane * 2;
+                    write(aligned + (at & 15) as u32, self.byte(group + i, lane * 2));
+                    write(
+                        aligned + ((at + 1) & 15) as u32,
+                        self.byte(group + i, lane * 2 + 1),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // COP2 computational instructions: vd = vs op vt[e].
+    pub fn execute(&mut self, instr: CpuInstrRSP, iw: u32) {
+        let e = ((iw >> 21) & 0xF) as usize;
+        let vt = ((iw >> 16) & 0x1F) as usize;
+        let vs = ((iw >> 11) & 0x1F) as usize;
+        let vd = ((iw >> 6) & 0x1F) as usize;
+        let s = self.vpr[vs];
+        let t: [u16; 8] = {
+            let reg = self.vpr[vt];
+            let mut t = [0; 8];
+            for (lane, value) in t.iter_mut().enumerate() {
+                *value = reg[select(e, lane)];
+            }
+            t
+        };
+        let mut result = [0u16; 8];
+
+        match instr {
+            VRCP | VRCPL | VRSQ | VRSQL | VRCPH | VRSQH | VMOV => {
+                self.divide(instr, e, vt, vs & 7, vd, t);
+                return;
+            }
+            VSAR => {
+                for (lane, value) in result.iter_mut().enumerate() {
+                    *value = match e {
+                        8 => (self.acc[lane] >> 32) as u16,
+                        9 => (self.acc[lane] >> 16) as u16,
+                        10 => self.acc[lane] as u16,
+                        _ => 0,
+                    };
+                }
+                self.vpr[vd] = result;
+                return;
+            }
+            VNOP | Invalid => return,
+            _ => {}
+        }
+
+        for lane in 0..8 {
+            let ss = i64::from(s[lane] as i16);
+            let su = i64::from(s[lane]);
+            let ts = i64::from(t[lane] as i16);
+            let tu = i64::from(t[lane]);
+            let carry = Vu::flag(self.vco_carry, lane);
+            let ne = Vu::flag(self.vco_ne, lane);
+            result[lane] = match instr {
+                VMULF | VMULU => {
+                    self.set_acc(lane, ss * ts * 2 + 0x8000);
+                    if instr == VMULF {
+                        self.clamp_signed(lane)
+                    } else {
+                        self.clamp_unsigned(lane)
+                    }
+                }
+                VMACF | VMACU => {
+                    self.set_acc(lane, self.acc_signed(lane) + ss * ts * 2);
+                    if instr == VMACF {
+                        self.clamp_signed(lane)
+                    } else {
+                        self.clamp_unsigned(lane)
+                    }
+                }
+                VRNDP | VRNDN => {
+                    let acc = self.acc_signed(lane);
+                    let round = if vs & 1 != 0 { ts << 16 } else { ts };
+                    if (instr == VRNDP && acc >= 0) || (instr == VRNDN && acc < 0) {
+                        self.set_acc(lane, acc + round);
+                    }
+                    self.clamp_signed(lane)
+                }
+                VMULQ => {
+                    let mut product = ss * ts;
+                    if product < 0 {
+                        product += 31;
+                    }
+                    self.set_acc(lane, product << 16);
+                    clamp_i16(product >> 1) & !0xF
+                }
+                VMACQ => {
+                    let mut product = self.acc_signed(lane) >> 16;
+                    if product & 0x20 == 0 {
+                        if product < 0 {
+                            product += 32;
+                        } else if product >= 32 {
+                            product -= 32;
+                        }
+                    }
+                    self.set_acc(lane, (product << 16) | (self.acc[lane] & 0xFFFF) as i64);
+                    clamp_i16(product >> 1) & !0xF
+                }
+                VMUDL => {
+                    self.set_acc(lane, (su * tu) >> 16);
+                    self.acc_lo(lane)
+                }
+                VMUDM => {
+                    self.set_acc(lane, ss * tu);
+                    self.clamp_signed(lane)
+                }
+                VMUDN => {
+                    self.set_acc(lane, su * ts);
+                    self.acc_lo(lane)
+                }
+                VMUDH => {
+                    self.set_acc(lane, (ss * ts) << 16);
+                    self.clamp_signed(lane)
+                }
+                VMADL => {
+                    self.set_acc(lane, self.acc_signed(lane) + ((su * tu) >> 16));
+                    self.clamp_low(lane)
+                }
+                VMADM => {
+                    self.set_acc(lane, self.acc_signed(lane) + ss * tu);
+                    self.clamp_signed(lane)
+                }
+                VMADN => {
+                    self.set_acc(lane, self.acc_signed(lane) + su * ts);
+                    self.clamp_low(lane)
+                }
+                VMADH => {
+                    self.set_acc(lane, self.acc_signed(lane) + ((ss * ts) << 16));
+                    self.clamp_signed(lane)
+                }
+                VADD => {
+                    let sum = ss + ts + carry as i64;
+                    self.set_acc_lo(lane, sum as u16);
+                    clamp_i16(sum)
+                }
+                VSUB => {
+                    let diff = ss - ts - carry as i64;
+                    self.set_acc_lo(lane, diff as u16);
+                    clamp_i16(diff)
+                }
+                VABS => {
+                    let value = if ss < 0 {
+                        if ts == i64::from(i16::MIN) {
+                            self.set_acc_lo(lane, 0x8000);
+                            result[lane] = 0x7FFF;
+                            continue;
+                        }
+                        (-ts) as u16
+                    } else if ss == 0 {
+                        0
+                    } else {
+                        t[lane]
+                    };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                VADDC => {
+                    let sum = su + tu;
+                    Vu::set_flag(&mut self.vco_carry, lane, sum > 0xFFFF);
+                    Vu::set_flag(&mut self.vco_ne, lane, false);
+                    self.set_acc_lo(lane, sum as u16);
+                    sum as u16
+                }
+                VSUBC => {
+                    let diff = su - tu;
+                    Vu::set_flag(&mut self.vco_carry, lane, diff < 0);
+                    Vu::set_flag(&mut self.vco_ne, lane, diff != 0);
+                    self.set_acc_lo(lane, diff as u16);
+                    diff as u16
+                }
+                VLT | VEQ | VNE | VGE => {
+                    let take_s = match instr {
+                        VLT => ss < ts || (ss == ts && ne && carry),
+                        VEQ => ss == ts && !ne,
+                        VNE => ss != ts || ne,
+                        _ => ss > ts || (ss == ts && !(ne && carry)),
+                    };
+                    Vu::set_flag(&mut self.vcc_lt, lane, take_s);
+                    Vu::set_flag(&mut self.vcc_ge, lane, false);
+                    let value = if take_s { s[lane] } else { t[lane] };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                VCH => {
+                    let value = if (ss ^ ts) < 0 {
+                        let sum = ss + ts;
+                        Vu::set_flag(&mut self.vcc_lt, lane, sum <= 0);
+                        Vu::set_flag(&mut self.vcc_ge, lane, ts < 0);
+                        Vu::set_flag(&mut self.vco_carry, lane, true);
+                        Vu::set_flag(&mut self.vco_ne, lane, sum != 0 && sum != -1);
+                        Vu::set_flag(&mut self.vce, lane, sum == -1);
+                        if sum <= 0 {
+                            (-ts) as u16
+                        } else {
+                            s[lane]
+                        }
+                    } else {
+                        let diff = ss - ts;
+                        Vu::set_flag(&mut self.vcc_lt, lane, ts < 0);
+                        Vu::set_flag(&mut self.vcc_ge, lane, diff >= 0);
+                        Vu::set_flag(&mut self.vco_carry, lane, false);
+                        Vu::set_flag(&mut self.vco_ne, lane, diff != 0 && ss != !ts);
+                        Vu::set_flag(&mut self.vce, lane, false);
+                        if diff >= 0 {
+                            t[lane]
+                        } else {
+                            s[lane]
+                        }
+                    };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                VCL => {
+                    let value = if carry {
+                        if !ne {
+                            let sum = su + tu;
+                            let zero = sum & 0xFFFF == 0;
+                            let overflow = sum > 0xFFFF;
+                            // The high halves summed to 0 or -1, so the
+                            // full sum is <= 0 when the low half doesn't
+                            // carry out.
+                            let lt = if Vu::flag(self.vce, lane) {
+                                zero || !overflow
+                            } else {
+                                zero && !overflow
+                            };
+                            Vu::set_flag(&mut self.vcc_lt, lane, lt);
+                        }
+                        if Vu::flag(self.vcc_lt, lane) {
+                            (-tu) as u16
+                        } else {
+                            s[lane]
+                        }
+                    } else {
+                        if !ne {
+                            Vu::set_flag(&mut self.vcc_ge, lane, su - tu >= 0);
+                        }
+                        if Vu::flag(self.vcc_ge, lane) {
+                            t[lane]
+                        } else {
+                            s[lane]
+                        }
+                    };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                VCR => {
+                    let value = if (ss ^ ts) < 0 {
+                        let lt = ss + ts < 0;
+                        Vu::set_flag(&mut self.vcc_ge, lane, ts < 0);
+                        Vu::set_flag(&mut self.vcc_lt, lane, lt);
+                        if lt {
+                            !t[lane]
+                        } else {
+                            s[lane]
+                        }
+                    } else {
+                        let ge = ss - ts >= 0;
+                        Vu::set_flag(&mut self.vcc_lt, lane, ts < 0);
+                        Vu::set_flag(&mut self.vcc_ge, lane, ge);
+                        if ge {
+                            t[lane]
+                        } else {
+                            s[lane]
+                        }
+                    };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                VMRG => {
+                    let value = if Vu::flag(self.vcc_lt, lane) {
+                        s[lane]
+                    } else {
+                        t[lane]
+                    };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                VAND | VNAND | VOR | VNOR | VXOR | VNXOR => {
+                    let value = match instr {
+                        VAND => s[lane] & t[lane],
+                        VNAND => !(s[lane] & t[lane]),
+                        VOR => s[lane] | t[lane],
+                        VNOR => !(s[lane] | t[lane]),
+                        VXOR => s[lane] ^ t[lane],
+                        _ => !(s[lane] ^ t[lane]),
+                    };
+                    self.set_acc_lo(lane, value);
+                    value
+                }
+                _ => 0,
+            };
+        }
+
+        // Instructions that consume the carry or compare flags clear them.
+        match instr {
+            VADD | VSUB | VLT | VEQ | VNE | VGE | VMRG => {
+                self.vco_carry = 0;
+                self.vco_ne = 0;
+            }
+            VCL => {
+                self.vco_carry = 0;
+                self.vco_ne = 0;
+                self.vce = 0;
+            }
+            VCR => {
+                self.vco_carry = 0;
+                self.vco_ne = 0;
+                self.vce = 0;
+            }
+            _ => {}
+        }
+        self.vpr[vd] = result;
+    }
+
+    // The divide unit works on a single element, vt[e & 7], writing one
+    // element of vd (selected by the vs field) and the whole of the low
+    // accumulator slice.
+    fn divide(
+        &mut self,
+        instr: CpuInstrRSP,
+        e: usize,
+        vt: usize,
+        de: usize,
+        vd: usize,
+        t: [u16; 8],
+    ) {
+        for (lane, value) in t.iter().enumerate() {
+            self.set_acc_lo(lane, *value);
+        }
+        let element = self.vpr[vt][e & 7];
+        match instr {
+            VMOV => {
+                self.vpr[vd][de] = t[de];
+            }
+            VRCPH | VRSQH => {
+                self.div_in = element;
+                self.div_dp = true;
+                self.vpr[vd][de] = self.div_out;
+            }
+            _ => {
+                let long = matches!(instr, VRCPL | VRSQL) && self.div_dp;
+                let input: i32 = if long {
+                    ((u32::from(self.div_in) << 16) | u32::from(element)) as i32
+                } else {
+                    i32::from(element as i16)
+                };
+                let sqrt = matches!(instr, VRSQ | VRSQL);
+                let result = Vu::reciprocal(input, sqrt);
+                self.div_dp = false;
+                self.div_out = (result >> 16) as u16;
+                self.vpr[vd][de] = result as u16;
+            }
+        }
+    }
+
+    fn reciprocal(input: i32, sqrt: bool) -> u32 {
+        let mask = input >> 31;
+        let mut data = input ^ mask;
+        if input > -32768 {
+            data = data.wrapping_sub(mask);
+        }
+        if data == 0 {
+            0x7FFF_FFFF
+        } else if input == -32768 {
+            0xFFFF_0000
+        } else {
+            let shift = (data as u32).leading_zeros();
+            let index = (((data as u64) << shift) & 0x7FC0_0000) >> 22;
+            let (fraction, shift) = if sqrt {
+                let index = (index as usize & 0x1FE) | (shift as usize & 1);
+                (rsq_rom(index), (31 - shift) >> 1)
+            } else {
+                (rcp_rom(index as usize), 31 - shift)
+            };
+            let result = ((0x10000 | fraction) << 14) >> shift;
+            result ^ mask as u32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsp_decoder::decode_rsp;
+
+    fn vector_op(funct: u32, e: u32, vt: u32, vs: u32, vd: u32) -> u32 {
+        (0x12 << 26) | (1 << 25) | (e << 21) | (vt << 16) | (vs << 11) | (vd << 6) | funct
+    }
+
+    fn run(vu: &mut Vu, funct: u32, e: u32) {
+        let iw = vector_op(funct, e, 2, 1, 3);
+        vu.execute(decode_rsp(iw), iw);
+    }
+
+    // (name, funct, vs, vt, VCO, VCC, VCE, acc before, vd, VCC after,
+    // acc after), broadcast to all lanes.
+    type Case = (
+        &'static str,
+        u32,
+        u16,
+        u16,
+        u16,
+        u16,
+        u8,
+        u64,
+        u16,
+        u16,
+        u64,
+    );
+
+    #[rustfmt::skip]
+    const CASES: &[Case] = &[
+        ("vmulf",         0x00, 0x4000, 0x4000, 0x0000, 0x0000, 0x00, 0,                0x2000, 0x0000, 0x0000_2000_8000),
+        ("vmulf -1*-1",   0x00, 0x8000, 0x8000, 0x0000, 0x0000, 0x00, 0,                0x7FFF, 0x0000, 0x0000_8000_8000),
+        ("vmulu neg",     0x01, 0x8000, 0x4000, 0x0000, 0x0000, 0x00, 0,                0x0000, 0x0000, 0xFFFF_C000_8000),
+        ("vmulu big",     0x01, 0x8000, 0x8000, 0x0000, 0x0000, 0x00, 0,                0xFFFF, 0x0000, 0x0000_8000_8000),
+        ("vrndp",         0x02, 0x0000, 0x0002, 0x0000, 0x0000, 0x00, 0x0000_0001_0000, 0x0003, 0x0000, 0x0000_0003_0000),
+        ("vmulq",         0x03, 0x0010, 0x0010, 0x0000, 0x0000, 0x00, 0,                0x0080, 0x0000, 0x0000_0100_0000),
+        ("vmulq round",   0x03, 0xFFFF, 0x0010, 0x0000, 0x0000, 0x00, 0,                0x0000, 0x0000, 0x0000_000F_0000),
+        ("vmulq sat",     0x03, 0x0100, 0x0100, 0x0000, 0x0000, 0x00, 0,                0x7FF0, 0x0000, 0x0001_0000_0000),
+        ("vmudl",         0x04, 0xFFFF, 0xFFFF, 0x0000, 0x0000, 0x00, 0,                0xFFFE, 0x0000, 0x0000_0000_FFFE),
+        ("vmudm",         0x05, 0xFFFF, 0xFFFF, 0x0000, 0x0000, 0x00, 0,                0xFFFF, 0x0000, 0xFFFF_FFFF_0001),
+        ("vmudn",         0x06, 0xFFFF, 0xFFFF, 0x0000, 0x0000, 0x00, 0,                0x0001, 0x0000, 0xFFFF_FFFF_0001),
+        ("vmudh",         0x07, 0x0100, 0x0100, 0x0000, 0x0000, 0x00, 0,                0x7FFF, 0x0000, 0x0001_0000_0000),
+        ("vmacf",         0x08, 0x4000, 0x4000, 0x0000, 0x0000, 0x00, 0x0000_2000_0000, 0x4000, 0x0000, 0x0000_4000_0000),
+        ("vmacu",         0x09, 0x4000, 0x4000, 0x0000, 0x0000, 0x00, 0x0000_2000_0000, 0x4000, 0x0000, 0x0000_4000_0000),
+        ("vmacu neg",     0x09, 0x8000, 0x4000, 0x0000, 0x0000, 0x00, 0,                0x0000, 0x0000, 0xFFFF_C000_0000),
+        ("vrndn pos",     0x0A, 0x0000, 0x0002, 0x0000, 0x0000, 0x00, 0x0000_0001_0000, 0x0001, 0x0000, 0x0000_0001_0000),
+        ("vrndn neg",     0x0A, 0x0000, 0x0002, 0x0000, 0x0000, 0x00, 0xFFFF_FFFF_0000, 0x0001, 0x0000, 0x0000_0001_0000),
+        ("vmacq",         0x0B, 0x0000, 0x0000, 0x0000, 0x0000, 0x00, 0x0000_0040_0000, 0x0010, 0x0000, 0x0000_0020_0000),
+        ("vmadl sat",     0x0C, 0xFFFF, 0xFFFF, 0x0000, 0x0000, 0x00, 0x0000_8000_0000, 0xFFFF, 0x0000, 0x0000_8000_FFFE),
+        ("vmadm",         0x0D, 0x0001, 0x0001, 0x0000, 0x0000, 0x00, 0xFFFF_FFFF_FFFF, 0x0000, 0x0000, 0x0000_0000_0000),
+        ("vmadn sat",     0x0E, 0x0001, 0x0001, 0x0000, 0x0000, 0x00, 0x0001_0000_0000, 0xFFFF, 0x0000, 0x0001_0000_0001),
+        ("vmadh",         0x0F, 0x0002, 0x0003, 0x0000, 0x0000, 0x00, 0x0000_0001_0000, 0x0007, 0x0000, 0x0000_0007_0000),
+        ("vadd sat",      0x10, 0x7FFF, 0x0001, 0x0000, 0x0000, 0x00, 0,                0x7FFF, 0x0000, 0x0000_0000_8000),
+        ("vsub sat",      0x11, 0x8000, 0x0001, 0x0000, 0x0000, 0x00, 0,                0x8000, 0x0000, 0x0000_0000_7FFF),
+        ("vabs neg",      0x13, 0xFFFF, 0x0005, 0x0000, 0x0000, 0x00, 0,                0xFFFB, 0x0000, 0x0000_0000_FFFB),
+        ("vabs min",      0x13, 0xFFFF, 0x8000, 0x0000, 0x0000, 0x00, 0,                0x7FFF, 0x0000, 0x0000_0000_8000),
+        ("vabs zero",     0x13, 0x0000, 0x1234, 0x0000, 0x0000, 0x00, 0,                0x0000, 0x0000, 0x0000_0000_0000),
+        ("vaddc",         0x14, 0xFFFF, 0x0002, 0x0000, 0x0000, 0x00, 0,                0x0001, 0x0000, 0x0000_0000_0001),
+        ("vsubc",         0x15, 0x0001, 0x0002, 0x0000, 0x0000, 0x00, 0,                0xFFFF, 0x0000, 0x0000_0000_FFFF),
+        ("vlt",           0x20, 0xFFFF, 0x0001, 0x0000, 0x0000, 0x00, 0,                0xFFFF, 0x00FF, 0x0000_0000_FFFF),
+        ("veq",           0x21, 0x0005, 0x0005, 0x0000, 0x0000, 0x00, 0,                0x0005, 0x00FF, 0x0000_0000_0005),
+        ("veq differ",    0x21, 0x0005, 0x0003, 0x0000, 0x0000, 0x00, 0,                0x0003, 0x0000, 0x0000_0000_0003),
+        ("veq ne",        0x21, 0x0005, 0x0005, 0xFF00, 0x0000, 0x00, 0,                0x0005, 0x0000, 0x0000_0000_0005),
+        ("vne",           0x22, 0x0005, 0x0003, 0x0000, 0x0000, 0x00, 0,                0x0005, 0x00FF, 0x0000_0000_0005),
+        ("vne equal",     0x22, 0x0005, 0x0005, 0x0000, 0x0000, 0x00, 0,                0x0005, 0x0000, 0x0000_0000_0005),
+        ("vne ne",        0x22, 0x0005, 0x0005, 0xFF00, 0x0000, 0x00, 0,                0x0005, 0x00FF, 0x0000_0000_0005),
+        ("vge",           0x23, 0xFFFF, 0x0001, 0x0000, 0x0000, 0x00, 0,                0x0001, 0x0000, 0x0000_0000_0001),
+        ("vcl zero",      0x24, 0x0000, 0x0000, 0x00FF, 0x0000, 0x00, 0,                0x0000, 0x00FF, 0x0000_0000_0000),
+        ("vcl carry out", 0x24, 0x8000, 0x8000, 0x00FF, 0x0000, 0x00, 0,                0x8000, 0x0000, 0x0000_0000_8000),
+        ("vcl no vce",    0x24, 0x0001, 0xFFFE, 0x00FF, 0x0000, 0x00, 0,                0x0001, 0x0000, 0x0000_0000_0001),
+        ("vcl vce",       0x24, 0x0001, 0xFFFE, 0x00FF, 0x0000, 0xFF, 0,               0x0002, 0x00FF, 0x0000_0000_0002),
+        ("vcl vce carry", 0x24, 0x0002, 0xFFFF, 0x00FF, 0x0000, 0xFF, 0,               0x0002, 0x0000, 0x0000_0000_0002),
+        ("vcl keep lt",   0x24, 0x0005, 0x0003, 0xFFFF, 0x00FF, 0x00, 0,                0xFFFD, 0x00FF, 0x0000_0000_FFFD),
+        ("vcl ge",        0x24, 0x0005, 0x0003, 0x0000, 0x0000, 0x00, 0,                0x0003, 0xFF00, 0x0000_0000_0003),
+        ("vcl not ge",    0x24, 0x0002, 0x0005, 0x0000, 0x0000, 0x00, 0,                0x0002, 0x0000, 0x0000_0000_0002),
+        ("vcl keep ge",   0x24, 0x0002, 0x0005, 0xFF00, 0xFF00, 0x00, 0,                0x0005, 0xFF00, 0x0000_0000_0005),
+        ("vch",           0x25, 0x0005, 0xFFFD, 0x0000, 0x0000, 0x00, 0,                0x0005, 0xFF00, 0x0000_0000_0005),
+        ("vch clip",      0x25, 0xFFFB, 0x0003, 0x0000, 0x0000, 0x00, 0,                0xFFFD, 0x00FF, 0x0000_0000_FFFD),
+        ("vcr",           0x26, 0x0005, 0xFFFD, 0x0000, 0x0000, 0x00, 0,                0x0005, 0xFF00, 0x0000_0000_0005),
+        ("vmrg s",        0x27, 0x0001, 0x0002, 0x0000, 0x00FF, 0x00, 0,                0x0001, 0x00FF, 0x0000_0000_0001),
+        ("vmrg t",        0x27, 0x0001, 0x0002, 0x0000, 0x0000, 0x00, 0,                0x0002, 0x0000, 0x0000_0000_0002),
+        ("vand",          0x28, 0x0FF0, 0x00FF, 0x0000, 0x0000, 0x00, 0,                0x00F0, 0x0000, 0x0000_0000_00F0),
+        ("vnand",         0x29, 0x0FF0, 0x00FF, 0x0000, 0x0000, 0x00, 0,                0xFF0F, 0x0000, 0x0000_0000_FF0F),
+        ("vor",           0x2A, 0x0FF0, 0x00FF, 0x0000, 0x0000, 0x00, 0,                0x0FFF, 0x0000, 0x0000_0000_0FFF),
+        ("vnor",          0x2B, 0x0FF0, 0x00FF, 0x0000, 0x0000, 0x00, 0,                0xF000, 0x0000, 0x0000_0000_F000),
+        ("vxor",          0x2C, 0x0FF0, 0x00FF, 0x0000, 0x0000, 0x00, 0,                0x0F0F, 0x0000, 0x0000_0000_0F0F),
+        ("vnxor",         0x2D, 0x0FF0, 0x00FF, 0x0000, 0x0000, 0x00, 0,                0xF0F0, 0x0000, 0x0000_0000_F0F0),
+    ];
+
+    #[test]
+    fn computational_ops() {
+        for &(name, funct, vs, vt, vco, vcc, vce, acc, vd, vcc_after, acc_after) in CASES {
+            let mut vu = Vu::default();
+            vu.vpr[1] = [vs; 8];
+            vu.vpr[2] = [vt; 8];
+            vu.ctc2(0, u32::from(vco));
+            vu.ctc2(1 << 11, u32::from(vcc));
+            vu.ctc2(2 << 11, u32::from(vce));
+            vu.acc = [acc; 8];
+            run(&mut vu, funct, 0);
+            assert_eq!(vu.vpr[3], [vd; 8], "{} result", name);
+            assert_eq!(vu.cfc2(1 << 11) as u16, vcc_after, "{} VCC", name);
+            assert_eq!(vu.acc, [acc_after; 8], "{} accumulator", name);
+        }
+    }
+
+    #[test]
+    fn carry_chain() {
+        let mut vu = Vu::default();
+        vu.vpr[1] = [0xFFFF, 0, 0, 0, 0, 0, 0, 0];
+        vu.vpr[2] = [0x0001, 0, 0, 0, 0, 0, 0, 0];
+        run(&mut vu, 0x14, 0);
+        assert_eq!(vu.cfc2(0) as u16, 0x0001);
+        // VADD consumes the carry from VADDC, then clears it.
+        vu.vpr[1] = [0; 8];
+        vu.vpr[2] = [0; 8];
+        run(&mut vu, 0x10, 0);
+        assert_eq!(vu.vpr[3][0], 1);
+        assert_eq!(vu.cfc2(0), 0);
+    }
+
+    #[test]
+    fn element_selectors() {
+        let mut vu = Vu::default();
+        vu.vpr[1] = [0; 8];
+        vu.vpr[2] = [0, 1, 2, 3, 4, 5, 6, 7];
+        run(&mut vu, 0x2A, 3);
+        assert_eq!(vu.vpr[3], [1, 1, 3, 3, 5, 5, 7, 7]);
+        run(&mut vu, 0x2A, 6);
+        assert_eq!(vu.vpr[3], [2, 2, 2, 2, 6, 6, 6, 6]);
+        run(&mut vu, 0x2A, 13);
+        assert_eq!(vu.vpr[3], [5; 8]);
+    }
+
+    #[test]
+    fn divide_edge_cases() {
+        let mut vu = Vu::default();
+        for &(input, expected) in &[
+            (0x0000u16, 0x7FFF_FFFFu32),
+            (0x8000, 0xFFFF_0000),
+            (0x0001, 0x7FFF_C000),
+            (0x4000, 0x0001_FFFF),
+        ] {
+            vu.vpr[2] = [input; 8];
+            // vrcp $v3[0], $v2[0]; vrcph $v4[0], $v2[0]
+            run(&mut vu, 0x30, 8);
+            let lo = vu.vpr[3][1];
+            let iw = vector_op(0x32, 8, 2, 0, 4);
+            vu.execute(decode_rsp(iw), iw);
+            assert_eq!((u32::from(vu.vpr[4][0]) << 16) | u32::from(lo), expected);
+        }
+        assert_eq!(rcp_rom(0), 0xFFFF);
+        assert_eq!(rcp_rom(1), 0xFF00);
+        assert_eq!(rsq_rom(1), 0xFFFF);
+    }
+
+    // (name, setup funct, setup vt, funct, vt, vd[1], high result after).
+    // The setup instruction writes $v4, so only the second one reaches $v3.
+    #[rustfmt::skip]
+    const DIVIDE_CASES: &[(&str, u32, u16, u32, u16, u16, u16)] = &[
+        ("vrcp",        0x37, 0x0000, 0x30, 0x4000, 0xFFFF, 0x0001),
+        ("vrcp zero",   0x37, 0x0000, 0x30, 0x0000, 0xFFFF, 0x7FFF),
+        ("vrcp neg",    0x37, 0x0000, 0x30, 0xC000, 0x0000, 0xFFFE),
+        ("vrcpl",       0x37, 0x0000, 0x31, 0x4000, 0xFFFF, 0x0001),
+        ("vrcpl long",  0x32, 0x0001, 0x31, 0x0000, 0x7FFF, 0x0000),
+        ("vrcph",       0x30, 0x4000, 0x32, 0x1234, 0x0001, 0x0001),
+        ("vmov",        0x37, 0x0000, 0x33, 0x1234, 0x1234, 0x0000),
+        ("vrsq",        0x37, 0x0000, 0x34, 0x4000, 0xFF80, 0x00FF),
+        ("vrsql",       0x37, 0x0000, 0x35, 0x4000, 0xFF80, 0x00FF),
+        ("vrsql long",  0x36, 0x0001, 0x35, 0x0000, 0xFFC0, 0x007F),
+        ("vrsqh",       0x34, 0x4000, 0x36, 0x1234, 0x00FF, 0x00FF),
+    ];
+
+    #[test]
+    fn divide_ops() {
+        for &(name, setup, setup_vt, funct, vt, vd, high) in DIVIDE_CASES {
+            let mut vu = Vu::default();
+            vu.vpr[2] = [setup_vt; 8];
+            let iw = vector_op(setup, 8, 2, 1, 4);
+            vu.execute(decode_rsp(iw), iw);
+            vu.vpr[2] = [vt; 8];
+            run(&mut vu, funct, 8);
+            assert_eq!(vu.vpr[3], [0, vd, 0, 0, 0, 0, 0, 0], "{} result", name);
+            assert_eq!(vu.div_out, high, "{} high result", name);
+            assert_eq!(vu.acc, [u64::from(vt); 8], "{} accumulator", name);
+        }
+    }
+
+    #[test]
+    fn accumulator_slices() {
+        let mut vu = Vu {
+            acc: [0x1234_5678_9ABC; 8],
+            ..Vu::default()
+        };
+        // vsar reads the slice picked by the element field.
+        for &(e, expected) in &[(8, 0x1234), (9, 0x5678), (10, 0x9ABC), (0, 0)] {
+            run(&mut vu, 0x1D, e);
+            assert_eq!(vu.vpr[3], [expected; 8], "vsar e={}", e);
+        }
+        assert_eq!(vu.acc, [0x1234_5678_9ABC; 8]);
+    }
+
+    #[test]
+    fn clip_high_flags() {
+        let mut vu = Vu::default();
+        vu.vpr[1] = [5, 2, 0xFFFE, 3, 0, 0, 0, 0];
+        vu.vpr[2] = [0xFFFA, 1, 1, 3, 0, 0, 0, 0];
+        run(&mut vu, 0x25, 0);
+        assert_eq!(vu.vpr[3][..4], [6, 1, 0xFFFF, 3]);
+        // VCC: lt in the low byte, ge in the high byte.
+        assert_eq!(vu.cfc2(1 << 11) as u16, 0xFB05);
+        assert_eq!(vu.cfc2(0) as u16, 0x0205);
+        assert_eq!(vu.cfc2(2 << 11), 0x05);
+    }
+
+    #[test]
+    fn quad_and_rest_loads() {
+        let mut dmem = [0u8; SP_MEM_SIZE];
+        for (i, byte) in dmem.iter_mut().enumerate().take(32) {
+            *byte = i as u8;
+        }
+        let mut vu = Vu::default();
+        // lqv $v1[0], 0($r) and lrv $v1[0], 16($r) with the base at 4.
+        vu.load(LQV, (1 << 16) | (4 << 11), 4, &dmem);
+        vu.load(LRV, (1 << 16) | (5 << 11) | 1, 4, &dmem);
+        assert_eq!(vu.vpr[1][0], 0x0405);
+        assert_eq!(vu.vpr[1][5], 0x0E0F);
+        assert_eq!(vu.vpr[1][6], 0x1011);
+        assert_eq!(vu.vpr[1][7], 0x1213);
+
+        let mut out = [0u8; SP_MEM_SIZE];
+        vu.store(SQV, (1 << 16) | (4 << 11), 0, &mut out);
+        assert_eq!(out[..16], dmem[4..20]);
+    }
+
+    #[test]
+    fn packed_and_transposed() {
+        let mut vu = Vu::default();
+        vu.vpr[1] = [0x1280, 0x3400, 0x5600, 0x7800, 0, 0, 0, 0x0180];
+        let mut dmem = [0u8; SP_MEM_SIZE];
+        vu.store(SPV, 1 << 16, 0, &mut dmem);
+        assert_eq!(dmem[..8], [0x12, 0x34, 0x56, 0x78, 0, 0, 0, 0x01]);
+        vu.store(SUV, 1 << 16, 8, &mut dmem);
+        assert_eq!(dmem[8..16], [0x25, 0x68, 0xAC, 0xF0, 0, 0, 0, 0x03]);
+        let mut back = Vu::default();
+        back.load(LUV, 2 << 16, 8, &dmem);
+        assert_eq!(back.vpr[2][..4], [0x1280, 0x3400, 0x5600, 0x7800]);
+
+        // stv and ltv move a diagonal of the group $v8-$v15.
+        for reg in 8..16 {
+            for lane in 0..8 {
+                vu.vpr[reg][lane] = (reg as u16) << 8 | lane as u16;
+            }
+        }
+        let mut dmem = [0u8; SP_MEM_SIZE];
+        // stv $v8[2], 0x100
+        vu.store(STV, (8 << 16) | (2 << 7), 0x100, &mut dmem);
+        assert_eq!(dmem[0x100..0x104], [0x09, 0x00, 0x0A, 0x01]);
+        assert_eq!(dmem[0x10E..0x110], [0x08, 0x07]);
+        let mut back = Vu::default();
+        back.load(LTV, 16 << 16, 0x100, &dmem);
+        for reg in 0..8 {
+            assert_eq!(back.vpr[16 + reg][reg], vu.vpr[8 + (reg + 1) % 8][reg]);
+        }
+    }
+
+    #[test]
+    fn moves() {
+        let mut vu = Vu::default();
+        vu.mtc2((1 << 11) | (3 << 7), 0xABCD);
+        assert_eq!(vu.vpr[1][1], 0x00AB);
+        assert_eq!(vu.vpr[1][2], 0xCD00);
+        assert_eq!(vu.mfc2((1 << 11) | (3 << 7)), 0xFFFF_ABCD);
+        vu.ctc2(2 << 11, 0x1FF);
+        assert_eq!(vu.cfc2(2 << 11), 0xFF);
+    }
+}
@@ -1,59 +1,109 @@
 #[cfg(test)]
 mod tests {
-    use itertools::Itertools;
     use magic_types::*;
     use std::fs::File;
     use std::io::prelude::*;
-    use ux::{u5, u6};
 
-    pub fn instructions_iter(instr_db: &str) -> impl Iterator<Item = u32> {
+    fn load_db(instr_db: &str) -> Vec<MetaInstruction> {
         let mut f = File::open(instr_db).unwrap();
         let mut src = String::new();
         f.read_to_string(&mut src).unwrap();
-        let instrs: Vec<MetaInstruction> = serde_json::from_str(&src).unwrap();
-        let mut results: Vec<Box<dyn Iterator<Item = u32>>> = Vec::new();
-        let mut i = 0;
-        for instr in instrs {
-            results.push(instr.legal_encodings());
-            i = i + 1;
-        }
+        serde_json::from_str(&src).unwrap()
+    }
 
-        results.into_iter().flatten()
+    // Runs `check` on every legal encoding of every entry, with each
+    // entry's encodings split evenly between one thread per core.
+    fn for_each_encoding<T: Sync>(
+        entries: &[(MetaInstruction, T)],
+        check: impl Fn(&MetaInstruction, &T, u32) + Sync,
+    ) {
+        let shards = std::thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+        let check = &check;
+        std::thread::scope(|scope| {
+            for shard in 0..shards {
+                scope.spawn(move || {
+                    for (metainstr, expected) in entries {
+                        for encoding in metainstr.legal_encodings().shard(shard, shards) {
+                            check(metainstr, expected, encoding);
+                        }
+                    }
+                });
+            }
+        });
     }
-    
+
     use magic::decoder::{decode_vr4300, CpuInstrVR4300};
-    
+
     #[test]
     fn test_scalar_decoder() {
-        let mut f = File::open("../magic-macros/mipsiii.json").unwrap();
-        let mut src = String::new();
-        f.read_to_string(&mut src).unwrap();
-        let metainstrs: Vec<MetaInstruction> = serde_json::from_str(&src).unwrap();
-        for metainstr in metainstrs {
-            let expected_op :CpuInstrVR4300 = str::parse(&metainstr.name.replace(".", "_")).expect(&format!("Couldn't find variant {:?}", metainstr.name));
-            for encoding in metainstr.legal_encodings() {
-                let decoded_op = decode_vr4300(encoding);
-                if decoded_op != expected_op {
-                    println!("meta-instr {:#X?}, encoding {:#X}, expected {:?}, got {:?}", metainstr, encoding, expected_op, decoded_op);
-                }
-                assert_eq!(decoded_op, expected_op);
+        let entries: Vec<(MetaInstruction, CpuInstrVR4300)> = load_db("../magic-macros/mipsiii.json")
+            .into_iter()
+            .map(|metainstr| {
+                let expected_op = str::parse(&metainstr.name.replace(".", "_")).unwrap_or_else(|_| panic!("Couldn't find variant {:?}", metainstr.name));
+                (metainstr, expected_op)
+            })
+            .collect();
+        for_each_encoding(&entries, |metainstr, &expected_op, encoding| {
+            let decoded_op = decode_vr4300(encoding);
+            if decoded_op != expected_op {
+                println!("meta-instr {:#X?}, encoding {:#X}, expected {:?}, got {:?}", metainstr, encoding, expected_op, decoded_op);
             }
-        }
+            assert_eq!(decoded_op, expected_op);
+        });
     }
 
     #[test]
-    #[ignore]
-    fn test_instruction_iterator() {
-        let mut set: Vec<u8> = Vec::with_capacity(0x2000_0000);
-        set.resize(0x2000_0000, 0);
-        let mut count: u32 = 0;
-        for instr in instructions_iter("../magic-macros/mipsiii.json") {
-            let index: usize = instr as usize / 8;
-            let mask = 1 << (instr % 8);
-            assert_eq!(set[index] & mask, 0);
-            set[index] |= mask;
-            count += 1;
+    fn test_fp_formats() {
+        // add.fmt f0, f0, f0 for each fmt, and cvt.d.fmt, which D lacks.
+        let add = |fmt: u32| 0x4400_0000 | fmt << 21;
+        let cvt_d = |fmt: u32| 0x4400_0021 | fmt << 21;
+        assert_eq!(decode_vr4300(add(16)), CpuInstrVR4300::ADD_S);
+        assert_eq!(decode_vr4300(add(17)), CpuInstrVR4300::ADD_D);
+        assert_eq!(decode_vr4300(add(20)), CpuInstrVR4300::Invalid);
+        assert_eq!(decode_vr4300(add(23)), CpuInstrVR4300::Invalid);
+        assert_eq!(decode_vr4300(add(31)), CpuInstrVR4300::Invalid);
+        assert_eq!(decode_vr4300(cvt_d(16)), CpuInstrVR4300::CVT_D_S);
+        assert_eq!(decode_vr4300(cvt_d(17)), CpuInstrVR4300::Invalid);
+        assert_eq!(decode_vr4300(cvt_d(20)), CpuInstrVR4300::CVT_D_W);
+        assert_eq!(decode_vr4300(cvt_d(21)), CpuInstrVR4300::CVT_D_L);
+        // COP2 isn't present on the VR4300.
+        assert_eq!(decode_vr4300(0x4800_0000), CpuInstrVR4300::Invalid);
+    }
+
+    use magic::rsp_decoder::{decode_rsp, CpuInstrRSP};
+
+    #[test]
+    fn test_rsp_decoder() {
+        let entries: Vec<(MetaInstruction, CpuInstrRSP)> = load_db("../magic-macros/rsp.json")
+            .into_iter()
+            .map(|metainstr| {
+                let expected_op = str::parse(&metainstr.name.replace(".", "_")).unwrap_or_else(|_| panic!("Couldn't find variant {:?}", metainstr.name));
+                (metainstr, expected_op)
+            })
+            .collect();
+        for_each_encoding(&entries, |metainstr, &expected_op, encoding| {
+            let decoded_op = decode_rsp(encoding);
+            if decoded_op != expected_op {
+                println!("meta-instr {:#X?}, encoding {:#X}, expected {:?}, got {:?}", metainstr, encoding, expected_op, decoded_op);
+            }
+            assert_eq!(decoded_op, expected_op);
+        });
+    }
+
+    // No two database entries claim the same word, so there's exactly one
+    // right answer for the decoder to give for each claimed word.
+    #[test]
+    fn test_encodings_disjoint() {
+        for instr_db in &["../magic-macros/mipsiii.json", "../magic-macros/rsp.json"] {
+            let metainstrs = load_db(instr_db);
+            let overlaps = overlaps(&metainstrs);
+            assert!(overlaps.is_empty(), "{}: {:#X?}", instr_db, overlaps);
+
+            let claimed: u64 = metainstrs.iter().map(|metainstr| word_count(metainstr.mask_match())).sum();
+            let unclaimed: u64 = unclaimed(&metainstrs).into_iter().map(word_count).sum();
+            assert_eq!(claimed + unclaimed, 1 << 32, "{}", instr_db);
         }
-        assert_eq!(count, 2_938_789_030);
+        let claimed: u64 = load_db("../magic-macros/mipsiii.json").iter().map(|metainstr| word_count(metainstr.mask_match())).sum();
+        assert_eq!(claimed, 2_938_789_030);
     }
 }